@@ -0,0 +1,72 @@
+/* Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+ * http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+ * http://opensource.org/licenses/MIT>, at your option. This file may not be
+ * copied, modified, or distributed except according to those terms. */
+
+/* The C interface of the dumpsyms library (see src/ffi.rs) */
+
+#ifndef DUMP_SYMS_H
+#define DUMP_SYMS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DUMP_SYMS_OK 0
+#define DUMP_SYMS_INVALID_ARGUMENT 1
+#define DUMP_SYMS_ERROR 2
+#define DUMP_SYMS_PANIC 3
+
+/* A handle on a cancel token: the dumps using it can be cancelled from another thread */
+typedef struct DumpSymsCancelToken DumpSymsCancelToken;
+
+/* The options of a dump: the zeroed struct (or NULL) gives the default options */
+typedef struct DumpSymsOptions {
+    /* The architecture to dump in a fat binary (NULL for the one of the build) */
+    const char* arch;
+    /* The number of jobs (0 for 1) */
+    size_t num_jobs;
+    /* Get the source server information from the pdb */
+    bool srcsrv;
+    /* Add the checksums to the FILE records */
+    bool file_checksums;
+    /* The token to cancel the dump from another thread (NULL for none) */
+    const DumpSymsCancelToken* cancel;
+} DumpSymsOptions;
+
+/* Dump the file at path and write the records in out_path ("-" for stdout) */
+int dump_syms_dump(const char* path, const char* out_path, const DumpSymsOptions* options);
+
+/* Dump the content of the file at path and set out to the records: they must be freed
+ * with dump_syms_free (path is used to get the name of the file and to find the ones
+ * next to it, e.g. the PE of a pdb) */
+int dump_syms_dump_buffer(const uint8_t* buf, size_t len, const char* path,
+                          const DumpSymsOptions* options, char** out);
+
+/* Free the records given by dump_syms_dump_buffer */
+void dump_syms_free(char* sym);
+
+/* Create a cancel token: it must be freed with dump_syms_cancel_token_free once the
+ * dumps using it are finished */
+DumpSymsCancelToken* dump_syms_cancel_token_new(void);
+
+/* Cancel the dumps using the token: they return DUMP_SYMS_ERROR (only the dumps of the
+ * pdbs check it for now) */
+void dump_syms_cancel(const DumpSymsCancelToken* token);
+
+/* Free a cancel token given by dump_syms_cancel_token_new */
+void dump_syms_cancel_token_free(DumpSymsCancelToken* token);
+
+/* Get the message of the last error of the thread (NULL if the last call succeeded):
+ * it's valid until the next call */
+const char* dump_syms_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -6,16 +6,24 @@
 use std::path::PathBuf;
 
 use crate::common::{self, FileType};
+use crate::ids;
 use crate::linux::elf::ElfInfo;
 use crate::mac::macho::MachoInfo;
 use crate::utils;
+use crate::wasm::module::WasmInfo;
 use crate::windows::pdb::PDBInfo;
 
 use super::dumper::{self, Config};
 
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum Action<'a> {
     Dump(Config<'a>),
     ListArch,
+    PrintIds {
+        arch: &'a str,
+        debug_id: bool,
+        code_id: bool,
+    },
 }
 
 impl Action<'_> {
@@ -38,6 +46,11 @@ impl Action<'_> {
                 let buf = utils::read_file(&path);
                 MachoInfo::print_architectures(&buf, filename)
             }
+            Self::PrintIds {
+                arch,
+                debug_id,
+                code_id,
+            } => ids::print_ids(&utils::read(filename)?, arch, *debug_id, *code_id),
         }
     }
 
@@ -47,6 +60,7 @@ impl Action<'_> {
                 FileType::Elf => dumper::several_files::<ElfInfo>(config, filenames),
                 FileType::Macho => dumper::several_files::<MachoInfo>(config, filenames),
                 FileType::Pdb => dumper::several_files::<PDBInfo>(config, filenames),
+                FileType::Wasm => dumper::several_files::<WasmInfo>(config, filenames),
                 _ => Ok(()),
             },
             Self::ListArch => {
@@ -59,6 +73,12 @@ impl Action<'_> {
                 }
                 Ok(())
             }
+            Self::PrintIds { .. } => {
+                for f in filenames {
+                    self.single_file(f)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -71,6 +91,7 @@ mod tests {
     use tempfile::Builder;
 
     use super::*;
+    use crate::common::DumpOptions;
 
     #[test]
     fn test_missing_pe() {
@@ -94,6 +115,8 @@ mod tests {
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            prefix_map: None,
+            options: DumpOptions::default(),
         });
 
         action.action(&[tmp_file.to_str().unwrap()]).unwrap();
@@ -130,6 +153,8 @@ mod tests {
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            prefix_map: None,
+            options: DumpOptions::default(),
         });
 
         action.action(&[tmp_pdb.to_str().unwrap()]).unwrap();
@@ -160,6 +185,8 @@ mod tests {
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            prefix_map: None,
+            options: DumpOptions::default(),
         });
 
         action.action(&[full.to_str().unwrap()]).unwrap();
@@ -194,6 +221,8 @@ mod tests {
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            prefix_map: None,
+            options: DumpOptions::default(),
         });
 
         action
@@ -235,6 +264,8 @@ mod tests {
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            prefix_map: None,
+            options: DumpOptions::default(),
         });
 
         action
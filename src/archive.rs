@@ -0,0 +1,164 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use tar::Archive;
+use zip::ZipArchive;
+
+use crate::common;
+
+/*
+ A member of an archive can be dumped without extracting it: either with app.apk!lib/arm64-v8a/libfoo.so
+ or with --member lib/arm64-v8a/libfoo.so.
+ The archive format is guessed from its first bytes: zip (and so apk, aab, jar, ...), tar and tar
+ compressed with gzip or zstd.
+*/
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const ZSTD_MAGIC: &[u8] = b"\x28\xb5\x2f\xfd";
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Split an input like app.apk!lib/arm64-v8a/libfoo.so in the archive path and the member name
+pub(crate) fn split_member(input: &str) -> Option<(&str, &str)> {
+    if Path::new(input).exists() {
+        return None;
+    }
+    input
+        .match_indices('!')
+        .map(|(i, _)| (&input[..i], &input[i + 1..]))
+        .find(|(archive, member)| !member.is_empty() && Path::new(archive).is_file())
+}
+
+/// The member names in a tar can start with ./
+fn is_member(name: &Path, member: &str) -> bool {
+    let member = Path::new(member.trim_start_matches("./"));
+    name.strip_prefix(".").unwrap_or(name) == member
+}
+
+fn read_tar<R: Read>(reader: R, member: &str) -> common::Result<Option<Vec<u8>>> {
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() && is_member(&entry.path()?, member) {
+            let mut buf = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut buf)?;
+            return Ok(Some(buf));
+        }
+    }
+    Ok(None)
+}
+
+fn read_zip(file: File, member: &str) -> common::Result<Option<Vec<u8>>> {
+    let mut archive = ZipArchive::new(BufReader::new(file))?;
+    let mut entry = match archive.by_name(member.trim_start_matches("./")) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut buf = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut buf)?;
+    Ok(Some(buf))
+}
+
+#[cfg(feature = "zstd")]
+fn read_zstd_tar(file: File, member: &str) -> common::Result<Option<Vec<u8>>> {
+    read_tar(zstd::stream::read::Decoder::new(file)?, member)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn read_zstd_tar(_file: File, _member: &str) -> common::Result<Option<Vec<u8>>> {
+    Err("The tar archives compressed with zstd require the zstd feature".into())
+}
+
+/// Read a member of an archive
+pub(crate) fn read_member(archive: &str, member: &str) -> common::Result<Vec<u8>> {
+    let mut file = File::open(archive)?;
+    let mut magic = Vec::new();
+    file.by_ref()
+        .take((TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64)
+        .read_to_end(&mut magic)?;
+    let file = File::open(archive)?;
+
+    let buf = if magic.starts_with(ZIP_MAGIC) {
+        read_zip(file, member)?
+    } else if magic.starts_with(GZIP_MAGIC) {
+        read_tar(GzDecoder::new(BufReader::new(file)), member)?
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        read_zstd_tar(file, member)?
+    } else if magic.get(TAR_MAGIC_OFFSET..) == Some(TAR_MAGIC) {
+        read_tar(BufReader::new(file), member)?
+    } else {
+        return Err(format!("{} isn't a zip or a tar archive", archive).into());
+    };
+
+    buf.ok_or_else(|| format!("No member {} in {}", member, archive).into())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::{Cursor, Write};
+    use tempfile::Builder;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn get_tar() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in &[("./lib/libbar.so", "bar"), ("./lib/libfoo.so", "foo")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_read_member() {
+        let tmp_dir = Builder::new().prefix("archive").tempdir().unwrap();
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("lib/arm64-v8a/libfoo.so", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"foo").unwrap();
+        let apk = tmp_dir.path().join("app.apk");
+        fs::write(&apk, zip.finish().unwrap().into_inner()).unwrap();
+
+        let tar = tmp_dir.path().join("libs.tar");
+        fs::write(&tar, get_tar()).unwrap();
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&get_tar()).unwrap();
+        let tgz = tmp_dir.path().join("libs.tgz");
+        fs::write(&tgz, gz.finish().unwrap()).unwrap();
+
+        let apk = apk.to_str().unwrap();
+        assert_eq!(read_member(apk, "lib/arm64-v8a/libfoo.so").unwrap(), b"foo");
+        assert!(read_member(apk, "lib/libfoo.so").is_err());
+        for archive in &[tar, tgz] {
+            let archive = archive.to_str().unwrap();
+            assert_eq!(read_member(archive, "lib/libfoo.so").unwrap(), b"foo");
+            assert_eq!(read_member(archive, "./lib/libbar.so").unwrap(), b"bar");
+            assert!(read_member(archive, "lib/libbaz.so").is_err());
+        }
+
+        let input = format!("{}!lib/arm64-v8a/libfoo.so", apk);
+        assert_eq!(split_member(&input), Some((apk, "lib/arm64-v8a/libfoo.so")));
+        assert_eq!(split_member(apk), None);
+        assert_eq!(split_member(&format!("{}!", apk)), None);
+    }
+}
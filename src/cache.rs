@@ -4,39 +4,136 @@
 // copied, modified, or distributed except according to those terms.
 
 use dirs::home_dir;
-use futures::{stream, StreamExt};
-use reqwest::{self, blocking, header::USER_AGENT, Client};
-use std::fs::{self, File};
-use std::io::{BufWriter, Read, Write};
+use log::warn;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
-use tokio::runtime::Runtime;
-use url::Url;
 
-use crate::common;
 use crate::utils;
 
+#[cfg(feature = "http")]
+use {
+    crate::{common, error, fetch},
+    futures::{stream, StreamExt},
+    reqwest::{
+        self, blocking,
+        header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, USER_AGENT},
+        Client,
+    },
+    std::fs,
+    std::io::{BufWriter, Write},
+    std::sync::{Arc, Mutex},
+    tokio::runtime::Runtime,
+    tracing::info_span,
+    url::Url,
+};
+
+/*
+ The files are searched in the caches of the symbol servers and then on the servers: without the
+ http feature, only the caches are used.
+ A private server takes its credentials in the last segment of its url: SRV*cache*https://syms.corp/token=...
+ gives an Authorization: Bearer header (e.g. Artifactory) and auth-token=... an Auth-Token one (Tecken).
+ The token can be read in the environment ($VAR) to keep it out of the command lines and the config
+ files, and it's never part of the urls or of what is logged.
+*/
+
 const DEFAULT_STORE: &str = "https://msdl.microsoft.com/download/symbols";
+#[cfg(feature = "http")]
 const DEFAULT_USER_AGENT: &str = "Microsoft-Symbol-Server/6.3.0.0";
 
+/// The credentials of a private symbol server
+#[derive(Clone, PartialEq)]
+enum Auth {
+    Bearer(String),
+    AuthToken(String),
+}
+
+impl Debug for Auth {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Bearer(_) => write!(f, "Bearer(***)"),
+            Self::AuthToken(_) => write!(f, "AuthToken(***)"),
+        }
+    }
+}
+
+impl Auth {
+    /// Split the url of the server and its credentials (if any)
+    fn split(server: &str) -> (String, Option<Self>) {
+        let (url, last) = match server.rsplit_once('/') {
+            Some(split) => split,
+            _ => return (server.to_string(), None),
+        };
+        let (auth, token): (fn(String) -> Self, _) =
+            if let Some(token) = last.strip_prefix("token=") {
+                (Self::Bearer, token)
+            } else if let Some(token) = last.strip_prefix("auth-token=") {
+                (Self::AuthToken, token)
+            } else {
+                return (server.to_string(), None);
+            };
+
+        let token = match token.strip_prefix('$') {
+            Some(var) => match std::env::var(var) {
+                Ok(token) => token,
+                _ => {
+                    warn!("The variable {} with the token of {} isn't set", var, url);
+                    return (url.to_string(), None);
+                }
+            },
+            None => token.to_string(),
+        };
+        (url.to_string(), Some(auth(token)))
+    }
+
+    #[cfg(feature = "http")]
+    fn header(&self) -> common::Result<(HeaderName, HeaderValue)> {
+        let (name, value) = match self {
+            Self::Bearer(token) => (AUTHORIZATION, format!("Bearer {}", token)),
+            Self::AuthToken(token) => (HeaderName::from_static("auth-token"), token.clone()),
+        };
+        // Don't put the token in the message
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|_| format!("Invalid characters in the {} header", name))?;
+        // A sensitive value isn't shown in the debug output of the requests
+        value.set_sensitive(true);
+        Ok((name, value))
+    }
+}
+
 #[derive(Debug)]
 pub struct SymbolServer {
     cache: Option<String>,
     server: String,
+    auth: Option<Auth>,
 }
 
+#[cfg(feature = "http")]
 #[derive(Clone, Debug)]
 struct Job {
     cache: Option<PathBuf>,
     url: String,
+    auth: Option<Auth>,
 }
 
+#[cfg(feature = "http")]
 impl Job {
-    fn new(cache: Option<PathBuf>, url: String) -> common::Result<Self> {
+    fn new(cache: Option<PathBuf>, url: String, auth: Option<Auth>) -> common::Result<Self> {
         if Url::parse(&url).is_err() {
             return Err(From::from(format!("Invalid url: {}", url)));
         }
-        Ok(Self { cache, url })
+        Ok(Self { cache, url, auth })
+    }
+
+    fn headers(&self) -> common::Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
+        if let Some(auth) = self.auth.as_ref() {
+            let (name, value) = auth.header()?;
+            headers.insert(name, value);
+        }
+        Ok(headers)
     }
 }
 
@@ -58,23 +155,19 @@ fn parse_srv(path: &str) -> Option<SymbolServer> {
     if parts.is_empty() || parts[0].to_lowercase() != "srv" {
         return None;
     }
-    let server = match parts.len() {
-        1 => SymbolServer {
-            cache: None,
-            server: DEFAULT_STORE.to_string(),
-        },
-        2 => SymbolServer {
-            cache: None,
-            server: parts[1].to_string(),
-        },
-        3 => SymbolServer {
-            cache: Some(correct_path(parts[1])),
-            server: parts[2].to_string(),
-        },
+    let (cache, server) = match parts.len() {
+        1 => (None, DEFAULT_STORE),
+        2 => (None, parts[1]),
+        3 => (Some(correct_path(parts[1])), parts[2]),
         _ => return None,
     };
+    let (server, auth) = Auth::split(server);
 
-    Some(server)
+    Some(SymbolServer {
+        cache,
+        server,
+        auth,
+    })
 }
 
 fn parse_sympath(path: &str) -> Vec<SymbolServer> {
@@ -119,6 +212,7 @@ pub fn get_sym_servers(symbol_server: Option<&str>) -> Option<Vec<SymbolServer>>
     symbol_server.map_or_else(read_config, read_config_from_str)
 }
 
+#[cfg(feature = "http")]
 fn copy_in_cache(path: Option<PathBuf>, data: &[u8]) -> bool {
     if data.is_empty() || data.starts_with(b"Symbol Not Found") {
         return false;
@@ -189,6 +283,7 @@ fn search_in_cache(
     None
 }
 
+#[cfg(feature = "http")]
 fn get_jobs(servers: &[SymbolServer], id: &str, base: &Path, file_name: &str) -> Vec<Job> {
     // The query urls are: https://symbols.mozilla.org/xul.pdb/DEBUG_ID/xul.pd_
     let mut jobs = Vec::new();
@@ -200,6 +295,7 @@ fn get_jobs(servers: &[SymbolServer], id: &str, base: &Path, file_name: &str) ->
         let job = Job::new(
             path.clone(),
             format!("{}/{}/{}/{}", server.server, file_name, id, file_name),
+            server.auth.clone(),
         )
         .unwrap_or_else(|e| panic!("{}", e));
         jobs.push(job);
@@ -213,6 +309,7 @@ fn get_jobs(servers: &[SymbolServer], id: &str, base: &Path, file_name: &str) ->
                     id,
                     &file_name[..file_name.len() - 1]
                 ),
+                server.auth.clone(),
             )
             .unwrap_or_else(|e| panic!("{}", e));
             jobs.push(job);
@@ -222,13 +319,16 @@ fn get_jobs(servers: &[SymbolServer], id: &str, base: &Path, file_name: &str) ->
     jobs
 }
 
+#[cfg(feature = "http")]
 async fn check_if_file_exists(results: Arc<Mutex<Vec<Job>>>, client: &Client, job: Job) {
-    if let Ok(res) = client
-        .head(&job.url)
-        .header(USER_AGENT, DEFAULT_USER_AGENT)
-        .send()
-        .await
-    {
+    let headers = match job.headers() {
+        Ok(headers) => headers,
+        Err(e) => {
+            warn!("Cannot query {}: {}", job.url, e);
+            return;
+        }
+    };
+    if let Ok(res) = client.head(&job.url).headers(headers).send().await {
         if res.status() == 200 {
             let mut results = results.lock().unwrap();
             results.push(job);
@@ -236,8 +336,15 @@ async fn check_if_file_exists(results: Arc<Mutex<Vec<Job>>>, client: &Client, jo
     }
 }
 
+#[cfg(feature = "http")]
 fn check_data(jobs: Vec<Job>) -> Option<Job> {
-    let client = Client::new();
+    let client = match fetch::build(Client::builder()) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
     let n_queries = jobs.len();
     let results = Arc::new(Mutex::new(Vec::new()));
 
@@ -257,27 +364,60 @@ fn check_data(jobs: Vec<Job>) -> Option<Job> {
     results.first().cloned()
 }
 
+/// Check with a HEAD request if the sym file is on the server
+#[cfg(feature = "http")]
+pub fn is_on_server(url: &str, sym_path: &Path) -> bool {
+    let path: Vec<_> = sym_path
+        .iter()
+        .map(|c| c.to_string_lossy().into_owned())
+        .collect();
+    let url = format!("{}/{}", url.trim_end_matches('/'), path.join("/"));
+    let client = match fetch::build_blocking(blocking::Client::builder()) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("{}", e);
+            return false;
+        }
+    };
+    match client
+        .head(&url)
+        .header(USER_AGENT, DEFAULT_USER_AGENT)
+        .send()
+    {
+        Ok(res) => res.status() == 200,
+        Err(e) => {
+            warn!("Cannot check if {} exists: {}", url, e);
+            false
+        }
+    }
+}
+
+#[cfg(feature = "http")]
 fn fetch_data(jobs: Vec<Job>) -> Option<Vec<u8>> {
-    if let Some(job) = check_data(jobs) {
-        let mut buf = Vec::new();
-        let client = blocking::Client::new();
-        let resp = client
-            .get(&job.url)
-            .header(USER_AGENT, DEFAULT_USER_AGENT)
-            .send();
-        if let Ok(mut resp) = resp {
-            if resp.copy_to(&mut buf).is_err() {
-                None
-            } else if copy_in_cache(job.cache, &buf) {
-                Some(buf)
-            } else {
-                None
-            }
-        } else {
+    let job = check_data(jobs)?;
+    let _span = info_span!("fetch", url = job.url.as_str()).entered();
+    let mut buf = Vec::new();
+    let client = match fetch::build_blocking(blocking::Client::builder()) {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
+    let headers = match job.headers() {
+        Ok(headers) => headers,
+        Err(e) => {
+            warn!("Cannot download {}: {}", job.url, e);
+            return None;
+        }
+    };
+    match fetch::get(&client, &job.url, headers, &fetch::get_retry(), &mut buf) {
+        Ok(_) if copy_in_cache(job.cache, &buf) => Some(buf),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("{}", error::report(&*e));
             None
         }
-    } else {
-        None
     }
 }
 
@@ -304,15 +444,52 @@ pub fn search_file(
 
     // Try the symbol servers
     // Each job contains the path where to cache data (if one) and a query url
-    let jobs = get_jobs(servers, id, &base, &file_name);
-    let buf = fetch_data(jobs);
-
-    if let Some(buf) = buf {
+    #[cfg(feature = "http")]
+    if let Some(buf) = fetch_data(get_jobs(servers, id, &base, &file_name)) {
         let path = PathBuf::from(&file_name);
         let buf = utils::read_cabinet(buf, path)
             .unwrap_or_else(|| panic!("Unable to read the file {} from the server", file_name));
-        (Some(buf), file_name)
-    } else {
-        (None, file_name)
+        return (Some(buf), file_name);
+    }
+
+    (None, file_name)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_auth() {
+        std::env::set_var("DUMP_SYMS_TEST_TOKEN", "secret");
+        let servers = parse_sympath(
+            "SRV*~/symcache*https://syms.corp/token=abc;SRV*https://tecken.corp/auth-token=$DUMP_SYMS_TEST_TOKEN;SRV*https://public.org/",
+        );
+        assert_eq!(servers.len(), 3);
+        assert_eq!(servers[0].server, "https://syms.corp");
+        assert_eq!(servers[0].auth, Some(Auth::Bearer("abc".to_string())));
+        assert_eq!(servers[1].server, "https://tecken.corp");
+        assert_eq!(servers[1].auth, Some(Auth::AuthToken("secret".to_string())));
+        assert_eq!(servers[2].server, "https://public.org/");
+        assert_eq!(servers[2].auth, None);
+
+        // The tokens aren't in the debug output
+        let debug = format!("{:?}", servers);
+        assert!(!debug.contains("abc") && !debug.contains("secret"));
+
+        let servers = parse_sympath("SRV*https://syms.corp/token=$DUMP_SYMS_UNSET_TOKEN");
+        assert_eq!(servers[0].auth, None);
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_auth_header() {
+        let (name, value) = Auth::AuthToken("secret".to_string()).header().unwrap();
+        assert_eq!(name, "auth-token");
+        assert!(value.is_sensitive());
+
+        // A token with a new line can't be sent
+        assert!(Auth::Bearer("abc\ndef".to_string()).header().is_err());
     }
 }
@@ -0,0 +1,101 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+#[cfg(feature = "cli")]
+use log::warn;
+use std::error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::common;
+
+// Only an atomic store is done in the signal handler: the long-running loops
+// poll this flag and bail out with a Cancelled error.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+pub(crate) struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Interrupted: the pending symbol files have not been written"
+        )
+    }
+}
+
+impl error::Error for Cancelled {}
+
+/// Install a Ctrl-C handler: the first one requests a cancellation,
+/// the second one exits immediately.
+#[cfg(feature = "cli")]
+pub(crate) fn install_handler() {
+    if let Err(e) = ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+    }) {
+        warn!("Cannot install the Ctrl-C handler: {}", e);
+    }
+}
+
+pub(crate) fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+fn cancelled_pdb() -> pdb::Error {
+    pdb::Error::IoError(io::Error::new(io::ErrorKind::Interrupted, Cancelled))
+}
+
+/// Return an error if a cancellation has been requested
+pub(crate) fn check() -> common::Result<()> {
+    if is_cancelled() {
+        Err(Box::new(Cancelled))
+    } else {
+        Ok(())
+    }
+}
+
+/// Same as check but for the functions returning a pdb::Result
+pub(crate) fn check_pdb() -> pdb::Result<()> {
+    if is_cancelled() {
+        Err(cancelled_pdb())
+    } else {
+        Ok(())
+    }
+}
+
+/// A cancellation for the dumps sharing the token (e.g. the ones of a request in a service)
+/// and not for the whole process: the Ctrl-C one cancels them too.
+/// The clones share the same state so a dump can be cancelled from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the cancellation: the dumps using the token fail with an Interrupted error
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed) || is_cancelled()
+    }
+
+    /// Same as check_pdb but for this token
+    pub(crate) fn check_pdb(&self) -> pdb::Result<()> {
+        if self.is_cancelled() {
+            Err(cancelled_pdb())
+        } else {
+            Ok(())
+        }
+    }
+}
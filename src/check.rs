@@ -0,0 +1,415 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashSet;
+
+use crate::common;
+use crate::json;
+use crate::model::{CfiKind, StackCfi};
+use crate::visit::{self, FunctionHeader, SymbolVisitor};
+
+/*
+ The processors expect the LINE records of a function to be right after its FUNC record,
+ sorted by address and in the range of the function: check it (and the FILE references)
+ on the dumped sym files.
+ The STACK CFI records must be in the range of the last STACK CFI INIT one.
+
+ A bad STACK record doesn't break the processors: the stack walker just gets a wrong frame (or falls
+ back on the scan of the stack) so --validate-cfi checks them more strictly once they're generated:
+  - the rules are postfix expressions (the operators + - * / % @ take two operands and ^ one) and a
+    STACK CFI INIT record has the rules of .cfa and .ra (without them no caller can be computed),
+  - the fields of the STACK WIN records are numbers and their program string is a list of
+    assignments (<var> <expression> =),
+  - the STACK CFI records of a range are sorted, the STACK CFI INIT ranges don't overlap and
+    a range starting in a function doesn't end after it.
+*/
+
+/// Check that the STACK CFI records are in the range of their STACK CFI INIT record
+/// (and when strict, the rules and the ranges of all the STACK records)
+#[derive(Default)]
+struct CfiChecker {
+    strict: bool,
+    init: Option<(u64, u64)>,
+    last_delta: Option<u64>,
+    functions: Vec<(u64, u64)>,
+    ranges: Vec<(CfiKind, u64, u64)>,
+    problems: Vec<String>,
+}
+
+/// Apply an operator or an operand of a postfix expression to the depth of the stack
+fn apply_token(depth: usize, token: &str) -> Result<usize, String> {
+    let operands = match token {
+        "+" | "-" | "*" | "/" | "%" | "@" => 2,
+        "^" => 1,
+        _ => return Ok(depth + 1),
+    };
+    if depth < operands {
+        return Err(format!("missing operand for {}", token));
+    }
+    Ok(depth + 1 - operands)
+}
+
+/// Check the rules (REG: expression ...) of a STACK CFI record
+fn check_cfi_rules(rules: &str, init: bool) -> Result<(), String> {
+    let mut registers = Vec::new();
+    let mut depth = 0;
+    for token in rules.split_whitespace() {
+        if let Some(register) = token.strip_suffix(':') {
+            if let Some(last) = registers.last() {
+                if depth != 1 {
+                    return Err(format!("invalid rule for {}", last));
+                }
+            }
+            registers.push(register);
+            depth = 0;
+        } else if registers.is_empty() {
+            return Err(format!("no register before {}", token));
+        } else {
+            depth = apply_token(depth, token)?;
+        }
+    }
+    if let Some(last) = registers.last() {
+        if depth != 1 {
+            return Err(format!("invalid rule for {}", last));
+        }
+    }
+    if init {
+        for register in [".cfa", ".ra"].iter() {
+            if !registers.contains(register) {
+                return Err(format!("no rule for {}", register));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check the fields after the size of a STACK WIN record
+fn check_win_fields(fields: &str) -> Result<(), String> {
+    let mut tokens = fields.split_whitespace();
+    for name in [
+        "prologue size",
+        "epilogue size",
+        "parameter size",
+        "saved registers size",
+        "locals size",
+        "max stack size",
+    ]
+    .iter()
+    {
+        match tokens.next() {
+            Some(token) if u64::from_str_radix(token, 16).is_ok() => {}
+            Some(token) => return Err(format!("invalid {}: {}", name, token)),
+            None => return Err(format!("no {}", name)),
+        }
+    }
+    match (tokens.next(), tokens.next()) {
+        (Some("0"), Some("0")) | (Some("0"), Some("1")) => {
+            if tokens.next().is_some() {
+                return Err("too many fields".to_string());
+            }
+        }
+        (Some("1"), Some(_)) => {
+            let mut depth = 0;
+            for token in fields.split_whitespace().skip(7) {
+                depth = if token == "=" {
+                    if depth < 2 {
+                        return Err("missing operand for =".to_string());
+                    }
+                    depth - 2
+                } else {
+                    apply_token(depth, token)?
+                };
+            }
+            if depth != 0 {
+                return Err("unterminated assignment in the program string".to_string());
+            }
+        }
+        _ => return Err("no program string or base pointer flag".to_string()),
+    }
+    Ok(())
+}
+
+impl CfiChecker {
+    fn strict() -> Self {
+        Self {
+            strict: true,
+            ..Default::default()
+        }
+    }
+
+    /// Check the ranges against the functions and between them
+    fn finish(mut self) -> Vec<String> {
+        self.functions.sort_unstable();
+        self.ranges.sort_by_key(|(_, address, _)| *address);
+
+        let mut last_init: Option<(u64, u64)> = None;
+        for (kind, address, size) in self.ranges.iter() {
+            let (kind, address, size) = (*kind, *address, *size);
+            let name = if kind == CfiKind::Init {
+                "STACK CFI INIT"
+            } else {
+                "STACK WIN"
+            };
+            let index = self.functions.partition_point(|(a, _)| *a <= address);
+            if let Some((f_address, f_size)) = index.checked_sub(1).map(|i| self.functions[i]) {
+                let end = f_address + f_size;
+                if address < end && address + size > end {
+                    self.problems.push(format!(
+                        "{} {:x} {:x}: out of FUNC {:x} {:x}",
+                        name, address, size, f_address, f_size
+                    ));
+                }
+            }
+            if kind == CfiKind::Init {
+                if let Some((l_address, l_size)) = last_init {
+                    if address < l_address + l_size {
+                        self.problems.push(format!(
+                            "STACK CFI INIT {:x} {:x}: overlaps STACK CFI INIT {:x} {:x}",
+                            address, size, l_address, l_size
+                        ));
+                    }
+                }
+                last_init = Some((address, size));
+            }
+        }
+
+        self.problems
+    }
+}
+
+impl<'a> SymbolVisitor<'a> for CfiChecker {
+    fn function(&mut self, function: FunctionHeader<'a>) -> common::Result<()> {
+        if self.strict {
+            self.functions.push((function.address, function.size));
+        }
+        Ok(())
+    }
+
+    fn cfi(&mut self, record: StackCfi<'a>) -> common::Result<()> {
+        match record.kind {
+            CfiKind::Init => {
+                self.init = Some((record.address, record.size.unwrap_or(0)));
+                self.last_delta = None;
+            }
+            CfiKind::Delta => match self.init {
+                Some((address, size))
+                    if record.address >= address && record.address < address + size => {}
+                Some((address, size)) => self.problems.push(format!(
+                    "STACK CFI {:x}: out of STACK CFI INIT {:x} {:x}",
+                    record.address, address, size
+                )),
+                None => self.problems.push(format!(
+                    "STACK CFI {:x}: without STACK CFI INIT",
+                    record.address
+                )),
+            },
+            CfiKind::Win => {}
+        }
+        if record.kind != CfiKind::Win && record.rules.trim().is_empty() {
+            self.problems
+                .push(format!("STACK CFI {:x}: no rules", record.address));
+        }
+        if !self.strict {
+            return Ok(());
+        }
+
+        let name = match record.kind {
+            CfiKind::Init => format!("STACK CFI INIT {:x}", record.address),
+            CfiKind::Delta => format!("STACK CFI {:x}", record.address),
+            CfiKind::Win => format!("STACK WIN {:x}", record.address),
+        };
+        let checked = match record.kind {
+            CfiKind::Win => check_win_fields(&record.rules),
+            kind => check_cfi_rules(&record.rules, kind == CfiKind::Init),
+        };
+        if let Err(e) = checked {
+            self.problems.push(format!("{}: {}", name, e));
+        }
+
+        if record.kind == CfiKind::Delta {
+            if let Some(last) = self.last_delta {
+                if record.address <= last {
+                    self.problems
+                        .push(format!("{}: not sorted (after STACK CFI {:x})", name, last));
+                }
+            }
+            self.last_delta = Some(record.address);
+        } else {
+            let size = record.size.unwrap_or(0);
+            if size == 0 {
+                self.problems.push(format!("{}: empty range", name));
+            }
+            self.ranges.push((record.kind, record.address, size));
+        }
+        Ok(())
+    }
+}
+
+/// Check the STACK records of a sym file: return the problems (empty if they're fine)
+pub(crate) fn validate_cfi(sym: &[u8]) -> Vec<String> {
+    let sym = match std::str::from_utf8(sym) {
+        Ok(sym) => sym,
+        Err(e) => return vec![format!("Invalid utf-8: {}", e)],
+    };
+    let mut cfi = CfiChecker::strict();
+    if let Err(e) = visit::visit(sym, &mut cfi) {
+        return vec![e.to_string()];
+    }
+    cfi.finish()
+}
+
+/// Check the records of a sym file: return the problems (empty if the file is fine)
+pub(crate) fn check_sym(sym: &[u8]) -> Vec<String> {
+    let sym = match std::str::from_utf8(sym) {
+        Ok(sym) => sym,
+        Err(e) => return vec![format!("Invalid utf-8: {}", e)],
+    };
+    let module = match json::parse(sym) {
+        Ok(module) => module,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let mut cfi = CfiChecker::default();
+    if let Err(e) = visit::visit(sym, &mut cfi) {
+        return vec![e.to_string()];
+    }
+
+    let mut problems = cfi.problems;
+    let files: HashSet<_> = module.files.iter().map(|f| f.id).collect();
+    let mut last_function = None;
+
+    for function in module.functions.iter() {
+        if let Some(last) = last_function {
+            if function.address < last {
+                problems.push(format!(
+                    "FUNC {:x} {}: not sorted (after FUNC {:x})",
+                    function.address, function.name, last
+                ));
+            }
+        }
+        last_function = Some(function.address);
+
+        let end = function.address + function.size;
+        let mut last_line = None;
+        for line in function.lines.iter() {
+            if line.address < function.address || line.address + line.size > end {
+                problems.push(format!(
+                    "FUNC {:x} {}: LINE {:x} {:x} out of the function",
+                    function.address, function.name, line.address, line.size
+                ));
+            }
+            if let Some(last) = last_line {
+                if line.address < last {
+                    problems.push(format!(
+                        "FUNC {:x} {}: LINE {:x} not sorted (after LINE {:x})",
+                        function.address, function.name, line.address, last
+                    ));
+                }
+            }
+            last_line = Some(line.address);
+
+            if !files.contains(&line.file) {
+                problems.push(format!(
+                    "FUNC {:x} {}: LINE {:x} references the missing FILE {}",
+                    function.address, function.name, line.address, line.file
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_check() {
+        let basic = crate::utils::read_file(PathBuf::from("./test_data/linux/basic.full.sym"));
+        assert!(check_sym(&basic).is_empty());
+
+        let sym = b"MODULE Linux x86_64 0123456789ABCDEF1 foo
+FILE 0 foo.c
+FUNC 1000 20 0 foo
+1010 10 2 0
+1000 8 1 0
+1018 10 3 1
+FUNC 900 10 0 bar
+";
+        assert_eq!(
+            check_sym(sym),
+            vec![
+                "FUNC 1000 foo: LINE 1000 not sorted (after LINE 1010)",
+                "FUNC 1000 foo: LINE 1018 10 out of the function",
+                "FUNC 1000 foo: LINE 1018 references the missing FILE 1",
+                "FUNC 900 bar: not sorted (after FUNC 1000)",
+            ]
+        );
+
+        let sym = b"MODULE Linux x86_64 0123456789ABCDEF1 foo
+STACK CFI 1000 .cfa: $rsp 8 +
+STACK CFI INIT 1000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1004 .cfa: $rsp 16 +
+STACK CFI 1020 .cfa: $rsp 8 +
+";
+        assert_eq!(
+            check_sym(sym),
+            vec![
+                "STACK CFI 1000: without STACK CFI INIT",
+                "STACK CFI 1020: out of STACK CFI INIT 1000 20",
+            ]
+        );
+
+        assert_eq!(
+            check_sym(b"1000 10 1 0\n"),
+            vec!["Line record without function: 1000 10 1 0"]
+        );
+    }
+
+    #[test]
+    fn test_validate_cfi() {
+        let basic = crate::utils::read_file(PathBuf::from("./test_data/windows/basic32.old.sym"));
+        assert!(validate_cfi(&basic).is_empty());
+        // The PLT has no rule for the return address
+        let basic = crate::utils::read_file(PathBuf::from("./test_data/linux/basic.full.sym"));
+        assert_eq!(
+            validate_cfi(&basic),
+            vec!["STACK CFI INIT 1040: no rule for .ra"]
+        );
+
+        let sym = b"MODULE Linux x86_64 0123456789ABCDEF1 foo
+FUNC 1000 20 0 foo
+FUNC 1020 10 0 bar
+STACK CFI INIT 1000 28 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1008 .cfa: $rsp 16 + $rbp: .cfa -16 + ^
+STACK CFI 1004 .cfa: $rsp +
+STACK CFI INIT 1020 8 .cfa: .ra: .cfa -8 + ^
+STACK CFI INIT 1024 4 $rsp 8 + .cfa: $rsp
+STACK CFI INIT 1040 0 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK WIN 4 1050 8 0 0 0 0 0 0 1 $T0 .raSearch = $eip $T0 ^ = $esp $T0 4 + =
+STACK WIN 4 1060 8 0 0 0 0 0 0 1 $T0 .raSearch $eip
+STACK WIN 4 1070 8 0 0 0 0 0 0 0 0
+STACK WIN 4 1080 8 0 0 0 0 zz 0 0 0
+";
+        assert_eq!(
+            validate_cfi(sym),
+            vec![
+                "STACK CFI 1004: missing operand for +",
+                "STACK CFI 1004: not sorted (after STACK CFI 1008)",
+                "STACK CFI INIT 1020: invalid rule for .cfa",
+                "STACK CFI INIT 1024: no register before $rsp",
+                "STACK CFI INIT 1040: empty range",
+                "STACK WIN 1060: unterminated assignment in the program string",
+                "STACK WIN 1080: invalid locals size: zz",
+                "STACK CFI INIT 1000 28: out of FUNC 1000 20",
+                "STACK CFI INIT 1020 8: overlaps STACK CFI INIT 1000 28",
+                "STACK CFI INIT 1024 4: overlaps STACK CFI INIT 1020 8",
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::common;
+use crate::utils;
+
+/*
+ With --checksums a FILE.sha256 is written next to each generated file (in the format of sha256sum, so
+ sha256sum -c FILE.sha256 checks it) and with --manifest a json lists the path, the size, the sha256
+ and the module of all the files generated by the run.
+ The hash is computed while the file is written: the uploads can be verified and deduplicated without
+ reading the (maybe huge) files again.
+*/
+
+/// A writer computing the sha256 and the size of what is written in it
+pub(crate) struct HashWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> HashWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    /// Flush the writer and get the sha256 and the size of the data
+    pub(crate) fn finish(mut self) -> common::Result<(String, u64)> {
+        self.writer.flush()?;
+        let sha256 = self
+            .hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok((sha256, self.size))
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A generated file
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Entry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    pub debug_file: String,
+    pub debug_id: String,
+}
+
+/// The files generated by the dumps (which can be in several threads)
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Manifest {
+    entries: Arc<Mutex<Vec<Entry>>>,
+}
+
+impl Manifest {
+    pub(crate) fn add(&self, entry: Entry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    /// Write the entries (sorted by path) in json
+    pub(crate) fn write(&self, path: &str) -> common::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        serde_json::to_writer_pretty(utils::get_writer_for_sym(path), &*entries)?;
+        Ok(())
+    }
+}
+
+/// Write the sha256 of the file in FILE.sha256
+pub(crate) fn write_sidecar(path: &str, sha256: &str) -> common::Result<()> {
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    fs::write(
+        format!("{}.sha256", path),
+        format!("{}  {}\n", sha256, name),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        let mut writer = HashWriter::new(Vec::new());
+        writer.write_all(b"MODULE Linux x86_64 ").unwrap();
+        writer.write_all(b"0123456789ABCDEF1 libfoo.so\n").unwrap();
+        let (sha256, size) = writer.finish().unwrap();
+        assert_eq!(size, 48);
+        assert_eq!(
+            sha256,
+            Sha256::digest(b"MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so\n")
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let sym = tmp_dir.path().join("libfoo.so.sym");
+        let sym = sym.to_str().unwrap();
+        write_sidecar(sym, &sha256).unwrap();
+        assert_eq!(
+            fs::read_to_string(format!("{}.sha256", sym)).unwrap(),
+            format!("{}  libfoo.so.sym\n", sha256)
+        );
+
+        let manifest = Manifest::default();
+        for path in &["b.sym", "a.sym"] {
+            manifest.clone().add(Entry {
+                path: path.to_string(),
+                size,
+                sha256: sha256.clone(),
+                debug_file: "libfoo.so".to_string(),
+                debug_id: "0123456789ABCDEF1".to_string(),
+            });
+        }
+        let json = tmp_dir.path().join("manifest.json");
+        let json = json.to_str().unwrap();
+        manifest.write(json).unwrap();
+        let entries: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(json).unwrap()).unwrap();
+        assert_eq!(entries[0]["path"], "a.sym");
+        assert_eq!(entries[1]["path"], "b.sym");
+        assert_eq!(entries[1]["size"], 48);
+        assert_eq!(entries[1]["debug_id"], "0123456789ABCDEF1");
+    }
+}
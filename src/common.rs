@@ -3,13 +3,28 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+#[cfg(feature = "minidump")]
+use failure::Fail;
 use regex::Regex;
+use serde::Serialize;
 use std::env::consts::ARCH;
 use std::error;
 use std::io::Write;
 use std::result;
 use symbolic::common::{Arch, Name};
-use symbolic::debuginfo::{peek, FileFormat};
+use symbolic::debuginfo::{peek, FileFormat, Object};
+#[cfg(feature = "minidump")]
+use symbolic::minidump::cfi::AsciiCfiWriter;
+
+use crate::cancel::CancelToken;
+use crate::checksums::Manifest;
+use crate::compat::Compat;
+use crate::compress::Compression;
+use crate::debug_id::DebugIdFallback;
+use crate::dotnet::ppdb::PortablePdbInfo;
+use crate::emit::Emit;
+use crate::stats::RunStats;
+use crate::visit::{self, SymbolVisitor};
 
 type Error = Box<dyn error::Error + std::marker::Send + std::marker::Sync>;
 pub type Result<T> = result::Result<T, Error>;
@@ -19,16 +34,23 @@ pub(crate) enum FileType {
     Pe,
     Elf,
     Macho,
+    Wasm,
+    PortablePdb,
     Unknown,
 }
 
 impl FileType {
     pub(crate) fn from_buf(buf: &[u8]) -> Self {
+        if PortablePdbInfo::is_portable_pdb(buf) {
+            return Self::PortablePdb;
+        }
+
         match peek(buf, true /* check for fat binary */) {
             FileFormat::Pdb => Self::Pdb,
             FileFormat::Pe => Self::Pe,
             FileFormat::Elf => Self::Elf,
             FileFormat::MachO => Self::Macho,
+            FileFormat::Wasm => Self::Wasm,
             _ => Self::Unknown,
         }
     }
@@ -39,15 +61,346 @@ impl FileType {
             "pdb" => Self::Pdb,
             "elf" => Self::Elf,
             "macho" => Self::Macho,
+            "wasm" => Self::Wasm,
             _ => Self::Unknown,
         }
     }
 }
 
+/// How the SourceLink information of a pdb is used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SourceLinkMode {
+    /// Replace the paths in the FILE records by the urls
+    Replace,
+    /// Keep the paths and add INFO URL records
+    Keep,
+}
+
+/// The record to keep when a public symbol is at the address of a function
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum PublicPrecedence {
+    /// Keep the FUNC record: the public only gives its stack parameter size
+    #[default]
+    Func,
+    /// Keep the PUBLIC record and drop the FUNC one
+    Public,
+}
+
+/// How the primitive types are named in the functions of a pdb
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum TypeNames {
+    /// long long, unsigned long long, int8_t, ...
+    #[default]
+    C,
+    /// __int64, unsigned __int64, __int8, ... as undname
+    Msvc,
+}
+
+/// How the thunks (incremental linking or imports) of a pdb are named
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum ThunkNames {
+    /// The name of the symbol (@ILT+1234(foo) for the incremental linking ones)
+    #[default]
+    Keep,
+    /// foo [thunk]
+    Annotate,
+    /// The name of the target function
+    Target,
+}
+
+/// How the MSVC lambdas are named
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum LambdaNames {
+    /// <lambda_02a71323d951a238fa4826e9f186893b>
+    #[default]
+    Full,
+    /// (lambda)
+    Short,
+    /// (lambda at foo::bar)
+    Owner,
+}
+
+/// The format of the output files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputFormat {
+    /// The Breakpad sym file
+    Breakpad,
+    /// The records of the sym file in json
+    Json,
+    /// The FUNC (and LINE) records in csv
+    Csv,
+}
+
+impl OutputFormat {
+    /// The formats which are written from the records of the sym file
+    pub(crate) const RECORDS: [OutputFormat; 2] = [OutputFormat::Json, OutputFormat::Csv];
+
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Self::Breakpad => "sym",
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+/// Options which are forwarded to the dumpers and used to write the sym files
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DumpOptions {
+    /// Emit INFO URL records for the files indexed in the srcsrv stream of a pdb
+    pub srcsrv: bool,
+    /// Keep the mangled names in the PUBLIC records
+    pub no_demangle_publics: bool,
+    /// The record to keep when a public symbol and a function are at the same address
+    pub public_precedence: PublicPrecedence,
+    /// How the primitive types are named in the functions of a pdb
+    pub type_names: TypeNames,
+    /// Write the names of the parameters in the functions of a pdb
+    pub parameter_names: bool,
+    /// Write PUBLIC records for the vftables and the RTTI descriptors of a pdb
+    pub data_publics: bool,
+    /// Get the x86 parameter sizes of the functions of a pdb from their S_FRAMEPROC and their types
+    /// when Google's dump_syms leaves them at 0 (it only uses the ebp-relative parameters)
+    pub x86_param_sizes: bool,
+    /// Fail on the names of a pdb which aren't valid UTF-8 instead of replacing the invalid bytes
+    pub strict_names: bool,
+    /// How the thunks of a pdb are named
+    pub thunk_names: ThunkNames,
+    /// Fail on the pdbs linked with /DEBUG:FASTLINK instead of dumping what is available
+    pub reject_fastlink: bool,
+    /// Overwrite the sym files which are already in the store
+    pub force: bool,
+    /// The server where the store is uploaded: the sym files which are on it aren't written
+    pub store_url: Option<String>,
+    /// The directories where the type servers (pdbs containing the types) are searched
+    pub type_server_paths: Vec<String>,
+    /// Print some statistics on stderr (pdb only)
+    pub stats: bool,
+    /// Write STACK records for the functions without unwind info from their prologues
+    pub scan_prologues: bool,
+    /// Append the checksums of the source files to the FILE records
+    pub file_checksums: bool,
+    /// Normalize the paths of the source files of a pdb to have one FILE record per file
+    pub normalize_paths: bool,
+    /// Lowercase the normalized paths of the source files of a pdb
+    pub fold_path_case: bool,
+    /// Write the functions moved by OMAP or folded by ICF in this file
+    pub address_report: Option<String>,
+    /// Write the provenance of the records in this json file
+    pub provenance: Option<String>,
+    /// Write the object paths and the build information of the modules in this json file
+    pub objects: Option<String>,
+    /// Write the scopes and the local variables of the functions in this json file
+    pub locals: Option<String>,
+    /// Use the SourceLink json of a pdb to get the urls of the source files
+    pub sourcelink: Option<SourceLinkMode>,
+    /// Compute a debug id from the content of the files without any
+    pub debug_id_fallback: Option<DebugIdFallback>,
+    /// Compress the sym files
+    pub compression: Option<Compression>,
+    /// Collect the source files in this zip archive
+    pub source_bundle: Option<String>,
+    /// The formats to write (only the Breakpad one when empty)
+    pub formats: Vec<OutputFormat>,
+    /// The directory where the .dwo files and the .dwp packages are searched first
+    pub dwo_dir: Option<String>,
+    /// The directories where the debug files of the stripped ELF are searched (before /usr/lib/debug)
+    pub debug_dirs: Vec<String>,
+    /// The directories where the dll or the exe of a pdb is searched (after the one of the pdb)
+    pub binary_paths: Vec<String>,
+    /// Search the dll or the exe of a pdb on the symbol servers when it isn't found locally
+    pub fetch_binary: bool,
+    /// Parse the file in a child process with reduced privileges
+    pub sandbox: bool,
+    /// Go on with the other files when a file can't be dumped (they're reported at the end)
+    pub keep_going: bool,
+    /// The directory where the inputs given as urls are downloaded (they're kept in memory else)
+    pub download_dir: Option<String>,
+    /// The member of the archive to dump
+    pub member: Option<String>,
+    /// Add a row per LINE record in the csv
+    pub csv_lines: bool,
+    /// Append the column to the LINE records (pdb only)
+    pub line_columns: bool,
+    /// The number of threads reading the modules of a pdb
+    pub module_jobs: usize,
+    /// Write the records of a pdb module by module (unsorted) instead of building the sym in memory
+    pub stream: bool,
+    /// The kinds of records to write
+    pub emit: Emit,
+    /// Use stable names for the anonymous namespaces and the unnamed types
+    pub normalize_names: bool,
+    /// How the MSVC lambdas are named
+    pub lambda_names: LambdaNames,
+    /// Replace the template argument lists nested deeper than this depth by <T>
+    pub collapse_templates: Option<usize>,
+    /// Truncate the names longer than this length
+    pub max_name_len: Option<usize>,
+    /// Write the sym as this tool does
+    pub compat: Option<Compat>,
+    /// The path of the sym files built from the fields of the modules (instead of the output)
+    pub output_template: Option<String>,
+    /// Write the sha256 of the generated files next to them
+    pub checksums: bool,
+    /// Collect the generated files to write the manifest
+    pub manifest: Option<Manifest>,
+    /// Collect the statistics of the modules
+    pub run_stats: Option<RunStats>,
+    /// Check the STACK records of the generated syms
+    pub validate_cfi: bool,
+    /// Stop the dump (with a Cancelled error) when it's cancelled
+    pub cancel: CancelToken,
+}
+
+impl DumpOptions {
+    pub(crate) fn emits(&self, format: OutputFormat) -> bool {
+        if self.formats.is_empty() {
+            format == OutputFormat::Breakpad
+        } else {
+            self.formats.contains(&format)
+        }
+    }
+
+    /// The format written in the output: the other ones are written next to it
+    pub(crate) fn main_format(&self) -> OutputFormat {
+        [
+            OutputFormat::Breakpad,
+            OutputFormat::Json,
+            OutputFormat::Csv,
+        ]
+        .iter()
+        .copied()
+        .find(|f| self.emits(*f))
+        .unwrap_or(OutputFormat::Breakpad)
+    }
+}
+
+/// Sort the files by name to have the same FILE records whatever the order
+/// in which they've been collected.
+/// The returned vector maps the old ids to the new ones.
+pub(crate) fn sort_files(files: &mut Vec<String>) -> Vec<u32> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by(|a, b| files[*a].cmp(&files[*b]));
+
+    let mut remapping = vec![0; files.len()];
+    for (new_id, old_id) in order.iter().enumerate() {
+        remapping[*old_id] = new_id as u32;
+    }
+
+    let mut old = std::mem::take(files);
+    files.extend(order.iter().map(|id| std::mem::take(&mut old[*id])));
+
+    remapping
+}
+
+/// Merge the files with the same name (e.g. once their paths are normalized):
+/// the files must be sorted and the returned vector maps the old ids to the new ones.
+pub(crate) fn dedup_files(files: &mut Vec<String>) -> Vec<u32> {
+    let mut remapping = Vec::with_capacity(files.len());
+    let mut new_id = 0;
+    for (id, file) in files.iter().enumerate() {
+        if id != 0 && *file != files[id - 1] {
+            new_id += 1;
+        }
+        remapping.push(new_id);
+    }
+    files.dedup();
+
+    remapping
+}
+
+/// Where a record comes from: the order is the one used to choose between
+/// two records at the same address when merging (the greatest wins)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Provenance {
+    /// Guessed (e.g. the <unknown in ...> symbol or the STACK records from the prologues)
+    #[default]
+    Heuristic,
+    /// The exception data (.pdata) of a PE
+    PeExceptionData,
+    /// The exports of a PE
+    PeExports,
+    /// The symbol table of an ELF or a Mach-O
+    SymbolTable,
+    /// The publics of a pdb
+    PdbPublics,
+    /// The frame data of a pdb
+    PdbFrameData,
+    /// The procedures of a pdb
+    PdbSymbols,
+    /// The DWARF debug info or CFI
+    Dwarf,
+}
+
+/// The origin of a FUNC, PUBLIC or STACK record
+#[derive(Debug, Serialize)]
+pub(crate) struct RecordProvenance<'a> {
+    pub record: &'static str,
+    pub address: u32,
+    pub size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'a str>,
+    pub provenance: Provenance,
+}
+
 pub(crate) trait Dumpable {
     fn dump<W: Write>(&self, writer: W) -> Result<()>;
     fn get_name(&self) -> &str;
     fn get_debug_id(&self) -> &str;
+    /// The paths in the FILE records
+    fn get_source_files(&self) -> &[String];
+    /// The provenance of the FUNC, PUBLIC and STACK records
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>>;
+
+    /// Give the records to the visitor: they're dumped and parsed back unless the collector
+    /// can give them itself
+    fn visit<V: for<'a> SymbolVisitor<'a>>(&self, visitor: &mut V) -> Result<()> {
+        let mut sym = Vec::new();
+        self.dump(&mut sym)?;
+        visit::visit(std::str::from_utf8(&sym)?, visitor)
+    }
+}
+
+/// Write the STACK records of the object
+#[cfg(feature = "minidump")]
+pub(crate) fn write_cfi<W: Write>(writer: W, object: &Object) -> Result<()> {
+    AsciiCfiWriter::new(writer)
+        .process(object)
+        .map_err(|e| e.compat().to_string().into())
+}
+
+/// Without the minidump feature there are no STACK records: the CFI writer is in symbolic's
+/// minidump which is built from C++
+#[cfg(not(feature = "minidump"))]
+pub(crate) fn write_cfi<W: Write>(_writer: W, _object: &Object) -> Result<()> {
+    Ok(())
+}
+
+/// Get the record kind ("STACK WIN" or "STACK CFI"), the address and the size
+/// of the STACK records which start a function
+pub(crate) fn get_stack_ranges(stack: &str) -> Vec<(&'static str, u32, u32)> {
+    let mut ranges = Vec::new();
+    for line in stack.lines() {
+        let (kind, rest) = if let Some(rest) = line.strip_prefix("STACK WIN ") {
+            // STACK WIN type rva size ...
+            (
+                "STACK WIN",
+                rest.split_once(' ').map_or("", |(_, rest)| rest),
+            )
+        } else if let Some(rest) = line.strip_prefix("STACK CFI INIT ") {
+            // STACK CFI INIT rva size ...
+            ("STACK CFI", rest)
+        } else {
+            continue;
+        };
+
+        let mut fields = rest.split(' ').map(|f| u32::from_str_radix(f, 16));
+        if let (Some(Ok(rva)), Some(Ok(size))) = (fields.next(), fields.next()) {
+            ranges.push((kind, rva, size));
+        }
+    }
+    ranges
 }
 
 pub(crate) trait Mergeable {
@@ -127,4 +480,21 @@ mod tests {
             normalize_anonymous_namespace(&name)
         );
     }
+
+    #[test]
+    fn test_dedup_files() {
+        let mut files: Vec<String> = ["c:\\foo.h", "c:\\bar.h", "c:\\foo.h", "c:\\a.h"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let remapping = sort_files(&mut files);
+        let dedup_remapping = dedup_files(&mut files);
+        let remapping: Vec<_> = remapping
+            .iter()
+            .map(|id| dedup_remapping[*id as usize])
+            .collect();
+
+        assert_eq!(files, vec!["c:\\a.h", "c:\\bar.h", "c:\\foo.h"]);
+        assert_eq!(remapping, vec![2, 1, 2, 0]);
+    }
 }
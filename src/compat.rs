@@ -0,0 +1,179 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::io::Write;
+
+use crate::common;
+
+/*
+ With --compat breakpad the sym is written as Google's dump_syms writes it, so both outputs can be
+ diffed and the tools reading its exact output can be fed with ours:
+  - the records are in the order of its Module::Write: MODULE, INFO CODE_ID, FILE, INLINE_ORIGIN,
+    the FUNC records (with their INLINE and LINE records) sorted by address, the PUBLIC records
+    sorted by address and then the STACK records;
+  - the other INFO records (URL, ...) aren't written;
+  - the FUNC and PUBLIC records have no m (multiple) marker and INFO CODE_ID has no file name except
+    for the pdbs (its ELF and Mach-O dumpers write them only on demand);
+  - a PUBLIC record at the address of a FUNC one isn't written (it's the default --public-precedence);
+  - the names of the pdb functions are written as DIA does ("A::f(int * *,unsigned __int64)": no
+    static or const qualifier) and the FILE records are in the order of the pdb (see pdb.rs).
+ The options adding some fields to the records (--line-columns and --file-checksums) can't be used
+ with it.
+*/
+
+/// The tools whose output can be reproduced
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Compat {
+    Breakpad,
+}
+
+fn get_address(record: &str) -> u64 {
+    record
+        .split(' ')
+        .find(|field| *field != "FUNC" && *field != "PUBLIC" && *field != "m")
+        .and_then(|address| u64::from_str_radix(address, 16).ok())
+        .unwrap_or_default()
+}
+
+fn strip_multiple(record: &str) -> String {
+    let (kind, rest) = record.split_once(' ').unwrap_or((record, ""));
+    format!("{} {}", kind, rest.strip_prefix("m ").unwrap_or(rest))
+}
+
+/// Write the records of the sym as Google's dump_syms does
+pub(crate) fn write_breakpad<W: Write>(sym: &str, mut writer: W) -> common::Result<()> {
+    let mut header = Vec::new();
+    let mut files = Vec::new();
+    let mut origins = Vec::new();
+    // A FUNC record and its INLINE and LINE records
+    let mut functions: Vec<(u64, Vec<String>)> = Vec::new();
+    let mut publics = Vec::new();
+    let mut stack = Vec::new();
+    let mut windows = false;
+
+    for record in sym.split_inclusive('\n') {
+        let kind = record.split(' ').next().unwrap_or("");
+        match kind {
+            "MODULE" => {
+                windows = record.split(' ').nth(1) == Some("windows");
+                header.push(record.to_string());
+            }
+            "INFO" => {
+                if let Some(rest) = record.strip_prefix("INFO CODE_ID ") {
+                    match rest.trim_end().split_once(' ') {
+                        Some((code_id, _)) if !windows => {
+                            header.push(format!("INFO CODE_ID {}\n", code_id))
+                        }
+                        _ => header.push(record.to_string()),
+                    }
+                }
+            }
+            "FILE" => files.push(record.to_string()),
+            "INLINE_ORIGIN" => origins.push(record.to_string()),
+            "FUNC" => {
+                let record = if windows {
+                    record.to_string()
+                } else {
+                    strip_multiple(record)
+                };
+                functions.push((get_address(&record), vec![record]));
+            }
+            "PUBLIC" => {
+                let record = if windows {
+                    record.to_string()
+                } else {
+                    strip_multiple(record)
+                };
+                publics.push((get_address(&record), record));
+            }
+            "STACK" => stack.push(record.to_string()),
+            _ => {
+                // The INLINE and LINE records of the last FUNC one
+                if let Some((_, function)) = functions.last_mut() {
+                    function.push(record.to_string());
+                }
+            }
+        }
+    }
+
+    // The sorts are stable so the records at the same address stay in the same order
+    functions.sort_by_key(|(address, _)| *address);
+    publics.sort_by_key(|(address, _)| *address);
+
+    let records = header
+        .iter()
+        .chain(files.iter())
+        .chain(origins.iter())
+        .chain(functions.iter().flat_map(|(_, records)| records.iter()))
+        .chain(publics.iter().map(|(_, record)| record))
+        .chain(stack.iter());
+    for record in records {
+        writer.write_all(record.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn to_breakpad(sym: &str) -> String {
+        let mut output = Vec::new();
+        write_breakpad(sym, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_breakpad() {
+        let sym = "MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so
+INFO CODE_ID 89674523CDAB0123 libfoo.so
+INFO GENERATOR foo
+FILE 0 foo.c
+INLINE_ORIGIN 0 bar
+PUBLIC 1000 0 _init
+FUNC m 2000 20 0 foo
+INLINE 0 12 0 0 2004 8
+2000 10 12 0
+PUBLIC 3000 0 baz
+FUNC 1100 8 0 qux
+1100 8 3 0
+STACK CFI INIT 2000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+";
+        assert_eq!(
+            to_breakpad(sym),
+            "MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so
+INFO CODE_ID 89674523CDAB0123
+FILE 0 foo.c
+INLINE_ORIGIN 0 bar
+FUNC 1100 8 0 qux
+1100 8 3 0
+FUNC 2000 20 0 foo
+INLINE 0 12 0 0 2004 8
+2000 10 12 0
+PUBLIC 1000 0 _init
+PUBLIC 3000 0 baz
+STACK CFI INIT 2000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+"
+        );
+
+        // The pdbs keep the multiple marker and the name of the PE
+        let sym = "MODULE windows x86_64 0123456789ABCDEF1 foo.pdb
+INFO CODE_ID 5E8F1A2B3000 foo.dll
+FILE 0 c:\\foo.cpp
+INFO URL 0 https://example.com/foo.cpp
+PUBLIC m 1000 0 foo
+";
+        assert_eq!(
+            to_breakpad(sym),
+            "MODULE windows x86_64 0123456789ABCDEF1 foo.pdb
+INFO CODE_ID 5E8F1A2B3000 foo.dll
+FILE 0 c:\\foo.cpp
+PUBLIC m 1000 0 foo
+"
+        );
+    }
+}
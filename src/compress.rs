@@ -0,0 +1,255 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(feature = "zstd")]
+use {log::info, std::io::BufWriter, zstd::stream::write::Encoder};
+
+use crate::common::{self, Dumpable};
+use crate::utils;
+
+// zstd doesn't take advantage of big samples so the sym files are cut in chunks
+const SAMPLE_SIZE: usize = 128 * 1024;
+// zstd recommends about 100 times the size of the dictionary for the training
+// and the samples are kept in memory: a bigger set only slows it down.
+const TRAINING_RATIO: usize = 100;
+const MAX_TRAINING_SIZE: usize = 1 << 30;
+
+#[derive(Clone, Debug)]
+pub(crate) enum Compression {
+    /// zstd with an optional dictionary
+    Zstd(Option<Arc<Vec<u8>>>),
+}
+
+impl Compression {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            Self::Zstd(_) => "zst",
+        }
+    }
+
+    /// Compress the dump of the dumpable in the writer
+    pub(crate) fn dump<D: Dumpable, W: Write>(
+        &self,
+        dumpable: &D,
+        writer: W,
+    ) -> common::Result<()> {
+        match self {
+            Self::Zstd(dict) => zstd_dump(
+                dict.as_ref().map_or(&[][..], |d| d.as_slice()),
+                dumpable,
+                writer,
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_dump<D: Dumpable, W: Write>(dict: &[u8], dumpable: &D, writer: W) -> common::Result<()> {
+    let mut encoder = Encoder::with_dictionary(writer, zstd::DEFAULT_COMPRESSION_LEVEL, dict)?;
+    let mut buf = BufWriter::new(&mut encoder);
+    dumpable.dump(&mut buf)?;
+    buf.flush()?;
+    drop(buf);
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_dump<D: Dumpable, W: Write>(_dict: &[u8], _dumpable: &D, _writer: W) -> common::Result<()> {
+    Err("The zstd compression requires the zstd feature".into())
+}
+
+fn collect_sym_files(path: &Path, files: &mut Vec<PathBuf>) -> common::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_sym_files(&entry?.path(), files)?;
+        }
+    } else if path.extension() == Some(OsStr::new("sym")) {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn read_samples(files: &[PathBuf]) -> common::Result<(Vec<u8>, Vec<usize>)> {
+    let mut data = Vec::new();
+    let mut sizes = Vec::new();
+
+    for file in files {
+        let buf = utils::read(file)?;
+        for chunk in buf.chunks(SAMPLE_SIZE) {
+            data.extend_from_slice(chunk);
+            sizes.push(chunk.len());
+        }
+    }
+
+    Ok((data, sizes))
+}
+
+/// Keep evenly spaced files whose total size is about the training budget
+fn select_files(files: Vec<PathBuf>, budget: usize) -> common::Result<Vec<PathBuf>> {
+    let mut total: usize = 0;
+    for file in files.iter() {
+        total = total.saturating_add(fs::metadata(file)?.len() as usize);
+    }
+    if total <= budget {
+        return Ok(files);
+    }
+    let step = total.div_ceil(budget);
+    Ok(files.into_iter().step_by(step).collect())
+}
+
+/// Train a zstd dictionary from the sym files (or the directories containing them)
+/// and write it in output.
+#[cfg(feature = "zstd")]
+pub(crate) fn train_dictionary(
+    inputs: &[&str],
+    output: &str,
+    max_size: usize,
+    num_jobs: usize,
+) -> common::Result<()> {
+    let mut files = Vec::new();
+    for input in inputs {
+        collect_sym_files(Path::new(input), &mut files)?;
+    }
+
+    if files.is_empty() {
+        return Err("No sym files to train the dictionary".into());
+    }
+
+    let budget = max_size
+        .saturating_mul(TRAINING_RATIO)
+        .clamp(SAMPLE_SIZE, MAX_TRAINING_SIZE);
+    let files = select_files(files, budget)?;
+
+    // The files are read in parallel and then the samples are concatenated
+    let chunk_size = files.len() / num_jobs.max(1) + 1;
+    let results: Vec<common::Result<(Vec<u8>, Vec<usize>)>> = crossbeam::scope(|scope| {
+        let handles: Vec<_> = files
+            .chunks(chunk_size)
+            .map(|files| scope.spawn(move |_| read_samples(files)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+    .unwrap();
+
+    let mut data = Vec::new();
+    let mut sizes = Vec::new();
+    for result in results {
+        let (d, s) = result?;
+        data.extend(d);
+        sizes.extend(s);
+    }
+
+    // The files aren't cut exactly at the budget
+    let mut len = 0;
+    let n = sizes
+        .iter()
+        .take_while(|&&size| {
+            len += size;
+            len <= budget
+        })
+        .count()
+        .max(1);
+    sizes.truncate(n);
+    data.truncate(sizes.iter().sum());
+
+    let dict = zstd::dict::from_continuous(&data, &sizes, max_size)?;
+    fs::write(output, &dict)?;
+    info!(
+        "Write a dictionary of {} bytes trained on {} files at {}",
+        dict.len(),
+        files.len(),
+        output
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    struct Text(String);
+
+    impl Dumpable for Text {
+        fn dump<W: Write>(&self, mut writer: W) -> common::Result<()> {
+            writer.write_all(self.0.as_bytes())?;
+            Ok(())
+        }
+
+        fn get_name(&self) -> &str {
+            ""
+        }
+
+        fn get_debug_id(&self) -> &str {
+            ""
+        }
+
+        fn get_source_files(&self) -> &[String] {
+            &[]
+        }
+
+        fn get_provenance(&self) -> Vec<common::RecordProvenance<'_>> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_roundtrip() {
+        let samples: Vec<_> = (0..1000)
+            .map(|i| {
+                format!(
+                    "FUNC {:x} 10 0 foo::bar{}(int)\n{:x} 4 12 0\n",
+                    i * 16,
+                    i,
+                    i * 16
+                )
+            })
+            .collect();
+        let dict = zstd::dict::from_samples(&samples, 4096).unwrap();
+        let text = Text(samples[..10].concat());
+
+        let mut output = Vec::new();
+        Compression::Zstd(Some(Arc::new(dict.clone())))
+            .dump(&text, Cursor::new(&mut output))
+            .unwrap();
+
+        let mut decoded = String::new();
+        zstd::stream::read::Decoder::with_dictionary(Cursor::new(&output), &dict)
+            .unwrap()
+            .read_to_string(&mut decoded)
+            .unwrap();
+
+        assert_eq!(decoded, text.0);
+    }
+
+    #[test]
+    fn test_select_files() {
+        let tmp_dir = tempfile::Builder::new().prefix("select").tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let files: Vec<_> = (0..10)
+            .map(|i| {
+                let path = dir.join(format!("{}.sym", i));
+                fs::write(&path, vec![b'a'; 100]).unwrap();
+                path
+            })
+            .collect();
+
+        assert_eq!(select_files(files.clone(), 1000).unwrap(), files);
+        let selected = select_files(files.clone(), 300).unwrap();
+        assert_eq!(
+            selected,
+            vec![files[0].clone(), files[4].clone(), files[8].clone()]
+        );
+    }
+}
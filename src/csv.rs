@@ -0,0 +1,112 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::io::Write;
+
+use crate::common;
+use crate::model::Module;
+
+/*
+ The csv output has a row per FUNC record (and per LINE record with --csv-lines) with the kind of the
+ record, its address and size (in decimal to be easily imported), the function name, the file and the
+ line (the ones of the first LINE record for a function).
+ The fields are quoted as in RFC 4180 when they contain a comma, a quote or a line break.
+*/
+
+fn escape(field: &str) -> Cow<'_, str> {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Write the FUNC (and the LINE) records of the module in csv
+pub(crate) fn write_csv<W: Write>(
+    module: &Module,
+    mut writer: W,
+    lines: bool,
+) -> common::Result<()> {
+    let file_name = |id| {
+        module
+            .files
+            .iter()
+            .find(|f| f.id == id)
+            .map_or("", |f| &f.name)
+    };
+
+    writeln!(writer, "kind,address,size,name,file,line")?;
+    for func in module.functions.iter() {
+        let name = escape(&func.name);
+        let (file, line) = func
+            .lines
+            .first()
+            .map_or(("", 0), |l| (file_name(l.file), l.line));
+        writeln!(
+            writer,
+            "FUNC,{},{},{},{},{}",
+            func.address,
+            func.size,
+            name,
+            escape(file),
+            line
+        )?;
+        if lines {
+            for line in func.lines.iter() {
+                writeln!(
+                    writer,
+                    "LINE,{},{},{},{},{}",
+                    line.address,
+                    line.size,
+                    name,
+                    escape(file_name(line.file)),
+                    line.line
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::json;
+
+    #[test]
+    fn test_csv() {
+        let sym = "MODULE windows x86_64 0123456789ABCDEF1 basic.pdb
+FILE 0 c:\\basic.cpp
+FILE 1 c:\\my \"dir\"\\util.h
+FUNC 1000 20 0 main(int, char**)
+1000 10 12 0
+1010 10 3 1
+FUNC 1020 8 0 foo
+PUBLIC 2000 4 bar
+";
+        let module = json::parse(sym).unwrap();
+        let mut output = Vec::new();
+        write_csv(&module, &mut output, false).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "kind,address,size,name,file,line
+FUNC,4096,32,\"main(int, char**)\",c:\\basic.cpp,12
+FUNC,4128,8,foo,,0
+"
+        );
+
+        let mut output = Vec::new();
+        write_csv(&module, &mut output, true).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let rows: Vec<_> = output.lines().collect();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(
+            rows[3],
+            "LINE,4112,16,\"main(int, char**)\",\"c:\\my \"\"dir\"\"\\util.h\",3"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use goblin::elf::{program_header::PT_LOAD, Elf};
+use sha2::{Digest, Sha256};
+use symbolic::common::DebugId;
+use uuid::Uuid;
+
+use crate::wasm::module::{self, CODE_SECTION};
+
+/*
+ Some files don't have any identity (e.g. an ELF without a build-id and without a .text section
+ as it can be the case for a raw firmware). For them a debug id is derived from a SHA-256 of
+ their content: the 16 first bytes of the digest are the uuid and the age is 0.
+
+ The hashed content depends on the algorithm:
+  - segments: for each PT_LOAD segment (in the program header order), its virtual address and its
+    size in the file (both as u64 little endian) and then its bytes in the file except the ones of
+    the ELF header (it contains the offset of the section headers).
+    The loaded segments aren't modified when the debug info are stripped so a binary and its debug
+    file have the same id.
+    For a WebAssembly module, it's the content of its code section (the same in a module and in its
+    separate DWARF file).
+  - file: the whole file.
+*/
+
+/// The content hashed to get a debug id for the files without any
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DebugIdFallback {
+    /// The loaded segments of an ELF or the code section of a WebAssembly module
+    /// (or the whole file for the other formats)
+    Segments,
+    /// The whole file
+    File,
+}
+
+fn hash_code_section(buf: &[u8], hasher: &mut Sha256) -> bool {
+    match module::get_sections(buf)
+        .iter()
+        .find(|s| s.id == CODE_SECTION)
+    {
+        Some(code) => {
+            hasher.update(code.data);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn hash_segments(buf: &[u8], hasher: &mut Sha256) -> bool {
+    if buf.starts_with(b"\0asm") {
+        return hash_code_section(buf, hasher);
+    }
+
+    let elf = match Elf::parse(buf) {
+        Ok(elf) => elf,
+        _ => return false,
+    };
+
+    let header_size = elf.header.e_ehsize as usize;
+    let mut found = false;
+    for header in elf.program_headers.iter().filter(|h| h.p_type == PT_LOAD) {
+        let start = header.p_offset as usize;
+        let end = start.saturating_add(header.p_filesz as usize);
+        let data = match buf.get(start.max(header_size).min(end)..end) {
+            Some(data) => data,
+            _ => return false,
+        };
+        hasher.update(header.p_vaddr.to_le_bytes());
+        hasher.update(header.p_filesz.to_le_bytes());
+        hasher.update(data);
+        found = true;
+    }
+
+    found
+}
+
+/// Compute a debug id from the content of the file
+pub(crate) fn compute(buf: &[u8], fallback: DebugIdFallback) -> DebugId {
+    let mut hasher = Sha256::new();
+    if fallback == DebugIdFallback::File || !hash_segments(buf, &mut hasher) {
+        hasher = Sha256::new();
+        hasher.update(buf);
+    }
+
+    let digest = hasher.finalize();
+    DebugId::from_uuid(Uuid::from_slice(&digest[..16]).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_fallback_debug_id() {
+        let full = crate::utils::read_file(PathBuf::from("./test_data/linux/basic.full"));
+        let stripped = crate::utils::read_file(PathBuf::from("./test_data/linux/basic.stripped"));
+
+        let id = compute(&full, DebugIdFallback::Segments);
+        assert_eq!(id, compute(&full, DebugIdFallback::Segments));
+        assert_eq!(id, compute(&stripped, DebugIdFallback::Segments));
+        assert_eq!(id.appendix(), 0);
+
+        assert_ne!(
+            compute(&full, DebugIdFallback::File),
+            compute(&stripped, DebugIdFallback::File)
+        );
+
+        // Not an ELF: the whole file is hashed
+        let data = b"not an elf";
+        assert_eq!(
+            compute(data, DebugIdFallback::Segments),
+            compute(data, DebugIdFallback::File)
+        );
+    }
+}
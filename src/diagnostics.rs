@@ -0,0 +1,130 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use lazy_static::lazy_static;
+use log::warn;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::common;
+use crate::utils;
+
+/*
+ The non-fatal issues met while dumping (a type we can't write, a forward reference whose size is
+ unknown, a name which can't be demangled or which isn't valid UTF-8, ...) are logged as warnings by
+ default: on a big pdb there are thousands of them, interleaved and repeated.
+ With --diagnostics FILE they're collected in a json report instead: for each category the number of
+ issues and the distinct messages (only the first ones are kept, the others are just counted).
+*/
+
+/// The maximal number of distinct messages kept in a category
+const MAX_MESSAGES: usize = 32;
+
+/// The kinds of non-fatal issues
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Category {
+    UnhandledType,
+    FwdRefSize,
+    Demangling,
+    InvalidName,
+    TypeCycle,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Issues {
+    count: usize,
+    distinct: usize,
+    messages: BTreeSet<String>,
+}
+
+/// The issues by category
+#[derive(Debug, Default, Serialize)]
+struct Diagnostics {
+    #[serde(flatten)]
+    categories: BTreeMap<Category, Issues>,
+}
+
+impl Diagnostics {
+    fn add(&mut self, category: Category, message: String) {
+        let issues = self.categories.entry(category).or_default();
+        issues.count += 1;
+        if issues.messages.contains(&message) {
+            return;
+        }
+        issues.distinct += 1;
+        if issues.messages.len() < MAX_MESSAGES {
+            issues.messages.insert(message);
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref DIAGNOSTICS: Mutex<Diagnostics> = Mutex::default();
+}
+
+/// Collect the issues rather than logging them
+pub(crate) fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Report a non-fatal issue
+pub(crate) fn report(category: Category, message: String) {
+    if ENABLED.load(Ordering::Relaxed) {
+        DIAGNOSTICS.lock().unwrap().add(category, message);
+    } else {
+        warn!("{}", message);
+    }
+}
+
+/// Write the collected issues in json
+pub(crate) fn write(path: &str) -> common::Result<()> {
+    let diagnostics = DIAGNOSTICS.lock().unwrap();
+    serde_json::to_writer_pretty(utils::get_writer_for_sym(path), &*diagnostics)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_diagnostics() {
+        let mut diagnostics = Diagnostics::default();
+        for _ in 0..3 {
+            diagnostics.add(Category::Demangling, "Didn't manage to demangle foo".into());
+        }
+        diagnostics.add(Category::Demangling, "Didn't manage to demangle bar".into());
+        for i in 0..MAX_MESSAGES + 8 {
+            diagnostics.add(
+                Category::FwdRefSize,
+                format!("No size for the forward reference S{}", i),
+            );
+        }
+
+        let json = serde_json::to_value(&diagnostics).unwrap();
+        assert_eq!(json["demangling"]["count"], 4);
+        assert_eq!(json["demangling"]["distinct"], 2);
+        assert_eq!(
+            json["demangling"]["messages"],
+            serde_json::json!([
+                "Didn't manage to demangle bar",
+                "Didn't manage to demangle foo"
+            ])
+        );
+        assert_eq!(json["fwd-ref-size"]["count"], MAX_MESSAGES + 8);
+        assert_eq!(json["fwd-ref-size"]["distinct"], MAX_MESSAGES + 8);
+        assert_eq!(
+            json["fwd-ref-size"]["messages"].as_array().unwrap().len(),
+            MAX_MESSAGES
+        );
+        assert!(json.get("unhandled-type").is_none());
+    }
+}
@@ -0,0 +1,528 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::common;
+
+// "BSJB"
+pub(super) const METADATA_SIGNATURE: u32 = 0x424A_5342;
+
+// The tables of a portable pdb (the type system ones, before 0x30, are in the assembly)
+const DOCUMENT_TABLE: u32 = 0x30;
+const METHOD_DEBUG_INFORMATION_TABLE: u32 = 0x31;
+const FIRST_PDB_TABLE: u32 = DOCUMENT_TABLE;
+
+// The flags in the heap sizes of the tables stream
+const HEAP_GUID_WIDE: u8 = 0x02;
+const HEAP_BLOB_WIDE: u8 = 0x04;
+
+// The line of the hidden sequence points
+const HIDDEN_LINE: u32 = 0x00FE_EFEE;
+
+/// A source document (Document table)
+#[derive(Debug, PartialEq)]
+pub(super) struct Document {
+    pub name: String,
+}
+
+/// A sequence point: the IL offset where the code of a line starts
+#[derive(Debug, PartialEq)]
+pub(super) struct SequencePoint {
+    pub il_offset: u32,
+    /// The index in the documents
+    pub document: u32,
+    pub line: u32,
+    pub column: u32,
+    /// The hidden sequence points end the previous one without starting a line
+    pub hidden: bool,
+}
+
+/// The debug information of a method (MethodDebugInformation table)
+#[derive(Debug, PartialEq)]
+pub(super) struct Method {
+    /// The metadata token of the method in the assembly (0x06 table)
+    pub token: u32,
+    pub sequence_points: Vec<SequencePoint>,
+}
+
+/// The content of a portable pdb we're interested in
+#[derive(Debug)]
+pub(super) struct Metadata {
+    /// The guid (16 bytes) and the stamp (u32) of the pdb
+    pub pdb_id: [u8; 20],
+    pub documents: Vec<Document>,
+    pub methods: Vec<Method>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, n: usize) -> common::Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).ok_or("Invalid portable pdb")?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or("Truncated portable pdb")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> common::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> common::Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> common::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> common::Result<u64> {
+        Ok(u64::from(self.u32()?) | (u64::from(self.u32()?) << 32))
+    }
+
+    /// An index in a heap or in a table: 2 or 4 bytes
+    fn index(&mut self, wide: bool) -> common::Result<u32> {
+        if wide {
+            self.u32()
+        } else {
+            Ok(u32::from(self.u16()?))
+        }
+    }
+
+    /// A compressed unsigned integer (ECMA-335 II.23.2)
+    fn compressed_u32(&mut self) -> common::Result<u32> {
+        let first = self.u8()?;
+        if first & 0x80 == 0 {
+            Ok(u32::from(first))
+        } else if first & 0xC0 == 0x80 {
+            Ok((u32::from(first & 0x3F) << 8) | u32::from(self.u8()?))
+        } else if first & 0xE0 == 0xC0 {
+            let b = self.bytes(3)?;
+            Ok((u32::from(first & 0x1F) << 24)
+                | (u32::from(b[0]) << 16)
+                | (u32::from(b[1]) << 8)
+                | u32::from(b[2]))
+        } else {
+            Err("Invalid compressed integer in portable pdb".into())
+        }
+    }
+
+    /// A compressed signed integer: the sign is the lowest bit of the unsigned one
+    fn compressed_i32(&mut self) -> common::Result<i32> {
+        let start = self.pos;
+        let value = self.compressed_u32()?;
+        let offset = match self.pos - start {
+            1 => 0x40,
+            2 => 0x2000,
+            _ => 0x1000_0000,
+        };
+        let magnitude = (value >> 1) as i32;
+        if value & 1 == 0 {
+            Ok(magnitude)
+        } else {
+            Ok(magnitude - offset)
+        }
+    }
+
+    fn null_terminated(&mut self, align: usize) -> common::Result<&'a [u8]> {
+        let rest = self.data.get(self.pos..).ok_or("Truncated portable pdb")?;
+        let len = rest
+            .iter()
+            .position(|c| *c == 0)
+            .ok_or("Truncated portable pdb")?;
+        // The name and its null terminator are padded to a multiple of align (a power of 2)
+        let padded = (len + align) & !(align - 1);
+        self.bytes(padded)?;
+        Ok(&rest[..len])
+    }
+}
+
+struct Heaps<'a> {
+    guid_wide: bool,
+    blob_wide: bool,
+    blob: &'a [u8],
+}
+
+impl<'a> Heaps<'a> {
+    fn blob(&self, index: u32) -> common::Result<&'a [u8]> {
+        let mut reader = Reader::new(self.blob);
+        reader.bytes(index as usize)?;
+        let len = reader.compressed_u32()?;
+        reader.bytes(len as usize)
+    }
+}
+
+/*
+ The metadata root is:
+   signature "BSJB" (u32), major, minor (u16), reserved (u32),
+   version length (u32) and the version (padded to 4 bytes),
+   flags, streams count (u16),
+   and for each stream: offset, size (u32) and its name (null terminated, padded to 4 bytes).
+*/
+fn get_streams(buf: &[u8]) -> common::Result<Vec<(&[u8], &[u8])>> {
+    let mut reader = Reader::new(buf);
+    if reader.u32()? != METADATA_SIGNATURE {
+        return Err("Not a portable pdb".into());
+    }
+    reader.bytes(8)?;
+    let version_len = reader.u32()?;
+    reader.bytes(version_len as usize)?;
+    reader.u16()?;
+    let count = reader.u16()?;
+
+    let mut streams = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let offset = reader.u32()? as usize;
+        let size = reader.u32()? as usize;
+        let name = reader.null_terminated(4)?;
+        let data = offset
+            .checked_add(size)
+            .and_then(|end| buf.get(offset..end))
+            .ok_or("Invalid stream in portable pdb")?;
+        streams.push((name, data));
+    }
+
+    Ok(streams)
+}
+
+/*
+ The sequence points blob is:
+   local signature, initial document (only when the method has several documents),
+   and then the records:
+     - document: 0 (IL offset delta, not for the first record), document
+     - sequence point: IL offset delta, lines delta, columns delta,
+       start line and start column (signed deltas after the first non hidden point),
+     - hidden sequence point: IL offset delta, 0, 0.
+*/
+fn parse_sequence_points(blob: &[u8], document: u32) -> common::Result<Vec<SequencePoint>> {
+    let mut reader = Reader::new(blob);
+    let mut points = Vec::new();
+    reader.compressed_u32()?;
+    let mut document = if document == 0 {
+        reader.compressed_u32()?
+    } else {
+        document
+    };
+
+    let mut il_offset = 0u32;
+    let mut previous: Option<(u32, u32)> = None;
+    while !reader.is_empty() {
+        let il_delta = reader.compressed_u32()?;
+        if il_delta == 0 && !points.is_empty() {
+            document = reader.compressed_u32()?;
+            continue;
+        }
+        il_offset = il_offset.wrapping_add(il_delta);
+
+        let lines = reader.compressed_u32()?;
+        let columns = if lines == 0 {
+            reader.compressed_u32()? as i32
+        } else {
+            reader.compressed_i32()?
+        };
+
+        if lines == 0 && columns == 0 {
+            points.push(SequencePoint {
+                il_offset,
+                document,
+                line: HIDDEN_LINE,
+                column: 0,
+                hidden: true,
+            });
+            continue;
+        }
+
+        let (line, column) = if let Some((line, column)) = previous {
+            (
+                (line as i32).wrapping_add(reader.compressed_i32()?) as u32,
+                (column as i32).wrapping_add(reader.compressed_i32()?) as u32,
+            )
+        } else {
+            (reader.compressed_u32()?, reader.compressed_u32()?)
+        };
+        previous = Some((line, column));
+
+        points.push(SequencePoint {
+            il_offset,
+            document,
+            line,
+            column,
+            hidden: false,
+        });
+    }
+
+    Ok(points)
+}
+
+/*
+ A document name is: the separator (u8, 0 for none) followed by the blob indices
+ (compressed) of the parts (UTF-8) to join with the separator.
+*/
+fn parse_document_name(heaps: &Heaps, blob: &[u8]) -> common::Result<String> {
+    let mut reader = Reader::new(blob);
+    let separator = reader.u8()?;
+    let mut name = String::new();
+    let mut first = true;
+    while !reader.is_empty() {
+        if !first && separator != 0 {
+            name.push(char::from(separator));
+        }
+        first = false;
+        let part = reader.compressed_u32()?;
+        if part != 0 {
+            name.push_str(&String::from_utf8_lossy(heaps.blob(part)?));
+        }
+    }
+
+    Ok(name)
+}
+
+/*
+ The tables stream (#~) is:
+   reserved (u32), major, minor, heap sizes, reserved (u8),
+   valid, sorted (u64 bitmasks of the present tables),
+   the rows count (u32) of each present table and then the tables in the order of the bits.
+ Document: name (blob), hash algorithm (guid), hash (blob), language (guid).
+ MethodDebugInformation: document (Document index), sequence points (blob).
+*/
+fn parse_tables(tables: &[u8], blob: &[u8]) -> common::Result<(Vec<Document>, Vec<Method>)> {
+    let mut reader = Reader::new(tables);
+    reader.bytes(6)?;
+    let heap_sizes = reader.u8()?;
+    reader.u8()?;
+    let valid = reader.u64()?;
+    reader.u64()?;
+
+    if valid & ((1 << FIRST_PDB_TABLE) - 1) != 0 {
+        return Err("The portable pdb contains type system tables".into());
+    }
+
+    let heaps = Heaps {
+        guid_wide: heap_sizes & HEAP_GUID_WIDE != 0,
+        blob_wide: heap_sizes & HEAP_BLOB_WIDE != 0,
+        blob,
+    };
+
+    let mut rows = [0u32; 64];
+    for (table, count) in rows.iter_mut().enumerate() {
+        if valid & (1 << table) != 0 {
+            *count = reader.u32()?;
+        }
+    }
+
+    let mut documents = Vec::new();
+    if valid & (1 << DOCUMENT_TABLE) != 0 {
+        for _ in 0..rows[DOCUMENT_TABLE as usize] {
+            let name = reader.index(heaps.blob_wide)?;
+            reader.index(heaps.guid_wide)?;
+            reader.index(heaps.blob_wide)?;
+            reader.index(heaps.guid_wide)?;
+            documents.push(Document {
+                name: parse_document_name(&heaps, heaps.blob(name)?)?,
+            });
+        }
+    }
+
+    let mut methods = Vec::new();
+    if valid & (1 << METHOD_DEBUG_INFORMATION_TABLE) != 0 {
+        let document_wide = rows[DOCUMENT_TABLE as usize] > 0xFFFF;
+        for row in 1..=rows[METHOD_DEBUG_INFORMATION_TABLE as usize] {
+            let document = reader.index(document_wide)?;
+            let sequence_points = reader.index(heaps.blob_wide)?;
+            if sequence_points == 0 {
+                continue;
+            }
+            methods.push(Method {
+                token: 0x0600_0000 | row,
+                sequence_points: parse_sequence_points(heaps.blob(sequence_points)?, document)?,
+            });
+        }
+    }
+
+    Ok((documents, methods))
+}
+
+impl Metadata {
+    pub(super) fn parse(buf: &[u8]) -> common::Result<Self> {
+        let streams = get_streams(buf)?;
+        let get = |name: &[u8]| {
+            streams
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, data)| *data)
+        };
+
+        let pdb = get(b"#Pdb").ok_or("No #Pdb stream in the portable pdb")?;
+        let tables = get(b"#~").ok_or("No #~ stream in the portable pdb")?;
+        let blob = get(b"#Blob").unwrap_or(&[]);
+
+        let mut pdb_id = [0; 20];
+        pdb_id.copy_from_slice(Reader::new(pdb).bytes(20)?);
+        let (documents, methods) = parse_tables(tables, blob)?;
+
+        Ok(Self {
+            pdb_id,
+            documents,
+            methods,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+
+    use super::*;
+
+    /// Build a portable pdb with two documents and two methods
+    pub(crate) fn get_portable_pdb() -> Vec<u8> {
+        let mut blob = vec![0];
+        let mut add_blob = |data: &[u8]| {
+            let index = blob.len() as u16;
+            blob.push(data.len() as u8);
+            blob.extend_from_slice(data);
+            index
+        };
+
+        let src = add_blob(b"src");
+        let program = add_blob(b"Program.cs");
+        let util = add_blob(b"Util.cs");
+        let name1 = add_blob(&[b'/', 0, src as u8, program as u8]);
+        let name2 = add_blob(&[b'/', 0, src as u8, util as u8]);
+        // Method 1: IL 0 -> line 10 col 5-20, IL 6 -> line 11, IL 0xc hidden, IL 0x10 -> line 13
+        let points1 = add_blob(&[
+            0, // local signature
+            0, 1, 15, 10, 5, // IL 0, 1 line, 15 columns, line 10, column 5
+            6, 0, 10, 2, 0, // IL +6, 0 line, 10 columns, line +1, column +0
+            6, 0, 0, // IL +6 hidden
+            4, 0, 4, 4, 2, // IL +4, 0 line, 4 columns, line +2, column +1
+        ]);
+        // Method 2: in two documents
+        let points2 = add_blob(&[
+            0, 1, // local signature, initial document
+            0, 0, 3, 20, 9, // IL 0, line 20 col 9
+            0, 2, // document 2
+            2, 0, 3, 0x7F, 0, // IL +2, line -1 (0x7f is -1), column +0
+        ]);
+
+        let mut tables = Vec::new();
+        tables.extend_from_slice(&[0, 0, 0, 0, 2, 0, 0, 1]);
+        let valid = (1u64 << DOCUMENT_TABLE) | (1u64 << METHOD_DEBUG_INFORMATION_TABLE);
+        tables.extend_from_slice(&valid.to_le_bytes());
+        tables.extend_from_slice(&0u64.to_le_bytes());
+        tables.extend_from_slice(&2u32.to_le_bytes());
+        tables.extend_from_slice(&3u32.to_le_bytes());
+        for name in &[name1, name2] {
+            for col in &[*name, 0, 0, 0] {
+                tables.extend_from_slice(&col.to_le_bytes());
+            }
+        }
+        for (document, points) in &[(1u16, points1), (0, points2), (1, 0)] {
+            tables.extend_from_slice(&document.to_le_bytes());
+            tables.extend_from_slice(&points.to_le_bytes());
+        }
+
+        let mut pdb = (0u8..20).collect::<Vec<_>>();
+        pdb.extend_from_slice(&[0; 12]);
+
+        let streams: [(&[u8], Vec<u8>); 3] = [
+            (b"#Pdb\0\0\0\0", pdb),
+            (b"#~\0\0", tables),
+            (b"#Blob\0\0\0", blob),
+        ];
+        let mut root = Vec::new();
+        root.extend_from_slice(&METADATA_SIGNATURE.to_le_bytes());
+        root.extend_from_slice(&[1, 0, 1, 0, 0, 0, 0, 0]);
+        root.extend_from_slice(&12u32.to_le_bytes());
+        root.extend_from_slice(b"PDB v1.0\0\0\0\0");
+        root.extend_from_slice(&[0, 0, streams.len() as u8, 0]);
+
+        let headers_len: usize = streams.iter().map(|(name, _)| 8 + name.len()).sum();
+        let mut offset = root.len() + headers_len;
+        let mut data = Vec::new();
+        for (name, stream) in streams.iter() {
+            root.extend_from_slice(&(offset as u32).to_le_bytes());
+            root.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+            root.extend_from_slice(name);
+            offset += stream.len();
+            data.extend_from_slice(stream);
+        }
+        root.extend_from_slice(&data);
+        root
+    }
+
+    #[test]
+    fn test_compressed_integers() {
+        let data = [0x03, 0x80, 0x80, 0xC0, 0x00, 0x40, 0x00, 0x7B, 0x7F];
+        let mut reader = Reader::new(&data);
+        assert_eq!(reader.compressed_u32().unwrap(), 3);
+        assert_eq!(reader.compressed_u32().unwrap(), 0x80);
+        assert_eq!(reader.compressed_u32().unwrap(), 0x4000);
+        assert_eq!(reader.compressed_i32().unwrap(), -3);
+        assert_eq!(reader.compressed_i32().unwrap(), -1);
+        assert!(reader.compressed_u32().is_err());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let metadata = Metadata::parse(&get_portable_pdb()).unwrap();
+        assert_eq!(&metadata.pdb_id[..4], &[0, 1, 2, 3]);
+        assert_eq!(
+            metadata.documents,
+            vec![
+                Document {
+                    name: "/src/Program.cs".to_string()
+                },
+                Document {
+                    name: "/src/Util.cs".to_string()
+                },
+            ]
+        );
+
+        assert_eq!(metadata.methods.len(), 2);
+        let method = &metadata.methods[0];
+        assert_eq!(method.token, 0x0600_0001);
+        let points = method
+            .sequence_points
+            .iter()
+            .map(|p| (p.il_offset, p.document, p.line, p.column, p.hidden))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            points,
+            vec![
+                (0, 1, 10, 5, false),
+                (6, 1, 11, 5, false),
+                (12, 1, HIDDEN_LINE, 0, true),
+                (16, 1, 13, 6, false),
+            ]
+        );
+
+        let method = &metadata.methods[1];
+        assert_eq!(method.token, 0x0600_0002);
+        let points = method
+            .sequence_points
+            .iter()
+            .map(|p| (p.il_offset, p.document, p.line))
+            .collect::<Vec<_>>();
+        assert_eq!(points, vec![(0, 1, 20), (2, 2, 19)]);
+
+        assert!(Metadata::parse(b"BSJB").is_err());
+        assert!(Metadata::parse(b"MZ\0\0\0\0\0\0").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::sync::Arc;
+use symbolic::common::DebugId;
+
+use super::metadata::{Metadata, Method, METADATA_SIGNATURE};
+use crate::common::{self, Dumpable, Mergeable, RecordProvenance};
+use crate::mapping::PathMappings;
+
+/*
+ The managed code hasn't any address: a location in a method is identified by the metadata token
+ of the method and by an offset in its IL code.
+ So in the sym file the address of a FUNC or a LINE is: (token << 32) | IL offset,
+ and the name of a function is the token (the names are in the assembly and not in the pdb).
+ The size of the last line of a method is 1 since its IL size isn't in the pdb.
+*/
+
+#[derive(Debug, PartialEq)]
+struct Line {
+    il_offset: u32,
+    size: u32,
+    line: u32,
+    file_id: u32,
+}
+
+#[derive(Debug, PartialEq)]
+struct Function {
+    token: u32,
+    size: u32,
+    lines: Vec<Line>,
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let address = u64::from(self.token) << 32;
+        writeln!(
+            f,
+            "FUNC {:x} {:x} 0 <method {:08x}>",
+            address, self.size, self.token
+        )?;
+        for line in self.lines.iter() {
+            writeln!(
+                f,
+                "{:x} {:x} {} {}",
+                address | u64::from(line.il_offset),
+                line.size,
+                line.line,
+                line.file_id
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Function {
+    fn new(method: &Method) -> Option<Self> {
+        let mut points = method.sequence_points.iter().collect::<Vec<_>>();
+        points.sort_by_key(|p| p.il_offset);
+
+        let mut lines = Vec::new();
+        for (i, point) in points.iter().enumerate() {
+            if point.hidden || point.document == 0 {
+                continue;
+            }
+            let size = points
+                .get(i + 1)
+                .map_or(1, |next| next.il_offset - point.il_offset);
+            if size == 0 {
+                continue;
+            }
+            lines.push(Line {
+                il_offset: point.il_offset,
+                size,
+                line: point.line,
+                // The documents are 1-based
+                file_id: point.document - 1,
+            });
+        }
+
+        let last = points.last()?;
+        Some(Self {
+            token: method.token,
+            size: last.il_offset + 1,
+            lines,
+        })
+    }
+}
+
+/// The FUNC/FILE/LINE records of the managed methods in a portable pdb
+#[derive(Debug)]
+pub struct PortablePdbInfo {
+    name: String,
+    debug_id: String,
+    files: Vec<String>,
+    functions: Vec<Function>,
+}
+
+impl Display for PortablePdbInfo {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "MODULE windows il {} {}", self.debug_id, self.name)?;
+
+        for (n, file_name) in self.files.iter().enumerate() {
+            writeln!(f, "FILE {} {}", n, file_name)?;
+        }
+
+        for function in self.functions.iter() {
+            write!(f, "{}", function)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PortablePdbInfo {
+    pub fn is_portable_pdb(buf: &[u8]) -> bool {
+        buf.len() >= 4 && u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) == METADATA_SIGNATURE
+    }
+
+    pub fn new(
+        buf: &[u8],
+        file_name: &str,
+        mapping: Option<Arc<PathMappings>>,
+    ) -> common::Result<Self> {
+        let metadata = Metadata::parse(buf)?;
+
+        // The CodeView record of the assembly contains the guid of the pdb and an age of 1
+        let debug_id = DebugId::from_guid_age(&metadata.pdb_id[..16], 1)
+            .map_err(|_| "Invalid portable pdb id")?;
+
+        let mut files = Vec::with_capacity(metadata.documents.len());
+        for document in metadata.documents {
+            let name = if let Some(mapping) = mapping.as_ref() {
+                mapping.map(&document.name)?.unwrap_or(document.name)
+            } else {
+                document.name
+            };
+            files.push(name);
+        }
+
+        let mut functions = metadata
+            .methods
+            .iter()
+            .filter_map(Function::new)
+            .collect::<Vec<_>>();
+
+        let remapping = common::sort_files(&mut files);
+        for function in functions.iter_mut() {
+            for line in function.lines.iter_mut() {
+                line.file_id = remapping[line.file_id as usize];
+            }
+        }
+
+        Ok(Self {
+            name: file_name.to_string(),
+            debug_id: debug_id.breakpad().to_string(),
+            files,
+            functions,
+        })
+    }
+}
+
+impl Mergeable for PortablePdbInfo {
+    fn merge(_left: PortablePdbInfo, _right: PortablePdbInfo) -> common::Result<PortablePdbInfo> {
+        Err("Cannot merge portable pdbs".into())
+    }
+}
+
+impl Dumpable for PortablePdbInfo {
+    fn dump<W: Write>(&self, mut writer: W) -> common::Result<()> {
+        write!(writer, "{}", self)?;
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_debug_id(&self) -> &str {
+        &self.debug_id
+    }
+
+    fn get_source_files(&self) -> &[String] {
+        &self.files
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        // The addresses aren't rvas
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::dotnet::metadata::tests::get_portable_pdb;
+
+    #[test]
+    fn test_portable_pdb() {
+        let buf = get_portable_pdb();
+        assert!(PortablePdbInfo::is_portable_pdb(&buf));
+        assert!(!PortablePdbInfo::is_portable_pdb(b"MZ"));
+
+        let info = PortablePdbInfo::new(&buf, "Program.pdb", None).unwrap();
+        let mut out = Vec::new();
+        info.dump(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(
+            out,
+            "MODULE windows il 030201000504070608090A0B0C0D0E0F1 Program.pdb\n\
+             FILE 0 /src/Program.cs\n\
+             FILE 1 /src/Util.cs\n\
+             FUNC 600000100000000 11 0 <method 06000001>\n\
+             600000100000000 6 10 0\n\
+             600000100000006 6 11 0\n\
+             600000100000010 1 13 0\n\
+             FUNC 600000200000000 3 0 <method 06000002>\n\
+             600000200000000 2 20 0\n\
+             600000200000002 1 19 1\n"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use log::info;
+use reqwest::blocking::Client;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::path::Path;
+use tracing::info_span;
+use url::Url;
+
+use crate::common::{self, FileType};
+use crate::fetch;
+
+/*
+ An input can be an url (e.g. an artifact store exposing the binaries over HTTP): the file name is the
+ last segment of the url path and the expected sha256 of the file can be given in the fragment
+ (https://example.com/libfoo.so#sha256=...).
+ The response is kept in memory or streamed to a temporary file (when a download directory is given),
+ the download is retried (see fetch) and the file is checked before being dumped: its length must match the Content-Length, its sha256 the expected one
+ and it must be a binary or a debug file (and not some html error page).
+*/
+
+/// Check if an input is an url to download
+pub(crate) fn is_url(input: &str) -> bool {
+    let input = input.to_lowercase();
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// Get the url to fetch, the file name and the expected sha256
+fn parse_url(input: &str) -> common::Result<(Url, String, Option<String>)> {
+    let mut url = Url::parse(input).map_err(|e| format!("Invalid url {}: {}", input, e))?;
+    let sha256 = match url.fragment() {
+        Some(fragment) => Some(
+            fragment
+                .strip_prefix("sha256=")
+                .ok_or_else(|| format!("Invalid fragment in {} (expected #sha256=...)", input))?
+                .to_lowercase(),
+        ),
+        None => None,
+    };
+    url.set_fragment(None);
+
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+        .ok_or_else(|| format!("No file name in the url {}", input))?
+        .to_string();
+
+    Ok((url, name, sha256))
+}
+
+fn to_temp_file(
+    client: &Client,
+    url: &Url,
+    tmp: &Path,
+) -> common::Result<(Vec<u8>, fetch::Fetched)> {
+    let mut file = File::create(tmp)?;
+    let fetched = fetch::get(
+        client,
+        url.as_str(),
+        HeaderMap::new(),
+        &fetch::get_retry(),
+        &mut file,
+    )?;
+    drop(file);
+    Ok((fs::read(tmp)?, fetched))
+}
+
+fn check(
+    buf: &[u8],
+    url: &Url,
+    length: Option<u64>,
+    sha256: Option<&str>,
+    content_type: &str,
+) -> common::Result<()> {
+    if let Some(length) = length {
+        if length != buf.len() as u64 {
+            return Err(format!(
+                "Truncated download of {}: got {} bytes instead of {}",
+                url,
+                buf.len(),
+                length
+            )
+            .into());
+        }
+    }
+    if let Some(expected) = sha256 {
+        let sha256: String = Sha256::digest(buf)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if sha256 != expected {
+            return Err(format!(
+                "Bad sha256 for {}: got {} instead of {}",
+                url, sha256, expected
+            )
+            .into());
+        }
+    }
+    if let FileType::Unknown = FileType::from_buf(buf) {
+        return Err(format!(
+            "The content of {} isn't a binary or a debug file (Content-Type: {})",
+            url, content_type
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Download the file at the given url (in a temporary file in dir if any) and get its content and its name
+pub(crate) fn download(input: &str, dir: Option<&str>) -> common::Result<(Vec<u8>, String)> {
+    let _span = info_span!("fetch", url = input).entered();
+    let (url, name, sha256) = parse_url(input)?;
+    let client = fetch::build_blocking(Client::builder().timeout(None))?;
+    let (buf, fetched) = if let Some(dir) = dir {
+        let tmp = Path::new(dir).join(format!("{}.{}.tmp", name, std::process::id()));
+        let res = to_temp_file(&client, &url, &tmp);
+        let _ = fs::remove_file(&tmp);
+        res?
+    } else {
+        let mut buf = Vec::new();
+        let fetched = fetch::get(
+            &client,
+            url.as_str(),
+            HeaderMap::new(),
+            &fetch::get_retry(),
+            &mut buf,
+        )?;
+        (buf, fetched)
+    };
+
+    check(
+        &buf,
+        &url,
+        fetched.length,
+        sha256.as_deref(),
+        &fetched.content_type,
+    )?;
+    info!("{} downloaded ({} bytes)", url, buf.len());
+
+    Ok((buf, name))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use tempfile::Builder;
+
+    /// A server answering to each request with the given status and body
+    fn serve(responses: Vec<(u16, Vec<u8>)>) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                reader.get_mut().write_all(&body).unwrap();
+            }
+        });
+        (url, handle)
+    }
+
+    fn get_elf() -> Vec<u8> {
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(64, 0);
+        elf
+    }
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/libfoo.so"));
+        assert!(is_url("HTTP://example.com/libfoo.so"));
+        assert!(!is_url("./libfoo.so"));
+        assert!(!is_url("C:\\libfoo.dll"));
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let (url, name, sha256) =
+            parse_url("https://example.com/artifacts/libfoo.so?raw=1#sha256=ABCD").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://example.com/artifacts/libfoo.so?raw=1"
+        );
+        assert_eq!(name, "libfoo.so");
+        assert_eq!(sha256, Some("abcd".to_string()));
+
+        let (_, name, sha256) = parse_url("https://example.com/basic.pdb/").unwrap();
+        assert_eq!(name, "basic.pdb");
+        assert_eq!(sha256, None);
+
+        assert!(parse_url("https://example.com/").is_err());
+        assert!(parse_url("https://example.com/libfoo.so#md5=1234").is_err());
+    }
+
+    #[test]
+    fn test_download() {
+        let elf = get_elf();
+        let sha256: String = Sha256::digest(&elf)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let tmp_dir = Builder::new().prefix("download").tempdir().unwrap();
+        let (url, handle) = serve(vec![
+            (200, elf.clone()),
+            (200, elf.clone()),
+            (200, elf.clone()),
+            (200, b"<html>Not found</html>".to_vec()),
+            (404, Vec::new()),
+        ]);
+
+        let (buf, name) = download(&format!("{}/libfoo.so", url), None).unwrap();
+        assert_eq!(buf, elf);
+        assert_eq!(name, "libfoo.so");
+
+        let input = format!("{}/libfoo.so#sha256={}", url, sha256);
+        let (buf, _) = download(&input, tmp_dir.path().to_str()).unwrap();
+        assert_eq!(buf, elf);
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 0);
+
+        let input = format!("{}/libfoo.so#sha256=1234", url);
+        assert!(download(&input, None).is_err());
+        assert!(download(&format!("{}/libfoo.so", url), None).is_err());
+        assert!(download(&format!("{}/libfoo.so", url), None).is_err());
+
+        handle.join().unwrap();
+    }
+}
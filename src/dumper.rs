@@ -3,11 +3,12 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use crossbeam::channel::{bounded, Receiver, Sender};
+use crossbeam::channel::{unbounded, Receiver, Sender};
 use failure::Fail;
 use hashbrown::HashMap;
-use log::{error, info};
+use log::{error, info, warn};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -15,14 +16,33 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use symbolic::common::Arch;
 use symbolic::debuginfo::pe::PeObject;
+use tracing::info_span;
 
+use crate::archive;
 use crate::cache;
-use crate::common::{self, Dumpable, FileType, Mergeable};
-use crate::linux::elf::{ElfInfo, Platform};
-use crate::mac::macho::MachoInfo;
+use crate::cancel;
+use crate::check;
+use crate::checksums::{self, Entry, HashWriter};
+use crate::common::{self, DumpOptions, Dumpable, FileType, Mergeable, OutputFormat};
+use crate::dotnet::ppdb::PortablePdbInfo;
+#[cfg(feature = "http")]
+use crate::download;
+use crate::emit::{self, Filtered};
+use crate::error::{self, report};
+use crate::json::Dumped;
+use crate::linux::split_dwarf::SplitDwarf;
+use crate::linux::{self, elf::ElfInfo, elf::Platform};
+use crate::mac::macho::{self, MachoInfo};
 use crate::mapping::PathMappings;
+#[cfg(feature = "http")]
+use crate::s3;
+use crate::sandbox;
+use crate::source_bundle;
+use crate::template;
 use crate::utils;
-use crate::windows::{self, pdb::PDBInfo, pdb::PEInfo};
+use crate::visit::SymbolVisitor;
+use crate::wasm::module::WasmInfo;
+use crate::windows::{self, pdb::PDBInfo, pdb::PDBStream, pdb::PEInfo};
 
 pub(crate) struct Config<'a> {
     pub output: &'a str,
@@ -37,6 +57,40 @@ pub(crate) struct Config<'a> {
     pub mapping_src: Option<Vec<&'a str>>,
     pub mapping_dest: Option<Vec<&'a str>>,
     pub mapping_file: Option<&'a str>,
+    pub prefix_map: Option<Vec<&'a str>>,
+    pub options: DumpOptions,
+}
+
+impl Default for Config<'_> {
+    fn default() -> Self {
+        Self {
+            output: "-",
+            symbol_server: None,
+            store: None,
+            debug_id: None,
+            code_id: None,
+            arch: common::get_compile_time_arch(),
+            file_type: FileType::Unknown,
+            num_jobs: 1,
+            mapping_var: None,
+            mapping_src: None,
+            mapping_dest: None,
+            mapping_file: None,
+            prefix_map: None,
+            options: DumpOptions::default(),
+        }
+    }
+}
+
+impl<'a> Config<'a> {
+    /// The default config to dump the files in a store
+    pub(crate) fn with_store(store: &'a str, symbol_server: Option<&'a str>) -> Self {
+        Self {
+            symbol_server,
+            store: Some(store),
+            ..Default::default()
+        }
+    }
 }
 
 pub(crate) trait Creator: Mergeable + Dumpable + Sized {
@@ -46,6 +100,7 @@ pub(crate) trait Creator: Mergeable + Dumpable + Sized {
         path: &Path,
         filename: &str,
         mapping: Option<Arc<PathMappings>>,
+        options: &DumpOptions,
     ) -> common::Result<Self>;
 
     fn get_pe<'a>(
@@ -63,11 +118,31 @@ impl Creator for ElfInfo {
     fn get_dbg(
         _arch: Arch,
         buf: &[u8],
+        path: &Path,
+        filename: &str,
+        mapping: Option<Arc<PathMappings>>,
+        options: &DumpOptions,
+    ) -> common::Result<Self> {
+        let split_dwarf = SplitDwarf::new(path, options.dwo_dir.as_deref().map(Path::new));
+        let mut elf = Self::new(buf, filename, Platform::Linux, Some(&split_dwarf), mapping)?;
+        if let Some(fallback) = options.debug_id_fallback {
+            elf.set_fallback_debug_id(buf, fallback);
+        }
+        Ok(elf)
+    }
+
+    fn get_pe<'a>(
+        conf: &Config<'a>,
+        buf: &[u8],
         _path: &Path,
         filename: &str,
         mapping: Option<Arc<PathMappings>>,
     ) -> common::Result<Self> {
-        Self::new(buf, filename, Platform::Linux, mapping)
+        let mut elf = Self::from_pe(buf, filename, mapping)?;
+        if let Some(fallback) = conf.options.debug_id_fallback {
+            elf.set_fallback_debug_id(buf, fallback);
+        }
+        Ok(elf)
     }
 }
 
@@ -78,11 +153,42 @@ impl Creator for MachoInfo {
         _path: &Path,
         filename: &str,
         mapping: Option<Arc<PathMappings>>,
+        _options: &DumpOptions,
     ) -> common::Result<Self> {
         Self::new(buf, filename, arch, mapping)
     }
 }
 
+impl Creator for WasmInfo {
+    fn get_dbg(
+        _arch: Arch,
+        buf: &[u8],
+        path: &Path,
+        filename: &str,
+        mapping: Option<Arc<PathMappings>>,
+        options: &DumpOptions,
+    ) -> common::Result<Self> {
+        let mut wasm = Self::new(buf, path, filename, mapping)?;
+        if let Some(fallback) = options.debug_id_fallback {
+            wasm.set_fallback_debug_id(buf, fallback);
+        }
+        Ok(wasm)
+    }
+}
+
+impl Creator for PortablePdbInfo {
+    fn get_dbg(
+        _arch: Arch,
+        buf: &[u8],
+        _path: &Path,
+        filename: &str,
+        mapping: Option<Arc<PathMappings>>,
+        _options: &DumpOptions,
+    ) -> common::Result<Self> {
+        Self::new(buf, filename, mapping)
+    }
+}
+
 impl Creator for PDBInfo {
     fn get_dbg(
         _arch: Arch,
@@ -90,9 +196,12 @@ impl Creator for PDBInfo {
         path: &Path,
         filename: &str,
         mapping: Option<Arc<PathMappings>>,
+        options: &DumpOptions,
     ) -> common::Result<Self> {
-        let mut pdb = Self::new(buf, filename, "", None, mapping)?;
-        windows::utils::try_to_set_pe(path, &mut pdb, buf);
+        let mut pdb = Self::new(buf, filename, "", None, mapping, options)?;
+        windows::utils::try_to_set_pe(path, &options.binary_paths, |pe_name, pe| {
+            pdb.set_pe(pe_name, pe, buf)
+        });
         Ok(pdb)
     }
 
@@ -104,10 +213,17 @@ impl Creator for PDBInfo {
         mapping: Option<Arc<PathMappings>>,
     ) -> common::Result<Self> {
         let symbol_server = cache::get_sym_servers(conf.symbol_server);
-        let res = windows::utils::get_pe_pdb_buf(path, buf, symbol_server.as_ref());
+        let res = windows::utils::get_pe_pdb_buf(path, buf, symbol_server.as_ref())?;
 
         if let Some((pe, pdb_buf, pdb_name)) = res {
-            let pdb = Self::new(&pdb_buf, &pdb_name, filename, Some(pe), mapping)?;
+            let pdb = Self::new(
+                &pdb_buf,
+                &pdb_name,
+                filename,
+                Some(pe),
+                mapping,
+                &conf.options,
+            )?;
             Ok(pdb)
         } else {
             Err("No pdb file found".into())
@@ -122,20 +238,20 @@ impl Creator for PEInfo {
         _path: &Path,
         _filename: &str,
         _mapping: Option<Arc<PathMappings>>,
+        _options: &DumpOptions,
     ) -> common::Result<Self> {
         Err("Not implemented".into())
     }
 
     fn get_pe<'a>(
-        _conf: &Config<'a>,
+        conf: &Config<'a>,
         buf: &[u8],
         path: &Path,
         filename: &str,
         _mapping: Option<Arc<PathMappings>>,
     ) -> common::Result<Self> {
-        let pe = PeObject::parse(buf)
-            .unwrap_or_else(|_| panic!("Unable to parse the PE file {}", path.to_str().unwrap()));
-        let pe = Self::new(filename, pe)?;
+        let pe = windows::utils::parse_pe(path, buf)?;
+        let pe = Self::new(filename, pe, &conf.options)?;
         Ok(pe)
     }
 }
@@ -144,33 +260,421 @@ fn store<D: Dumpable, S1: AsRef<str>, S2: AsRef<str>>(
     output: S1,
     store: Option<S2>,
     dumpable: D,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    let _span = info_span!(
+        "write",
+        output = output.as_ref(),
+        module = dumpable.get_name()
+    )
+    .entered();
+    if options.output_template.is_none() && options.run_stats.is_none() && !options.validate_cfi {
+        return store_filtered(output, store, dumpable, options);
+    }
+
+    // The fields of the path, the statistics and the checks come from the sym: the module is
+    // serialized once
+    let dumped = Dumped::new(&dumpable)?;
+    if options.validate_cfi {
+        let problems = check::validate_cfi(&dumped.sym);
+        if !problems.is_empty() {
+            for problem in problems.iter() {
+                error!("{}: {}", dumped.get_name(), problem);
+            }
+            return Err(format!(
+                "Invalid STACK records in {}: {} problem(s)",
+                dumped.get_name(),
+                problems.len()
+            )
+            .into());
+        }
+    }
+    if let Some(stats) = options.run_stats.as_ref() {
+        stats.add_module(dumped.get_name(), dumped.get_debug_id(), &dumped.sym);
+    }
+    if let Some(template) = options.output_template.as_ref() {
+        let output = template::expand(template, store.as_ref().map(|s| s.as_ref()), &dumped.sym)?;
+        if let Some(parent) = Path::new(&output).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        return store_filtered(&output, None::<&str>, dumped, options);
+    }
+    store_filtered(output, store, dumped, options)
+}
+
+fn store_filtered<D: Dumpable, S1: AsRef<str>, S2: AsRef<str>>(
+    output: S1,
+    store: Option<S2>,
+    dumpable: D,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    if emit::is_needed(options) {
+        let filtered = Filtered::new(&dumpable, options);
+        store_dumpable(output, store, &filtered, options)?;
+        if filtered.get_truncated() != 0 {
+            warn!(
+                "{} names longer than {} bytes have been truncated in {}",
+                filtered.get_truncated(),
+                options.max_name_len.unwrap_or_default(),
+                dumpable.get_name()
+            );
+        }
+        Ok(())
+    } else {
+        store_dumpable(output, store, &dumpable, options)
+    }
+}
+
+fn store_dumpable<D: Dumpable, S1: AsRef<str>, S2: AsRef<str>>(
+    output: S1,
+    store: Option<S2>,
+    dumpable: &D,
+    options: &DumpOptions,
 ) -> common::Result<()> {
     let output = output.as_ref();
-    let store = store.filter(|p| !p.as_ref().is_empty()).map(|p| {
-        PathBuf::from(p.as_ref()).join(cache::get_path_for_sym(
-            dumpable.get_name(),
-            dumpable.get_debug_id(),
-        ))
-    });
+    let store = store.filter(|p| !p.as_ref().is_empty());
+    #[cfg(feature = "http")]
+    let bucket = store
+        .as_ref()
+        .map(|p| s3::Bucket::from_url(p.as_ref()))
+        .transpose()?
+        .flatten();
+    let store = store.map(|p| PathBuf::from(p.as_ref()));
+
+    if let Some(bundle) = options.source_bundle.as_ref() {
+        source_bundle::write_source_bundle(dumpable, bundle)?;
+    }
+
+    if let Some(provenance) = options.provenance.as_ref() {
+        serde_json::to_writer_pretty(
+            utils::get_writer_for_sym(provenance),
+            &dumpable.get_provenance(),
+        )?;
+        info!("Write provenance at {}", provenance);
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(bucket) = bucket.as_ref() {
+        return store_s3(output, bucket, dumpable, options)
+            .map_err(|e| error::Error::Network(e).into());
+    }
+
+    if OutputFormat::RECORDS.iter().any(|f| options.emits(*f)) {
+        // The module is serialized once for all the formats
+        let dumped = Dumped::new(dumpable)?;
+        for format in OutputFormat::RECORDS.iter().filter(|f| options.emits(**f)) {
+            store_records(output, store.as_deref(), &dumped, *format, options)?;
+        }
+        if options.emits(OutputFormat::Breakpad) {
+            store_sym(output, store.as_deref(), &dumped, options)?;
+        }
+        Ok(())
+    } else {
+        store_sym(output, store.as_deref(), dumpable, options)
+    }
+}
+
+/// Write the records in json or in csv in the store and in the output
+fn store_records<D: Dumpable>(
+    output: &str,
+    store: Option<&Path>,
+    dumped: &Dumped<D>,
+    format: OutputFormat,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    let extension = format.extension();
+    if let Some(store) = store {
+        let path = cache::get_path_for_sym(dumped.get_name(), dumped.get_debug_id());
+        let path = store.join(path.with_extension(extension));
+        fs::create_dir_all(path.parent().unwrap())?;
+        let path = path.to_str().unwrap();
+        write_file(path, dumped, options, |w| {
+            dumped.write_records(format, w, options)
+        })?;
+        info!("Write {} at {}", extension, path);
+    }
+
+    if output != "-" || store.is_none() {
+        let path = if options.main_format() != format {
+            if output.is_empty() || output == "-" {
+                return Err(format!(
+                    "An output file is required to write the {} next to the {}",
+                    extension,
+                    options.main_format().extension()
+                )
+                .into());
+            }
+            PathBuf::from(output).with_extension(extension)
+        } else {
+            PathBuf::from(output)
+        };
+        let path = path.to_str().unwrap();
+        write_file(path, dumped, options, |w| {
+            dumped.write_records(format, w, options)
+        })?;
+        info!("Write {} at {}", extension, path);
+    }
+
+    Ok(())
+}
+
+/// Get the path of the sym file (maybe compressed) relatively to the store
+fn get_sym_path<D: Dumpable>(dumpable: &D, options: &DumpOptions) -> PathBuf {
+    let sym_path = cache::get_path_for_sym(dumpable.get_name(), dumpable.get_debug_id());
+    if let Some(compression) = options.compression.as_ref() {
+        let mut path = sym_path.into_os_string();
+        path.push(".");
+        path.push(compression.extension());
+        PathBuf::from(path)
+    } else {
+        sym_path
+    }
+}
+
+fn store_sym<D: Dumpable>(
+    output: &str,
+    store: Option<&Path>,
+    dumpable: &D,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    let sym_path = get_sym_path(dumpable, options);
+    let store = store.map(|p| p.join(&sym_path));
 
     if let Some(store) = store.as_ref() {
-        fs::create_dir_all(store.parent().unwrap())?;
-        let store = store.to_str().unwrap();
-        let output = utils::get_writer_for_sym(store);
-        if let Err(e) = dumpable.dump(output) {
-            return Err(e);
+        if options.force || !is_already_stored(store, &sym_path, dumpable, options)? {
+            fs::create_dir_all(store.parent().unwrap())?;
+            let store = store.to_str().unwrap();
+            write_sym(store, dumpable, options)?;
+            info!("Write symbols at {}", store);
         }
-        info!("Write symbols at {}", store);
     }
 
     if output != "-" || store.is_none() {
-        let output_stream = utils::get_writer_for_sym(output);
-        dumpable.dump(output_stream)?;
+        write_sym(output, dumpable, options)?;
+        info!("Write symbols at {}", output);
+    }
+    Ok(())
+}
+
+/// Put the sym file (and the json) in a S3 bucket with the layout of a store
+#[cfg(feature = "http")]
+fn store_s3<D: Dumpable>(
+    output: &str,
+    bucket: &s3::Bucket,
+    dumpable: &D,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    if OutputFormat::RECORDS.iter().any(|f| options.emits(*f)) {
+        let dumped = Dumped::new(dumpable)?;
+        let path = cache::get_path_for_sym(dumped.get_name(), dumped.get_debug_id());
+        for format in OutputFormat::RECORDS.iter().filter(|f| options.emits(**f)) {
+            let mut buf = Vec::new();
+            dumped.write_records(*format, &mut buf, options)?;
+            bucket.put(&path.with_extension(format.extension()), &buf)?;
+            if output != "-" {
+                store_records(output, None, &dumped, *format, options)?;
+            }
+        }
+        if !options.emits(OutputFormat::Breakpad) {
+            return Ok(());
+        }
+    }
+
+    let sym_path = get_sym_path(dumpable, options);
+    if options.force || !bucket.exists(&sym_path)? {
+        let mut buf = Vec::new();
+        dump_to(&mut buf, dumpable, options)?;
+        cancel::check()?;
+        bucket.put(&sym_path, &buf)?;
+    } else {
+        info!(
+            "Symbols {} are already in the bucket: use --force to overwrite them",
+            sym_path.display()
+        );
+    }
+
+    if output != "-" {
+        write_sym(output, dumpable, options)?;
         info!("Write symbols at {}", output);
     }
     Ok(())
 }
 
+/// Check if the sym file is already in the store or on the server of the store
+/// (if any) in order to avoid to write it again
+fn is_already_stored<D: Dumpable>(
+    path: &Path,
+    sym_path: &Path,
+    dumpable: &D,
+    options: &DumpOptions,
+) -> common::Result<bool> {
+    if path.is_file() {
+        let mut buf = Vec::new();
+        dump_to(&mut buf, dumpable, options)?;
+        if fs::read(path)? == buf {
+            info!("Symbols at {} are up to date", path.display());
+        } else {
+            warn!(
+                "Symbols at {} are different: use --force to overwrite them",
+                path.display()
+            );
+        }
+        return Ok(true);
+    }
+
+    Ok(is_on_store_server(sym_path, options))
+}
+
+/// Check if the sym file is on the server of the store (with --store-url)
+#[cfg(feature = "http")]
+fn is_on_store_server(sym_path: &Path, options: &DumpOptions) -> bool {
+    if let Some(url) = options.store_url.as_ref() {
+        if cache::is_on_server(url, sym_path) {
+            info!(
+                "Symbols {} are already on the server {}",
+                sym_path.display(),
+                url
+            );
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(not(feature = "http"))]
+fn is_on_store_server(_sym_path: &Path, _options: &DumpOptions) -> bool {
+    false
+}
+
+fn dump_to<D: Dumpable, W: Write>(
+    writer: W,
+    dumpable: &D,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    if let Some(compression) = options.compression.as_ref() {
+        compression.dump(dumpable, writer)
+    } else {
+        dumpable.dump(writer)
+    }
+}
+
+fn dump_sym<D: Dumpable>(output: &str, dumpable: &D, options: &DumpOptions) -> common::Result<()> {
+    dump_to(utils::get_writer_for_sym(output), dumpable, options)
+}
+
+fn write_sym<D: Dumpable>(output: &str, dumpable: &D, options: &DumpOptions) -> common::Result<()> {
+    if output.is_empty() || output == "-" {
+        cancel::check()?;
+        return dump_sym(output, dumpable, options);
+    }
+
+    // Write in a temporary file and rename it once everything is fine:
+    // an interruption mustn't leave a truncated sym file (in the store for example).
+    let tmp = format!("{}.{}.tmp", output, std::process::id());
+    let res = write_hashed(&tmp, options, |w| dump_to(w, dumpable, options))
+        .and_then(|hash| cancel::check().map(|_| hash))
+        .and_then(|hash| fs::rename(&tmp, output).map(|_| hash).map_err(|e| e.into()));
+
+    match res {
+        Ok(hash) => add_checksums(output, hash, dumpable, options),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Write the file with f and get its sha256 and its size when they're needed (with --checksums or
+/// --manifest)
+fn write_hashed<F>(path: &str, options: &DumpOptions, f: F) -> common::Result<Option<(String, u64)>>
+where
+    F: FnOnce(&mut dyn Write) -> common::Result<()>,
+{
+    let mut writer = utils::get_writer_for_sym(path);
+    if options.checksums || options.manifest.is_some() {
+        let mut writer = HashWriter::new(writer);
+        f(&mut writer)?;
+        writer.finish().map(Some)
+    } else {
+        f(&mut writer)?;
+        writer.flush()?;
+        Ok(None)
+    }
+}
+
+/// Write the sidecar of the file and add it to the manifest
+fn add_checksums<D: Dumpable>(
+    path: &str,
+    hash: Option<(String, u64)>,
+    dumpable: &D,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    let (sha256, size) = match hash {
+        Some(hash) if !path.is_empty() && path != "-" => hash,
+        _ => return Ok(()),
+    };
+    if options.checksums {
+        checksums::write_sidecar(path, &sha256)?;
+    }
+    if let Some(manifest) = options.manifest.as_ref() {
+        manifest.add(Entry {
+            path: path.to_string(),
+            size,
+            sha256,
+            debug_file: dumpable.get_name().to_string(),
+            debug_id: dumpable.get_debug_id().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Write the file with f and its checksums
+fn write_file<D, F>(path: &str, dumpable: &D, options: &DumpOptions, f: F) -> common::Result<()>
+where
+    D: Dumpable,
+    F: FnOnce(&mut dyn Write) -> common::Result<()>,
+{
+    let hash = write_hashed(path, options, f)?;
+    add_checksums(path, hash, dumpable, options)
+}
+
+fn write_pdb_reports(config: &Config, pdb_info: &PDBInfo) -> common::Result<()> {
+    if let Some(report) = config.options.address_report.as_ref() {
+        pdb_info.dump_address_report(utils::get_writer_for_sym(report))?;
+        info!("Write address report at {}", report);
+    }
+    if let Some(objects) = config.options.objects.as_ref() {
+        pdb_info.dump_objects(utils::get_writer_for_sym(objects))?;
+        info!("Write objects at {}", objects);
+    }
+    if let Some(locals) = config.options.locals.as_ref() {
+        pdb_info.dump_locals(utils::get_writer_for_sym(locals))?;
+        info!("Write locals at {}", locals);
+    }
+    Ok(())
+}
+
+/// Insert the architecture in the name of the output: foo.sym gives foo.arm64.sym
+fn get_output_for_arch(output: &str, arch: &str) -> String {
+    if output.is_empty() || output == "-" {
+        return output.to_string();
+    }
+
+    let path = Path::new(output);
+    let file_name = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => format!(
+            "{}.{}.{}",
+            stem.to_string_lossy(),
+            arch,
+            ext.to_string_lossy()
+        ),
+        _ => format!("{}.{}", output, arch),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
 fn get_from_id(
     config: &Config,
     path: &Path,
@@ -186,51 +690,266 @@ fn get_from_id(
         };
     }
 
-    Ok((utils::read_file(&path), filename))
+    Ok((read_input(path)?, filename))
+}
+
+/// Read a file to dump (its absence is an error and not a panic)
+fn read_input(path: &Path) -> common::Result<Vec<u8>> {
+    fs::metadata(path).map_err(|source| error::Error::Input {
+        path: path.display().to_string(),
+        source,
+    })?;
+    Ok(utils::read_file(path))
+}
+
+/// Get the content of the input, its name and its path: the input can be an url, a member of an archive
+/// (app.apk!lib/libfoo.so or with --member) or a file
+fn get_input(config: &Config, filename: &str) -> common::Result<(Vec<u8>, String, PathBuf)> {
+    #[cfg(feature = "http")]
+    if download::is_url(filename) {
+        let (buf, filename) = download::download(filename, config.options.download_dir.as_deref())
+            .map_err(error::Error::Network)?;
+        let path = PathBuf::from(&filename);
+        return Ok((buf, filename, path));
+    }
+
+    let member = match config.options.member.as_deref() {
+        Some(member) => Some((filename, member)),
+        None => archive::split_member(filename),
+    };
+    if let Some((archive, member)) = member {
+        let buf = archive::read_member(archive, member)?;
+        // The files next to the archive are used to find the debug files
+        let filename = utils::get_filename(Path::new(member));
+        let path = Path::new(archive).with_file_name(&filename);
+        return Ok((buf, filename, path));
+    }
+
+    let path = Path::new(filename);
+    let (buf, filename) = get_from_id(config, path, utils::get_filename(path))?;
+    Ok((buf, filename, path.to_path_buf()))
 }
 
 pub(crate) fn single_file(config: &Config, filename: &str) -> common::Result<()> {
+    let (buf, filename, path) =
+        info_span!("open", file = filename).in_scope(|| get_input(config, filename))?;
+    if config.options.sandbox {
+        let sym = sandbox::dump(&buf, &filename)?;
+        return store(config.output, config.store, sym, &config.options);
+    }
+
+    dump_buf(config, &path, filename, &buf)
+}
+
+/// Dump the content of a file
+pub(crate) fn dump_buf(
+    config: &Config,
+    path: &Path,
+    filename: String,
+    buf: &[u8],
+) -> common::Result<()> {
+    dump_buf_to(config, path, filename, buf, &mut StoreSink(config))
+}
+
+/// What is done with the dumped modules
+trait Sink {
+    fn put<D: Dumpable>(&mut self, output: &str, dumpable: D) -> common::Result<()>;
+}
+
+/// Write the modules in the output and the store of the config
+struct StoreSink<'a, 'b>(&'a Config<'b>);
+
+impl Sink for StoreSink<'_, '_> {
+    fn put<D: Dumpable>(&mut self, output: &str, dumpable: D) -> common::Result<()> {
+        store(output, self.0.store, dumpable, &self.0.options)
+    }
+}
+
+/// Keep the records of the dumped module in memory
+#[derive(Default)]
+struct SymSink {
+    sym: Vec<u8>,
+}
+
+impl Sink for SymSink {
+    fn put<D: Dumpable>(&mut self, _output: &str, dumpable: D) -> common::Result<()> {
+        if !self.sym.is_empty() {
+            return Err(
+                "Several modules have been dumped: an architecture must be selected".into(),
+            );
+        }
+        dumpable.dump(&mut self.sym)
+    }
+}
+
+/// Give the records of the dumped module to a visitor
+struct VisitSink<'a, V> {
+    visitor: &'a mut V,
+    visited: bool,
+}
+
+impl<V: for<'a> SymbolVisitor<'a>> Sink for VisitSink<'_, V> {
+    fn put<D: Dumpable>(&mut self, _output: &str, dumpable: D) -> common::Result<()> {
+        if self.visited {
+            return Err(
+                "Several modules have been dumped: an architecture must be selected".into(),
+            );
+        }
+        self.visited = true;
+        dumpable.visit(self.visitor)
+    }
+}
+
+/// Dump a file in memory and give its records to the visitor
+pub(crate) fn visit_in_memory<V: for<'a> SymbolVisitor<'a>>(
+    config: &Config,
+    path: &Path,
+    visitor: &mut V,
+) -> common::Result<()> {
+    let buf = utils::read(path)?;
+    let mut sink = VisitSink {
+        visitor,
+        visited: false,
+    };
+    dump_buf_to(config, path, utils::get_filename(path), &buf, &mut sink)
+}
+
+/// Dump a file in memory and get the records
+pub(crate) fn dump_in_memory(config: &Config, filename: &str) -> common::Result<String> {
     let path = Path::new(filename);
-    let filename = utils::get_filename(path);
+    let buf = utils::read(path)?;
+    dump_buf_in_memory(config, path, &buf)
+}
+
+/// Dump the content of a file in memory and get the records
+pub(crate) fn dump_buf_in_memory(
+    config: &Config,
+    path: &Path,
+    buf: &[u8],
+) -> common::Result<String> {
+    let mut sink = SymSink::default();
+    dump_buf_to(config, path, utils::get_filename(path), buf, &mut sink)?;
+    Ok(String::from_utf8(sink.sym)?)
+}
 
-    let (buf, filename) = get_from_id(config, path, filename)?;
+/// Search the dll or the exe of a pdb on the symbol servers (with --fetch-binary) to get its code id
+fn fetch_pe<F: FnMut(String, PeObject) -> bool>(
+    config: &Config,
+    buf: &[u8],
+    path: &Path,
+    set_pe: F,
+) {
+    if config.options.fetch_binary {
+        let symbol_server = cache::get_sym_servers(config.symbol_server);
+        windows::utils::fetch_pe(buf, path, symbol_server.as_ref(), set_pe);
+    }
+}
+
+/// Dump the content of a file and give the modules to the sink
+fn dump_buf_to<S: Sink>(
+    config: &Config,
+    path: &Path,
+    filename: String,
+    buf: &[u8],
+    sink: &mut S,
+) -> common::Result<()> {
+    let _span = info_span!("dump", file = filename.as_str()).entered();
     let file_mapping = PathMappings::new(
         &config.mapping_var,
         &config.mapping_src,
         &config.mapping_dest,
         &config.mapping_file,
+        &config.prefix_map,
     )?
     .map(Arc::new);
-    let arch = Arch::from_str(config.arch).map_err(|e| e.compat())?;
+    let all_archs = config.arch == macho::ALL_ARCHS;
+    let arch = if all_archs {
+        Arch::Unknown
+    } else {
+        Arch::from_str(config.arch).map_err(|e| e.compat())?
+    };
 
-    match FileType::from_buf(&buf) {
-        FileType::Elf => store(
-            config.output,
-            config.store,
-            ElfInfo::get_dbg(arch, &buf, path, &filename, file_mapping)?,
-        ),
-        FileType::Pdb => store(
-            config.output,
-            config.store,
-            PDBInfo::get_dbg(arch, &buf, path, &filename, file_mapping)?,
-        ),
+    match FileType::from_buf(buf) {
+        FileType::Elf => {
+            let mut elf = ElfInfo::get_dbg(
+                arch,
+                buf,
+                path,
+                &filename,
+                file_mapping.clone(),
+                &config.options,
+            )?;
+            if let Some(debug_path) =
+                linux::debug_file::find_debug_file(buf, path, &config.options.debug_dirs)
+            {
+                let debug_buf = utils::read(&debug_path)?;
+                let debug_elf = ElfInfo::get_dbg(
+                    arch,
+                    &debug_buf,
+                    &debug_path,
+                    &utils::get_filename(&debug_path),
+                    file_mapping,
+                    &config.options,
+                )?;
+                elf = ElfInfo::merge(elf, debug_elf)?;
+            }
+            sink.put(config.output, elf)
+        }
+        FileType::Pdb if config.options.stream => {
+            let mut stream = PDBStream::new(buf, path, &filename, file_mapping, &config.options)?;
+            if !stream.has_pe() {
+                fetch_pe(config, buf, path, |pe_name, pe| stream.set_pe(pe_name, pe));
+            }
+            sink.put(config.output, stream)
+        }
+        FileType::Pdb => {
+            let mut pdb_info =
+                PDBInfo::get_dbg(arch, buf, path, &filename, file_mapping, &config.options)?;
+            if !pdb_info.has_pe() {
+                fetch_pe(config, buf, path, |pe_name, pe| {
+                    pdb_info.set_pe(pe_name, pe, buf)
+                });
+            }
+            write_pdb_reports(config, &pdb_info)?;
+            sink.put(config.output, pdb_info)
+        }
         FileType::Pe => {
-            if let Ok(pdb_info) = PDBInfo::get_pe(config, &buf, path, &filename, file_mapping) {
-                store(config.output, config.store, pdb_info)
+            if windows::pe_dwarf::has_dwarf(buf) {
+                sink.put(
+                    config.output,
+                    ElfInfo::get_pe(config, buf, path, &filename, file_mapping)?,
+                )
+            } else if let Ok(pdb_info) = PDBInfo::get_pe(config, buf, path, &filename, file_mapping)
+            {
+                write_pdb_reports(config, &pdb_info)?;
+                sink.put(config.output, pdb_info)
             } else {
-                store(
+                sink.put(
                     config.output,
-                    config.store,
-                    PEInfo::get_pe(config, &buf, path, &filename, None)?,
+                    PEInfo::get_pe(config, buf, path, &filename, None)?,
                 )
             }
         }
-        FileType::Macho => store(
+        FileType::Macho if all_archs => {
+            for (arch, macho) in MachoInfo::get_all(buf, &filename, file_mapping)? {
+                let output = get_output_for_arch(config.output, arch.name());
+                sink.put(&output, macho)?;
+            }
+            Ok(())
+        }
+        FileType::Macho => sink.put(
             config.output,
-            config.store,
-            MachoInfo::get_dbg(arch, &buf, path, &filename, file_mapping)?,
+            MachoInfo::get_dbg(arch, buf, path, &filename, file_mapping, &config.options)?,
         ),
-        FileType::Unknown => Err("Unknown file format".into()),
+        FileType::Wasm => sink.put(
+            config.output,
+            WasmInfo::get_dbg(arch, buf, path, &filename, file_mapping, &config.options)?,
+        ),
+        FileType::PortablePdb => sink.put(
+            config.output,
+            PortablePdbInfo::get_dbg(arch, buf, path, &filename, file_mapping, &config.options)?,
+        ),
+        FileType::Unknown => Err(error::Error::UnknownFormat.into()),
     }
 }
 
@@ -251,10 +970,11 @@ fn send_store_jobs<T: Creator>(
     num_threads: usize,
     output: &str,
     store: &Option<String>,
+    options: &DumpOptions,
 ) -> common::Result<()> {
-    if results.len() == 1 {
+    let res = if results.len() == 1 {
         let (_, d) = results.drain().take(1).next().unwrap();
-        self::store(&output, store.as_ref(), d)?;
+        self::store(&output, store.as_ref(), d, options)
     } else {
         for (_, d) in results.drain() {
             sender
@@ -265,10 +985,12 @@ fn send_store_jobs<T: Creator>(
                 }))
                 .unwrap();
         }
-    }
+        Ok(())
+    };
 
+    // The other consumers are stopped even if the store failed
     poison_queue(sender, num_threads);
-    Ok(())
+    res
 }
 
 fn poison_queue<T: Dumpable>(sender: &Sender<Option<JobItem<T>>>, num_threads: usize) {
@@ -278,6 +1000,51 @@ fn poison_queue<T: Dumpable>(sender: &Sender<Option<JobItem<T>>>, num_threads: u
     }
 }
 
+/// Read a file and merge its debug info with the ones of the files with the same debug id
+fn get_info<T: Creator>(
+    arch: Arch,
+    file: &str,
+    mapping: Option<Arc<PathMappings>>,
+    options: &DumpOptions,
+    results: &Mutex<HashMap<String, T>>,
+) -> common::Result<()> {
+    let _span = info_span!("dump", file).entered();
+    let path = PathBuf::from(file);
+    let filename = utils::get_filename(&path);
+    let buf = info_span!("open").in_scope(|| read_input(&path))?;
+
+    let info = T::get_dbg(arch, &buf, &path, &filename, mapping, options)?;
+
+    let mut results = results.lock().unwrap();
+    let info = if let Some(prev) = results.remove(info.get_debug_id()) {
+        T::merge(info, prev)?
+    } else {
+        info
+    };
+    results.insert(info.get_debug_id().to_string(), info);
+    Ok(())
+}
+
+/// Add the file to the failed ones if it can't be dumped: with --keep-going the error is logged
+/// and the other files are dumped
+fn check_failure(
+    res: common::Result<()>,
+    file: String,
+    failed: &Mutex<Vec<String>>,
+    options: &DumpOptions,
+) -> common::Result<()> {
+    if let Err(e) = res {
+        if options.keep_going && !cancel::is_cancelled() {
+            error!("{}: {}", file, report(&*e));
+            failed.lock().unwrap().push(file);
+            return Ok(());
+        }
+        failed.lock().unwrap().push(file);
+        return Err(e);
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 fn consumer<T: Creator>(
     arch: Arch,
@@ -285,43 +1052,49 @@ fn consumer<T: Creator>(
     receiver: Receiver<Option<JobItem<T>>>,
     results: Arc<Mutex<HashMap<String, T>>>,
     counter: Arc<AtomicUsize>,
+    failed: Arc<Mutex<Vec<String>>>,
     num_threads: usize,
     output: String,
     store: Option<String>,
+    options: DumpOptions,
 ) -> common::Result<()> {
+    let mut res = Ok(());
     while let Ok(job) = receiver.recv() {
         if job.is_none() {
-            return Ok(());
+            return res;
         }
 
         let JobItem { file, typ, mapping } = job.unwrap();
 
-        match typ {
-            JobType::Get => {
-                let path = PathBuf::from(file);
-                let filename = utils::get_filename(&path);
-                let buf = utils::read_file(&path);
-
-                let info = T::get_dbg(arch, &buf, &path, &filename, mapping).map_err(|e| {
+        // Without --keep-going, a failure stops the dump as a cancellation does
+        let stopped = !options.keep_going && !failed.lock().unwrap().is_empty();
+        if cancel::is_cancelled() || stopped {
+            // Just drain the queue: the consumers will be stopped once the last file is seen
+            if let JobType::Get = typ {
+                if counter.fetch_sub(1, Ordering::SeqCst) == 1 {
                     poison_queue(&sender, num_threads);
-                    e
-                })?;
+                }
+            }
+            continue;
+        }
 
-                let mut results = results.lock().unwrap();
-                let info = if let Some(prev) = results.remove(info.get_debug_id()) {
-                    T::merge(info, prev).map_err(|e| {
+        match typ {
+            JobType::Get => {
+                let info = get_info(arch, &file, mapping, &options, &results);
+                if let Err(e) = check_failure(info, file, &failed, &options) {
+                    res = res.and(Err(e));
+                    if counter.fetch_sub(1, Ordering::SeqCst) == 1 {
                         poison_queue(&sender, num_threads);
-                        e
-                    })?
-                } else {
-                    info
-                };
-                results.insert(info.get_debug_id().to_string(), info);
+                    }
+                    continue;
+                }
             }
             JobType::Dump(d) => {
                 let cwd = ".".to_string();
                 let store = Some(store.as_ref().unwrap_or(&cwd));
-                self::store(&output, store.as_ref(), d)?;
+                let name = d.get_name().to_string();
+                let stored = self::store(&output, store.as_ref(), d, &options);
+                res = res.and(check_failure(stored, name, &failed, &options));
                 continue;
             }
         }
@@ -330,13 +1103,20 @@ fn consumer<T: Creator>(
             // it was the last file: so we just have to add jobs to dump & store
             // and then poison the queue
             let mut results = results.lock().unwrap();
-            send_store_jobs(&sender, &mut results, num_threads, &output, &store)?;
+            res = res.and(send_store_jobs(
+                &sender,
+                &mut results,
+                num_threads,
+                &output,
+                &store,
+                &options,
+            ));
         } else {
             counter.fetch_sub(1, Ordering::SeqCst);
         }
     }
 
-    Ok(())
+    res
 }
 
 pub(crate) fn several_files<T: 'static + Creator + std::marker::Send>(
@@ -348,14 +1128,18 @@ pub(crate) fn several_files<T: 'static + Creator + std::marker::Send>(
         &config.mapping_src,
         &config.mapping_dest,
         &config.mapping_file,
+        &config.prefix_map,
     )?
     .map(Arc::new);
     let arch = Arch::from_str(config.arch).map_err(|e| e.compat())?;
     let results = Arc::new(Mutex::new(HashMap::default()));
     let num_jobs = config.num_jobs.min(filenames.len());
     let counter = Arc::new(AtomicUsize::new(filenames.len()));
+    let failed = Arc::new(Mutex::new(Vec::new()));
 
-    let (sender, receiver) = bounded(num_jobs + 1);
+    // The queue is unbounded: a consumer can't be blocked when it sends the dump jobs and poisons
+    // the queue (it holds the names of the files and the modules which are already in memory)
+    let (sender, receiver) = unbounded();
 
     let mut receivers = Vec::with_capacity(num_jobs);
     for i in 0..num_jobs {
@@ -363,14 +1147,17 @@ pub(crate) fn several_files<T: 'static + Creator + std::marker::Send>(
         let receiver = receiver.clone();
         let results = Arc::clone(&results);
         let counter = Arc::clone(&counter);
+        let failed = Arc::clone(&failed);
         let output = config.output.to_string();
         let store = config.store.map(|s| s.to_string());
+        let options = config.options.clone();
 
         let t = thread::Builder::new()
             .name(format!("dump-syms {}", i))
             .spawn(move || {
                 consumer::<T>(
-                    arch, sender, receiver, results, counter, num_jobs, output, store,
+                    arch, sender, receiver, results, counter, failed, num_jobs, output, store,
+                    options,
                 )
             })
             .unwrap();
@@ -388,11 +1175,109 @@ pub(crate) fn several_files<T: 'static + Creator + std::marker::Send>(
             .unwrap();
     }
 
+    // The first error is returned and the other ones are logged
+    let mut res = Ok(());
     for receiver in receivers {
         if let Err(e) = receiver.join().unwrap() {
-            error!("{}", e);
+            if res.is_ok() {
+                res = Err(e);
+            } else if !cancel::is_cancelled() {
+                error!("{}", report(&*e));
+            }
         }
     }
 
-    Ok(())
+    cancel::check()?;
+    res?;
+
+    let failed = std::mem::take(&mut *failed.lock().unwrap());
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(error::Error::PartialOutput {
+            failed,
+            total: filenames.len(),
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_store_dedup() {
+        let tmp_dir = tempfile::Builder::new().prefix("store").tempdir().unwrap();
+        let store_dir = tmp_dir.path().to_str().unwrap();
+        let buf = utils::read_file("./test_data/linux/basic.full");
+        let get_elf = || ElfInfo::new(&buf, "basic.full", Platform::Linux, None, None).unwrap();
+        let path = tmp_dir.path().join(cache::get_path_for_sym(
+            "basic.full",
+            get_elf().get_debug_id(),
+        ));
+        let options = DumpOptions::default();
+
+        store("-", Some(store_dir), get_elf(), &options).unwrap();
+        let expected = fs::read(&path).unwrap();
+
+        // The sym file is in the store so it isn't overwritten
+        fs::write(&path, b"MODULE").unwrap();
+        store("-", Some(store_dir), get_elf(), &options).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"MODULE");
+
+        let options = DumpOptions {
+            force: true,
+            ..Default::default()
+        };
+        store("-", Some(store_dir), get_elf(), &options).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_output_for_arch() {
+        assert_eq!(get_output_for_arch("foo.sym", "arm64"), "foo.arm64.sym");
+        assert_eq!(
+            get_output_for_arch("out/libfoo.dylib.sym", "x86_64"),
+            Path::new("out")
+                .join("libfoo.dylib.x86_64.sym")
+                .to_str()
+                .unwrap()
+        );
+        assert_eq!(get_output_for_arch("foo", "arm64"), "foo.arm64");
+        assert_eq!(get_output_for_arch("-", "arm64"), "-");
+    }
+
+    #[test]
+    fn test_keep_going() {
+        let tmp_dir = tempfile::Builder::new().prefix("store").tempdir().unwrap();
+        let store_dir = tmp_dir.path().to_str().unwrap();
+        let files = [
+            "./test_data/windows/basic64.pdb",
+            "./test_data/windows/missing.pdb",
+            "./test_data/windows/basic-opt64.pdb",
+        ];
+        let config = |keep_going| Config {
+            num_jobs: 2,
+            options: DumpOptions {
+                keep_going,
+                ..Default::default()
+            },
+            ..Config::with_store(store_dir, None)
+        };
+
+        let e = several_files::<PDBInfo>(&config(false), &files).unwrap_err();
+        assert_eq!(error::exit_code(&*e), error::EXIT_BAD_INPUT);
+
+        let e = several_files::<PDBInfo>(&config(true), &files).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "1 of 3 files couldn't be dumped: ./test_data/windows/missing.pdb"
+        );
+        assert_eq!(error::exit_code(&*e), error::EXIT_PARTIAL_OUTPUT);
+        // The other files have been dumped
+        let dumped = fs::read_dir(tmp_dir.path()).unwrap().count();
+        assert_eq!(dumped, 2);
+    }
 }
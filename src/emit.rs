@@ -0,0 +1,339 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::io::Write;
+
+use crate::common::{self, DumpOptions, Dumpable, LambdaNames, RecordProvenance};
+use crate::compat::{self, Compat};
+use crate::names;
+
+/*
+ With --emit only some kinds of records are written: the MODULE and INFO records are always there,
+ the symbols are the FUNC and PUBLIC records, the lines the LINE (and FILE) records, the inlines the
+ INLINE and INLINE_ORIGIN records and the cfi the STACK records.
+ The records are removed when the sym is written (so whatever the dumper) but the dumpers which know the
+ options don't compute the unwind info at all when the cfi isn't emitted.
+ The names of the kept records are rewritten at the same time (see names.rs) and the number of
+ truncated names is logged once the file is written.
+ With --compat the kept records are then reordered and rewritten as the other tool does (see compat.rs).
+*/
+
+/// The kinds of records to write
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Emit {
+    pub symbols: bool,
+    pub lines: bool,
+    pub inlines: bool,
+    pub cfi: bool,
+}
+
+impl Default for Emit {
+    fn default() -> Self {
+        Self {
+            symbols: true,
+            lines: true,
+            inlines: true,
+            cfi: true,
+        }
+    }
+}
+
+impl Emit {
+    /// Get the records to write from a list of kinds (symbols, lines, inlines and/or cfi)
+    pub(crate) fn new<'a, I: IntoIterator<Item = &'a str>>(kinds: I) -> common::Result<Self> {
+        let mut emit = Self {
+            symbols: false,
+            lines: false,
+            inlines: false,
+            cfi: false,
+        };
+        for kind in kinds {
+            match kind {
+                "symbols" => emit.symbols = true,
+                "lines" => emit.lines = true,
+                "inlines" => emit.inlines = true,
+                "cfi" => emit.cfi = true,
+                _ => return Err(format!("Invalid kind of records: {}", kind).into()),
+            }
+        }
+        if (emit.lines || emit.inlines) && !emit.symbols {
+            return Err("The lines and the inlines can't be emitted without the symbols".into());
+        }
+        Ok(emit)
+    }
+
+    pub(crate) fn is_all(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn keeps(&self, record: &str) -> bool {
+        let kind = record.split(' ').next().unwrap_or("");
+        match kind {
+            "MODULE" => true,
+            // The INFO URL records are about the FILE records
+            "INFO" if record.starts_with("INFO URL ") => self.lines,
+            "INFO" => true,
+            "FILE" => self.lines || self.inlines,
+            "INLINE" | "INLINE_ORIGIN" => self.inlines,
+            "FUNC" | "PUBLIC" => self.symbols,
+            "STACK" => self.cfi,
+            _ => self.lines,
+        }
+    }
+}
+
+/// Check if the records must be filtered or rewritten
+pub(crate) fn is_needed(options: &DumpOptions) -> bool {
+    !options.emit.is_all()
+        || options.normalize_names
+        || options.lambda_names != LambdaNames::Full
+        || options.collapse_templates.is_some()
+        || options.max_name_len.is_some()
+        || options.compat.is_some()
+}
+
+/// Write the records of the sym which are mapped to something
+fn filter<'a, W, F>(sym: &'a [u8], mut writer: W, mut map: F) -> common::Result<()>
+where
+    W: Write,
+    F: FnMut(&'a str) -> Option<Cow<'a, str>>,
+{
+    for record in std::str::from_utf8(sym)?.split_inclusive('\n') {
+        if let Some(record) = map(record) {
+            writer.write_all(record.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// A dumpable writing only some kinds of records, with the names rewritten
+pub(crate) struct Filtered<'a, D: Dumpable> {
+    dumpable: &'a D,
+    emit: Emit,
+    normalize_names: bool,
+    lambda_names: LambdaNames,
+    collapse_templates: Option<usize>,
+    max_name_len: Option<usize>,
+    compat: Option<Compat>,
+    // The number of names truncated in the last dump
+    truncated: Cell<usize>,
+}
+
+impl<'a, D: Dumpable> Filtered<'a, D> {
+    pub(crate) fn new(dumpable: &'a D, options: &DumpOptions) -> Self {
+        Self {
+            dumpable,
+            emit: options.emit,
+            normalize_names: options.normalize_names,
+            lambda_names: options.lambda_names,
+            collapse_templates: options.collapse_templates,
+            max_name_len: options.max_name_len,
+            compat: options.compat,
+            truncated: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn get_truncated(&self) -> usize {
+        self.truncated.get()
+    }
+
+    fn rewrite_name<'b>(&self, name: &'b str) -> Cow<'b, str> {
+        let name = if self.normalize_names {
+            names::normalize(name)
+        } else {
+            Cow::Borrowed(name)
+        };
+        let name = match names::rename_lambdas(&name, self.lambda_names) {
+            Cow::Owned(renamed) => Cow::Owned(renamed),
+            Cow::Borrowed(_) => name,
+        };
+        let name = match self.collapse_templates {
+            Some(depth) => match names::collapse_templates(&name, depth) {
+                Cow::Owned(collapsed) => Cow::Owned(collapsed),
+                Cow::Borrowed(_) => name,
+            },
+            None => name,
+        };
+        match self.max_name_len {
+            Some(max_len) if name.len() > max_len => {
+                self.truncated.set(self.truncated.get() + 1);
+                Cow::Owned(names::truncate(&name, max_len).into_owned())
+            }
+            _ => name,
+        }
+    }
+
+    fn rewrite<'b>(&self, record: &'b str) -> Option<Cow<'b, str>> {
+        if !self.emit.keeps(record) {
+            return None;
+        }
+        let line = record.trim_end_matches('\n');
+        match names::split_name(line) {
+            Some((fields, name)) => match self.rewrite_name(name) {
+                Cow::Owned(name) => {
+                    Some(format!("{}{}{}", fields, name, &record[line.len()..]).into())
+                }
+                Cow::Borrowed(_) => Some(record.into()),
+            },
+            None => Some(record.into()),
+        }
+    }
+}
+
+impl<'a, D: Dumpable> Dumpable for Filtered<'a, D> {
+    fn dump<W: Write>(&self, writer: W) -> common::Result<()> {
+        let mut sym = Vec::new();
+        self.dumpable.dump(&mut sym)?;
+        self.truncated.set(0);
+        match self.compat {
+            Some(Compat::Breakpad) => {
+                let mut filtered = Vec::new();
+                filter(&sym, &mut filtered, |record| self.rewrite(record))?;
+                compat::write_breakpad(std::str::from_utf8(&filtered)?, writer)
+            }
+            None => filter(&sym, writer, |record| self.rewrite(record)),
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        self.dumpable.get_name()
+    }
+
+    fn get_debug_id(&self) -> &str {
+        self.dumpable.get_debug_id()
+    }
+
+    fn get_source_files(&self) -> &[String] {
+        self.dumpable.get_source_files()
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        let mut provenance = self.dumpable.get_provenance();
+        provenance.retain(|p| {
+            if p.record.starts_with("STACK") {
+                self.emit.cfi
+            } else {
+                self.emit.symbols
+            }
+        });
+        provenance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const SYM: &str = "MODULE windows x86_64 0123456789ABCDEF1 basic.pdb
+INFO CODE_ID 5E8F1A2B3000 basic.dll
+FILE 0 c:\\basic.cpp
+INFO URL 0 https://example.com/basic.cpp
+FUNC 1000 20 0 main
+1000 10 12 0
+1010 10 13 0
+PUBLIC 2000 0 foo
+STACK CFI INIT 1000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+";
+
+    fn filter_sym(kinds: &[&str]) -> String {
+        let emit = Emit::new(kinds.iter().copied()).unwrap();
+        let mut output = Vec::new();
+        filter(SYM.as_bytes(), &mut output, |r| {
+            emit.keeps(r).then(|| r.into())
+        })
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_emit() {
+        assert!(Emit::new(vec!["symbols", "lines", "inlines", "cfi"])
+            .unwrap()
+            .is_all());
+        assert!(Emit::new(vec!["lines"]).is_err());
+        assert!(Emit::new(vec!["symbols", "stack"]).is_err());
+
+        assert_eq!(
+            filter_sym(&["cfi"]),
+            "MODULE windows x86_64 0123456789ABCDEF1 basic.pdb
+INFO CODE_ID 5E8F1A2B3000 basic.dll
+STACK CFI INIT 1000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+"
+        );
+        assert_eq!(
+            filter_sym(&["symbols"]),
+            "MODULE windows x86_64 0123456789ABCDEF1 basic.pdb
+INFO CODE_ID 5E8F1A2B3000 basic.dll
+FUNC 1000 20 0 main
+PUBLIC 2000 0 foo
+"
+        );
+        assert_eq!(filter_sym(&["symbols", "lines", "cfi"]), SYM);
+    }
+
+    #[test]
+    fn test_collapse_templates() {
+        let sym = "MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so
+FUNC m 1000 20 0 std::vector<int>::push_back(int const&)
+1000 20 12 0
+PUBLIC 2000 0 foo<bar<int>>
+";
+        struct Sym(&'static str);
+        impl Dumpable for Sym {
+            fn dump<W: Write>(&self, mut writer: W) -> common::Result<()> {
+                writer.write_all(self.0.as_bytes())?;
+                Ok(())
+            }
+            fn get_name(&self) -> &str {
+                "libfoo.so"
+            }
+            fn get_debug_id(&self) -> &str {
+                "0123456789ABCDEF1"
+            }
+            fn get_source_files(&self) -> &[String] {
+                &[]
+            }
+            fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+                Vec::new()
+            }
+        }
+
+        let mut options = DumpOptions {
+            collapse_templates: Some(1),
+            ..Default::default()
+        };
+        assert!(is_needed(&options));
+        let mut output = Vec::new();
+        Filtered::new(&Sym(sym), &options)
+            .dump(&mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so
+FUNC m 1000 20 0 std::vector<int>::push_back(int const&)
+1000 20 12 0
+PUBLIC 2000 0 foo<bar<T>>
+"
+        );
+
+        options.max_name_len = Some(16);
+        let sym = Sym(sym);
+        let filtered = Filtered::new(&sym, &options);
+        let mut output = Vec::new();
+        filtered.dump(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so
+FUNC m 1000 20 0 std::vector<i...
+1000 20 12 0
+PUBLIC 2000 0 foo<bar<T>>
+"
+        );
+        assert_eq!(filtered.get_truncated(), 1);
+    }
+}
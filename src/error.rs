@@ -0,0 +1,177 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::error::Error as StdError;
+use std::io;
+use symbolic::debuginfo::pe::PeError;
+use thiserror::Error;
+
+/*
+ An error from the pdb crate only says what is wrong (e.g. "unexpected end of file") and not where,
+ so the errors met while reading a pdb are wrapped with what was read: the stream, the module and
+ the offset of the symbol or the index of the type.
+ They're boxed in common::Result as the other errors and the CLI writes them with their sources
+ (see report).
+ The exit code of the CLI is given by the kind of the error (see exit_code) so the pipelines can tell
+ a bad input, an unsupported format, a partial output or a network failure apart.
+*/
+
+/// The exit codes of the CLI
+pub(crate) const EXIT_ERROR: i32 = 1;
+pub(crate) const EXIT_BAD_INPUT: i32 = 2;
+pub(crate) const EXIT_UNSUPPORTED_FORMAT: i32 = 3;
+pub(crate) const EXIT_PARTIAL_OUTPUT: i32 = 4;
+pub(crate) const EXIT_NETWORK: i32 = 5;
+pub(crate) const EXIT_CANCELLED: i32 = 130;
+
+/// The errors with the context in which they happened
+#[derive(Debug, Error)]
+pub(crate) enum Error {
+    #[error("cannot read the {stream} of the pdb")]
+    PdbStream {
+        stream: &'static str,
+        #[source]
+        source: pdb::Error,
+    },
+    #[error("cannot read the symbol at offset {offset:#x} in the module {module}")]
+    PdbSymbol {
+        module: String,
+        offset: u32,
+        #[source]
+        source: pdb::Error,
+    },
+    #[error("cannot read the type {index:#x}")]
+    PdbType {
+        index: u32,
+        #[source]
+        source: pdb::Error,
+    },
+    #[error("{pdb} has been linked with /DEBUG:FASTLINK: the types and the functions are in the object files so only the available information (publics, lines) is dumped (use mspdbcmf.exe to convert it into a full pdb)")]
+    Fastlink { pdb: String },
+    #[error("the name {name} in the pdb isn't valid UTF-8")]
+    InvalidName { name: String },
+    #[error("cannot parse the PE file {path}")]
+    Pe {
+        path: String,
+        #[source]
+        source: PeError,
+    },
+    #[error("cannot read {path}")]
+    Input {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Unknown file format")]
+    UnknownFormat,
+    #[error("{} of {total} files couldn't be dumped: {}", .failed.len(), .failed.join(", "))]
+    PartialOutput { failed: Vec<String>, total: usize },
+    /// An error from a symbol server, a download, an upload or a S3 store
+    #[error(transparent)]
+    Network(Box<dyn StdError + Send + Sync>),
+}
+
+/// Give the stream which was read to the errors of the pdb crate
+pub(crate) trait PdbContext<T> {
+    fn stream(self, stream: &'static str) -> Result<T, Error>;
+}
+
+impl<T> PdbContext<T> for pdb::Result<T> {
+    fn stream(self, stream: &'static str) -> Result<T, Error> {
+        self.map_err(|source| Error::PdbStream { stream, source })
+    }
+}
+
+/// Get the exit code for an error (or one of its sources)
+pub(crate) fn exit_code(error: &(dyn StdError + 'static)) -> i32 {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if let Some(error) = error.downcast_ref::<Error>() {
+            return match error {
+                Error::UnknownFormat => EXIT_UNSUPPORTED_FORMAT,
+                Error::PartialOutput { .. } => EXIT_PARTIAL_OUTPUT,
+                Error::Network(_) => EXIT_NETWORK,
+                Error::Fastlink { .. } => EXIT_ERROR,
+                _ => EXIT_BAD_INPUT,
+            };
+        }
+        if error.is::<pdb::Error>() || error.is::<goblin::error::Error>() {
+            return EXIT_BAD_INPUT;
+        }
+        source = error.source();
+    }
+    EXIT_ERROR
+}
+
+/// Get the message of an error followed by the ones of its sources
+pub(crate) fn report(error: &(dyn StdError + 'static)) -> String {
+    let mut msg = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        msg.push_str(": ");
+        msg.push_str(&error.to_string());
+        source = error.source();
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::common;
+
+    #[test]
+    fn test_report() {
+        let res: pdb::Result<()> = Err(pdb::Error::UnexpectedEof);
+        let error: common::Result<()> = res.stream("DBI stream").map_err(Into::into);
+        assert_eq!(
+            report(&*error.unwrap_err()),
+            format!(
+                "cannot read the DBI stream of the pdb: {}",
+                pdb::Error::UnexpectedEof
+            )
+        );
+
+        let error = Error::PdbType {
+            index: 0x1234,
+            source: pdb::Error::TypeNotFound(0x1234),
+        };
+        assert_eq!(
+            report(&error),
+            format!(
+                "cannot read the type 0x1234: {}",
+                pdb::Error::TypeNotFound(0x1234)
+            )
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let error: Box<dyn StdError> = Error::UnknownFormat.into();
+        assert_eq!(exit_code(&*error), EXIT_UNSUPPORTED_FORMAT);
+
+        let error = Error::PartialOutput {
+            failed: vec!["foo.pdb".to_string(), "bar.pdb".to_string()],
+            total: 3,
+        };
+        assert_eq!(
+            error.to_string(),
+            "2 of 3 files couldn't be dumped: foo.pdb, bar.pdb"
+        );
+        assert_eq!(exit_code(&error), EXIT_PARTIAL_OUTPUT);
+
+        let error = Error::Network("Cannot download foo: 404".into());
+        assert_eq!(error.to_string(), "Cannot download foo: 404");
+        assert_eq!(exit_code(&error), EXIT_NETWORK);
+
+        let res: pdb::Result<()> = Err(pdb::Error::UnexpectedEof);
+        let error: common::Result<()> = res.stream("DBI stream").map_err(Into::into);
+        assert_eq!(exit_code(&*error.unwrap_err()), EXIT_BAD_INPUT);
+
+        let error: Box<dyn StdError> = "Something went wrong".into();
+        assert_eq!(exit_code(&*error), EXIT_ERROR);
+    }
+}
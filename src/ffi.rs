@@ -0,0 +1,308 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use thiserror::Error;
+
+use crate::cancel::CancelToken;
+use crate::common::{self, DumpOptions};
+use crate::dumper::{self, Config};
+use crate::error;
+
+/*
+ The C interface (declared in include/dump_syms.h): the functions return a status and the message
+ of the last error is kept for the calling thread (see dump_syms_last_error).
+ A panic doesn't cross the interface: it's caught and returned as DUMP_SYMS_PANIC.
+*/
+
+pub const DUMP_SYMS_OK: c_int = 0;
+pub const DUMP_SYMS_INVALID_ARGUMENT: c_int = 1;
+pub const DUMP_SYMS_ERROR: c_int = 2;
+pub const DUMP_SYMS_PANIC: c_int = 3;
+
+/// The options of a dump: the zeroed struct (or NULL) gives the default options
+#[repr(C)]
+pub struct DumpSymsOptions {
+    /// The architecture to dump in a fat binary (NULL for the one of the build)
+    pub arch: *const c_char,
+    /// The number of jobs (0 for 1)
+    pub num_jobs: usize,
+    /// Get the source server information from the pdb
+    pub srcsrv: bool,
+    /// Add the checksums to the FILE records
+    pub file_checksums: bool,
+    /// The token to cancel the dump from another thread (NULL for none)
+    pub cancel: *const DumpSymsCancelToken,
+}
+
+/// A handle on a cancel token: the dumps using it can be cancelled from another thread
+pub struct DumpSymsCancelToken(CancelToken);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: String) {
+    // The message can't contain a NUL
+    let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// A NULL pointer or a string which isn't utf-8
+#[derive(Debug, Error)]
+#[error("{0}")]
+struct InvalidArgument(String);
+
+unsafe fn to_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, InvalidArgument> {
+    if s.is_null() {
+        return Err(InvalidArgument(format!("The {} is NULL", what)));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| InvalidArgument(format!("The {} isn't valid utf-8", what)))
+}
+
+unsafe fn to_config<'a>(options: *const DumpSymsOptions) -> Result<Config<'a>, InvalidArgument> {
+    let mut config = Config::default();
+    if let Some(options) = options.as_ref() {
+        if !options.arch.is_null() {
+            config.arch = to_str(options.arch, "architecture")?;
+        }
+        config.num_jobs = options.num_jobs.max(1);
+        config.options = DumpOptions {
+            srcsrv: options.srcsrv,
+            file_checksums: options.file_checksums,
+            ..Default::default()
+        };
+        if let Some(cancel) = options.cancel.as_ref() {
+            config.options.cancel = cancel.0.clone();
+        }
+    }
+    Ok(config)
+}
+
+/// Call the function and turn its result (or its panic) into a status
+fn call<F: FnOnce() -> common::Result<()>>(f: F) -> c_int {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => DUMP_SYMS_OK,
+        Ok(Err(e)) => {
+            let status = if e.is::<InvalidArgument>() {
+                DUMP_SYMS_INVALID_ARGUMENT
+            } else {
+                DUMP_SYMS_ERROR
+            };
+            set_last_error(error::report(&*e));
+            status
+        }
+        Err(e) => {
+            let msg = e
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| e.downcast_ref::<&str>().copied())
+                .unwrap_or("unknown error");
+            set_last_error(format!("dump_syms panicked: {}", msg));
+            DUMP_SYMS_PANIC
+        }
+    }
+}
+
+/// Dump the file at path and write the records in out_path ("-" for stdout)
+///
+/// # Safety
+///
+/// path and out_path must be NUL-terminated strings and options NULL or a pointer on the options.
+#[no_mangle]
+pub unsafe extern "C" fn dump_syms_dump(
+    path: *const c_char,
+    out_path: *const c_char,
+    options: *const DumpSymsOptions,
+) -> c_int {
+    call(|| {
+        let path = to_str(path, "path")?;
+        let out_path = to_str(out_path, "output path")?;
+        let mut config = to_config(options)?;
+        config.output = out_path;
+        dumper::single_file(&config, path)
+    })
+}
+
+/// Dump the content of the file at path and set out to the records: they must be freed with
+/// dump_syms_free (path is used to get the name of the file and to find the ones next to it, e.g.
+/// the PE of a pdb)
+///
+/// # Safety
+///
+/// buf must point on len bytes, path must be a NUL-terminated string, options NULL or a pointer on
+/// the options and out a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn dump_syms_dump_buffer(
+    buf: *const u8,
+    len: usize,
+    path: *const c_char,
+    options: *const DumpSymsOptions,
+    out: *mut *mut c_char,
+) -> c_int {
+    call(|| {
+        if buf.is_null() || out.is_null() {
+            return Err(InvalidArgument("The buffer or the output is NULL".to_string()).into());
+        }
+        let buf = std::slice::from_raw_parts(buf, len);
+        let path = to_str(path, "path")?;
+        let config = to_config(options)?;
+        let sym = dumper::dump_buf_in_memory(&config, Path::new(path), buf)?;
+        *out = CString::new(sym)?.into_raw();
+        Ok(())
+    })
+}
+
+/// Free the records given by dump_syms_dump_buffer
+///
+/// # Safety
+///
+/// sym must be NULL or a pointer given by dump_syms_dump_buffer which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn dump_syms_free(sym: *mut c_char) {
+    if !sym.is_null() {
+        drop(CString::from_raw(sym));
+    }
+}
+
+/// Create a cancel token: it must be freed with dump_syms_cancel_token_free once the dumps using it
+/// are finished
+#[no_mangle]
+pub extern "C" fn dump_syms_cancel_token_new() -> *mut DumpSymsCancelToken {
+    Box::into_raw(Box::new(DumpSymsCancelToken(CancelToken::new())))
+}
+
+/// Cancel the dumps using the token: they return DUMP_SYMS_ERROR (only the dumps of the pdbs check
+/// it for now)
+///
+/// # Safety
+///
+/// token must be NULL or a pointer given by dump_syms_cancel_token_new which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn dump_syms_cancel(token: *const DumpSymsCancelToken) {
+    if let Some(token) = token.as_ref() {
+        token.0.cancel();
+    }
+}
+
+/// Free a cancel token given by dump_syms_cancel_token_new
+///
+/// # Safety
+///
+/// token must be NULL or a pointer given by dump_syms_cancel_token_new which hasn't been freed and
+/// which isn't used by a dump anymore.
+#[no_mangle]
+pub unsafe extern "C" fn dump_syms_cancel_token_free(token: *mut DumpSymsCancelToken) {
+    if !token.is_null() {
+        drop(Box::from_raw(token));
+    }
+}
+
+/// Get the message of the last error of the thread (NULL if the last call succeeded): it's valid
+/// until the next call
+#[no_mangle]
+pub extern "C" fn dump_syms_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_ffi() {
+        let pdb = CString::new("./test_data/windows/basic64.pdb").unwrap();
+        let data = fs::read("./test_data/windows/basic64.pdb").unwrap();
+
+        let mut sym = ptr::null_mut();
+        let status = unsafe {
+            dump_syms_dump_buffer(
+                data.as_ptr(),
+                data.len(),
+                pdb.as_ptr(),
+                ptr::null(),
+                &mut sym,
+            )
+        };
+        assert_eq!(status, DUMP_SYMS_OK);
+        assert!(dump_syms_last_error().is_null());
+        let from_buf = unsafe { CStr::from_ptr(sym) }.to_str().unwrap().to_string();
+        unsafe { dump_syms_free(sym) };
+        assert!(from_buf.starts_with("MODULE windows x86_64 "));
+        // The dll next to the pdb has been found
+        assert!(from_buf.contains("INFO CODE_ID "));
+
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("basic64.sym");
+        let out_path = CString::new(out.to_str().unwrap()).unwrap();
+        let options = DumpSymsOptions {
+            arch: ptr::null(),
+            num_jobs: 2,
+            srcsrv: false,
+            file_checksums: false,
+            cancel: ptr::null(),
+        };
+        let status = unsafe { dump_syms_dump(pdb.as_ptr(), out_path.as_ptr(), &options) };
+        assert_eq!(status, DUMP_SYMS_OK);
+        assert_eq!(fs::read_to_string(&out).unwrap(), from_buf);
+
+        let status = unsafe { dump_syms_dump(ptr::null(), out_path.as_ptr(), ptr::null()) };
+        assert_eq!(status, DUMP_SYMS_INVALID_ARGUMENT);
+        let error = unsafe { CStr::from_ptr(dump_syms_last_error()) };
+        assert_eq!(error.to_str().unwrap(), "The path is NULL");
+
+        let data = b"not a debug file";
+        let status = unsafe {
+            dump_syms_dump_buffer(
+                data.as_ptr(),
+                data.len(),
+                pdb.as_ptr(),
+                ptr::null(),
+                &mut sym,
+            )
+        };
+        assert_eq!(status, DUMP_SYMS_ERROR);
+        assert!(!dump_syms_last_error().is_null());
+    }
+
+    #[test]
+    fn test_ffi_cancel() {
+        let pdb = CString::new("./test_data/windows/basic64.pdb").unwrap();
+        let data = fs::read("./test_data/windows/basic64.pdb").unwrap();
+        let token = dump_syms_cancel_token_new();
+        let options = DumpSymsOptions {
+            arch: ptr::null(),
+            num_jobs: 1,
+            srcsrv: false,
+            file_checksums: false,
+            cancel: token,
+        };
+        let dump = || {
+            let mut sym = ptr::null_mut();
+            let status = unsafe {
+                dump_syms_dump_buffer(data.as_ptr(), data.len(), pdb.as_ptr(), &options, &mut sym)
+            };
+            unsafe { dump_syms_free(sym) };
+            status
+        };
+
+        assert_eq!(dump(), DUMP_SYMS_OK);
+        unsafe { dump_syms_cancel(token) };
+        assert_eq!(dump(), DUMP_SYMS_ERROR);
+        let error = unsafe { CStr::from_ptr(dump_syms_last_error()) };
+        assert!(error.to_str().unwrap().contains("Interrupted"));
+        unsafe { dump_syms_cancel_token_free(token) };
+    }
+}
@@ -0,0 +1,314 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::{HashMap, HashSet};
+use log::{info, warn};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::common;
+
+/*
+ A store is a directory <store>/<name>/<debug id>/ containing the sym file (maybe compressed) and
+ the json: an entry (the debug id directory) is expired when its newest file is older than the
+ max age of its channel (from its INFO CHANNEL record) or the default max age, unless its debug id
+ is in the keep-list.
+*/
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// The retention rules
+#[derive(Debug, Default)]
+pub(crate) struct Rules {
+    /// The max age of the entries without a channel rule (never expired if None)
+    pub max_age: Option<Duration>,
+    /// The max age of the entries of a channel
+    pub channels: HashMap<String, Duration>,
+    /// The debug ids (uppercase) of the entries to keep whatever their age
+    pub keep: HashSet<String>,
+}
+
+impl Rules {
+    /// Parse a channel rule: CHANNEL=DAYS
+    pub(crate) fn add_channel(&mut self, rule: &str) -> common::Result<()> {
+        let (channel, days) = rule
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid channel rule (CHANNEL=DAYS expected): {}", rule))?;
+        let days = days
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid number of days in the channel rule: {}", rule))?;
+        self.channels
+            .insert(channel.to_string(), days_to_duration(days));
+        Ok(())
+    }
+
+    /// Read a keep-list: one debug id per line (the empty lines and the ones starting with # are ignored)
+    pub(crate) fn add_keep_list(&mut self, path: &Path) -> common::Result<()> {
+        let list = fs::read_to_string(path)?;
+        self.keep.extend(
+            list.lines()
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|l| l.to_uppercase()),
+        );
+        Ok(())
+    }
+
+    fn get_max_age(&self, channel: Option<&str>) -> Option<Duration> {
+        channel
+            .and_then(|c| self.channels.get(c).copied())
+            .or(self.max_age)
+    }
+}
+
+pub(crate) fn days_to_duration(days: u64) -> Duration {
+    Duration::from_secs(days * DAY)
+}
+
+/// What to do with the expired entries
+#[derive(Debug)]
+pub(crate) enum Disposal {
+    Delete,
+    /// Move them in a directory (with the same layout as the store)
+    Archive(PathBuf),
+    /// Only print them
+    DryRun,
+}
+
+/// An entry of the store
+#[derive(Debug)]
+struct Entry {
+    /// The path relative to the store: <name>/<debug id>
+    path: PathBuf,
+    debug_id: String,
+    /// The modification time of the newest file
+    modified: SystemTime,
+    /// The sym file (maybe compressed)
+    sym: Option<PathBuf>,
+}
+
+fn get_entries(store: &Path) -> common::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for name in fs::read_dir(store)? {
+        let name = name?;
+        if !name.file_type()?.is_dir() {
+            continue;
+        }
+        for id in fs::read_dir(name.path())? {
+            let id = id?;
+            if !id.file_type()?.is_dir() {
+                continue;
+            }
+
+            let mut modified = None;
+            let mut sym = None;
+            for file in fs::read_dir(id.path())? {
+                let file = file?;
+                let metadata = file.metadata()?;
+                if !metadata.is_file() {
+                    continue;
+                }
+                let time = metadata.modified()?;
+                if modified < Some(time) {
+                    modified = Some(time);
+                }
+                let file_name = file.file_name();
+                let file_name = file_name.to_string_lossy();
+                if file_name.ends_with(".sym") || file_name.ends_with(".sym.zst") {
+                    sym = Some(file.path());
+                }
+            }
+
+            if let Some(modified) = modified {
+                entries.push(Entry {
+                    path: Path::new(&name.file_name()).join(id.file_name()),
+                    debug_id: id.file_name().to_string_lossy().to_uppercase(),
+                    modified,
+                    sym,
+                });
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(entries)
+}
+
+/// Get the channel in the INFO CHANNEL record (the INFO records follow the MODULE one)
+fn get_channel(sym: &Path, dict: Option<&[u8]>) -> common::Result<Option<String>> {
+    let file = File::open(sym)?;
+    let reader: Box<dyn Read> = if sym.extension() == Some(OsStr::new("zst")) {
+        let dict = dict.unwrap_or(&[]);
+        Box::new(zstd::stream::read::Decoder::with_dictionary(
+            BufReader::new(file),
+            dict,
+        )?)
+    } else {
+        Box::new(file)
+    };
+
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.starts_with("MODULE ") {
+            continue;
+        }
+        match line.strip_prefix("INFO ") {
+            Some(info) => {
+                if let Some(channel) = info.strip_prefix("CHANNEL ") {
+                    return Ok(Some(channel.trim().to_string()));
+                }
+            }
+            None => break,
+        }
+    }
+
+    Ok(None)
+}
+
+fn is_expired(entry: &Entry, rules: &Rules, dict: Option<&[u8]>, now: SystemTime) -> bool {
+    if rules.keep.contains(&entry.debug_id) {
+        return false;
+    }
+
+    // Only read the sym file when there are some channel rules
+    let channel = match entry.sym.as_ref() {
+        Some(sym) if !rules.channels.is_empty() => get_channel(sym, dict).unwrap_or_else(|e| {
+            warn!("Cannot read the channel in {}: {}", sym.display(), e);
+            None
+        }),
+        _ => None,
+    };
+
+    match rules.get_max_age(channel.as_deref()) {
+        Some(max_age) => matches!(now.duration_since(entry.modified), Ok(age) if age > max_age),
+        None => false,
+    }
+}
+
+fn dispose(store: &Path, path: &Path, disposal: &Disposal) -> common::Result<()> {
+    let from = store.join(path);
+    match disposal {
+        Disposal::Delete => fs::remove_dir_all(&from)?,
+        Disposal::Archive(archive) => {
+            let to = archive.join(path);
+            fs::create_dir_all(to.parent().unwrap())?;
+            fs::rename(&from, &to)?;
+        }
+        Disposal::DryRun => return Ok(()),
+    }
+
+    // Remove the name directory once its last entry is gone
+    let parent = from.parent().unwrap();
+    if fs::read_dir(parent)?.next().is_none() {
+        fs::remove_dir(parent)?;
+    }
+    Ok(())
+}
+
+/// Remove (or archive) the expired entries of the store and return their paths
+pub(crate) fn collect_garbage(
+    store: &Path,
+    rules: &Rules,
+    disposal: &Disposal,
+    dict: Option<&[u8]>,
+    now: SystemTime,
+) -> common::Result<Vec<PathBuf>> {
+    let entries = get_entries(store)?;
+    let total = entries.len();
+    let mut expired = Vec::new();
+
+    for entry in entries {
+        if is_expired(&entry, rules, dict, now) {
+            dispose(store, &entry.path, disposal)?;
+            expired.push(entry.path);
+        }
+    }
+    info!(
+        "{} expired entries out of {} in {}",
+        expired.len(),
+        total,
+        store.display()
+    );
+
+    Ok(expired)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tempfile::Builder;
+
+    fn add_entry(store: &Path, name: &str, id: &str, channel: Option<&str>) {
+        let dir = store.join(name).join(id);
+        fs::create_dir_all(&dir).unwrap();
+        let mut sym = format!("MODULE Linux x86_64 {} {}\nINFO CODE_ID 1234\n", id, name);
+        if let Some(channel) = channel {
+            sym.push_str(&format!("INFO CHANNEL {}\n", channel));
+        }
+        sym.push_str("FILE 0 foo.c\n");
+        fs::write(dir.join(format!("{}.sym", name)), sym).unwrap();
+    }
+
+    #[test]
+    fn test_gc() {
+        let tmp_dir = Builder::new().prefix("gc").tempdir().unwrap();
+        let store = tmp_dir.path().join("store");
+        add_entry(&store, "foo", "AAAA1", None);
+        add_entry(&store, "foo", "BBBB1", Some("release"));
+        add_entry(&store, "bar", "CCCC1", Some("nightly"));
+        add_entry(&store, "baz", "DDDD1", Some("nightly"));
+
+        let mut rules = Rules {
+            max_age: Some(days_to_duration(30)),
+            ..Default::default()
+        };
+        rules.add_channel("release=365").unwrap();
+        rules.add_channel("nightly=7").unwrap();
+        assert!(rules.add_channel("beta").is_err());
+        let keep_list = tmp_dir.path().join("keep");
+        fs::write(&keep_list, "# active releases\n\ndddd1\n").unwrap();
+        rules.add_keep_list(&keep_list).unwrap();
+
+        let in_days = |days| SystemTime::now() + days_to_duration(days);
+
+        // Nothing is expired yet
+        let expired = collect_garbage(&store, &rules, &Disposal::DryRun, None, in_days(1)).unwrap();
+        assert!(expired.is_empty());
+
+        // The nightly is expired but the dry run doesn't touch it
+        let expired =
+            collect_garbage(&store, &rules, &Disposal::DryRun, None, in_days(10)).unwrap();
+        assert_eq!(expired, vec![Path::new("bar").join("CCCC1")]);
+        assert!(store.join("bar").join("CCCC1").is_dir());
+
+        let archive = tmp_dir.path().join("archive");
+        let expired = collect_garbage(
+            &store,
+            &rules,
+            &Disposal::Archive(archive.clone()),
+            None,
+            in_days(10),
+        )
+        .unwrap();
+        assert_eq!(expired, vec![Path::new("bar").join("CCCC1")]);
+        assert!(archive.join("bar").join("CCCC1").join("bar.sym").is_file());
+        assert!(!store.join("bar").exists());
+
+        // The entry without channel is expired after 30 days, the release after a year
+        // and the kept nightly never
+        let expired =
+            collect_garbage(&store, &rules, &Disposal::Delete, None, in_days(40)).unwrap();
+        assert_eq!(expired, vec![Path::new("foo").join("AAAA1")]);
+        let expired =
+            collect_garbage(&store, &rules, &Disposal::Delete, None, in_days(400)).unwrap();
+        assert_eq!(expired, vec![Path::new("foo").join("BBBB1")]);
+        assert!(!store.join("foo").exists());
+        assert!(store.join("baz").join("DDDD1").is_dir());
+    }
+}
@@ -0,0 +1,104 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use failure::Fail;
+use symbolic::debuginfo::{Archive, Object};
+
+use crate::common;
+use crate::mac::macho;
+
+/*
+ Only the headers are parsed to get the ids (the debug id is in the CodeView record of a PE, in the PDB
+ info stream and the DBI header of a PDB, in the build id of an ELF and in the LC_UUID of a Mach-O):
+ scripts can compute the store paths (<debug file>/<debug id>/<debug file>.sym) without a full dump.
+*/
+
+/// The ids of an object
+#[derive(Debug, PartialEq)]
+pub(crate) struct Ids {
+    pub debug_id: String,
+    pub code_id: Option<String>,
+}
+
+/// Get the objects of a file (the one with the given arch in a fat Mach-O)
+pub(crate) fn get_objects<'d>(buf: &'d [u8], arch: &str) -> common::Result<Vec<Object<'d>>> {
+    let archive = Archive::parse(buf).map_err(|e| e.compat())?;
+    let mut objects = archive
+        .objects()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.compat())?;
+    if objects.len() > 1 && arch != macho::ALL_ARCHS {
+        objects.retain(|o| o.arch().name() == arch);
+    }
+    if objects.is_empty() {
+        return Err(format!("No object with the architecture {}", arch).into());
+    }
+    Ok(objects)
+}
+
+/// Get the ids of the objects of a file
+pub(crate) fn get_ids(buf: &[u8], arch: &str) -> common::Result<Vec<Ids>> {
+    Ok(get_objects(buf, arch)?
+        .iter()
+        .map(|o| Ids {
+            debug_id: o.debug_id().breakpad().to_string(),
+            code_id: o.code_id().map(|c| c.as_str().to_uppercase()),
+        })
+        .collect())
+}
+
+/// Print the debug id and/or the code id of the objects of a file (one line per object)
+pub(crate) fn print_ids(
+    buf: &[u8],
+    arch: &str,
+    debug_id: bool,
+    code_id: bool,
+) -> common::Result<()> {
+    for ids in get_ids(buf, arch)? {
+        let mut fields = Vec::new();
+        if debug_id {
+            fields.push(ids.debug_id.as_str());
+        }
+        if code_id {
+            fields.push(ids.code_id.as_deref().unwrap_or(""));
+        }
+        println!("{}", fields.join(" "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn get_ids_for(file: &str, arch: &str) -> Vec<Ids> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data")
+            .join(file);
+        get_ids(&fs::read(path).unwrap(), arch).unwrap()
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(
+            get_ids_for("linux/basic.full", "x86_64"),
+            vec![Ids {
+                debug_id: "20AD60B0B4C68177552708AA192E77390".to_string(),
+                code_id: Some("B060AD20C6B47781552708AA192E7739FAC7C84A".to_string()),
+            }]
+        );
+        assert_eq!(
+            get_ids_for("windows/basic64.pdb", "x86_64"),
+            vec![Ids {
+                debug_id: "D09EA7D6D2C24C1EBFFE78B0C866BB7F2".to_string(),
+                code_id: None,
+            }]
+        );
+        assert!(get_ids(b"not an object", "x86_64").is_err());
+    }
+}
@@ -0,0 +1,185 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::time::{Duration, UNIX_EPOCH};
+use symbolic::debuginfo::dwarf::Dwarf;
+use symbolic::debuginfo::Object;
+
+use crate::common;
+use crate::ids;
+use crate::utils;
+
+/*
+ The information about a module which are useful to know why it can't be symbolicated: its ids (which
+ must match the ones in the minidump), what it contains (symbols, line info, unwind info) and how it
+ has been built (the linker version and the timestamp of a PE, the .comment section of an ELF).
+*/
+
+/// The information about an object
+#[derive(Debug, PartialEq)]
+pub(crate) struct Info {
+    pub format: String,
+    pub arch: String,
+    pub debug_file: String,
+    pub debug_id: String,
+    pub code_id: Option<String>,
+    pub linker: Option<String>,
+    pub timestamp: Option<u32>,
+    pub symbols: bool,
+    pub lines: bool,
+    pub cfi: bool,
+    pub sources: bool,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    let bytes = buf.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Get the timestamp in the COFF header of a PE and the linker version in its optional header
+fn get_pe_info(buf: &[u8]) -> Option<(u32, String)> {
+    let pe = read_u32(buf, 0x3c)? as usize;
+    if buf.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+        return None;
+    }
+    let coff = pe + 4;
+    let timestamp = read_u32(buf, coff + 4)?;
+    let optional = coff + 20;
+    read_u16(buf, optional)?;
+    let linker = format!("{}.{}", buf.get(optional + 2)?, buf.get(optional + 3)?);
+    Some((timestamp, linker))
+}
+
+/// Get the strings in the .comment section of an ELF (the versions of the compilers and the linker)
+fn get_elf_comment(object: &Object) -> Option<String> {
+    let section = match object {
+        Object::Elf(elf) => elf.raw_section("comment")?,
+        _ => return None,
+    };
+    let comments: Vec<_> = section
+        .data
+        .split(|c| *c == 0)
+        .filter(|s| !s.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect();
+    if comments.is_empty() {
+        None
+    } else {
+        Some(comments.join("; "))
+    }
+}
+
+/// Get the information about the objects of a file
+pub(crate) fn get_info(buf: &[u8], file_name: &str, arch: &str) -> common::Result<Vec<Info>> {
+    let pe_info = get_pe_info(buf);
+    Ok(ids::get_objects(buf, arch)?
+        .iter()
+        .map(|o| {
+            // The path of the pdb given to the linker is in a PE
+            let debug_file = match o {
+                Object::Pe(pe) => pe
+                    .debug_file_name()
+                    .map(|f| f.rsplit(['/', '\\']).next().unwrap_or(&f).to_string()),
+                _ => None,
+            };
+            let (timestamp, linker) = match (o, pe_info.as_ref()) {
+                (Object::Pe(_), Some((timestamp, linker))) => {
+                    (Some(*timestamp), Some(linker.clone()))
+                }
+                _ => (None, get_elf_comment(o)),
+            };
+            Info {
+                format: o.file_format().to_string(),
+                arch: o.arch().name().to_string(),
+                debug_file: debug_file.unwrap_or_else(|| file_name.to_string()),
+                debug_id: o.debug_id().breakpad().to_string(),
+                code_id: o.code_id().map(|c| c.as_str().to_uppercase()),
+                linker,
+                timestamp,
+                symbols: o.has_symbols(),
+                lines: o.has_debug_info(),
+                cfi: o.has_unwind_info(),
+                sources: o.has_sources(),
+            }
+        })
+        .collect())
+}
+
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "format: {}", self.format)?;
+        writeln!(f, "arch: {}", self.arch)?;
+        writeln!(f, "debug file: {}", self.debug_file)?;
+        writeln!(f, "debug id: {}", self.debug_id)?;
+        writeln!(f, "code id: {}", self.code_id.as_deref().unwrap_or("none"))?;
+        writeln!(f, "linker: {}", self.linker.as_deref().unwrap_or("unknown"))?;
+        if let Some(timestamp) = self.timestamp {
+            let time = UNIX_EPOCH + Duration::from_secs(timestamp.into());
+            writeln!(
+                f,
+                "timestamp: {:08X} ({})",
+                timestamp,
+                utils::get_amz_date(time).1
+            )?;
+        }
+        writeln!(f, "symbols: {}", yes_no(self.symbols))?;
+        writeln!(f, "line info: {}", yes_no(self.lines))?;
+        writeln!(f, "cfi: {}", yes_no(self.cfi))?;
+        write!(f, "sources: {}", yes_no(self.sources))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn get_info_for(file: &str) -> Info {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data")
+            .join(file);
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        let mut info = get_info(&fs::read(path).unwrap(), &name, "all").unwrap();
+        assert_eq!(info.len(), 1);
+        info.pop().unwrap()
+    }
+
+    #[test]
+    fn test_info() {
+        let info = get_info_for("windows/basic64.dll");
+        assert_eq!(info.format, "pe");
+        assert_eq!(info.arch, "x86_64");
+        assert_eq!(info.debug_file, "basic64.pdb");
+        assert_eq!(info.debug_id, "D09EA7D6D2C24C1EBFFE78B0C866BB7F2");
+        assert_eq!(info.code_id.as_deref(), Some("5DDC1E998C000"));
+        assert_eq!(info.timestamp, Some(0x5ddc_1e99));
+        assert!(info.linker.is_some());
+        assert!(info.cfi);
+        assert!(info.to_string().contains("timestamp: 5DDC1E99 (20191125T"));
+
+        let info = get_info_for("linux/basic.full");
+        assert_eq!(info.format, "elf");
+        assert_eq!(info.debug_file, "basic.full");
+        assert!(info.lines);
+        assert!(info.linker.unwrap().contains("GCC"));
+        assert_eq!(info.timestamp, None);
+    }
+}
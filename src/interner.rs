@@ -0,0 +1,52 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashSet;
+use std::sync::Arc;
+
+/*
+ The same strings are computed again and again while dumping a big module: the paths of the files
+ (a lot of raw paths are mapped on the same one) and the names of the types in the signatures.
+ The interner keeps one copy of each string and gives a shared reference on it, so the identical
+ strings are only allocated once; they're converted into owned strings only when the final output
+ is built.
+*/
+
+/// A set of strings where the identical ones share the same storage
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// Get the shared copy of the string (added if it isn't already there)
+    pub(crate) fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(s);
+        self.strings.insert(interned.clone());
+        interned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = Interner::default();
+        let foo = interner.intern("/src/foo.cpp");
+        let bar = interner.intern("/src/bar.cpp");
+        let foo_again = interner.intern(&String::from("/src/foo.cpp"));
+
+        assert!(Arc::ptr_eq(&foo, &foo_again));
+        assert!(!Arc::ptr_eq(&foo, &bar));
+        assert_eq!(&*foo, "/src/foo.cpp");
+        assert_eq!(interner.strings.len(), 2);
+    }
+}
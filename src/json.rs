@@ -0,0 +1,316 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::Path;
+
+use crate::common::{self, DumpOptions, Dumpable, OutputFormat, RecordProvenance};
+use crate::csv;
+use crate::model::{Function, LineRecord, Module, Public, SourceFile, StackCfi};
+use crate::visit::{self, FunctionHeader, ModuleHeader, SymbolVisitor};
+
+/*
+ The json output is the module of the data model (see model.rs) filled by a visit of the dumped
+ module: the pdbs, the ELFs and the Mach-Os give their items right from their collectors and the
+ other files (or the modules whose records are filtered) are dumped and their records parsed back.
+ A sym file can be parsed in a module borrowing its text too.
+*/
+
+impl<'a> SymbolVisitor<'a> for Module<'a> {
+    fn module(&mut self, module: ModuleHeader<'a>) -> common::Result<()> {
+        self.os = Cow::Borrowed(module.os);
+        self.arch = Cow::Borrowed(module.arch);
+        self.debug_id = Cow::Borrowed(module.debug_id);
+        self.name = Cow::Borrowed(module.name);
+        Ok(())
+    }
+
+    fn info(&mut self, info: Cow<'a, str>) -> common::Result<()> {
+        self.info.push(info);
+        Ok(())
+    }
+
+    fn file(&mut self, file: SourceFile<'a>) -> common::Result<()> {
+        self.files.push(file);
+        Ok(())
+    }
+
+    fn function(&mut self, function: FunctionHeader<'a>) -> common::Result<()> {
+        self.functions.push(Function {
+            address: function.address,
+            size: function.size,
+            parameter_size: function.parameter_size,
+            name: Cow::Borrowed(function.name),
+            multiple: function.multiple,
+            lines: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn line(&mut self, line: LineRecord) -> common::Result<()> {
+        // The visit checks that a LINE record follows a FUNC one
+        if let Some(function) = self.functions.last_mut() {
+            function.lines.push(line);
+        }
+        Ok(())
+    }
+
+    fn public(&mut self, public: Public<'a>) -> common::Result<()> {
+        self.publics.push(public);
+        Ok(())
+    }
+
+    fn cfi(&mut self, record: StackCfi<'a>) -> common::Result<()> {
+        self.stack.push(record);
+        Ok(())
+    }
+}
+
+pub(crate) fn parse(sym: &str) -> common::Result<Module<'_>> {
+    let mut module = Module::default();
+    visit::visit(sym, &mut module)?;
+    Ok(module)
+}
+
+/// Build a module which owns the items given by a collector
+#[derive(Default)]
+struct ModuleBuilder(Module<'static>);
+
+impl<'a> SymbolVisitor<'a> for ModuleBuilder {
+    fn module(&mut self, module: ModuleHeader<'a>) -> common::Result<()> {
+        self.0.os = Cow::Owned(module.os.to_string());
+        self.0.arch = Cow::Owned(module.arch.to_string());
+        self.0.debug_id = Cow::Owned(module.debug_id.to_string());
+        self.0.name = Cow::Owned(module.name.to_string());
+        Ok(())
+    }
+
+    fn info(&mut self, info: Cow<'a, str>) -> common::Result<()> {
+        self.0.info.push(Cow::Owned(info.into_owned()));
+        Ok(())
+    }
+
+    fn file(&mut self, file: SourceFile<'a>) -> common::Result<()> {
+        self.0.files.push(file.into_owned());
+        Ok(())
+    }
+
+    fn function(&mut self, function: FunctionHeader<'a>) -> common::Result<()> {
+        self.0.functions.push(Function {
+            address: function.address,
+            size: function.size,
+            parameter_size: function.parameter_size,
+            name: Cow::Owned(function.name.to_string()),
+            multiple: function.multiple,
+            lines: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn line(&mut self, line: LineRecord) -> common::Result<()> {
+        if let Some(function) = self.0.functions.last_mut() {
+            function.lines.push(line);
+        }
+        Ok(())
+    }
+
+    fn public(&mut self, public: Public<'a>) -> common::Result<()> {
+        self.0.publics.push(public.into_owned());
+        Ok(())
+    }
+
+    fn cfi(&mut self, record: StackCfi<'a>) -> common::Result<()> {
+        self.0.stack.push(record.into_owned());
+        Ok(())
+    }
+}
+
+impl Module<'static> {
+    /// Dump the file at path and get its module: the one of the architecture of the build for a
+    /// fat binary
+    pub fn from_file<P: AsRef<Path>>(path: P) -> common::Result<Self> {
+        let mut builder = ModuleBuilder::default();
+        visit::visit_file(path, &mut builder)?;
+        Ok(builder.0)
+    }
+
+    pub(crate) fn from_dumpable<D: Dumpable>(dumpable: &D) -> common::Result<Self> {
+        let mut builder = ModuleBuilder::default();
+        dumpable.visit(&mut builder)?;
+        Ok(builder.0)
+    }
+}
+
+/// Write the module in json
+pub(crate) fn write_json<W: Write>(module: &Module, writer: W) -> common::Result<()> {
+    serde_json::to_writer_pretty(writer, module)?;
+    Ok(())
+}
+
+/// A module which has been serialized: its records are kept to be written in several formats
+pub(crate) struct Dumped<'a, D: Dumpable> {
+    pub(crate) dumpable: &'a D,
+    pub(crate) sym: Vec<u8>,
+}
+
+impl<'a, D: Dumpable> Dumped<'a, D> {
+    pub(crate) fn new(dumpable: &'a D) -> common::Result<Self> {
+        let mut sym = Vec::new();
+        dumpable.dump(&mut sym)?;
+        Ok(Self { dumpable, sym })
+    }
+
+    /// Write the records in json or in csv
+    pub(crate) fn write_records<W: Write>(
+        &self,
+        format: OutputFormat,
+        writer: W,
+        options: &DumpOptions,
+    ) -> common::Result<()> {
+        // The collector gives the items itself: the records aren't parsed back
+        let module = Module::from_dumpable(self.dumpable)?;
+        match format {
+            OutputFormat::Csv => csv::write_csv(&module, writer, options.csv_lines),
+            _ => write_json(&module, writer),
+        }
+    }
+}
+
+impl<'a, D: Dumpable> Dumpable for Dumped<'a, D> {
+    fn dump<W: Write>(&self, mut writer: W) -> common::Result<()> {
+        writer.write_all(&self.sym)?;
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        self.dumpable.get_name()
+    }
+
+    fn get_debug_id(&self) -> &str {
+        self.dumpable.get_debug_id()
+    }
+
+    fn get_source_files(&self) -> &[String] {
+        self.dumpable.get_source_files()
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        self.dumpable.get_provenance()
+    }
+
+    fn visit<V: for<'b> SymbolVisitor<'b>>(&self, visitor: &mut V) -> common::Result<()> {
+        self.dumpable.visit(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::model::CfiKind;
+
+    #[test]
+    fn test_json() {
+        let sym = "MODULE windows x86_64 0123456789ABCDEF1 basic 64.pdb
+INFO CODE_ID 5D9C3D2F1000 basic64.exe
+FILE 0 c:\\my dir\\basic.cpp md5:0123abcd
+FILE 1 c:\\other dir\\util.cpp
+FUNC m 1000 20 0 main(int, char**)
+1000 10 12 0
+1010 10 13 1 5
+PUBLIC 2000 4 _foo@4
+STACK WIN 4 1000 20 0 0 0 0 0 0 1
+";
+        let module = parse(sym).unwrap();
+        assert_eq!(module.name, "basic 64.pdb");
+        assert_eq!(module.info, vec!["CODE_ID 5D9C3D2F1000 basic64.exe"]);
+        assert_eq!(
+            module.files,
+            vec![
+                SourceFile {
+                    id: 0,
+                    name: "c:\\my dir\\basic.cpp".into(),
+                    checksum: Some("md5:0123abcd".into()),
+                },
+                SourceFile {
+                    id: 1,
+                    name: "c:\\other dir\\util.cpp".into(),
+                    checksum: None,
+                },
+            ]
+        );
+        assert_eq!(
+            module.functions,
+            vec![Function {
+                address: 0x1000,
+                size: 0x20,
+                parameter_size: 0,
+                name: "main(int, char**)".into(),
+                multiple: true,
+                lines: vec![
+                    LineRecord {
+                        address: 0x1000,
+                        size: 0x10,
+                        line: 12,
+                        file: 0,
+                        column: None,
+                    },
+                    LineRecord {
+                        address: 0x1010,
+                        size: 0x10,
+                        line: 13,
+                        file: 1,
+                        column: Some(5),
+                    },
+                ],
+            }]
+        );
+        assert_eq!(
+            module.publics,
+            vec![Public {
+                address: 0x2000,
+                parameter_size: 4,
+                name: "_foo@4".into(),
+                multiple: false,
+            }]
+        );
+        assert_eq!(
+            module.stack,
+            vec![StackCfi {
+                kind: CfiKind::Win,
+                frame_type: Some(4),
+                address: 0x1000,
+                size: Some(0x20),
+                rules: "0 0 0 0 0 0 1".into(),
+            }]
+        );
+
+        let mut out = Vec::new();
+        write_json(&module, &mut out).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value["functions"][0]["lines"][1]["line"], 13);
+        assert_eq!(value["functions"][0]["lines"][1]["column"], 5);
+        assert!(value["functions"][0]["lines"][0].get("column").is_none());
+        assert_eq!(value["stack"][0]["kind"], "win");
+
+        // The json can be read back in the data model
+        let read: Module = serde_json::from_slice(&out).unwrap();
+        assert_eq!(read, module);
+
+        assert!(parse("1000 10 12 0").is_err());
+    }
+
+    #[test]
+    fn test_module_from_file() {
+        // The module filled by the collector is the one of its records
+        let path = "./test_data/windows/basic64.pdb";
+        let module = Module::from_file(path).unwrap();
+        let sym = crate::dumper::dump_in_memory(&Default::default(), path).unwrap();
+        assert_eq!(module, parse(&sym).unwrap());
+        assert_eq!(module.name, "basic64.pdb");
+        assert!(module.functions.iter().any(|f| !f.lines.is_empty()));
+    }
+}
@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/*
+ The crate is a library: the binary only runs the command line (see cli.rs) and the C functions
+ in ffi.rs can be called from a dll (or a shared library) to dump a file without spawning a process.
+ Without the default features, the library is the core which parses the debug files and writes
+ the records: it doesn't use the network nor any C code.
+ It can't be built for wasm32 yet: symbolic-common maps the files with memmap, which has no wasm
+ backend.
+*/
+
+// Some functions are only used by the command line
+#![cfg_attr(not(feature = "cli"), allow(dead_code))]
+
+#[cfg(feature = "cli")]
+mod action;
+mod archive;
+mod cache;
+mod cancel;
+mod check;
+mod checksums;
+#[cfg(feature = "cli")]
+mod cli;
+mod common;
+mod compat;
+mod compress;
+mod csv;
+mod debug_id;
+mod diagnostics;
+mod dotnet;
+#[cfg(feature = "http")]
+mod download;
+mod dumper;
+mod emit;
+mod error;
+#[cfg(feature = "http")]
+mod fetch;
+pub mod ffi;
+#[cfg(feature = "cli")]
+mod gc;
+mod ids;
+mod info;
+mod interner;
+mod json;
+mod line;
+mod linux;
+mod mac;
+mod mapping;
+#[cfg(feature = "cli")]
+mod minidump;
+mod model;
+mod names;
+#[cfg(feature = "python")]
+mod python;
+mod record;
+#[cfg(feature = "cli")]
+mod report;
+#[cfg(feature = "http")]
+mod s3;
+mod sandbox;
+#[cfg(feature = "cli")]
+mod serve;
+mod source_bundle;
+mod stats;
+#[cfg(feature = "cli")]
+mod symbolicate;
+mod template;
+#[cfg(feature = "cli")]
+mod upload;
+mod utils;
+mod visit;
+mod wasm;
+#[cfg(feature = "cli")]
+mod watch;
+mod windows;
+
+pub use crate::cancel::CancelToken;
+#[cfg(feature = "cli")]
+pub use crate::cli::run;
+pub use crate::model::{CfiKind, Function, LineRecord, Module, Public, SourceFile, StackCfi};
+pub use crate::visit::{visit_file, FunctionHeader, ModuleHeader, SymbolVisitor};
+
+#[macro_use]
+extern crate lazy_static;
@@ -5,6 +5,11 @@
 
 use std::fmt::{self, Debug, Display, Formatter};
 
+use crate::common;
+use crate::model::LineRecord;
+use crate::record::RecordWriter;
+use crate::visit::SymbolVisitor;
+
 #[derive(Clone, Default)]
 pub(crate) struct Line {
     // rva stands for relative virtual address
@@ -16,14 +21,16 @@ pub(crate) struct Line {
     pub(crate) num: u32,
     // file identifier where this line is
     pub(crate) file_id: u32,
+    // column number (0 when unknown): written after the file identifier
+    pub(crate) col: u32,
 }
 
 impl Debug for Line {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Line {{ rva: {:x}, len: {:x}, line: {}, file_id: {} }}",
-            self.rva, self.len, self.num, self.file_id
+            "Line {{ rva: {:x}, len: {:x}, line: {}, file_id: {}, col: {} }}",
+            self.rva, self.len, self.num, self.file_id, self.col
         )
     }
 }
@@ -41,18 +48,48 @@ pub struct Lines {
 
 impl Display for Lines {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let mut writer = RecordWriter::new();
+        self.write_records(&mut writer, f)?;
+        writer.flush(f)
+    }
+}
+
+impl Lines {
+    /// Write the LINE records: they're sorted once finalized (whatever the order of the
+    /// line subsections in the pdb) and they're written right after their FUNC record
+    pub(crate) fn write_records(&self, w: &mut RecordWriter, f: &mut Formatter) -> fmt::Result {
+        debug_assert!(self.lines.windows(2).all(|w| w[0].rva <= w[1].rva));
         for line in self.lines.iter() {
-            writeln!(
-                f,
-                "{:x} {:x} {} {}",
-                line.rva, line.len, line.num, line.file_id
-            )?;
+            w.hex(line.rva.into())
+                .space()
+                .hex(line.len.into())
+                .space()
+                .dec(line.num.into())
+                .space()
+                .dec(line.file_id.into());
+            if line.col != 0 {
+                w.space().dec(line.col.into());
+            }
+            w.end();
+            w.flush_if_full(f)?;
+        }
+        Ok(())
+    }
+
+    /// Give the LINE records to the visitor (as write_records writes them)
+    pub(crate) fn visit<'a, V: SymbolVisitor<'a>>(&self, visitor: &mut V) -> common::Result<()> {
+        for line in self.lines.iter() {
+            visitor.line(LineRecord {
+                address: line.rva.into(),
+                size: line.len.into(),
+                line: line.num,
+                file: line.file_id,
+                column: if line.col != 0 { Some(line.col) } else { None },
+            })?;
         }
         Ok(())
     }
-}
 
-impl Lines {
     pub(crate) fn new() -> Self {
         Self {
             lines: Vec::new(),
@@ -62,11 +99,16 @@ impl Lines {
     }
 
     pub(crate) fn add_line(&mut self, rva: u32, num: u32, file_id: u32) {
+        self.add_line_with_column(rva, num, 0, file_id);
+    }
+
+    pub(crate) fn add_line_with_column(&mut self, rva: u32, num: u32, col: u32, file_id: u32) {
         self.lines.push(Line {
             rva,
             num,
             len: 0,
             file_id,
+            col,
         });
 
         // There are no guarantee that the rva are sorted
@@ -123,6 +165,13 @@ impl Lines {
         }
     }
 
+    /// Change the file ids: remapping[old_id] == new_id
+    pub(crate) fn remap_files(&mut self, remapping: &[u32]) {
+        for line in self.lines.iter_mut() {
+            line.file_id = remapping[line.file_id as usize];
+        }
+    }
+
     pub(crate) fn retain(&self, rva: u32, len: u32) -> Option<Lines> {
         // A symbol space can be split in several chunks
         // so we need to retain the lines which are in the different chunks
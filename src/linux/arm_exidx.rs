@@ -0,0 +1,477 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use goblin::elf::header::EM_ARM;
+use goblin::elf::section_header::SHF_EXECINSTR;
+use goblin::elf::Elf;
+use hashbrown::HashSet;
+use log::{info, warn};
+use std::fmt::{self, Display, Formatter, Write};
+
+use crate::common;
+
+/*
+ The 32-bit ARM binaries (e.g. the Android libraries) have their unwind info in the ARM EHABI tables
+ rather than in .eh_frame: .ARM.exidx is sorted by function address and each entry contains either
+ some unwind opcodes (compact model) or a reference to the opcodes in .ARM.extab.
+ As breakpad does, the opcodes are interpreted to get the rules at the end of the prologue and they're
+ used for the whole function (in a STACK CFI INIT record), which is enough to unwind after a call.
+ The tables aren't used when there's a .debug_frame and the functions having some CFI are skipped.
+*/
+
+const EXIDX_CANTUNWIND: u32 = 1;
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+const REG_NAMES: [&str; 16] = [
+    "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "r11", "r12", "sp", "lr",
+    "pc",
+];
+
+/// A postfix expression
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    /// reg + offset
+    Reg(&'static str, i64),
+    /// The value at reg + offset
+    Deref(&'static str, i64),
+    Postfix(String),
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Reg(reg, offset) => write!(f, "{} {} +", reg, offset),
+            Self::Deref(reg, offset) => write!(f, "{} {} + ^", reg, offset),
+            Self::Postfix(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl Expr {
+    fn add(self, value: i64) -> Self {
+        match self {
+            Self::Reg(reg, offset) => Self::Reg(reg, offset + value),
+            _ => Self::Postfix(format!("{} {} +", self, value)),
+        }
+    }
+
+    fn deref(&self) -> Self {
+        match self {
+            Self::Reg(reg, offset) => Self::Deref(reg, *offset),
+            _ => Self::Postfix(format!("{} ^", self)),
+        }
+    }
+}
+
+/// The rules to recover the registers of the caller
+#[derive(Debug)]
+struct Rules {
+    cfa: Expr,
+    /// The virtual stack pointer of the EHABI
+    vsp: Expr,
+    regs: [Option<Expr>; 16],
+}
+
+impl Rules {
+    fn new() -> Self {
+        Self {
+            cfa: Expr::Reg("sp", 0),
+            vsp: Expr::Reg("sp", 0),
+            regs: Default::default(),
+        }
+    }
+
+    fn add_to_vsp(&mut self, value: i64) {
+        self.vsp = std::mem::replace(&mut self.vsp, Expr::Reg("sp", 0)).add(value);
+    }
+
+    fn pop(&mut self, mask: u16) {
+        for i in 0..self.regs.len() {
+            if mask & (1 << i) != 0 {
+                self.regs[i] = Some(self.vsp.deref());
+                self.add_to_vsp(4);
+            }
+        }
+        // The stack pointer has been popped
+        if mask & (1 << SP) != 0 {
+            self.vsp = self.regs[SP].clone().unwrap();
+        }
+    }
+
+    /// Interpret the opcodes (EHABI section 10.3): None if the function can't be unwound
+    fn interpret(opcodes: &[u8]) -> Option<Self> {
+        let mut rules = Self::new();
+        let mut opcodes = opcodes.iter().copied();
+
+        while let Some(op) = opcodes.next() {
+            match op {
+                0x00..=0x3f => rules.add_to_vsp(i64::from(op & 0x3f) * 4 + 4),
+                0x40..=0x7f => rules.add_to_vsp(-(i64::from(op & 0x3f) * 4 + 4)),
+                0x80..=0x8f => {
+                    let mask = (u16::from(op & 0x0f) << 12) | (u16::from(opcodes.next()?) << 4);
+                    if mask == 0 {
+                        // Refuse to unwind
+                        return None;
+                    }
+                    rules.pop(mask);
+                }
+                0x90..=0x9f => {
+                    let reg = usize::from(op & 0x0f);
+                    if reg == SP || reg == PC {
+                        return None;
+                    }
+                    rules.vsp = rules.regs[reg]
+                        .clone()
+                        .unwrap_or(Expr::Reg(REG_NAMES[reg], 0));
+                    rules.cfa = rules.vsp.clone();
+                }
+                0xa0..=0xaf => {
+                    // Pop r4-r[4+nnn] (and lr)
+                    let mut mask = ((1u16 << ((op & 0x07) + 1)) - 1) << 4;
+                    if op & 0x08 != 0 {
+                        mask |= 1 << LR;
+                    }
+                    rules.pop(mask);
+                }
+                0xb0 => break,
+                0xb1 => {
+                    let mask = opcodes.next()?;
+                    if mask == 0 || mask & 0xf0 != 0 {
+                        return None;
+                    }
+                    rules.pop(u16::from(mask));
+                }
+                0xb2 => {
+                    let mut value = 0u64;
+                    let mut shift = 0;
+                    loop {
+                        let byte = opcodes.next()?;
+                        value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+                        if byte & 0x80 == 0 {
+                            break;
+                        }
+                        shift += 7;
+                    }
+                    rules.add_to_vsp(0x204 + (value as i64) * 4);
+                }
+                // The VFP and the iWMMXt registers aren't recovered but the stack is adjusted
+                0xb3 => {
+                    let count = opcodes.next()? & 0x0f;
+                    rules.add_to_vsp(8 * (i64::from(count) + 1) + 4);
+                }
+                0xb8..=0xbf => rules.add_to_vsp(8 * (i64::from(op & 0x07) + 1) + 4),
+                0xc6 | 0xc8 | 0xc9 => {
+                    let count = opcodes.next()? & 0x0f;
+                    rules.add_to_vsp(8 * (i64::from(count) + 1));
+                }
+                0xc7 => {
+                    let mask = opcodes.next()?;
+                    if mask == 0 || mask & 0xf0 != 0 {
+                        return None;
+                    }
+                    rules.add_to_vsp(4 * i64::from(mask.count_ones()));
+                }
+                0xc0..=0xc5 | 0xd0..=0xd7 => rules.add_to_vsp(8 * (i64::from(op & 0x07) + 1)),
+                _ => return None,
+            }
+        }
+
+        // The return address is in lr if it hasn't been popped in pc
+        if rules.regs[PC].is_none() {
+            rules.regs[PC] = Some(rules.regs[LR].clone().unwrap_or(Expr::Reg("lr", 0)));
+        }
+        // The final vsp is the stack pointer of the caller
+        rules.regs[SP] = Some(rules.vsp.clone());
+
+        Some(rules)
+    }
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            ".cfa: {} .ra: {}",
+            self.cfa,
+            self.regs[PC].as_ref().unwrap()
+        )?;
+        for (name, reg) in REG_NAMES.iter().zip(self.regs.iter()) {
+            if let Some(reg) = reg {
+                write!(f, " {}: {}", name, reg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The content of a section and its address
+struct Table<'a> {
+    data: &'a [u8],
+    address: u64,
+    little_endian: bool,
+}
+
+impl Table<'_> {
+    fn word(&self, address: u64) -> Option<u32> {
+        let offset = address.checked_sub(self.address)? as usize;
+        let bytes = self.data.get(offset..offset.checked_add(4)?)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// Get the address pointed by the prel31 offset at the given address
+    fn prel31(&self, address: u64) -> Option<u64> {
+        let offset = ((self.word(address)? << 1) as i32) >> 1;
+        Some(address.wrapping_add(offset as i64 as u64))
+    }
+}
+
+fn push_bytes(opcodes: &mut Vec<u8>, word: u32, count: usize) {
+    opcodes.extend_from_slice(&word.to_be_bytes()[4 - count..]);
+}
+
+/// Get the unwind opcodes of the entry at the given address in .ARM.exidx
+fn get_opcodes(exidx: &Table, extab: &Table, address: u64) -> Option<Vec<u8>> {
+    let data = exidx.word(address + 4)?;
+    if data == EXIDX_CANTUNWIND {
+        return None;
+    }
+
+    let mut opcodes = Vec::new();
+    if data & 0x8000_0000 != 0 {
+        // Compact model inlined in the table: personality 0 and 3 opcodes
+        if data & 0x7f00_0000 != 0 {
+            return None;
+        }
+        push_bytes(&mut opcodes, data, 3);
+        return Some(opcodes);
+    }
+
+    let mut entry = exidx.prel31(address + 4)?;
+    let data = extab.word(entry)?;
+    let extra = if data & 0x8000_0000 != 0 {
+        match (data >> 24) & 0x0f {
+            0 => {
+                push_bytes(&mut opcodes, data, 3);
+                0
+            }
+            1 | 2 => {
+                push_bytes(&mut opcodes, data, 2);
+                (data >> 16) & 0xff
+            }
+            _ => return None,
+        }
+    } else {
+        // Generic model: the personality routine (e.g. __gxx_personality_v0) is followed by
+        // the opcodes in the same format
+        entry += 4;
+        let data = extab.word(entry)?;
+        push_bytes(&mut opcodes, data, 3);
+        data >> 24
+    };
+
+    for _ in 0..extra {
+        entry += 4;
+        push_bytes(&mut opcodes, extab.word(entry)?, 4);
+    }
+
+    Some(opcodes)
+}
+
+/// Get the address, the size and the rules of the functions in .ARM.exidx
+fn get_entries(exidx: &Table, extab: &Table, text_end: u64) -> Vec<(u64, u64, Rules)> {
+    let starts: Vec<_> = (0..exidx.data.len() as u64 / 8)
+        .filter_map(|i| {
+            let address = exidx.address + i * 8;
+            // The thumb bit can be set
+            exidx.prel31(address).map(|start| (address, start & !1))
+        })
+        .collect();
+
+    let mut entries = Vec::new();
+    for (i, (address, start)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(text_end, |(_, next)| *next);
+        if end <= *start {
+            continue;
+        }
+        if let Some(rules) = get_opcodes(exidx, extab, *address).and_then(|o| Rules::interpret(&o))
+        {
+            entries.push((*start, end - *start, rules));
+        }
+    }
+
+    entries
+}
+
+/// Get the STACK CFI records from the ARM exception tables of the functions which don't have
+/// any in stack
+pub(super) fn get_stack_info(data: &[u8], load_address: u64, stack: &str) -> String {
+    let mut records = String::new();
+    let elf = match Elf::parse(data) {
+        Ok(elf) if elf.header.e_machine == EM_ARM => elf,
+        _ => return records,
+    };
+
+    let get_table = |name: &str| {
+        elf.section_headers
+            .iter()
+            .find(|h| elf.shdr_strtab.get_at(h.sh_name) == Some(name))
+            .and_then(|h| {
+                let offset = h.sh_offset as usize;
+                Some(Table {
+                    data: data.get(offset..offset.checked_add(h.sh_size as usize)?)?,
+                    address: h.sh_addr,
+                    little_endian: elf.little_endian,
+                })
+            })
+    };
+
+    if get_table(".debug_frame").is_some() {
+        return records;
+    }
+    let exidx = match get_table(".ARM.exidx") {
+        Some(exidx) => exidx,
+        None => return records,
+    };
+    let extab = get_table(".ARM.extab").unwrap_or(Table {
+        data: &[],
+        address: 0,
+        little_endian: elf.little_endian,
+    });
+    let text_end = elf
+        .section_headers
+        .iter()
+        .filter(|h| h.sh_flags & u64::from(SHF_EXECINSTR) != 0)
+        .map(|h| h.sh_addr + h.sh_size)
+        .max()
+        .unwrap_or(0);
+
+    let with_cfi: HashSet<_> = common::get_stack_ranges(stack)
+        .into_iter()
+        .map(|(_, rva, _)| u64::from(rva))
+        .collect();
+    let mut count = 0;
+    for (start, size, rules) in get_entries(&exidx, &extab, text_end) {
+        let rva = match start.checked_sub(load_address) {
+            Some(rva) => rva,
+            None => {
+                warn!("Invalid address in .ARM.exidx: {:x}", start);
+                continue;
+            }
+        };
+        if with_cfi.contains(&rva) {
+            continue;
+        }
+        writeln!(records, "STACK CFI INIT {:x} {:x} {}", rva, size, rules).unwrap();
+        count += 1;
+    }
+    info!("{} STACK CFI records from .ARM.exidx", count);
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn rules(opcodes: &[u8]) -> String {
+        Rules::interpret(opcodes)
+            .map(|r| r.to_string())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_opcodes() {
+        // Leaf function
+        assert_eq!(
+            rules(&[0xb0]),
+            ".cfa: sp 0 + .ra: lr 0 + sp: sp 0 + pc: lr 0 +"
+        );
+        // push {r4, r5, lr}; sub sp, sp, #8
+        assert_eq!(
+            rules(&[0x01, 0xa9, 0xb0]),
+            ".cfa: sp 0 + .ra: sp 16 + ^ r4: sp 8 + ^ r5: sp 12 + ^ sp: sp 20 + lr: sp 16 + ^ pc: sp 16 + ^"
+        );
+        // push {r7, lr}; mov r7, sp (frame pointer)
+        assert_eq!(
+            rules(&[0x97, 0x84, 0x08]),
+            ".cfa: r7 0 + .ra: r7 4 + ^ r7: r7 0 + ^ sp: r7 8 + lr: r7 4 + ^ pc: r7 4 + ^"
+        );
+        // vpush {d8-d9}; push {r4, lr}
+        assert_eq!(
+            rules(&[0xa8, 0xb9]),
+            ".cfa: sp 0 + .ra: sp 4 + ^ r4: sp 0 + ^ sp: sp 28 + lr: sp 4 + ^ pc: sp 4 + ^"
+        );
+        // sub sp, sp, #0x400 (uleb)
+        assert_eq!(
+            rules(&[0xb2, 0x7f]),
+            ".cfa: sp 0 + .ra: lr 0 + sp: sp 1024 + pc: lr 0 +"
+        );
+        // Refuse to unwind and spare opcodes
+        assert!(Rules::interpret(&[0x80, 0x00]).is_none());
+        assert!(Rules::interpret(&[0xff]).is_none());
+    }
+
+    #[test]
+    fn test_exidx() {
+        // The prel31 offset to the target from the word at the given address
+        let prel31 = |target: u32, at: u32| (target.wrapping_sub(at) & 0x7fff_ffff).to_le_bytes();
+        let mut exidx = Vec::new();
+        // 0x1000: inlined opcodes (push {r4, lr})
+        exidx.extend_from_slice(&prel31(0x1000, 0x2000));
+        exidx.extend_from_slice(&0x80a8_b0b0u32.to_le_bytes());
+        // 0x1020 (thumb): can't unwind
+        exidx.extend_from_slice(&prel31(0x1021, 0x2008));
+        exidx.extend_from_slice(&EXIDX_CANTUNWIND.to_le_bytes());
+        // 0x1040: in .ARM.extab at 0x3000 with a personality routine
+        exidx.extend_from_slice(&prel31(0x1040, 0x2010));
+        exidx.extend_from_slice(&prel31(0x3000, 0x2014));
+        // 0x1080: in .ARM.extab at 0x3008 with the personality 1 and an extra word
+        exidx.extend_from_slice(&prel31(0x1080, 0x2018));
+        exidx.extend_from_slice(&prel31(0x3008, 0x201c));
+
+        let mut extab = Vec::new();
+        extab.extend_from_slice(&0x0000_1234u32.to_le_bytes());
+        extab.extend_from_slice(&0x00a8_b0b0u32.to_le_bytes());
+        extab.extend_from_slice(&0x8101_0000u32.to_le_bytes());
+        extab.extend_from_slice(&0xa8b0_b0b0u32.to_le_bytes());
+
+        let exidx = Table {
+            data: &exidx,
+            address: 0x2000,
+            little_endian: true,
+        };
+        let extab = Table {
+            data: &extab,
+            address: 0x3000,
+            little_endian: true,
+        };
+
+        let entries: Vec<_> = get_entries(&exidx, &extab, 0x1100)
+            .into_iter()
+            .map(|(start, size, rules)| format!("{:x} {:x} {}", start, size, rules))
+            .collect();
+        let push = ".cfa: sp 0 + .ra: sp 4 + ^ r4: sp 0 + ^ sp: sp 8 + lr: sp 4 + ^ pc: sp 4 + ^";
+        assert_eq!(
+            entries,
+            vec![
+                format!("1000 20 {}", push),
+                format!("1040 40 {}", push),
+                format!(
+                    "1080 80 .cfa: sp 0 + .ra: sp 12 + ^ r4: sp 8 + ^ sp: sp 16 + lr: sp 12 + ^ pc: sp 12 + ^"
+                ),
+            ]
+        );
+
+        // Not an ARM ELF
+        assert!(get_stack_info(b"\x7fELF", 0, "").is_empty());
+    }
+}
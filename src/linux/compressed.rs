@@ -0,0 +1,168 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use goblin::container::{Container, Ctx, Endian};
+use goblin::elf::compression_header::CompressionHeader;
+use goblin::elf::section_header::SHF_COMPRESSED;
+use goblin::elf::{Elf, SectionHeader};
+use log::{info, warn};
+use std::borrow::Cow;
+
+/*
+ symbolic decompresses the debug sections compressed with zlib (SHF_COMPRESSED or the legacy
+ .zdebug_* sections) but not the ones compressed with zstd (e.g. objcopy --compress-debug-sections=zstd
+ or ld --compress-debug-sections=zstd as done by some distros).
+ So the ELF is rewritten in memory: the decompressed sections are appended to the file and their
+ headers point to them, hence everything reading the sections (DWARF, CFI, split DWARF) gets them
+ transparently.
+*/
+
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+struct Decompressed {
+    /// The index of the section header
+    index: usize,
+    data: Vec<u8>,
+    align: u64,
+}
+
+fn get_data<'a>(buf: &'a [u8], header: &SectionHeader) -> Option<&'a [u8]> {
+    let offset = header.sh_offset as usize;
+    buf.get(offset..offset.checked_add(header.sh_size as usize)?)
+}
+
+fn put(buf: &mut [u8], offset: usize, size: usize, value: u64, endian: Endian) {
+    if endian.is_little() {
+        buf[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    } else {
+        buf[offset..offset + size].copy_from_slice(&value.to_be_bytes()[8 - size..]);
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    zstd::stream::decode_all(data).ok()
+}
+
+// Without zstd, the sections stay compressed (and symbolic ignores them)
+#[cfg(not(feature = "zstd"))]
+fn decompress(_data: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+/// Get the ELF with its zstd compressed sections decompressed (or the ELF itself if there's
+/// no such section)
+pub(crate) fn decompress_sections(buf: &[u8]) -> Cow<'_, [u8]> {
+    let elf = match Elf::parse(buf) {
+        Ok(elf) => elf,
+        _ => return Cow::Borrowed(buf),
+    };
+    let (container, endian) = match (elf.header.container(), elf.header.endianness()) {
+        (Ok(container), Ok(endian)) => (container, endian),
+        _ => return Cow::Borrowed(buf),
+    };
+    let ctx = Ctx::new(container, endian);
+
+    let mut sections = Vec::new();
+    for (index, header) in elf.section_headers.iter().enumerate() {
+        if header.sh_flags & u64::from(SHF_COMPRESSED) == 0 {
+            continue;
+        }
+        let data = match get_data(buf, header) {
+            Some(data) => data,
+            _ => continue,
+        };
+        let compression = match CompressionHeader::parse(data, 0, ctx) {
+            Ok(compression) if compression.ch_type == ELFCOMPRESS_ZSTD => compression,
+            // zlib: symbolic does the job
+            _ => continue,
+        };
+
+        match decompress(&data[CompressionHeader::size(ctx)..]) {
+            Some(data) if data.len() as u64 == compression.ch_size => sections.push(Decompressed {
+                index,
+                data,
+                align: compression.ch_addralign,
+            }),
+            _ => warn!(
+                "Cannot decompress the section {}",
+                elf.shdr_strtab.get_at(header.sh_name).unwrap_or("")
+            ),
+        }
+    }
+
+    if sections.is_empty() {
+        return Cow::Borrowed(buf);
+    }
+
+    // Offsets in a section header: (flags, offset, size) and their size
+    let (fields, size) = if container == Container::Big {
+        ([8, 24, 32], 8)
+    } else {
+        ([8, 16, 20], 4)
+    };
+
+    let shoff = elf.header.e_shoff as usize;
+    let shentsize = elf.header.e_shentsize as usize;
+    let mut out = buf.to_vec();
+    for section in sections {
+        let align = section.align.max(1) as usize;
+        let offset = out.len() + (align - out.len() % align) % align;
+        out.resize(offset, 0);
+        out.extend_from_slice(&section.data);
+
+        let header = &elf.section_headers[section.index];
+        let pos = shoff + section.index * shentsize;
+        let flags = header.sh_flags & !u64::from(SHF_COMPRESSED);
+        put(&mut out, pos + fields[0], size, flags, endian);
+        put(&mut out, pos + fields[1], size, offset as u64, endian);
+        put(
+            &mut out,
+            pos + fields[2],
+            size,
+            section.data.len() as u64,
+            endian,
+        );
+    }
+    info!("The zstd compressed sections have been decompressed");
+
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+    use symbolic::debuginfo::dwarf::Dwarf;
+    use symbolic::debuginfo::elf::ElfObject;
+
+    use crate::utils;
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_decompress_sections() {
+        let full = utils::read_file(PathBuf::from("./test_data/linux/basic.full"));
+        let zstd = utils::read_file(PathBuf::from("./test_data/linux/basic.zstd"));
+        let zlib = utils::read_file(PathBuf::from("./test_data/linux/basic.zlib"));
+
+        // Nothing to do
+        assert!(matches!(decompress_sections(&full), Cow::Borrowed(_)));
+        assert!(matches!(decompress_sections(&zlib), Cow::Borrowed(_)));
+
+        let decompressed = decompress_sections(&zstd);
+        assert!(matches!(decompressed, Cow::Owned(_)));
+
+        let full = ElfObject::parse(&full).unwrap();
+        let zstd = ElfObject::parse(&zstd).unwrap();
+        let decompressed = ElfObject::parse(&decompressed).unwrap();
+        for name in &["debug_info", "debug_abbrev", "debug_line", "debug_str"] {
+            let section = full.section(name).unwrap();
+            assert!(zstd.section(name).is_none());
+            assert_eq!(decompressed.section(name).unwrap().data, section.data);
+        }
+        assert!(decompressed.has_debug_info());
+    }
+}
@@ -0,0 +1,221 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use goblin::elf::Elf;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use symbolic::common::CodeId;
+use symbolic::debuginfo::elf::ElfObject;
+use symbolic::debuginfo::Object;
+
+use crate::utils;
+
+/*
+ The debug file of a stripped ELF is searched like gdb does:
+  - by build-id: <dir>/.build-id/xx/yyyy.debug;
+  - by .gnu_debuglink: next to the ELF, in its .debug directory and in <dir>/<directory of the ELF>.
+ where dir is one of the --debug-dir or /usr/lib/debug.
+ The build-id of the debug file or its CRC must match the one in the ELF.
+*/
+
+const GLOBAL_DEBUG_DIR: &str = "/usr/lib/debug";
+
+fn get_debug_dirs(debug_dirs: &[String]) -> Vec<PathBuf> {
+    debug_dirs
+        .iter()
+        .map(PathBuf::from)
+        .chain(std::iter::once(PathBuf::from(GLOBAL_DEBUG_DIR)))
+        .collect()
+}
+
+fn get_code_id(buf: &[u8]) -> Option<CodeId> {
+    match Object::parse(buf) {
+        Ok(Object::Elf(elf)) => elf.code_id(),
+        _ => None,
+    }
+}
+
+fn crc32(buf: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(buf);
+    hasher.finalize()
+}
+
+fn find_by_build_id(code_id: &CodeId, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let id = code_id.as_str();
+    if id.len() <= 2 {
+        return None;
+    }
+
+    for dir in dirs {
+        let path = dir
+            .join(".build-id")
+            .join(&id[..2])
+            .join(format!("{}.debug", &id[2..]));
+        if !path.is_file() {
+            continue;
+        }
+        match utils::read(&path) {
+            Ok(buf) if get_code_id(&buf).as_ref() == Some(code_id) => return Some(path),
+            Ok(_) => warn!("The build-id of {} doesn't match", path.display()),
+            Err(e) => warn!("Cannot read {}: {}", path.display(), e),
+        }
+    }
+
+    None
+}
+
+/// Get the file name and the CRC in the .gnu_debuglink section of an ELF: a NUL-terminated name
+/// padded to a multiple of 4 bytes and followed by the CRC32 of the debug file
+fn get_debug_link(buf: &[u8]) -> Result<Option<(String, u32)>, &'static str> {
+    let elf = Elf::parse(buf).map_err(|_| "invalid ELF")?;
+    let header = match elf
+        .section_headers
+        .iter()
+        .find(|h| elf.shdr_strtab.get_at(h.sh_name) == Some(".gnu_debuglink"))
+    {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let offset = header.sh_offset as usize;
+    let data = offset
+        .checked_add(header.sh_size as usize)
+        .and_then(|end| buf.get(offset..end))
+        .ok_or("section out of the file")?;
+    let len = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or("unterminated file name")?;
+    let crc_offset = (len + 4) & !3;
+    let crc = data.get(crc_offset..crc_offset + 4).ok_or("missing CRC")?;
+    let crc = [crc[0], crc[1], crc[2], crc[3]];
+    let crc = if elf.little_endian {
+        u32::from_le_bytes(crc)
+    } else {
+        u32::from_be_bytes(crc)
+    };
+
+    Ok(Some((
+        String::from_utf8_lossy(&data[..len]).into_owned(),
+        crc,
+    )))
+}
+
+fn find_by_debug_link(file_name: &str, crc: u32, path: &Path, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let bin_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut candidates = vec![
+        bin_dir.join(file_name),
+        bin_dir.join(".debug").join(file_name),
+    ];
+    if let Ok(bin_dir) = bin_dir.canonicalize() {
+        // The debug file of /usr/bin/foo is in /usr/lib/debug/usr/bin/
+        let relative = bin_dir.strip_prefix("/").unwrap_or(&bin_dir);
+        candidates.extend(dirs.iter().map(|dir| dir.join(relative).join(file_name)));
+    }
+    candidates.extend(dirs.iter().map(|dir| dir.join(file_name)));
+
+    for candidate in candidates {
+        // The debug link can point to the ELF itself
+        if !candidate.is_file() || candidate == path {
+            continue;
+        }
+        match utils::read(&candidate) {
+            Ok(buf) if crc32(&buf) == crc => return Some(candidate),
+            Ok(_) => warn!("The CRC of {} doesn't match", candidate.display()),
+            Err(e) => warn!("Cannot read {}: {}", candidate.display(), e),
+        }
+    }
+
+    None
+}
+
+/// Find the debug file of an ELF without debug info
+pub(crate) fn find_debug_file(buf: &[u8], path: &Path, debug_dirs: &[String]) -> Option<PathBuf> {
+    let elf = ElfObject::parse(buf).ok()?;
+    if elf.has_debug_info() {
+        return None;
+    }
+
+    let dirs = get_debug_dirs(debug_dirs);
+    let debug_path = elf
+        .code_id()
+        .and_then(|code_id| find_by_build_id(&code_id, &dirs))
+        .or_else(|| match get_debug_link(buf) {
+            Ok(Some((file_name, crc))) => find_by_debug_link(&file_name, crc, path, &dirs),
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Invalid .gnu_debuglink in {}: {}", path.display(), e);
+                None
+            }
+        });
+
+    if let Some(debug_path) = debug_path.as_ref() {
+        info!(
+            "Use the debug file {} for {}",
+            debug_path.display(),
+            path.display()
+        );
+    }
+
+    debug_path
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_build_id() {
+        let stripped = PathBuf::from("./test_data/linux/basic.stripped");
+        let buf = utils::read_file(&stripped);
+        let code_id = get_code_id(&buf).unwrap();
+        let id = code_id.as_str();
+
+        let tmp_dir = Builder::new().prefix("build_id").tempdir().unwrap();
+        let dir = tmp_dir.path().join(".build-id").join(&id[..2]);
+        fs::create_dir_all(&dir).unwrap();
+        let debug_path = dir.join(format!("{}.debug", &id[2..]));
+
+        // Not the right build-id
+        fs::copy("./test_data/linux/basic.split", &debug_path).unwrap();
+        let debug_dirs = vec![tmp_dir.path().to_str().unwrap().to_string()];
+        assert_eq!(find_debug_file(&buf, &stripped, &debug_dirs), None);
+
+        fs::copy("./test_data/linux/basic.dbg", &debug_path).unwrap();
+        assert_eq!(
+            find_debug_file(&buf, &stripped, &debug_dirs),
+            Some(debug_path)
+        );
+
+        // Nothing to find for a file with debug info
+        let full = utils::read_file(PathBuf::from("./test_data/linux/basic.full"));
+        assert_eq!(find_debug_file(&full, &stripped, &debug_dirs), None);
+    }
+
+    #[test]
+    fn test_debug_link() {
+        let path = PathBuf::from("./test_data/linux/basic.debuglink");
+        let buf = utils::read_file(&path);
+        assert_eq!(
+            find_debug_file(&buf, &path, &[]),
+            Some(PathBuf::from("./test_data/linux/basic.dbg"))
+        );
+
+        // A file with the right name but the wrong CRC
+        let tmp_dir = Builder::new().prefix("debug_link").tempdir().unwrap();
+        let tmp_path = tmp_dir.path().join("basic.debuglink");
+        fs::copy(&path, &tmp_path).unwrap();
+        fs::copy(
+            "./test_data/linux/basic.full",
+            tmp_dir.path().join("basic.dbg"),
+        )
+        .unwrap();
+        assert_eq!(find_debug_file(&buf, &tmp_path, &[]), None);
+    }
+}
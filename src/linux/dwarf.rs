@@ -0,0 +1,476 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use gimli::{
+    constants, AttributeValue, DebuggingInformationEntry, DwarfPackage, EndianSlice,
+    EntriesTreeNode, RunTimeEndian, SectionId, UnitType,
+};
+use hashbrown::HashMap;
+use std::borrow::Cow;
+use symbolic::common::{Language, Name, NameMangling};
+use symbolic::debuginfo::dwarf::{self as symbolic_dwarf, Dwarf};
+use symbolic::debuginfo::{FileInfo, Function, LineInfo};
+
+use super::elf::Collector;
+use super::source::SourceFiles;
+use crate::cancel;
+use crate::common;
+
+/*
+ A DWARF reader built on gimli for what symbolic doesn't handle: the split units (see split_dwarf)
+ and the DWARF 5 units using .debug_addr (DW_FORM_addrx, emitted by clang and rustc) since
+ symbolic doesn't load this section.
+ The functions are read with their ranges (DW_AT_ranges from .debug_ranges or .debug_rnglists),
+ their names (DW_FORM_strx from .debug_str_offsets) and the rows of the line table (with the
+ DWARF 5 file indices starting at 0 and the strings in .debug_line_str). The lines in the
+ inlined functions are the ones of their outermost call.
+*/
+
+pub(super) type Slice<'a> = EndianSlice<'a, RunTimeEndian>;
+pub(super) type Unit<'a> = gimli::Unit<Slice<'a>>;
+
+const SECTIONS: &[SectionId] = &[
+    SectionId::DebugAbbrev,
+    SectionId::DebugAddr,
+    SectionId::DebugCuIndex,
+    SectionId::DebugInfo,
+    SectionId::DebugLine,
+    SectionId::DebugLineStr,
+    SectionId::DebugLoc,
+    SectionId::DebugLocLists,
+    SectionId::DebugRanges,
+    SectionId::DebugRngLists,
+    SectionId::DebugStr,
+    SectionId::DebugStrOffsets,
+    SectionId::DebugTuIndex,
+    SectionId::DebugTypes,
+];
+
+/// The DWARF sections of an object, a .dwo or a .dwp (decompressed if needed)
+pub(super) struct Sections<'data> {
+    data: HashMap<SectionId, Cow<'data, [u8]>>,
+    endian: RunTimeEndian,
+}
+
+impl<'data> Sections<'data> {
+    pub(super) fn new<D: Dwarf<'data>>(object: &D, dwo: bool) -> Self {
+        let data = SECTIONS
+            .iter()
+            .filter_map(|id| {
+                let name = if dwo { id.dwo_name()? } else { id.name() };
+                let section = object.section(&name[1..])?;
+                Some((*id, section.data))
+            })
+            .collect();
+
+        Self {
+            data,
+            // symbolic's endianness comes from its own gimli
+            endian: match object.endianity() {
+                symbolic_dwarf::Endian::Little => RunTimeEndian::Little,
+                symbolic_dwarf::Endian::Big => RunTimeEndian::Big,
+            },
+        }
+    }
+
+    fn reader(&self, id: SectionId) -> Slice<'_> {
+        let data = self.data.get(&id).map_or(&[][..], |d| d.as_ref());
+        EndianSlice::new(data, self.endian)
+    }
+
+    pub(super) fn dwarf(&self) -> common::Result<gimli::Dwarf<Slice<'_>>> {
+        Ok(gimli::Dwarf::load(|id| {
+            Ok::<_, gimli::Error>(self.reader(id))
+        })?)
+    }
+
+    pub(super) fn package(&self) -> common::Result<DwarfPackage<Slice<'_>>> {
+        Ok(DwarfPackage::load(
+            |id| Ok::<_, gimli::Error>(self.reader(id)),
+            EndianSlice::new(&[], self.endian),
+        )?)
+    }
+}
+
+/// The location of the call of an inlined function
+struct Call<'a> {
+    start: u64,
+    end: u64,
+    file: FileInfo<'a>,
+    line: u64,
+}
+
+/// A unit with the one containing its line table and its addresses: the skeleton unit referencing
+/// a split unit or the unit itself
+struct DwarfUnit<'a, 'u> {
+    skeleton: &'u gimli::Dwarf<Slice<'a>>,
+    skeleton_unit: &'u Unit<'a>,
+    dwarf: &'u gimli::Dwarf<Slice<'a>>,
+    unit: &'u Unit<'a>,
+    /// The lines of the skeleton unit sorted by address
+    lines: &'u [LineInfo<'a>],
+    address_offset: u64,
+}
+
+impl<'a, 'u> DwarfUnit<'a, 'u> {
+    fn get_name(
+        &self,
+        entry: &DebuggingInformationEntry<Slice<'a>>,
+        depth: u8,
+    ) -> common::Result<Option<Name<'a>>> {
+        let mut name = None;
+        let mut origin = None;
+        let mut attrs = entry.attrs();
+        while let Some(attr) = attrs.next()? {
+            match attr.name() {
+                constants::DW_AT_linkage_name | constants::DW_AT_MIPS_linkage_name => {
+                    if let Ok(s) = self.dwarf.attr_string(self.unit, attr.value()) {
+                        return Ok(Some(Name::new(
+                            String::from_utf8_lossy(s.slice()),
+                            NameMangling::Mangled,
+                            Language::Unknown,
+                        )));
+                    }
+                }
+                constants::DW_AT_name => {
+                    name = self.dwarf.attr_string(self.unit, attr.value()).ok();
+                }
+                constants::DW_AT_abstract_origin | constants::DW_AT_specification => {
+                    origin = Some(attr.value());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(name) = name {
+            return Ok(Some(Name::new(
+                String::from_utf8_lossy(name.slice()),
+                NameMangling::Unmangled,
+                Language::Unknown,
+            )));
+        }
+
+        // The name of an out-of-line definition is in its declaration
+        match origin {
+            Some(AttributeValue::UnitRef(offset)) if depth < 8 => {
+                let entry = self.unit.entry(offset)?;
+                self.get_name(&entry, depth + 1)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn get_ranges(
+        &self,
+        entry: &DebuggingInformationEntry<Slice<'a>>,
+    ) -> common::Result<Vec<(u64, u64)>> {
+        let mut ranges = Vec::new();
+        let mut iter = self.dwarf.die_ranges(self.unit, entry)?;
+        while let Some(range) = iter.next()? {
+            if range.begin < range.end && range.begin >= self.address_offset {
+                ranges.push((range.begin, range.end));
+            }
+        }
+        Ok(ranges)
+    }
+
+    fn get_file(&self, index: u64) -> FileInfo<'a> {
+        // The file indices are the ones of the line table of the skeleton unit
+        let (dwarf, unit) = if self.unit.line_program.is_some() {
+            (self.dwarf, self.unit)
+        } else {
+            (self.skeleton, self.skeleton_unit)
+        };
+        let header = match unit.line_program.as_ref() {
+            Some(program) => program.header(),
+            None => return FileInfo::default(),
+        };
+        get_file(dwarf, unit, header, index)
+    }
+
+    /// Collect the locations where the inlined functions are called (the outermost ones)
+    fn collect_calls(
+        &self,
+        node: EntriesTreeNode<Slice<'a>>,
+        calls: &mut Vec<Call<'a>>,
+    ) -> common::Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                constants::DW_TAG_inlined_subroutine => {
+                    let file = match entry.attr_value(constants::DW_AT_call_file)? {
+                        Some(AttributeValue::FileIndex(index)) => self.get_file(index),
+                        Some(AttributeValue::Udata(index)) => self.get_file(index),
+                        _ => continue,
+                    };
+                    let line = match entry.attr_value(constants::DW_AT_call_line)? {
+                        Some(value) => value.udata_value().unwrap_or(0),
+                        _ => 0,
+                    };
+                    for (start, end) in self.get_ranges(entry)? {
+                        calls.push(Call {
+                            start,
+                            end,
+                            file: file.clone(),
+                            line,
+                        });
+                    }
+                }
+                constants::DW_TAG_lexical_block => {
+                    self.collect_calls(child, calls)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn get_function(
+        &self,
+        start: u64,
+        end: u64,
+        name: Name<'a>,
+        calls: &[Call<'a>],
+    ) -> Function<'a> {
+        let first = self.lines.partition_point(|l| l.address < start);
+        let lines = self.lines[first..]
+            .iter()
+            .take_while(|l| l.address < end)
+            .map(|l| {
+                // The lines in an inlined function are the location of the call
+                let (file, line) = match calls
+                    .iter()
+                    .find(|c| c.start <= l.address && l.address < c.end)
+                {
+                    Some(call) => (call.file.clone(), call.line),
+                    None => (l.file.clone(), l.line),
+                };
+                LineInfo {
+                    address: l.address - self.address_offset,
+                    size: l.size,
+                    file,
+                    line,
+                }
+            })
+            .collect();
+
+        Function {
+            address: start - self.address_offset,
+            size: end - start,
+            name,
+            compilation_dir: self
+                .skeleton_unit
+                .comp_dir
+                .map_or(&[][..], |dir| dir.slice()),
+            lines,
+            inlinees: Vec::new(),
+            inline: false,
+        }
+    }
+
+    fn collect_functions(
+        &self,
+        node: EntriesTreeNode<Slice<'a>>,
+        functions: &mut Vec<Function<'a>>,
+    ) -> common::Result<()> {
+        let entry = node.entry();
+        if entry.tag() != constants::DW_TAG_subprogram {
+            let mut children = node.children();
+            while let Some(child) = children.next()? {
+                self.collect_functions(child, functions)?;
+            }
+            return Ok(());
+        }
+
+        let ranges = self.get_ranges(entry)?;
+        let name = self.get_name(entry, 0)?;
+        let (start, end) = match (
+            ranges.iter().map(|r| r.0).min(),
+            ranges.iter().map(|r| r.1).max(),
+        ) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Ok(()),
+        };
+        let name = match name {
+            Some(name) => name,
+            _ => return Ok(()),
+        };
+
+        let mut calls = Vec::new();
+        self.collect_calls(node, &mut calls)?;
+        functions.push(self.get_function(start, end, name, &calls));
+
+        Ok(())
+    }
+}
+
+fn get_file<'a>(
+    dwarf: &gimli::Dwarf<Slice<'a>>,
+    unit: &Unit<'a>,
+    header: &gimli::LineProgramHeader<Slice<'a>>,
+    index: u64,
+) -> FileInfo<'a> {
+    let file = match header.file(index) {
+        Some(file) => file,
+        None => return FileInfo::default(),
+    };
+    let name = dwarf
+        .attr_string(unit, file.path_name())
+        .map_or(&[][..], |s| s.slice());
+    let dir = file
+        .directory(header)
+        .and_then(|dir| dwarf.attr_string(unit, dir).ok())
+        .map_or(&[][..], |s| s.slice());
+
+    FileInfo { name, dir }
+}
+
+/// Get the rows of the line table of a unit with their sizes
+fn get_lines<'a>(
+    dwarf: &gimli::Dwarf<Slice<'a>>,
+    unit: &Unit<'a>,
+) -> common::Result<Vec<LineInfo<'a>>> {
+    let program = match unit.line_program.clone() {
+        Some(program) => program,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut lines: Vec<LineInfo> = Vec::new();
+    let mut rows = program.rows();
+    while let Some((header, row)) = rows.next_row()? {
+        if let Some(last) = lines.last_mut() {
+            if last.size.is_none() {
+                last.size = Some(row.address().saturating_sub(last.address));
+            }
+        }
+        if row.end_sequence() {
+            continue;
+        }
+        lines.push(LineInfo {
+            address: row.address(),
+            size: None,
+            file: get_file(dwarf, unit, header, row.file_index()),
+            line: row.line().map_or(0, |l| l.get()),
+        });
+    }
+    lines.sort_by_key(|l| l.address);
+
+    Ok(lines)
+}
+
+/// Get the functions of a unit
+pub(super) fn get_functions<'a>(
+    skeleton: &gimli::Dwarf<Slice<'a>>,
+    skeleton_unit: &Unit<'a>,
+    dwarf: &gimli::Dwarf<Slice<'a>>,
+    unit: &Unit<'a>,
+    address_offset: u64,
+) -> common::Result<Vec<Function<'a>>> {
+    let lines = get_lines(skeleton, skeleton_unit)?;
+    let dwarf_unit = DwarfUnit {
+        skeleton,
+        skeleton_unit,
+        dwarf,
+        unit,
+        lines: &lines,
+        address_offset,
+    };
+    let mut functions = Vec::new();
+    let mut tree = unit.entries_tree(None)?;
+    dwarf_unit.collect_functions(tree.root()?, &mut functions)?;
+
+    Ok(functions)
+}
+
+/// Collect the functions of a unit
+#[allow(clippy::too_many_arguments)]
+pub(super) fn collect_unit(
+    skeleton: &gimli::Dwarf<Slice<'_>>,
+    skeleton_unit: &Unit<'_>,
+    dwarf: &gimli::Dwarf<Slice<'_>>,
+    unit: &Unit<'_>,
+    address_offset: u64,
+    collector: &mut Collector,
+    source: &mut SourceFiles,
+) -> common::Result<()> {
+    let functions = get_functions(skeleton, skeleton_unit, dwarf, unit, address_offset)?;
+    for fun in functions.iter() {
+        collector.collect_function(fun, source);
+    }
+    Ok(())
+}
+
+/// Check if a unit is a skeleton one: DW_UT_skeleton or a DW_AT_GNU_dwo_id in its root DIE (the GNU
+/// extension used by gcc)
+fn is_skeleton(unit: &Unit<'_>) -> common::Result<bool> {
+    if let UnitType::Skeleton(_) = unit.header.type_() {
+        return Ok(true);
+    }
+    let mut entries = unit.entries();
+    Ok(match entries.next_dfs()? {
+        Some((_, root)) => root.attr(constants::DW_AT_GNU_dwo_id)?.is_some(),
+        None => false,
+    })
+}
+
+/// Collect the functions of the DWARF 5 units whose addresses are indices in .debug_addr
+/// (DW_FORM_addrx): symbolic doesn't read this section and fails on these units
+pub(super) fn collect_indexed_units<'data, D: Dwarf<'data>>(
+    object: &D,
+    address_offset: u64,
+    collector: &mut Collector,
+    source: &mut SourceFiles,
+) -> common::Result<()> {
+    let sections = Sections::new(object, false);
+    if !sections.data.contains_key(&SectionId::DebugAddr) {
+        return Ok(());
+    }
+
+    let dwarf = sections.dwarf()?;
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        cancel::check()?;
+
+        let unit = dwarf.unit(header)?;
+        // The skeleton units are handled with the split ones
+        if unit.header.version() < 5 || unit.addr_base.0 == 0 || is_skeleton(&unit)? {
+            continue;
+        }
+        collect_unit(
+            &dwarf,
+            &unit,
+            &dwarf,
+            &unit,
+            address_offset,
+            collector,
+            source,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::PathBuf;
+
+    use crate::linux::elf::{ElfInfo, Platform};
+    use crate::utils;
+
+    #[test]
+    fn test_dwarf5() {
+        // The addresses are some indices in .debug_addr, the names some indices in
+        // .debug_str_offsets and the ranges of the unit are in .debug_rnglists
+        let buf = utils::read_file(PathBuf::from("./test_data/linux/dwarf5"));
+        let elf = ElfInfo::new(&buf, "dwarf5", Platform::Linux, None, None).unwrap();
+        let sym = elf.to_string();
+
+        assert!(sym.contains("FILE 0 /tmp/dump_syms/dwarf5.c\n"));
+        assert!(sym.contains("FUNC 1040 10 0 square\n1040 7 1 0\n1047 7 2 0\n104e 2 3 0\n"));
+        assert!(sym.contains("FUNC 1140 b 0 main\n1140 4 6 0\n1144 7 7 0\n"));
+    }
+}
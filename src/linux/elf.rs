@@ -5,21 +5,37 @@
 
 use failure::Fail;
 use hashbrown::HashMap;
-use log::{error, warn};
+use log::{error, info, warn};
+use std::borrow::Cow;
 use std::collections::btree_map;
 use std::fmt::{Display, Formatter};
 use std::io::{Cursor, Write};
 use std::sync::Arc;
-use symbolic::common::{Language, Name, NameMangling};
-use symbolic::debuginfo::{Function, Object, ObjectDebugSession};
-use symbolic::demangle::{Demangle, DemangleOptions};
-use symbolic::minidump::cfi::AsciiCfiWriter;
-
+use symbolic::common::{DebugId, Language, Name, NameMangling};
+use symbolic::debuginfo::dwarf::DwarfDebugSession;
+use symbolic::debuginfo::pe::PeObject;
+use symbolic::debuginfo::{Function, Object, ObjectDebugSession, SymbolMap};
+use symbolic_demangle::{Demangle, DemangleOptions};
+
+use super::arm_exidx;
+use super::compressed;
+use super::dwarf;
+use super::gopclntab;
+use super::oso;
 use super::source::{SourceFiles, SourceMap};
+use super::split_dwarf::SplitDwarf;
 use super::symbol::{ElfSymbol, ElfSymbols};
-use crate::common::{self, Dumpable, LineFinalizer, Mergeable};
+use crate::cancel;
+use crate::common::{self, Dumpable, LineFinalizer, Mergeable, Provenance, RecordProvenance};
+use crate::debug_id::{self, DebugIdFallback};
+use crate::diagnostics::{self, Category};
 use crate::line::Lines;
+use crate::mac::compact_unwind;
 use crate::mapping::PathMappings;
+use crate::model::SourceFile;
+use crate::record::RecordWriter;
+use crate::visit::{self, ModuleHeader, SymbolVisitor};
+use crate::windows::pe_dwarf::PeDwarf;
 
 #[derive(Debug, PartialEq)]
 pub enum Type {
@@ -31,15 +47,25 @@ pub enum Type {
 pub enum Platform {
     Linux,
     Mac,
+    Windows,
+    /// No operating system (e.g. WebAssembly)
+    Unknown,
 }
 
-impl Display for Platform {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        let p = match self {
+impl Platform {
+    fn as_str(self) -> &'static str {
+        match self {
             Self::Linux => "Linux",
             Self::Mac => "Mac",
-        };
-        write!(f, "{}", p)
+            Self::Windows => "windows",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -68,13 +94,21 @@ impl Display for ElfInfo {
             writeln!(f, "INFO CODE_ID {}", code_id)?;
         }
 
+        let mut writer = RecordWriter::new();
         for (n, file_name) in self.files.get_mapping().iter().enumerate() {
-            writeln!(f, "FILE {} {}", n, file_name)?;
+            writer
+                .str("FILE ")
+                .dec(n as u64)
+                .space()
+                .name(file_name)
+                .end();
+            writer.flush_if_full(f)?;
         }
 
         for (_, sym) in self.symbols.iter() {
-            write!(f, "{}", sym)?;
+            sym.write_records(&mut writer, f)?;
         }
+        writer.flush(f)?;
 
         write!(f, "{}", self.stack)?;
 
@@ -189,7 +223,7 @@ impl InlineeManager {
 
 #[derive(Debug, Default)]
 pub struct Collector {
-    syms: ElfSymbols,
+    pub(super) syms: ElfSymbols,
 }
 
 impl Collector {
@@ -218,7 +252,10 @@ impl Collector {
             Some(demangled) => demangled,
             None => {
                 let aname = name.as_str();
-                warn!("Didn't manage to demangle {:?}", name);
+                diagnostics::report(
+                    Category::Demangling,
+                    format!("Didn't manage to demangle {:?}", name),
+                );
                 aname.to_string()
             }
         }
@@ -236,7 +273,10 @@ impl Collector {
         match name.demangle(DemangleOptions::complete()) {
             Some(demangled) => demangled,
             None => {
-                warn!("Didn't manage to demangle {}", name);
+                diagnostics::report(
+                    Category::Demangling,
+                    format!("Didn't manage to demangle {}", name),
+                );
                 name.to_string()
             }
         }
@@ -275,33 +315,44 @@ impl Collector {
         // compute line length
         lines.finalize(fun.address as u32, fun.size as u32, &());
 
-        self.syms.insert(
-            fun.address as u32,
-            ElfSymbol {
-                name: Self::demangle(&fun.name),
-                is_public: false,
-                is_multiple: false,
-                rva: fun.address as u32,
-                len: fun.size as u32,
-                parameter_size: 0,
-                source: lines,
-            },
-        );
+        let name = Self::demangle(&fun.name);
+        match self.syms.entry(fun.address as u32) {
+            btree_map::Entry::Occupied(mut e) => {
+                // Several functions have been folded at the same address (ICF):
+                // keep the smallest name to always get the same one
+                let sym = e.get_mut();
+                if sym.name == name {
+                    return;
+                }
+                sym.is_multiple = true;
+                if name < sym.name {
+                    sym.name = name;
+                    sym.len = fun.size as u32;
+                    sym.source = lines;
+                }
+            }
+            btree_map::Entry::Vacant(e) => {
+                e.insert(ElfSymbol {
+                    name,
+                    is_public: false,
+                    is_multiple: false,
+                    rva: fun.address as u32,
+                    len: fun.size as u32,
+                    parameter_size: 0,
+                    source: lines,
+                    provenance: Provenance::Dwarf,
+                });
+            }
+        }
     }
 
     pub fn collect_functions(
         &mut self,
-        o: &Object,
+        ds: &DwarfDebugSession,
         source: &mut SourceFiles,
     ) -> common::Result<()> {
-        let ds = o.debug_session().map_err(|e| e.compat())?;
-        let ds = if let ObjectDebugSession::Dwarf(ds) = ds {
-            ds
-        } else {
-            unreachable!();
-        };
-
         for fun in ds.functions() {
+            cancel::check()?;
             match fun {
                 Ok(fun) => {
                     self.collect_function(&fun, source);
@@ -315,15 +366,15 @@ impl Collector {
         Ok(())
     }
 
-    fn collect_publics(&mut self, o: &Object) {
-        for sym in o.symbols() {
+    fn collect_publics(&mut self, symbols: &SymbolMap) {
+        for sym in symbols.iter() {
             match self.syms.entry(sym.address as u32) {
                 btree_map::Entry::Occupied(_) => {}
                 btree_map::Entry::Vacant(e) => {
-                    let sym_name = sym.name.map_or_else(
-                        || "<name omitted>".to_string(),
-                        |n| Self::demangle_str(&n.to_owned()),
-                    );
+                    let sym_name = sym
+                        .name
+                        .as_ref()
+                        .map_or_else(|| "<name omitted>".to_string(), |n| Self::demangle_str(n));
                     e.insert(ElfSymbol {
                         name: sym_name,
                         is_public: true,
@@ -332,6 +383,7 @@ impl Collector {
                         len: sym.size as u32,
                         parameter_size: 0,
                         source: Lines::default(),
+                        provenance: Provenance::SymbolTable,
                     });
                 }
             }
@@ -340,11 +392,8 @@ impl Collector {
 
     fn get_stack_info(o: &Object) -> String {
         let mut buf = Vec::new();
-        let writer = Cursor::new(&mut buf);
-
-        let mut cfi_writer = AsciiCfiWriter::new(writer);
-        if let Err(e) = cfi_writer.process(o) {
-            error!("CFI: {:?}", e);
+        if let Err(e) = common::write_cfi(Cursor::new(&mut buf), o) {
+            error!("CFI: {}", e);
         }
 
         String::from_utf8(buf).unwrap()
@@ -356,36 +405,138 @@ impl ElfInfo {
         buf: &[u8],
         file_name: &str,
         platform: Platform,
+        split_dwarf: Option<&SplitDwarf>,
         mapping: Option<Arc<PathMappings>>,
     ) -> common::Result<Self> {
-        let o = Object::parse(buf).map_err(|e| e.compat())?;
-        Self::from_object(&o, file_name, platform, mapping)
+        let buf = compressed::decompress_sections(buf);
+        let o = Object::parse(&buf).map_err(|e| e.compat())?;
+        Self::from_object(&o, file_name, platform, split_dwarf, mapping)
     }
 
     pub fn from_object(
         o: &Object,
         file_name: &str,
         platform: Platform,
+        split_dwarf: Option<&SplitDwarf>,
+        mapping: Option<Arc<PathMappings>>,
+    ) -> common::Result<Self> {
+        let ds = o.debug_session().map_err(|e| e.compat())?;
+        let ds = if let ObjectDebugSession::Dwarf(ds) = ds {
+            ds
+        } else {
+            unreachable!();
+        };
+
+        Self::from_session(
+            o,
+            &ds,
+            &o.symbol_map(),
+            o.has_debug_info(),
+            file_name,
+            platform,
+            split_dwarf,
+            mapping,
+        )
+    }
+
+    /// Dump a PE containing some DWARF (MinGW or Cygwin toolchains)
+    pub(crate) fn from_pe(
+        buf: &[u8],
+        file_name: &str,
+        mapping: Option<Arc<PathMappings>>,
+    ) -> common::Result<Self> {
+        let pe = PeObject::parse(buf).map_err(|e| e.compat())?;
+        let ds = PeDwarf::new(&pe).debug_session()?;
+        let symbols = pe.symbol_map();
+        let o = Object::Pe(pe);
+
+        let elf = Self::from_session(
+            &o,
+            &ds,
+            &symbols,
+            true,
+            file_name,
+            Platform::Windows,
+            None,
+            mapping,
+        )?;
+        if elf.debug_id == DebugId::nil().breakpad().to_string() {
+            warn!(
+                "No CodeView record in {}: link with --build-id to get a debug id",
+                file_name
+            );
+        }
+
+        Ok(elf)
+    }
+
+    /// Dump the functions of a DWARF session and the symbols (their addresses must be in the same space)
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_session(
+        o: &Object,
+        ds: &DwarfDebugSession,
+        symbols: &SymbolMap,
+        has_debug_info: bool,
+        file_name: &str,
+        platform: Platform,
+        split_dwarf: Option<&SplitDwarf>,
         mapping: Option<Arc<PathMappings>>,
     ) -> common::Result<Self> {
         let mut collector = Collector::default();
         let mut source = SourceFiles::new(mapping);
+        // For a PE, it's the guid and the age of the CodeView record
         let debug_id = format!("{}", o.debug_id().breakpad());
         let code_id = o.code_id().map(|c| c.as_str().to_string().to_uppercase());
         let cpu = o.arch().name();
-        let bin_type = if o.has_debug_info() {
+        let bin_type = if has_debug_info {
             Type::DebugInfo
         } else {
             Type::Stripped
         };
 
-        collector.collect_functions(o, &mut source)?;
-        collector.collect_publics(o);
+        collector.collect_functions(ds, &mut source)?;
+        let address_offset = o.load_address();
+        match o {
+            Object::Elf(elf) => {
+                dwarf::collect_indexed_units(elf, address_offset, &mut collector, &mut source)?
+            }
+            Object::MachO(macho) => {
+                dwarf::collect_indexed_units(macho, address_offset, &mut collector, &mut source)?
+            }
+            Object::Wasm(wasm) => {
+                dwarf::collect_indexed_units(wasm, address_offset, &mut collector, &mut source)?
+            }
+            _ => {}
+        }
+        if let (Some(split_dwarf), Object::Elf(elf)) = (split_dwarf, o) {
+            split_dwarf.collect_functions(elf, &mut collector, &mut source)?;
+        }
+        if let (false, Object::MachO(macho)) = (has_debug_info, o) {
+            oso::collect_functions(macho.data(), address_offset, &mut collector, &mut source)?;
+        }
+        match (has_debug_info, o) {
+            (false, Object::Elf(elf)) => {
+                gopclntab::collect_functions(elf, address_offset, &mut collector, &mut source)?
+            }
+            (false, Object::MachO(macho)) => {
+                gopclntab::collect_functions(macho, address_offset, &mut collector, &mut source)?
+            }
+            _ => {}
+        }
+        collector.collect_publics(symbols);
 
-        let stack = Collector::get_stack_info(o);
+        let mut stack = Collector::get_stack_info(o);
+        if let Object::Elf(elf) = o {
+            let exidx = arm_exidx::get_stack_info(elf.data(), elf.load_address(), &stack);
+            stack.push_str(&exidx);
+        }
+        if let Object::MachO(macho) = o {
+            let compact = compact_unwind::get_stack_info(macho.data(), &stack);
+            stack.push_str(&compact);
+        }
         let symbols = crate::linux::symbol::append_dummy_symbol(collector.syms, file_name);
 
-        Ok(Self {
+        let mut elf = Self {
             symbols,
             files: source.get_mapping(),
             file_name: String::from(file_name),
@@ -395,7 +546,31 @@ impl ElfInfo {
             stack,
             bin_type,
             platform,
-        })
+        };
+        elf.sort_files();
+
+        Ok(elf)
+    }
+
+    /// Use a debug id computed from the content when the object hasn't any
+    pub(crate) fn set_fallback_debug_id(&mut self, buf: &[u8], fallback: DebugIdFallback) {
+        if self.debug_id != DebugId::nil().breakpad().to_string() {
+            return;
+        }
+
+        self.debug_id = debug_id::compute(buf, fallback).breakpad().to_string();
+        info!(
+            "No debug id in {}: use {} computed from the content",
+            self.file_name, self.debug_id
+        );
+    }
+
+    /// Sort the FILE records by name to have a reproducible output
+    fn sort_files(&mut self) {
+        let remapping = self.files.sort();
+        for sym in self.symbols.values_mut() {
+            sym.fix_lines(Some(&remapping));
+        }
     }
 }
 
@@ -410,11 +585,13 @@ impl Mergeable for ElfInfo {
         }
 
         // Just to avoid to iterate on the bigger
-        let (mut left, mut right) = if left.symbols.len() > right.symbols.len() {
-            (left, right)
-        } else {
-            (right, left)
-        };
+        // (the file name is used to always get the same result whatever the order of the arguments)
+        let (mut left, mut right) =
+            if (left.symbols.len(), &left.file_name) > (right.symbols.len(), &right.file_name) {
+                (left, right)
+            } else {
+                (right, left)
+            };
 
         // merge the CFIs
         if left.stack.is_empty() {
@@ -445,7 +622,12 @@ impl Mergeable for ElfInfo {
                 match left.symbols.entry(*addr) {
                     btree_map::Entry::Occupied(mut e) => {
                         // we already have one so just discard this one
-                        e.get_mut().is_multiple = true;
+                        // unless it comes from a better source
+                        let a_sym = e.get_mut();
+                        if sym.provenance > a_sym.provenance {
+                            std::mem::swap(a_sym, sym);
+                        }
+                        a_sym.is_multiple = true;
                     }
                     btree_map::Entry::Vacant(e) => {
                         e.insert(sym.clone());
@@ -458,8 +640,8 @@ impl Mergeable for ElfInfo {
             match left.symbols.entry(*addr) {
                 btree_map::Entry::Occupied(mut e) => {
                     let a_sym = e.get_mut();
-                    if a_sym.is_public {
-                        // FUNC is more interesting than the PUBLIC
+                    if sym.provenance > a_sym.provenance {
+                        // FUNC (from DWARF) is more interesting than the PUBLIC
                         // so just keep the FUNC
                         sym.fix_lines(remapping.as_ref());
                         std::mem::swap(a_sym, sym);
@@ -481,6 +663,8 @@ impl Mergeable for ElfInfo {
             left.file_name = right.file_name;
         }
 
+        left.sort_files();
+
         Ok(left)
     }
 }
@@ -498,4 +682,95 @@ impl Dumpable for ElfInfo {
     fn get_name(&self) -> &str {
         &self.file_name
     }
+
+    fn get_source_files(&self) -> &[String] {
+        self.files.get_mapping()
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        let symbols = self.symbols.values().map(|sym| RecordProvenance {
+            record: if sym.is_public { "PUBLIC" } else { "FUNC" },
+            address: sym.rva,
+            size: sym.len,
+            name: Some(&sym.name),
+            provenance: sym.provenance,
+        });
+        let stack =
+            common::get_stack_ranges(&self.stack)
+                .into_iter()
+                .map(|(record, address, size)| RecordProvenance {
+                    record,
+                    address,
+                    size,
+                    name: None,
+                    provenance: Provenance::Dwarf,
+                });
+        symbols.chain(stack).collect()
+    }
+
+    /// Give the records as Display writes them
+    fn visit<V: for<'a> SymbolVisitor<'a>>(&self, visitor: &mut V) -> common::Result<()> {
+        visitor.module(ModuleHeader {
+            os: self.platform.as_str(),
+            arch: self.cpu,
+            debug_id: &self.debug_id,
+            name: &self.file_name,
+        })?;
+
+        if let Some(code_id) = self.code_id.as_ref() {
+            visitor.info(format!("CODE_ID {}", code_id).into())?;
+        }
+
+        for (n, file_name) in self.files.get_mapping().iter().enumerate() {
+            visitor.file(SourceFile {
+                id: n as u32,
+                name: Cow::Borrowed(file_name),
+                checksum: None,
+            })?;
+        }
+
+        for sym in self.symbols.values() {
+            sym.visit(visitor)?;
+        }
+
+        visit::visit_stack(&self.stack, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn get_function(name: &str) -> Function<'_> {
+        Function {
+            address: 0x1000,
+            size: 0x10,
+            name: Name::new(name, NameMangling::Unmangled, Language::C),
+            compilation_dir: b"",
+            lines: Vec::new(),
+            inlinees: Vec::new(),
+            inline: false,
+        }
+    }
+
+    #[test]
+    fn test_folded_functions() {
+        let mut collector = Collector::default();
+        let mut source = SourceFiles::new(None);
+
+        collector.collect_function(&get_function("foo"), &mut source);
+        assert!(!collector.syms[&0x1000].is_multiple);
+
+        // Same function seen twice
+        collector.collect_function(&get_function("foo"), &mut source);
+        assert!(!collector.syms[&0x1000].is_multiple);
+
+        collector.collect_function(&get_function("bar"), &mut source);
+        collector.collect_function(&get_function("oof"), &mut source);
+        let sym = &collector.syms[&0x1000];
+        assert!(sym.is_multiple);
+        assert_eq!(sym.name, "bar");
+        assert!(sym.to_string().starts_with("FUNC m 1000 10 0 bar"));
+    }
 }
@@ -0,0 +1,382 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use log::{info, warn};
+use std::convert::TryFrom;
+use symbolic::common::{Language, Name, NameMangling};
+use symbolic::debuginfo::dwarf::Dwarf;
+use symbolic::debuginfo::{FileInfo, Function, LineInfo};
+
+use super::elf::Collector;
+use super::source::SourceFiles;
+use crate::cancel;
+use crate::common;
+
+/*
+ The Go runtime needs the function names and the pc to file:line tables for its stack traces
+ so they're in .gopclntab (__gopclntab in Mach-O) even when the binary is built with -ldflags=-s -w
+ (no DWARF and no symbol table).
+ The table starts with a header (pcHeader in runtime/symtab.go) with the offsets of the sub-tables:
+ the function names, the compilation units (indices in the file table), the file names, the pc-value
+ tables and the function table which is sorted by address and gives the _func of each function.
+ The pc-value tables are a sequence of (zigzag value delta, pc delta) varints starting at value -1.
+ The formats of Go 1.16, Go 1.18 (addresses relative to the start of the text) and Go 1.20 are handled.
+*/
+
+const GO_116_MAGIC: u32 = 0xffff_fffa;
+const GO_118_MAGIC: u32 = 0xffff_fff0;
+const GO_120_MAGIC: u32 = 0xffff_fff1;
+
+struct Table<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+    ptr_size: usize,
+    /// The size of an instruction unit (1 on x86, 4 on arm)
+    min_lc: u64,
+    /// The start of the text (Go >= 1.18)
+    text_start: Option<u64>,
+    nfunc: usize,
+    funcnametab: usize,
+    cutab: usize,
+    filetab: usize,
+    pctab: usize,
+    functab: usize,
+}
+
+impl<'a> Table<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let magic = data.get(..4)?;
+        let magic_le = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+        let magic_be = u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]);
+        let is_magic = |m| matches!(m, GO_116_MAGIC | GO_118_MAGIC | GO_120_MAGIC);
+        let (magic, little_endian) = if is_magic(magic_le) {
+            (magic_le, true)
+        } else if is_magic(magic_be) {
+            (magic_be, false)
+        } else {
+            warn!("Unsupported .gopclntab (magic {:x})", magic_le);
+            return None;
+        };
+
+        let ptr_size = *data.get(7)? as usize;
+        if ptr_size != 4 && ptr_size != 8 {
+            return None;
+        }
+        let mut table = Self {
+            data,
+            little_endian,
+            ptr_size,
+            min_lc: u64::from(*data.get(6)?),
+            text_start: None,
+            nfunc: 0,
+            funcnametab: 0,
+            cutab: 0,
+            filetab: 0,
+            pctab: 0,
+            functab: 0,
+        };
+
+        // nfunc, nfiles, [textStart], funcnameOffset, cuOffset, filetabOffset, pctabOffset, pclnOffset
+        let fields: Vec<_> = (0..8).map(|i| table.uint(8 + i * ptr_size)).collect();
+        let mut fields = fields.into_iter();
+        table.nfunc = fields.next()?? as usize;
+        fields.next()?;
+        if magic != GO_116_MAGIC {
+            table.text_start = Some(fields.next()??);
+        }
+        table.funcnametab = fields.next()?? as usize;
+        table.cutab = fields.next()?? as usize;
+        table.filetab = fields.next()?? as usize;
+        table.pctab = fields.next()?? as usize;
+        table.functab = fields.next()?? as usize;
+
+        Some(table)
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset.checked_add(4)?)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn uint(&self, offset: usize) -> Option<u64> {
+        if self.ptr_size == 4 {
+            return self.u32(offset).map(u64::from);
+        }
+        let bytes = self.data.get(offset..offset.checked_add(8)?)?;
+        let mut buf = [0; 8];
+        buf.copy_from_slice(bytes);
+        Some(if self.little_endian {
+            u64::from_le_bytes(buf)
+        } else {
+            u64::from_be_bytes(buf)
+        })
+    }
+
+    fn cstr(&self, offset: usize) -> Option<&'a [u8]> {
+        let data = self.data.get(offset..)?;
+        let end = data.iter().position(|c| *c == 0)?;
+        Some(&data[..end])
+    }
+
+    fn uvarint(&self, offset: &mut usize) -> Option<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *self.data.get(*offset)?;
+            *offset += 1;
+            value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+            shift += 7;
+        }
+    }
+
+    /// Get the address of the i-th function and the offset of its _func
+    fn get_entry(&self, index: usize) -> Option<(u64, usize)> {
+        match self.text_start {
+            Some(text_start) => {
+                let offset = self.functab + index * 8;
+                let entry = text_start + u64::from(self.u32(offset)?);
+                Some((entry, self.functab + self.u32(offset + 4)? as usize))
+            }
+            None => {
+                let offset = self.functab + index * 2 * self.ptr_size;
+                let entry = self.uint(offset)?;
+                Some((
+                    entry,
+                    self.functab + self.uint(offset + self.ptr_size)? as usize,
+                ))
+            }
+        }
+    }
+
+    /// Get the ranges of pc and their values in a pc-value table
+    fn get_values(&self, offset: u32, entry: u64) -> Vec<(u64, u64, i64)> {
+        let mut values = Vec::new();
+        if offset == 0 {
+            return values;
+        }
+
+        let mut pos = self.pctab + offset as usize;
+        let mut pc = entry;
+        let mut value = -1i64;
+        while let Some(delta) = self.uvarint(&mut pos) {
+            if delta == 0 && !values.is_empty() {
+                break;
+            }
+            // zigzag encoding
+            value += ((delta >> 1) as i64) ^ -((delta & 1) as i64);
+            let end = match self.uvarint(&mut pos) {
+                Some(pc_delta) => pc + pc_delta * self.min_lc,
+                None => break,
+            };
+            values.push((pc, end, value));
+            pc = end;
+        }
+
+        values
+    }
+
+    fn get_file(&self, cu_offset: u32, index: i64) -> Option<FileInfo<'a>> {
+        let index = u32::try_from(index).ok()?;
+        let offset = self.u32(self.cutab + (cu_offset.checked_add(index)? as usize) * 4)?;
+        if offset == u32::MAX {
+            return None;
+        }
+        Some(FileInfo {
+            name: self.cstr(self.filetab + offset as usize)?,
+            dir: &[],
+        })
+    }
+
+    fn get_function(&self, index: usize, load_address: u64) -> Option<Function<'a>> {
+        let (entry, func) = self.get_entry(index)?;
+        let (end, _) = self.get_entry(index + 1)?;
+
+        // The offset of the fields following the entry in _func
+        let base = func
+            + if self.text_start.is_some() {
+                4
+            } else {
+                self.ptr_size
+            };
+        let name = self.cstr(self.funcnametab + self.u32(base)? as usize)?;
+        let pcfile = self.u32(base + 16)?;
+        let pcln = self.u32(base + 20)?;
+        let cu_offset = self.u32(base + 28)?;
+
+        // Split the line ranges at the file changes (with the inlined functions)
+        let files = self.get_values(pcfile, entry);
+        let mut lines = Vec::new();
+        let mut i = 0;
+        for (start, end, line) in self.get_values(pcln, entry) {
+            let mut pc = start;
+            while pc < end {
+                while i < files.len() && files[i].1 <= pc {
+                    i += 1;
+                }
+                let (file, until) = match files.get(i) {
+                    Some((file_start, file_end, file)) if *file_start <= pc => {
+                        (self.get_file(cu_offset, *file), (*file_end).min(end))
+                    }
+                    Some((file_start, _, _)) => (None, (*file_start).min(end)),
+                    None => (None, end),
+                };
+                if line > 0 {
+                    lines.push(LineInfo {
+                        address: pc - load_address,
+                        size: Some(until - pc),
+                        file: file.unwrap_or_default(),
+                        line: line as u64,
+                    });
+                }
+                pc = until;
+            }
+        }
+
+        Some(Function {
+            address: entry.checked_sub(load_address)?,
+            size: end.saturating_sub(entry),
+            name: Name::new(
+                String::from_utf8_lossy(name),
+                NameMangling::Unmangled,
+                Language::Go,
+            ),
+            compilation_dir: &[],
+            lines,
+            inlinees: Vec::new(),
+            inline: false,
+        })
+    }
+}
+
+fn collect_table(
+    data: &[u8],
+    load_address: u64,
+    collector: &mut Collector,
+    source: &mut SourceFiles,
+) -> common::Result<usize> {
+    let table = match Table::parse(data) {
+        Some(table) => table,
+        None => return Ok(0),
+    };
+
+    let mut count = 0;
+    for index in 0..table.nfunc {
+        cancel::check()?;
+        match table.get_function(index, load_address) {
+            Some(fun) => {
+                collector.collect_function(&fun, source);
+                count += 1;
+            }
+            None => warn!("Invalid function {} in .gopclntab", index),
+        }
+    }
+
+    Ok(count)
+}
+
+/// Collect the functions in the Go line table (used when there's no DWARF)
+pub(super) fn collect_functions<'data, D: Dwarf<'data>>(
+    object: &D,
+    load_address: u64,
+    collector: &mut Collector,
+    source: &mut SourceFiles,
+) -> common::Result<()> {
+    if let Some(section) = object.raw_section("gopclntab") {
+        let count = collect_table(&section.data, load_address, collector, source)?;
+        info!("{} functions from the Go line table", count);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn put(data: &mut Vec<u8>, offset: usize, value: u64, size: usize) {
+        if data.len() < offset + size {
+            data.resize(offset + size, 0);
+        }
+        data[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    /// A Go 1.20 table with main.main at 0x401000 and main.square at 0x401020
+    fn get_table() -> Vec<u8> {
+        let mut data = Vec::new();
+        put(&mut data, 0, u64::from(GO_120_MAGIC), 4);
+        put(&mut data, 6, 1, 1);
+        put(&mut data, 7, 8, 1);
+        // nfunc, nfiles, textStart and the offsets of the sub-tables
+        let header = [2, 1, 0x40_1000, 72, 96, 100, 116, 136];
+        for (i, value) in header.iter().enumerate() {
+            put(&mut data, 8 + i * 8, *value, 8);
+        }
+
+        data.extend_from_slice(b"main.main\0main.square\0");
+        put(&mut data, 96, 0, 4);
+        data.extend_from_slice(b"/tmp/main.go\0");
+
+        // pc-value tables (at 1, 0 is for no table):
+        //  - file 0 for 0x20 bytes,
+        //  - line 3 for 8 bytes and line 4 for 0x18 bytes,
+        //  - file 0 for 0x90 bytes (a varint on 2 bytes),
+        //  - line 10 for 0x90 bytes
+        let pctab = [
+            0, 2, 0x20, 0, 8, 8, 2, 0x18, 0, 2, 0x90, 1, 0, 22, 0x90, 1, 0,
+        ];
+        data.resize(116, 0);
+        data.extend_from_slice(&pctab);
+
+        // The function table and the _func (offsets relative to the function table)
+        let functab = [(0, 24), (0x20, 64), (0xb0, 0)];
+        for (i, (entry, func)) in functab.iter().enumerate() {
+            put(&mut data, 136 + i * 8, *entry, 4);
+            put(&mut data, 136 + i * 8 + 4, *func, 4);
+        }
+        let funcs = [(0, 0, 1, 4), (0x20, 10, 9, 13)];
+        for (i, (entry, name, pcfile, pcln)) in funcs.iter().enumerate() {
+            let func = 136 + 24 + i * 40;
+            put(&mut data, func, *entry, 4);
+            put(&mut data, func + 4, *name, 4);
+            put(&mut data, func + 20, *pcfile, 4);
+            put(&mut data, func + 24, *pcln, 4);
+            put(&mut data, func + 32, 0, 4);
+            put(&mut data, func + 39, 0, 1);
+        }
+
+        data
+    }
+
+    #[test]
+    fn test_gopclntab() {
+        let data = get_table();
+        let mut collector = Collector::default();
+        let mut source = SourceFiles::new(None);
+        let count = collect_table(&data, 0x40_0000, &mut collector, &mut source).unwrap();
+        assert_eq!(count, 2);
+
+        let sym: String = collector.syms.values().map(|sym| sym.to_string()).collect();
+        assert_eq!(
+            sym,
+            "FUNC 1000 20 0 main.main\n1000 8 3 0\n1008 18 4 0\nFUNC 1020 90 0 main.square\n1020 90 10 0\n"
+        );
+
+        // Not a Go line table (Go 1.2 magic)
+        assert_eq!(
+            collect_table(&[0xfb, 0xff, 0xff, 0xff], 0, &mut collector, &mut source).unwrap(),
+            0
+        );
+        assert_eq!(source.get_mapping().get_mapping(), &["/tmp/main.go"]);
+    }
+}
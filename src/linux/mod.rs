@@ -3,8 +3,15 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+mod arm_exidx;
+mod compressed;
+pub(crate) mod debug_file;
+mod dwarf;
 pub mod elf;
+mod gopclntab;
 
 mod lines;
+mod oso;
 mod source;
+pub(crate) mod split_dwarf;
 mod symbol;
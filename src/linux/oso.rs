@@ -0,0 +1,257 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use failure::Fail;
+use goblin::archive::Archive;
+use goblin::mach::symbols::{N_FUN, N_OSO, N_SECT, N_SO, N_STAB, N_TYPE};
+use goblin::mach::MachO;
+use hashbrown::HashMap;
+use log::{info, warn};
+use std::fs;
+use std::time::UNIX_EPOCH;
+use symbolic::debuginfo::macho::MachObject;
+use symbolic::debuginfo::Function;
+
+use super::dwarf::{self, Sections};
+use super::elf::Collector;
+use super::source::SourceFiles;
+use crate::cancel;
+use crate::common;
+use crate::utils;
+
+/*
+ When a macOS binary hasn't been processed by dsymutil, its DWARF is still in the object files:
+ the linker only leaves a debug map in the symbol table made of some stabs, an N_OSO with the path
+ of an object file (or lib.a(foo.o) for a member of an archive) and its modification time, followed
+ by the N_FUN giving the address in the binary of each function of this object.
+ The functions are read in the object files and moved at their address in the binary (matched with
+ the symbols of the object), the ones which have been dead-stripped are dropped.
+ An object file modified since the link is skipped (its addresses are likely wrong).
+*/
+
+/// An object file referenced in the debug map
+#[derive(Debug, Default, PartialEq)]
+struct OsoObject {
+    path: String,
+    /// The modification time (in seconds since the epoch) at link time (0 if unknown)
+    mtime: u64,
+    /// The address in the binary of the functions by name
+    functions: HashMap<String, u64>,
+}
+
+/// Get the object files and their functions from the stabs (name, type and value)
+fn get_objects<'a>(stabs: impl Iterator<Item = (&'a str, u8, u64)>) -> Vec<OsoObject> {
+    let mut objects = Vec::new();
+    let mut current: Option<OsoObject> = None;
+    for (name, typ, value) in stabs {
+        match typ {
+            N_OSO => {
+                objects.extend(current.take());
+                current = Some(OsoObject {
+                    path: name.to_string(),
+                    mtime: value,
+                    ..Default::default()
+                });
+            }
+            // An empty N_SO ends the compilation unit
+            N_SO if name.is_empty() => objects.extend(current.take()),
+            // The second N_FUN of a function (without name) contains its size
+            N_FUN if !name.is_empty() => {
+                if let Some(current) = current.as_mut() {
+                    current.functions.insert(name.to_string(), value);
+                }
+            }
+            _ => {}
+        }
+    }
+    objects.extend(current);
+
+    objects
+}
+
+fn get_mtime(path: &str) -> common::Result<u64> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(modified.duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Read an object file (maybe in an archive) and check that it hasn't been modified since the link
+fn read_object(object: &OsoObject) -> common::Result<Vec<u8>> {
+    let member = object
+        .path
+        .strip_suffix(')')
+        .and_then(|path| path.split_once('('));
+    let (buf, mtime) = if let Some((archive_path, member)) = member {
+        let buf = utils::read(archive_path)?;
+        let archive = Archive::parse(&buf).map_err(|e| e.compat())?;
+        let data = archive.extract(member, &buf).map_err(|e| e.compat())?;
+        // The member header contains its modification time (12 decimal digits after the name)
+        let offset = archive.get(member).unwrap().header_offset as usize + 16;
+        let mtime = buf
+            .get(offset..offset + 12)
+            .and_then(|t| std::str::from_utf8(t).ok())
+            .and_then(|t| t.trim().parse().ok())
+            .unwrap_or(0);
+        (data.to_vec(), mtime)
+    } else {
+        (utils::read(&object.path)?, get_mtime(&object.path)?)
+    };
+
+    if object.mtime != 0 && mtime != object.mtime {
+        return Err(format!("{} has been modified since the link", object.path).into());
+    }
+
+    Ok(buf)
+}
+
+fn relocate(fun: &mut Function, delta: u64) {
+    fun.address = fun.address.wrapping_add(delta);
+    for line in fun.lines.iter_mut() {
+        line.address = line.address.wrapping_add(delta);
+    }
+    for inlinee in fun.inlinees.iter_mut() {
+        relocate(inlinee, delta);
+    }
+}
+
+/// Collect the functions of an object file at their address in the binary
+fn collect_object(
+    object: &OsoObject,
+    buf: &[u8],
+    load_address: u64,
+    collector: &mut Collector,
+    source: &mut SourceFiles,
+) -> common::Result<usize> {
+    // The address of the functions in the object file and their rva in the binary
+    let macho = MachO::parse(buf, 0).map_err(|e| e.compat())?;
+    let mut rvas = HashMap::new();
+    for (name, nlist) in macho.symbols().flatten() {
+        if nlist.n_type & N_STAB != 0 || nlist.n_type & N_TYPE != N_SECT {
+            continue;
+        }
+        if let Some(address) = object.functions.get(name) {
+            rvas.insert(nlist.n_value, address.wrapping_sub(load_address));
+        }
+    }
+
+    // symbolic skips the line rows at address 0 where the first function of an object is
+    let o = MachObject::parse(buf).map_err(|e| e.compat())?;
+    let sections = Sections::new(&o, false);
+    let dwarf = sections.dwarf()?;
+
+    let mut count = 0;
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        cancel::check()?;
+        let unit = dwarf.unit(header)?;
+        for mut fun in dwarf::get_functions(&dwarf, &unit, &dwarf, &unit, 0)? {
+            // A function without symbol in the debug map has been dead-stripped
+            if let Some(rva) = rvas.get(&fun.address) {
+                let delta = rva.wrapping_sub(fun.address);
+                relocate(&mut fun, delta);
+                collector.collect_function(&fun, source);
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Collect the functions in the object files referenced by the debug map of a Mach-O binary
+pub(super) fn collect_functions(
+    data: &[u8],
+    load_address: u64,
+    collector: &mut Collector,
+    source: &mut SourceFiles,
+) -> common::Result<()> {
+    let macho = match MachO::parse(data, 0) {
+        Ok(macho) => macho,
+        _ => return Ok(()),
+    };
+    let stabs = macho
+        .symbols()
+        .flatten()
+        .filter(|(_, nlist)| nlist.n_type & N_STAB != 0)
+        .map(|(name, nlist)| (name, nlist.n_type, nlist.n_value));
+    let objects = get_objects(stabs);
+
+    for object in objects.iter() {
+        cancel::check()?;
+        let res = read_object(object)
+            .and_then(|buf| collect_object(object, &buf, load_address, collector, source));
+        match res {
+            Ok(count) => info!("{} functions from {}", count, object.path),
+            Err(e) => warn!("Cannot read the debug info in {}: {}", object.path, e),
+        }
+        // The buffer of the object has been freed so its strings can't be used as keys anymore
+        source.clear_cache();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn get_object(path: &str, mtime: u64) -> OsoObject {
+        OsoObject {
+            path: path.to_string(),
+            mtime,
+            functions: vec![("_square", 0x1_0000_3f40), ("_main", 0x1_0000_3f80)]
+                .into_iter()
+                .map(|(name, address)| (name.to_string(), address))
+                .collect(),
+        }
+    }
+
+    fn dump(object: &OsoObject) -> String {
+        let buf = read_object(object).unwrap();
+        let mut collector = Collector::default();
+        let mut source = SourceFiles::new(None);
+        let count =
+            collect_object(object, &buf, 0x1_0000_0000, &mut collector, &mut source).unwrap();
+        assert_eq!(count, 2);
+        collector.syms.values().map(|sym| sym.to_string()).collect()
+    }
+
+    #[test]
+    fn test_stabs() {
+        let stabs = vec![
+            ("/tmp/", N_SO, 0),
+            ("foo.c", N_SO, 0),
+            ("/tmp/foo.o", N_OSO, 1234),
+            ("", 0x2e, 0x1000),
+            ("_foo", N_FUN, 0x1000),
+            ("", N_FUN, 0x20),
+            ("", N_SO, 0),
+            ("/tmp/liba.a(bar.o)", N_OSO, 0),
+            ("_bar", N_FUN, 0x1020),
+            ("", N_FUN, 0x10),
+        ];
+        let objects = get_objects(stabs.into_iter());
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].path, "/tmp/foo.o");
+        assert_eq!(objects[0].mtime, 1234);
+        assert_eq!(objects[0].functions.len(), 1);
+        assert_eq!(objects[0].functions["_foo"], 0x1000);
+        assert_eq!(objects[1].path, "/tmp/liba.a(bar.o)");
+        assert_eq!(objects[1].functions["_bar"], 0x1020);
+    }
+
+    #[test]
+    fn test_oso() {
+        let sym = dump(&get_object("./test_data/mac/oso.o", 0));
+        assert!(sym.contains("FUNC 3f40 10 0 square\n3f40 7 1 0\n3f47 7 2 0\n3f4e 2 3 0\n"));
+        assert!(sym.contains("FUNC 3f80 b 0 main\n3f80 4 6 0\n3f84 7 7 0\n"));
+
+        // The same object in an archive
+        assert_eq!(dump(&get_object("./test_data/mac/liboso.a(oso.o)", 0)), sym);
+
+        // The object has been modified since the link
+        assert!(read_object(&get_object("./test_data/mac/oso.o", 1)).is_err());
+    }
+}
@@ -10,6 +10,8 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use symbolic::debuginfo::FileInfo;
 
+use crate::common;
+use crate::interner::Interner;
 use crate::mapping::PathMappings;
 use crate::utils;
 
@@ -18,10 +20,12 @@ type SliceRef = (*const u8, usize);
 #[derive(Debug, Default)]
 pub struct SourceFiles {
     ref_to_id: HashMap<PathBuf, u32>,
-    fake_id_to_ref: Vec<(Option<u32>, String)>,
-    id_to_ref: Vec<String>,
+    fake_id_to_ref: Vec<(Option<u32>, Arc<str>)>,
+    id_to_ref: Vec<Arc<str>>,
     cache: HashMap<(SliceRef, SliceRef, SliceRef), u32>,
     mapping: Option<Arc<PathMappings>>,
+    // Several paths can be mapped on the same one
+    paths: Interner,
 }
 
 #[derive(Debug, Default)]
@@ -103,7 +107,10 @@ impl SourceFiles {
                         } else {
                             None
                         };
-                        let path = new_path.unwrap_or_else(|| path.to_str().unwrap().to_string());
+                        let path = match new_path {
+                            Some(new_path) => self.paths.intern(&new_path),
+                            None => self.paths.intern(path.to_str().unwrap()),
+                        };
                         e.insert(id);
                         self.fake_id_to_ref.push((None, path));
                         id
@@ -115,6 +122,11 @@ impl SourceFiles {
         }
     }
 
+    /// Forget the strings used as keys in the cache (when the buffer containing them is freed)
+    pub(super) fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
     // Some file_ids are not consumed because they correspond to some inlinee stuff
     // So in order to have consecutive ids just return an id for really used files
     pub(super) fn get_true_id(&mut self, fake_id: u32) -> u32 {
@@ -130,10 +142,13 @@ impl SourceFiles {
     }
 
     pub(super) fn get_mapping(self) -> SourceMap {
-        SourceMap {
-            ref_to_id: self.ref_to_id,
-            id_to_ref: self.id_to_ref,
-        }
+        // self.ref_to_id contains the fake ids so rebuild it with the true ones
+        let mut map = SourceMap {
+            ref_to_id: HashMap::default(),
+            id_to_ref: self.id_to_ref.iter().map(|path| path.to_string()).collect(),
+        };
+        map.update_ref_to_id();
+        map
     }
 }
 
@@ -142,6 +157,22 @@ impl SourceMap {
         &self.id_to_ref
     }
 
+    fn update_ref_to_id(&mut self) {
+        self.ref_to_id = self
+            .id_to_ref
+            .iter()
+            .enumerate()
+            .map(|(id, path)| (PathBuf::from(path), id as u32))
+            .collect();
+    }
+
+    /// Sort the files by name: the returned vector maps the old ids to the new ones
+    pub(super) fn sort(&mut self) -> Vec<u32> {
+        let remapping = common::sort_files(&mut self.id_to_ref);
+        self.update_ref_to_id();
+        remapping
+    }
+
     pub(super) fn merge(&mut self, other: &mut SourceMap) -> Option<Vec<u32>> {
         // No FUNC so nothing to do
         if other.id_to_ref.is_empty() {
@@ -160,15 +191,16 @@ impl SourceMap {
         let mut remapping = vec![0; other.id_to_ref.len()];
         self.id_to_ref.reserve(other.id_to_ref.len());
 
-        for (path, id) in other.ref_to_id.iter() {
-            let id = *id as usize;
-            if let Some(an_id) = self.ref_to_id.get(path) {
+        for (id, path) in other.id_to_ref.iter().enumerate() {
+            let path_buf = PathBuf::from(path);
+            if let Some(an_id) = self.ref_to_id.get(&path_buf) {
                 // self has already this path so map the id to the existing one
                 remapping[id] = *an_id;
             } else {
                 let new_id = self.id_to_ref.len() as u32;
                 remapping[id] = new_id;
-                self.id_to_ref.push(other.id_to_ref[id].clone());
+                self.id_to_ref.push(path.clone());
+                self.ref_to_id.insert(path_buf, new_id);
             }
         }
 
@@ -0,0 +1,244 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use failure::Fail;
+use gimli::{constants, DwarfFileType, RangeLists};
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use symbolic::debuginfo::elf::ElfObject;
+
+use super::compressed;
+use super::dwarf::{self, Sections, Slice, Unit};
+use super::elf::Collector;
+use super::source::SourceFiles;
+use crate::cancel;
+use crate::common;
+use crate::utils;
+
+/*
+ With -gsplit-dwarf (Fission), the ELF only contains some skeleton units: their addresses, their
+ line tables and the name of the .dwo file containing the rest of the unit (the functions).
+ The .dwo files can be merged in a .dwp package which is next to the ELF (<elf>.dwp).
+ The .dwo files are searched in --dwo-dir, in the compilation directory and next to the ELF.
+*/
+
+/// Where the .dwo files and the .dwp package of an ELF are searched
+#[derive(Debug, Default)]
+pub(crate) struct SplitDwarf {
+    /// The path of the ELF containing the skeleton units
+    path: PathBuf,
+    /// The directory given with --dwo-dir
+    dwo_dir: Option<PathBuf>,
+}
+
+impl SplitDwarf {
+    pub(crate) fn new(path: &Path, dwo_dir: Option<&Path>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            dwo_dir: dwo_dir.map(|d| d.to_path_buf()),
+        }
+    }
+
+    fn find_dwp(&self) -> Option<PathBuf> {
+        let file_name = format!("{}.dwp", self.path.file_name()?.to_str()?);
+        self.dwo_dir
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .chain(std::iter::once(self.path.with_file_name(&file_name)))
+            .find(|p| p.is_file())
+    }
+
+    fn find_dwo(&self, dwo_name: &str, comp_dir: Option<&str>) -> Option<PathBuf> {
+        let dwo_name = Path::new(dwo_name);
+        let file_name = dwo_name.file_name()?;
+        let mut candidates = Vec::new();
+        if let Some(dir) = self.dwo_dir.as_ref() {
+            candidates.push(dir.join(dwo_name));
+            candidates.push(dir.join(file_name));
+        }
+        candidates.push(match comp_dir {
+            Some(comp_dir) => Path::new(comp_dir).join(dwo_name),
+            None => dwo_name.to_path_buf(),
+        });
+        candidates.push(self.path.with_file_name(file_name));
+
+        candidates.into_iter().find(|p| p.is_file())
+    }
+
+    fn get_skeleton_units<'a>(
+        skeleton: &gimli::Dwarf<Slice<'a>>,
+    ) -> common::Result<Vec<(Unit<'a>, Option<String>)>> {
+        let mut units = Vec::new();
+        let mut headers = skeleton.units();
+        while let Some(header) = headers.next()? {
+            let unit = skeleton.unit(header)?;
+            if unit.dwo_id.is_none() {
+                continue;
+            }
+
+            let mut entries = unit.entries();
+            let dwo_name = match entries.next_dfs()? {
+                Some((_, root)) => root
+                    .attr_value(constants::DW_AT_dwo_name)?
+                    .or(root.attr_value(constants::DW_AT_GNU_dwo_name)?),
+                None => None,
+            };
+            let dwo_name = dwo_name
+                .and_then(|name| skeleton.attr_string(&unit, name).ok())
+                .map(|name| String::from_utf8_lossy(name.slice()).into_owned());
+            units.push((unit, dwo_name));
+        }
+        Ok(units)
+    }
+
+    fn collect_unit(
+        skeleton: &gimli::Dwarf<Slice<'_>>,
+        skeleton_unit: &Unit<'_>,
+        dwarf: &gimli::Dwarf<Slice<'_>>,
+        address_offset: u64,
+        collector: &mut Collector,
+        source: &mut SourceFiles,
+    ) -> common::Result<()> {
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            let mut unit = dwarf.unit(header)?;
+            if unit.dwo_id != skeleton_unit.dwo_id {
+                continue;
+            }
+            unit.copy_relocated_attributes(skeleton_unit);
+
+            return dwarf::collect_unit(
+                skeleton,
+                skeleton_unit,
+                dwarf,
+                &unit,
+                address_offset,
+                collector,
+                source,
+            );
+        }
+
+        warn!("No split unit with the id {:?}", skeleton_unit.dwo_id);
+        Ok(())
+    }
+
+    /// Collect the functions in the split units referenced by the skeleton units of the ELF
+    pub(super) fn collect_functions(
+        &self,
+        elf: &ElfObject,
+        collector: &mut Collector,
+        source: &mut SourceFiles,
+    ) -> common::Result<()> {
+        let sections = Sections::new(elf, false);
+        let skeleton = sections.dwarf()?;
+        let units = Self::get_skeleton_units(&skeleton)?;
+        if units.is_empty() {
+            return Ok(());
+        }
+
+        let address_offset = elf.load_address();
+        let dwp_buf = match self.find_dwp() {
+            Some(path) => {
+                info!("Use the DWARF package {}", path.display());
+                Some(utils::read(&path)?)
+            }
+            None => None,
+        };
+        let dwp_buf = dwp_buf.as_deref().map(compressed::decompress_sections);
+        let dwp_elf = match dwp_buf.as_ref() {
+            Some(buf) => Some(ElfObject::parse(buf).map_err(|e| e.compat())?),
+            None => None,
+        };
+        let dwp_sections = dwp_elf.as_ref().map(|elf| Sections::new(elf, true));
+        let package = match dwp_sections.as_ref() {
+            Some(sections) => Some(sections.package()?),
+            None => None,
+        };
+
+        for (unit, dwo_name) in units.iter() {
+            cancel::check()?;
+
+            if let Some(package) = package.as_ref() {
+                if let Some(dwarf) = package.find_cu(unit.dwo_id.unwrap(), &skeleton)? {
+                    Self::collect_unit(&skeleton, unit, &dwarf, address_offset, collector, source)?;
+                    continue;
+                }
+            }
+
+            let dwo_name = match dwo_name.as_ref() {
+                Some(dwo_name) => dwo_name,
+                None => {
+                    warn!("No dwo name in the skeleton unit {:?}", unit.dwo_id);
+                    continue;
+                }
+            };
+            let comp_dir = unit
+                .comp_dir
+                .map(|dir| String::from_utf8_lossy(dir.slice()).into_owned());
+            let path = match self.find_dwo(dwo_name, comp_dir.as_deref()) {
+                Some(path) => path,
+                None => {
+                    warn!("Cannot find the split DWARF file {}", dwo_name);
+                    continue;
+                }
+            };
+
+            let buf = utils::read(&path)?;
+            let buf = compressed::decompress_sections(&buf);
+            let dwo = ElfObject::parse(&buf).map_err(|e| e.compat())?;
+            let dwo_sections = Sections::new(&dwo, true);
+            let mut dwarf = dwo_sections.dwarf()?;
+            // The addresses (and the ranges with DWARF 4) are in the skeleton
+            dwarf.file_type = DwarfFileType::Dwo;
+            dwarf.debug_addr = skeleton.debug_addr;
+            dwarf.ranges = RangeLists::new(
+                *skeleton.ranges.debug_ranges(),
+                *dwarf.ranges.debug_rnglists(),
+            );
+            Self::collect_unit(&skeleton, unit, &dwarf, address_offset, collector, source)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::linux::elf::{ElfInfo, Platform};
+
+    fn dump(dwo_dir: Option<&Path>) -> String {
+        let path = PathBuf::from("./test_data/linux/basic.split");
+        let buf = utils::read_file(&path);
+        let split_dwarf = SplitDwarf::new(&path, dwo_dir);
+        let elf = ElfInfo::new(
+            &buf,
+            "basic.split",
+            Platform::Linux,
+            Some(&split_dwarf),
+            None,
+        )
+        .unwrap();
+        elf.to_string()
+    }
+
+    #[test]
+    fn test_dwo() {
+        let sym = dump(None);
+        assert!(sym
+            .lines()
+            .any(|l| l.starts_with("FILE 0 ") && l.ends_with("basic.cpp")));
+        // The functions are in basic.split-basic.dwo
+        assert!(sym.contains("FUNC 1129 26 0 inline_1(int)\n1129 7 2 0\n"));
+        assert!(sym.contains("FUNC 12c1 1c 0 main\n12c1 f 35 0\n"));
+    }
+
+    #[test]
+    fn test_dwp() {
+        let dwo_dir = PathBuf::from("./test_data/linux/dwp");
+        assert_eq!(dump(Some(&dwo_dir)), dump(None));
+    }
+}
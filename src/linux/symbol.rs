@@ -3,10 +3,15 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 
+use crate::common::{self, Provenance};
 use crate::line::Lines;
+use crate::model::Public;
+use crate::record::RecordWriter;
+use crate::visit::{FunctionHeader, SymbolVisitor};
 
 #[derive(Clone, Debug, Default)]
 pub(super) struct ElfSymbol {
@@ -17,45 +22,68 @@ pub(super) struct ElfSymbol {
     pub len: u32,
     pub parameter_size: u32,
     pub source: Lines,
+    pub provenance: Provenance,
 }
 
 pub(super) type ElfSymbols = BTreeMap<u32, ElfSymbol>;
 
 impl Display for ElfSymbol {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        if self.is_public {
-            writeln!(
-                f,
-                "PUBLIC {}{:x} {:x} {}",
-                if self.is_multiple { "m " } else { "" },
-                self.rva,
-                self.parameter_size,
-                self.name,
-            )?;
-        } else {
-            writeln!(
-                f,
-                "FUNC {}{:x} {:x} {:x} {}",
-                if self.is_multiple { "m " } else { "" },
-                self.rva,
-                self.len,
-                self.parameter_size,
-                self.name,
-            )?;
+        let mut writer = RecordWriter::new();
+        self.write_records(&mut writer, f)?;
+        writer.flush(f)
+    }
+}
+
+impl ElfSymbol {
+    /// Write the PUBLIC record or the FUNC record and its LINE records
+    pub(super) fn write_records(
+        &self,
+        w: &mut RecordWriter,
+        f: &mut Formatter,
+    ) -> std::fmt::Result {
+        w.str(if self.is_public { "PUBLIC " } else { "FUNC " });
+        if self.is_multiple {
+            w.str("m ");
+        }
+        w.hex(self.rva.into()).space();
+        if !self.is_public {
+            w.hex(self.len.into()).space();
+        }
+        w.hex(self.parameter_size.into())
+            .space()
+            .name(&self.name)
+            .end();
 
-            write!(f, "{}", self.source)?;
+        if !self.is_public {
+            self.source.write_records(w, f)?;
         }
+        w.flush_if_full(f)
+    }
 
-        Ok(())
+    /// Give the PUBLIC record or the FUNC record and its LINE records to the visitor
+    pub(super) fn visit<'a, V: SymbolVisitor<'a>>(&'a self, visitor: &mut V) -> common::Result<()> {
+        if self.is_public {
+            return visitor.public(Public {
+                address: self.rva.into(),
+                parameter_size: self.parameter_size.into(),
+                name: Cow::Borrowed(&self.name),
+                multiple: self.is_multiple,
+            });
+        }
+        visitor.function(FunctionHeader {
+            address: self.rva.into(),
+            size: self.len.into(),
+            parameter_size: self.parameter_size.into(),
+            name: &self.name,
+            multiple: self.is_multiple,
+        })?;
+        self.source.visit(visitor)
     }
-}
 
-impl ElfSymbol {
     pub(super) fn fix_lines(&mut self, remapping: Option<&Vec<u32>>) {
         if let Some(remapping) = remapping {
-            for line in self.source.lines.iter_mut() {
-                line.file_id = remapping[line.file_id as usize];
-            }
+            self.source.remap_files(remapping);
         }
     }
 }
@@ -85,6 +113,7 @@ pub(super) fn append_dummy_symbol(mut syms: ElfSymbols, name: &str) -> ElfSymbol
             len: 0,
             parameter_size: 0,
             source: Lines::new(),
+            provenance: Provenance::Heuristic,
         },
     );
 
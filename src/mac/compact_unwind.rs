@@ -0,0 +1,401 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use goblin::mach::cputype::{CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+use goblin::mach::MachO;
+use hashbrown::HashSet;
+use log::info;
+use std::fmt::Write;
+
+use crate::common;
+
+/*
+ The linker puts the unwind info of most of the functions in __TEXT,__unwind_info (compact unwind)
+ and only keeps in __eh_frame the ones which can't be described with a compact encoding.
+ An entry maps a function (an offset from the image base, so an rva) to a 32-bit encoding which
+ describes the frame at the end of the prologue: a frame pointer based frame, a frameless one with
+ a fixed stack size or a stack size read in the sub instruction of the prologue (x86_64 only).
+ The DWARF mode points in __eh_frame and is already handled with the other CFI so the functions
+ having some STACK CFI are skipped.
+ See compact_unwind_encoding.h and CompactUnwinder.hpp in libunwind.
+*/
+
+const UNWIND_SECTION_VERSION: u32 = 1;
+const UNWIND_SECOND_LEVEL_REGULAR: u32 = 2;
+const UNWIND_SECOND_LEVEL_COMPRESSED: u32 = 3;
+const UNWIND_MODE_MASK: u32 = 0x0f00_0000;
+
+const UNWIND_X86_64_MODE_RBP_FRAME: u32 = 0x0100_0000;
+const UNWIND_X86_64_MODE_STACK_IMMD: u32 = 0x0200_0000;
+const UNWIND_X86_64_MODE_STACK_IND: u32 = 0x0300_0000;
+
+const UNWIND_ARM64_MODE_FRAMELESS: u32 = 0x0200_0000;
+const UNWIND_ARM64_MODE_FRAME: u32 = 0x0400_0000;
+
+/// The registers in the x86_64 encodings (0 is for no register)
+const X86_64_REGS: [&str; 7] = ["", "$rbx", "$r12", "$r13", "$r14", "$r15", "$rbp"];
+/// The pairs of callee-saved registers in the arm64 encodings (the d registers aren't recovered)
+const ARM64_PAIRS: [(u32, Option<(&str, &str)>); 9] = [
+    (0x001, Some(("x19", "x20"))),
+    (0x002, Some(("x21", "x22"))),
+    (0x004, Some(("x23", "x24"))),
+    (0x008, Some(("x25", "x26"))),
+    (0x010, Some(("x27", "x28"))),
+    (0x100, None),
+    (0x200, None),
+    (0x400, None),
+    (0x800, None),
+];
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The sections of __TEXT (the instructions are read for the x86_64 indirect encoding)
+struct Text<'a> {
+    base: u64,
+    sections: Vec<(u64, &'a [u8])>,
+}
+
+impl Text<'_> {
+    fn read_u32(&self, rva: u64) -> Option<u32> {
+        let address = self.base.checked_add(rva)?;
+        self.sections.iter().find_map(|(start, data)| {
+            let offset = address.checked_sub(*start)? as usize;
+            read_u32(data, offset)
+        })
+    }
+}
+
+/// Get the functions (rva, size and encoding) in __unwind_info
+fn get_entries(data: &[u8]) -> Option<Vec<(u32, u32, u32)>> {
+    if read_u32(data, 0)? != UNWIND_SECTION_VERSION {
+        return None;
+    }
+    let common_offset = read_u32(data, 4)? as usize;
+    let common_count = read_u32(data, 8)?;
+    let index_offset = read_u32(data, 20)? as usize;
+    let index_count = read_u32(data, 24)? as usize;
+
+    let mut functions = Vec::new();
+    let mut end = 0;
+    for i in 0..index_count {
+        let index = index_offset + i * 12;
+        let function_offset = read_u32(data, index)?;
+        let page = read_u32(data, index + 4)? as usize;
+        // The last index entry only contains the end of the last function
+        end = end.max(function_offset);
+        if page == 0 {
+            continue;
+        }
+
+        let entry_offset = page + read_u16(data, page + 4)? as usize;
+        let entry_count = read_u16(data, page + 6)? as usize;
+        match read_u32(data, page)? {
+            UNWIND_SECOND_LEVEL_REGULAR => {
+                for j in 0..entry_count {
+                    let entry = entry_offset + j * 8;
+                    functions.push((read_u32(data, entry)?, read_u32(data, entry + 4)?));
+                }
+            }
+            UNWIND_SECOND_LEVEL_COMPRESSED => {
+                let encodings_offset = page + read_u16(data, page + 8)? as usize;
+                for j in 0..entry_count {
+                    let entry = read_u32(data, entry_offset + j * 4)?;
+                    let encoding_index = entry >> 24;
+                    let encoding = if encoding_index < common_count {
+                        read_u32(data, common_offset + encoding_index as usize * 4)?
+                    } else {
+                        let local = (encoding_index - common_count) as usize;
+                        read_u32(data, encodings_offset + local * 4)?
+                    };
+                    functions.push((function_offset + (entry & 0x00ff_ffff), encoding));
+                }
+            }
+            _ => return None,
+        }
+    }
+    functions.sort_unstable_by_key(|(rva, _)| *rva);
+
+    let mut entries = Vec::with_capacity(functions.len());
+    for (i, (rva, encoding)) in functions.iter().enumerate() {
+        let next = functions.get(i + 1).map_or(end, |(next, _)| *next);
+        if next > *rva {
+            entries.push((*rva, next - rva, *encoding));
+        }
+    }
+
+    Some(entries)
+}
+
+/// Get the registers saved in a frameless x86_64 function from the permutation
+fn get_permuted_registers(count: u32, mut permutation: u32) -> Option<Vec<&'static str>> {
+    if count > 6 {
+        return None;
+    }
+
+    // The permutation is encoded in a factorial base: the i-th register is the k-th unused one
+    let mut indices = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let divisor: u32 = (7 - count..=5 - i).product();
+        indices.push(permutation / divisor);
+        permutation %= divisor;
+    }
+
+    let mut used = [false; 7];
+    let mut registers = Vec::with_capacity(indices.len());
+    for index in indices {
+        let reg = (1..7).filter(|r| !used[*r]).nth(index as usize)?;
+        used[reg] = true;
+        registers.push(X86_64_REGS[reg]);
+    }
+
+    Some(registers)
+}
+
+fn get_x86_64_rules(rva: u32, encoding: u32, text: &Text) -> Option<String> {
+    let mut rules = String::new();
+    match encoding & UNWIND_MODE_MASK {
+        UNWIND_X86_64_MODE_RBP_FRAME => {
+            // The registers are saved from rbp - 8 * offset
+            let offset = i64::from((encoding >> 16) & 0xff);
+            rules.push_str(".cfa: $rbp 16 + .ra: .cfa -8 + ^ $rbp: .cfa -16 + ^");
+            for i in 0..5 {
+                let reg = ((encoding >> (3 * i)) & 0x7) as usize;
+                match reg {
+                    0 => {}
+                    1..=5 => {
+                        let pos = -16 - 8 * offset + 8 * i;
+                        write!(rules, " {}: .cfa {} + ^", X86_64_REGS[reg], pos).unwrap();
+                    }
+                    _ => return None,
+                }
+            }
+        }
+        mode @ (UNWIND_X86_64_MODE_STACK_IMMD | UNWIND_X86_64_MODE_STACK_IND) => {
+            let size = (encoding >> 16) & 0xff;
+            let size = if mode == UNWIND_X86_64_MODE_STACK_IMMD {
+                i64::from(size) * 8
+            } else {
+                // The size is the immediate of the sub in the prologue
+                let adjust = (encoding >> 13) & 0x7;
+                let size = text.read_u32(u64::from(rva) + u64::from(size))?;
+                i64::from(size) + i64::from(adjust) * 8
+            };
+            let count = (encoding >> 10) & 0x7;
+            let registers = get_permuted_registers(count, encoding & 0x3ff)?;
+
+            // The registers are pushed just after the return address
+            write!(rules, ".cfa: $rsp {} + .ra: .cfa -8 + ^", size).unwrap();
+            let count = registers.len() as i64;
+            for (i, reg) in registers.iter().enumerate() {
+                let pos = -8 - 8 * count + 8 * i as i64;
+                write!(rules, " {}: .cfa {} + ^", reg, pos).unwrap();
+            }
+        }
+        _ => return None,
+    }
+
+    Some(rules)
+}
+
+fn get_arm64_rules(encoding: u32) -> Option<String> {
+    let mut rules = String::new();
+    let mut pos = match encoding & UNWIND_MODE_MASK {
+        UNWIND_ARM64_MODE_FRAME => {
+            rules.push_str(".cfa: x29 16 + .ra: .cfa -8 + ^ x29: .cfa -16 + ^ x30: .cfa -8 + ^");
+            -24
+        }
+        UNWIND_ARM64_MODE_FRAMELESS => {
+            let size = ((encoding >> 12) & 0xfff) * 16;
+            write!(rules, ".cfa: sp {} + .ra: x30", size).unwrap();
+            -8
+        }
+        _ => return None,
+    };
+
+    // The pairs are saved from the top of the frame
+    for (bit, pair) in ARM64_PAIRS.iter() {
+        if encoding & bit == 0 {
+            continue;
+        }
+        if let Some((first, second)) = pair {
+            write!(
+                rules,
+                " {}: .cfa {} + ^ {}: .cfa {} + ^",
+                first,
+                pos,
+                second,
+                pos - 8
+            )
+            .unwrap();
+        }
+        pos -= 16;
+    }
+
+    Some(rules)
+}
+
+/// Get the STACK CFI records from the compact unwind info of the functions which don't have
+/// any in stack
+pub(crate) fn get_stack_info(data: &[u8], stack: &str) -> String {
+    let mut records = String::new();
+    let macho = match MachO::parse(data, 0) {
+        Ok(macho) => macho,
+        _ => return records,
+    };
+    let cputype = macho.header.cputype;
+    if cputype != CPU_TYPE_X86_64 && cputype != CPU_TYPE_ARM64 {
+        return records;
+    }
+
+    let mut text = Text {
+        base: 0,
+        sections: Vec::new(),
+    };
+    let mut unwind_info = None;
+    for segment in macho.segments.iter() {
+        if segment.name().ok() != Some("__TEXT") {
+            continue;
+        }
+        text.base = segment.vmaddr;
+        for (section, data) in segment.sections().unwrap_or_default() {
+            if section.name().ok() == Some("__unwind_info") {
+                unwind_info = Some(data);
+            }
+            text.sections.push((section.addr, data));
+        }
+    }
+
+    let entries = match unwind_info.and_then(get_entries) {
+        Some(entries) => entries,
+        None => return records,
+    };
+    let with_cfi: HashSet<_> = common::get_stack_ranges(stack)
+        .into_iter()
+        .map(|(_, rva, _)| rva)
+        .collect();
+
+    let mut count = 0;
+    for (rva, size, encoding) in entries {
+        if with_cfi.contains(&rva) {
+            continue;
+        }
+        let rules = if cputype == CPU_TYPE_X86_64 {
+            get_x86_64_rules(rva, encoding, &text)
+        } else {
+            get_arm64_rules(encoding)
+        };
+        if let Some(rules) = rules {
+            writeln!(records, "STACK CFI INIT {:x} {:x} {}", rva, size, rules).unwrap();
+            count += 1;
+        }
+    }
+    info!("{} STACK CFI records from __unwind_info", count);
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn push(data: &mut Vec<u8>, words: &[u32]) {
+        for word in words {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_entries() {
+        let mut data = Vec::new();
+        // Header: 1 common encoding at 28, the index at 32 with 2 entries
+        push(&mut data, &[1, 28, 1, 0, 0, 32, 2]);
+        push(&mut data, &[0x0100_0000]);
+        // Index: a compressed page at 56 and the end at 0x1100
+        push(&mut data, &[0x1000, 56, 0, 0x1100, 0, 0]);
+        // Compressed page: 3 entries at +12, 1 local encoding at +24
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(&12u16.to_le_bytes());
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(&24u16.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        push(&mut data, &[0x0000_0000, 0x0100_0040, 0x0000_0080]);
+        push(&mut data, &[0x0200_0000]);
+
+        assert_eq!(
+            get_entries(&data).unwrap(),
+            vec![
+                (0x1000, 0x40, 0x0100_0000),
+                (0x1040, 0x40, 0x0200_0000),
+                (0x1080, 0x80, 0x0100_0000)
+            ]
+        );
+        assert!(get_entries(&[2, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn test_x86_64() {
+        // sub $0x1234, %rsp at 0x2005 (the immediate is at 0x2008)
+        let instructions = [0, 0, 0, 0, 0, 0x48, 0x81, 0xec, 0x34, 0x12, 0, 0];
+        let text = Text {
+            base: 0x1_0000_0000,
+            sections: vec![(0x1_0000_2000, &instructions[..])],
+        };
+        let rules = |encoding| get_x86_64_rules(0x2000, encoding, &text).unwrap_or_default();
+
+        // push %rbp; mov %rsp, %rbp; push %rbx; push %r12
+        assert_eq!(
+            rules(0x0102_000a),
+            ".cfa: $rbp 16 + .ra: .cfa -8 + ^ $rbp: .cfa -16 + ^ $r12: .cfa -32 + ^ $rbx: .cfa -24 + ^"
+        );
+        // push %rbx; push %r14; sub $0x10, %rsp: 40 bytes with the return address
+        assert_eq!(
+            rules(0x0205_080f),
+            ".cfa: $rsp 40 + .ra: .cfa -8 + ^ $r14: .cfa -24 + ^ $rbx: .cfa -16 + ^"
+        );
+        // Indirect: the immediate at rva + 8 and one push
+        assert_eq!(
+            rules(0x0308_0000 | (1 << 13) | (1 << 10)),
+            ".cfa: $rsp 4668 + .ra: .cfa -8 + ^ $rbx: .cfa -16 + ^"
+        );
+        // DWARF
+        assert!(get_x86_64_rules(0x2000, 0x0400_0010, &text).is_none());
+    }
+
+    #[test]
+    fn test_permutation() {
+        let regs = |count, permutation| get_permuted_registers(count, permutation).unwrap();
+        assert_eq!(regs(1, 0), vec!["$rbx"]);
+        assert_eq!(regs(2, 15), vec!["$r14", "$rbx"]);
+        assert_eq!(regs(3, 0), vec!["$rbx", "$r12", "$r13"]);
+        assert_eq!(
+            regs(6, 719),
+            vec!["$rbp", "$r15", "$r14", "$r13", "$r12", "$rbx"]
+        );
+        assert!(get_permuted_registers(7, 0).is_none());
+    }
+
+    #[test]
+    fn test_arm64() {
+        assert_eq!(
+            get_arm64_rules(0x0400_0003).unwrap(),
+            ".cfa: x29 16 + .ra: .cfa -8 + ^ x29: .cfa -16 + ^ x30: .cfa -8 + ^ x19: .cfa -24 + ^ x20: .cfa -32 + ^ x21: .cfa -40 + ^ x22: .cfa -48 + ^"
+        );
+        // d8/d9 are saved after x21/x22
+        assert_eq!(
+            get_arm64_rules(0x0200_3102).unwrap(),
+            ".cfa: sp 48 + .ra: x30 x21: .cfa -8 + ^ x22: .cfa -16 + ^"
+        );
+        assert!(get_arm64_rules(0x0300_0000).is_none());
+    }
+}
@@ -10,9 +10,13 @@ use std::sync::Arc;
 use symbolic::common::Arch;
 use symbolic::debuginfo::Archive;
 
-use crate::common::{self, Dumpable, Mergeable};
+use crate::common::{self, Dumpable, Mergeable, RecordProvenance};
 use crate::linux::elf::{ElfInfo, Platform};
 use crate::mapping::PathMappings;
+use crate::visit::SymbolVisitor;
+
+/// The value of --arch to dump all the architectures of a fat binary
+pub const ALL_ARCHS: &str = "all";
 
 #[derive(Debug)]
 pub struct MachoInfo {
@@ -47,7 +51,7 @@ impl MachoInfo {
 
         if let Some(object) = object {
             Ok(Self {
-                elf: ElfInfo::from_object(&object, file_name, Platform::Mac, mapping)?,
+                elf: ElfInfo::from_object(&object, file_name, Platform::Mac, None, mapping)?,
             })
         } else {
             Err(format!(
@@ -59,6 +63,23 @@ impl MachoInfo {
         }
     }
 
+    /// Get the objects for all the architectures present in the file
+    pub fn get_all(
+        buf: &[u8],
+        file_name: &str,
+        mapping: Option<Arc<PathMappings>>,
+    ) -> common::Result<Vec<(Arch, Self)>> {
+        let archive = Archive::parse(buf).map_err(|e| e.compat())?;
+        let mut infos = Vec::new();
+        for object in archive.objects() {
+            let object = object.map_err(|e| e.compat())?;
+            let elf =
+                ElfInfo::from_object(&object, file_name, Platform::Mac, None, mapping.clone())?;
+            infos.push((object.arch(), Self { elf }));
+        }
+        Ok(infos)
+    }
+
     /// Print on screen the cpu arch for the different objects present in the fat file
     pub fn print_architectures(buf: &[u8], file_name: String) -> common::Result<()> {
         let archive = Archive::parse(buf).map_err(|e| e.compat())?;
@@ -98,4 +119,16 @@ impl Dumpable for MachoInfo {
     fn get_name(&self) -> &str {
         self.elf.get_name()
     }
+
+    fn get_source_files(&self) -> &[String] {
+        self.elf.get_source_files()
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        self.elf.get_provenance()
+    }
+
+    fn visit<V: for<'a> SymbolVisitor<'a>>(&self, visitor: &mut V) -> common::Result<()> {
+        self.elf.visit(visitor)
+    }
 }
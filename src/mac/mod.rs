@@ -3,4 +3,5 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+pub(crate) mod compact_unwind;
 pub mod macho;
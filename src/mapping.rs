@@ -180,6 +180,8 @@ impl PathMappingGenerator {
 #[derive(Debug, Default)]
 pub struct PathMappings {
     mappings: Vec<PathMappingGenerator>,
+    // (old, new) prefixes as for -fdebug-prefix-map
+    prefixes: Vec<(String, String)>,
     cache: Mutex<HashMap<String, String>>,
 }
 
@@ -189,23 +191,49 @@ impl PathMappings {
         sources: &Option<Vec<&str>>,
         destinations: &Option<Vec<&str>>,
         file: &Option<&str>,
+        prefixes: &Option<Vec<&str>>,
     ) -> common::Result<Option<Self>> {
         let vars = Self::get_variables(variables)?;
+        let prefixes = Self::get_prefixes(prefixes)?;
         let mut mappings = Vec::new();
 
         Self::get_mappings_from_file(&vars, file, &mut mappings)?;
         Self::get_mappings(&vars, sources, destinations, &mut mappings)?;
 
-        Ok(if mappings.is_empty() {
+        Ok(if mappings.is_empty() && prefixes.is_empty() {
             None
         } else {
             Some(PathMappings {
                 mappings,
+                prefixes,
                 cache: Mutex::new(HashMap::default()),
             })
         })
     }
 
+    fn get_prefixes(prefixes: &Option<Vec<&str>>) -> common::Result<Vec<(String, String)>> {
+        let mut res = Vec::new();
+        if let Some(prefixes) = prefixes {
+            for prefix in prefixes {
+                let pair = prefix.splitn(2, '=').collect::<Vec<_>>();
+                if pair.len() != 2 || pair[0].is_empty() {
+                    return Err(format!("Invalid prefix map {}: must be old=new", prefix).into());
+                }
+                res.push((pair[0].to_string(), pair[1].to_string()));
+            }
+        }
+        Ok(res)
+    }
+
+    fn map_prefix(&self, file: &str) -> Option<String> {
+        // As for -fdebug-prefix-map, the last matching prefix wins
+        self.prefixes
+            .iter()
+            .rev()
+            .find(|(old, _)| file.starts_with(old.as_str()))
+            .map(|(old, new)| format!("{}{}", new, &file[old.len()..]))
+    }
+
     fn get_variables(vars: &Option<Vec<&str>>) -> common::Result<HashMap<String, String>> {
         let mut variables = HashMap::default();
         if let Some(vars) = vars {
@@ -331,6 +359,12 @@ impl PathMappings {
             }
         }
 
+        // The prefixes are applied to the mapped path or to the original one
+        let res = match res {
+            Some(res) => Some(self.map_prefix(&res).unwrap_or(res)),
+            None => self.map_prefix(file_str),
+        };
+
         if let Some(res) = res {
             let mut cache = self.cache.lock().unwrap();
             cache.insert(file_str.to_string(), res.clone());
@@ -368,9 +402,15 @@ mod tests {
 
     #[test]
     fn test_path_mapping_file() {
-        let mappings = PathMappings::new(&None, &None, &None, &Some("./test_data/mapping.json"))
-            .unwrap()
-            .unwrap();
+        let mappings = PathMappings::new(
+            &None,
+            &None,
+            &None,
+            &Some("./test_data/mapping.json"),
+            &None,
+        )
+        .unwrap()
+        .unwrap();
 
         let files = vec![
             ("/home/worker/a/c/ddd.cpp", "hg:hg.mozilla.org/mozilla-central:a/c/ddd.cpp:6639deb894172375b05d6791f5f8c7d53ca79723"),
@@ -385,4 +425,32 @@ mod tests {
             assert_eq!(mappings.map(path).unwrap().unwrap(), expected.to_string())
         }
     }
+
+    #[test]
+    fn test_path_prefix_map() {
+        let mappings = PathMappings::new(
+            &None,
+            &None,
+            &None,
+            &None,
+            &Some(vec![
+                r"z:\build\=c:\src\",
+                r"z:\build\obj\=",
+                "/build/=/src/",
+            ]),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            mappings.map(r"z:\build\dom\foo.cpp").unwrap().unwrap(),
+            r"c:\src\dom\foo.cpp"
+        );
+        assert_eq!(
+            mappings.map(r"z:\build\obj\bar.h").unwrap().unwrap(),
+            "bar.h"
+        );
+        assert_eq!(mappings.map("/build/a/b.c").unwrap().unwrap(), "/src/a/b.c");
+        assert!(mappings.map("/usr/include/stdio.h").unwrap().is_none());
+    }
 }
@@ -0,0 +1,368 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use log::{info, warn};
+use std::io::Write;
+use std::path::Path;
+
+use crate::cache;
+use crate::cancel;
+use crate::common;
+use crate::dumper::{self, Config};
+use crate::utils;
+
+/*
+ A minidump starts with a header giving the location of a directory of streams: the module list stream
+ contains a MINIDUMP_MODULE for each module loaded in the crashed process with its path, its timestamp
+ and size (the code id of a PE) and a CodeView record giving its debug file and debug id (RSDS for PE and
+ Mach-O, BpEL with the build id for ELF).
+ Each module is fetched from the symbol servers (the binary first since its debug file is fetched with it
+ and it contains the unwind info) and dumped in the store.
+ The modules which aren't on any server can be written in the csv format of Socorro's missing symbols
+ list (debug_file,debug_id,code_file,code_id) to be fed to the pipeline fetching them.
+*/
+
+const MINIDUMP_SIGNATURE: u32 = 0x504d_444d;
+const MODULE_LIST_STREAM: u32 = 4;
+const MODULE_SIZE: usize = 108;
+const CV_SIGNATURE_RSDS: u32 = 0x5344_5352;
+const CV_SIGNATURE_ELF: u32 = 0x4c45_7042;
+
+/// A module loaded in the crashed process
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Module {
+    pub code_file: String,
+    pub code_id: Option<String>,
+    pub debug_file: Option<String>,
+    pub debug_id: Option<String>,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    let bytes = buf.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Read a MINIDUMP_STRING (its length in bytes followed by some UTF-16)
+fn read_string(buf: &[u8], offset: usize) -> Option<String> {
+    let len = read_u32(buf, offset)? as usize;
+    let chars: Option<Vec<_>> = (0..len / 2)
+        .map(|i| read_u16(buf, offset + 4 + 2 * i))
+        .collect();
+    Some(String::from_utf16_lossy(&chars?))
+}
+
+/// Format a guid as a debug id (the first three fields are little endian)
+fn get_debug_id(guid: &[u8], age: u32) -> String {
+    let mut id = format!(
+        "{:08X}{:04X}{:04X}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+    );
+    for byte in &guid[8..16] {
+        id.push_str(&format!("{:02X}", byte));
+    }
+    id.push_str(&format!("{:x}", age));
+    id
+}
+
+/// Get the debug file, the debug id and the code id (for ELF) from a CodeView record
+fn read_cv_record(cv: &[u8], code_file: &str, module: &mut Module) {
+    match read_u32(cv, 0) {
+        Some(CV_SIGNATURE_RSDS) if cv.len() >= 24 => {
+            let age = read_u32(cv, 20).unwrap();
+            module.debug_id = Some(get_debug_id(&cv[4..20], age));
+            let name = &cv[24..];
+            let name = &name[..name.iter().position(|c| *c == 0).unwrap_or(name.len())];
+            module.debug_file = Some(String::from_utf8_lossy(name).into_owned());
+        }
+        Some(CV_SIGNATURE_ELF) => {
+            let build_id = &cv[4..];
+            let mut guid = [0; 16];
+            let len = build_id.len().min(16);
+            guid[..len].copy_from_slice(&build_id[..len]);
+            module.debug_id = Some(get_debug_id(&guid, 0));
+            module.debug_file = Some(code_file.to_string());
+            module.code_id = Some(build_id.iter().map(|b| format!("{:02x}", b)).collect());
+        }
+        _ => {}
+    }
+}
+
+/// Get the modules in the module list of a minidump
+pub(crate) fn get_modules(buf: &[u8]) -> common::Result<Vec<Module>> {
+    if read_u32(buf, 0) != Some(MINIDUMP_SIGNATURE) {
+        return Err("Not a minidump".into());
+    }
+    let invalid = || "Invalid minidump";
+    let count = read_u32(buf, 8).ok_or_else(invalid)? as usize;
+    let directory = read_u32(buf, 12).ok_or_else(invalid)? as usize;
+
+    let list = (0..count)
+        .map(|i| directory + i * 12)
+        .find(|entry| read_u32(buf, *entry) == Some(MODULE_LIST_STREAM))
+        .and_then(|entry| read_u32(buf, entry + 8))
+        .ok_or("No module list in the minidump")? as usize;
+
+    let count = read_u32(buf, list).ok_or_else(invalid)? as usize;
+    let mut modules = Vec::new();
+    for i in 0..count {
+        let offset = list + 4 + i * MODULE_SIZE;
+        let entry = buf.get(offset..offset + MODULE_SIZE).ok_or_else(invalid)?;
+        let size = read_u32(entry, 8).unwrap();
+        let timestamp = read_u32(entry, 16).unwrap();
+        let name = read_u32(entry, 20).unwrap() as usize;
+        let cv_size = read_u32(entry, 76).unwrap() as usize;
+        let cv_offset = read_u32(entry, 80).unwrap() as usize;
+
+        let path = read_string(buf, name).ok_or_else(invalid)?;
+        let code_file = get_file_name(&path).to_string();
+        let mut module = Module {
+            code_id: Some(format!("{:08X}{:x}", timestamp, size)),
+            ..Default::default()
+        };
+        if let Some(cv) = buf.get(cv_offset..cv_offset + cv_size) {
+            read_cv_record(cv, &code_file, &mut module);
+        }
+        module.code_file = code_file;
+        modules.push(module);
+    }
+
+    Ok(modules)
+}
+
+/// The module paths come from Windows, Linux or macOS whatever the current platform
+fn get_file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Fetch a module from the symbol servers: the binary first and then the debug file
+fn fetch_module(
+    module: &Module,
+    servers: Option<&Vec<cache::SymbolServer>>,
+) -> Option<(Vec<u8>, String)> {
+    let files = [
+        (Some(&module.code_file), module.code_id.as_ref()),
+        (module.debug_file.as_ref(), module.debug_id.as_ref()),
+    ];
+    files.iter().find_map(|(file, id)| {
+        let file = get_file_name((*file)?).to_string();
+        match cache::search_file(file, (*id)?, servers) {
+            (Some(buf), file) => Some((buf, file)),
+            _ => None,
+        }
+    })
+}
+
+/// Write the modules (with a debug file and a debug id) as Socorro's missing symbols list
+fn write_missing_symbols<W: Write>(mut writer: W, modules: &[&Module]) -> common::Result<()> {
+    writeln!(writer, "debug_file,debug_id,code_file,code_id")?;
+    for module in modules {
+        if let (Some(debug_file), Some(debug_id)) = (&module.debug_file, &module.debug_id) {
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                debug_file,
+                debug_id,
+                module.code_file,
+                module.code_id.as_deref().unwrap_or_default()
+            )?;
+        }
+    }
+    // A BufWriter only reports the errors of the last write when it's flushed
+    writer.flush()?;
+    Ok(())
+}
+
+/// Fetch and dump in the store all the modules referenced by a minidump (and write the ones which
+/// aren't on the symbol servers in the missing symbols list)
+pub(crate) fn dump_modules(
+    config: &Config,
+    minidump: &str,
+    missing_symbols: Option<&str>,
+) -> common::Result<()> {
+    let buf = utils::read(minidump)?;
+    let modules = get_modules(&buf)?;
+    let servers = cache::get_sym_servers(config.symbol_server);
+
+    let mut dumped = 0;
+    let mut missing = Vec::new();
+    for module in modules.iter() {
+        cancel::check()?;
+        let (buf, file) = match fetch_module(module, servers.as_ref()) {
+            Some(res) => res,
+            None => {
+                warn!("Cannot find {} on the symbol servers", module.code_file);
+                missing.push(module);
+                continue;
+            }
+        };
+
+        let path = Path::new(&file).to_path_buf();
+        match dumper::dump_buf(config, &path, file, &buf) {
+            Ok(_) => dumped += 1,
+            Err(e) => warn!("Cannot dump {}: {}", module.code_file, e),
+        }
+    }
+    info!(
+        "{} modules dumped out of {} in {}",
+        dumped,
+        modules.len(),
+        minidump
+    );
+
+    if let Some(path) = missing_symbols {
+        let writer = utils::try_get_writer_for_sym(path)
+            .map_err(|e| format!("Cannot create the missing symbols list {}: {}", path, e))?;
+        write_missing_symbols(writer, &missing)?;
+        info!("Write {} missing symbols at {}", missing.len(), path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn put(buf: &mut Vec<u8>, offset: usize, data: &[u8]) {
+        if buf.len() < offset + data.len() {
+            buf.resize(offset + data.len(), 0);
+        }
+        buf[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn put_string(buf: &mut Vec<u8>, offset: usize, s: &str) {
+        let s: Vec<u8> = s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+        put(buf, offset, &(s.len() as u32).to_le_bytes());
+        put(buf, offset + 4, &s);
+    }
+
+    #[test]
+    fn test_minidump() {
+        let mut buf = Vec::new();
+        put(&mut buf, 0, &MINIDUMP_SIGNATURE.to_le_bytes());
+        // Two streams at 32: a dummy one and the module list at 64
+        put(&mut buf, 8, &2u32.to_le_bytes());
+        put(&mut buf, 12, &32u32.to_le_bytes());
+        put(&mut buf, 32, &3u32.to_le_bytes());
+        put(&mut buf, 44, &MODULE_LIST_STREAM.to_le_bytes());
+        put(&mut buf, 52, &64u32.to_le_bytes());
+
+        // Two modules: the names at 400 and 500 and the CodeView records at 600 and 700
+        put(&mut buf, 64, &2u32.to_le_bytes());
+        for (i, (size, timestamp, name, cv, cv_size)) in [
+            (0x2_3000u32, 0x5e9f_1b0au32, 400u32, 600u32, 34u32),
+            (0x1_0000, 0, 500, 700, 24),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let module = 68 + i * MODULE_SIZE;
+            put(&mut buf, module + 8, &size.to_le_bytes());
+            put(&mut buf, module + 16, &timestamp.to_le_bytes());
+            put(&mut buf, module + 20, &name.to_le_bytes());
+            put(&mut buf, module + 76, &cv_size.to_le_bytes());
+            put(&mut buf, module + 80, &cv.to_le_bytes());
+        }
+        put_string(&mut buf, 400, "C:\\Windows\\System32\\basic.dll");
+        put_string(&mut buf, 500, "/usr/lib/libfoo.so");
+
+        put(&mut buf, 600, &CV_SIGNATURE_RSDS.to_le_bytes());
+        put(
+            &mut buf,
+            604,
+            &[
+                0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+                0xee, 0xff,
+            ],
+        );
+        put(&mut buf, 620, &2u32.to_le_bytes());
+        put(&mut buf, 624, b"basic.pdb\0");
+
+        put(&mut buf, 700, &CV_SIGNATURE_ELF.to_le_bytes());
+        put(&mut buf, 704, &(1..=20).collect::<Vec<u8>>());
+
+        let modules = get_modules(&buf).unwrap();
+        assert_eq!(
+            modules,
+            vec![
+                Module {
+                    code_file: "basic.dll".to_string(),
+                    code_id: Some("5E9F1B0A23000".to_string()),
+                    debug_file: Some("basic.pdb".to_string()),
+                    debug_id: Some("00112233445566778899AABBCCDDEEFF2".to_string()),
+                },
+                Module {
+                    code_file: "libfoo.so".to_string(),
+                    code_id: Some("0102030405060708090a0b0c0d0e0f1011121314".to_string()),
+                    debug_file: Some("libfoo.so".to_string()),
+                    debug_id: Some("0403020106050807090A0B0C0D0E0F100".to_string()),
+                },
+            ]
+        );
+
+        assert!(get_modules(b"MDMQ").is_err());
+    }
+
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::WriteZero.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_missing_symbols() {
+        let basic = Module {
+            code_file: "basic.dll".to_string(),
+            code_id: Some("5E9F1B0A23000".to_string()),
+            debug_file: Some("basic.pdb".to_string()),
+            debug_id: Some("00112233445566778899AABBCCDDEEFF2".to_string()),
+        };
+        let libfoo = Module {
+            code_file: "libfoo.so".to_string(),
+            code_id: None,
+            debug_file: Some("libfoo.so".to_string()),
+            debug_id: Some("0403020106050807090A0B0C0D0E0F100".to_string()),
+        };
+        let no_debug_info = Module {
+            code_file: "foo.exe".to_string(),
+            ..Default::default()
+        };
+        let modules = [&basic, &no_debug_info, &libfoo];
+
+        let mut missing = Vec::new();
+        write_missing_symbols(&mut missing, &modules).unwrap();
+        assert_eq!(
+            String::from_utf8(missing).unwrap(),
+            "debug_file,debug_id,code_file,code_id
+basic.pdb,00112233445566778899AABBCCDDEEFF2,basic.dll,5E9F1B0A23000
+libfoo.so,0403020106050807090A0B0C0D0E0F100,libfoo.so,
+"
+        );
+
+        // The error of the buffered writes is only known when they're flushed
+        assert!(write_missing_symbols(std::io::BufWriter::new(FullDisk), &modules).is_err());
+
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("missing")
+            .tempdir()
+            .unwrap();
+        let path = tmp_dir.path().join("no_dir").join("missing.csv");
+        assert!(utils::try_get_writer_for_sym(path.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/*
+ The data of a sym file: it's what the json output is made of and what the visitor gives, so a
+ consumer can either read the json back, get the records while they're collected or get the whole
+ module of a file (Module::from_file).
+ The strings are borrowed from the sym file when it's parsed and from the json when it's possible
+ (a json string with an escaped char, e.g. a backslash in a Windows path, is copied): the module
+ built from the collectors owns them.
+*/
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Module<'a> {
+    #[serde(borrow)]
+    pub os: Cow<'a, str>,
+    #[serde(borrow)]
+    pub arch: Cow<'a, str>,
+    #[serde(borrow)]
+    pub debug_id: Cow<'a, str>,
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    /// The INFO records (without the INFO prefix)
+    #[serde(borrow)]
+    pub info: Vec<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub files: Vec<SourceFile<'a>>,
+    #[serde(borrow)]
+    pub functions: Vec<Function<'a>>,
+    #[serde(borrow)]
+    pub publics: Vec<Public<'a>>,
+    #[serde(borrow)]
+    pub stack: Vec<StackCfi<'a>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct SourceFile<'a> {
+    pub id: u32,
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Cow<'a, str>>,
+}
+
+impl SourceFile<'_> {
+    pub fn into_owned(self) -> SourceFile<'static> {
+        SourceFile {
+            id: self.id,
+            name: Cow::Owned(self.name.into_owned()),
+            checksum: self.checksum.map(|c| Cow::Owned(c.into_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Function<'a> {
+    pub address: u64,
+    pub size: u64,
+    pub parameter_size: u64,
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    pub multiple: bool,
+    pub lines: Vec<LineRecord>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct LineRecord {
+    pub address: u64,
+    pub size: u64,
+    pub line: u32,
+    pub file: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Public<'a> {
+    pub address: u64,
+    pub parameter_size: u64,
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    pub multiple: bool,
+}
+
+impl Public<'_> {
+    pub fn into_owned(self) -> Public<'static> {
+        Public {
+            address: self.address,
+            parameter_size: self.parameter_size,
+            name: Cow::Owned(self.name.into_owned()),
+            multiple: self.multiple,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CfiKind {
+    /// STACK CFI INIT: the rules at the start of a range
+    Init,
+    /// STACK CFI: the rules which change at an address
+    Delta,
+    /// STACK WIN: the frame data of a range
+    Win,
+}
+
+/// A STACK record
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct StackCfi<'a> {
+    pub kind: CfiKind,
+    /// The type of the frame data (STACK WIN only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_type: Option<u32>,
+    pub address: u64,
+    /// The size of the range (none for the STACK CFI records)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// The rules or the frame data after the address and the size
+    #[serde(borrow)]
+    pub rules: Cow<'a, str>,
+}
+
+impl StackCfi<'_> {
+    pub fn into_owned(self) -> StackCfi<'static> {
+        StackCfi {
+            kind: self.kind,
+            frame_type: self.frame_type,
+            address: self.address,
+            size: self.size,
+            rules: Cow::Owned(self.rules.into_owned()),
+        }
+    }
+}
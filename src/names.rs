@@ -0,0 +1,265 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+
+use crate::common::LambdaNames;
+
+/*
+ The names in the FUNC and PUBLIC records are rewritten once the sym is written, so the same rules are
+ applied whatever they come from (the TypeDumper of the pdbs or the demangled names).
+ With --collapse-templates DEPTH, the template argument lists nested deeper than DEPTH are replaced by
+ <T>: std::map<int, std::vector<int>>::find(int const&) becomes std::map<T>::find(int const&) with
+ a depth of 0 and std::map<int, std::vector<T>>::find(int const&) with a depth of 1.
+ A < only starts a template argument list after an identifier, so the comparison operators and the
+ MSVC names like `anonymous namespace'::<lambda_1> are kept as is.
+ With --normalize-names, the anonymous namespaces (`anonymous namespace' or the ?A0x1234abcd hashes
+ which change from a build to another) are (anonymous namespace) and the unnamed types (<unnamed-tag>,
+ <unnamed-type-foo>, <unnamed-enum-FOO>) are (unnamed), (unnamed type foo) and (unnamed enum FOO).
+ With --lambda-names short, the MSVC lambdas (<lambda_02a71323d951a238fa4826e9f186893b>, with an
+ optional class before) are (lambda) and with --lambda-names owner, the scope is moved inside:
+ foo::bar::<lambda_1>::operator() becomes (lambda at foo::bar)::operator().
+ With --max-name-len, the names which are still too long are cut (on a char boundary) and end with ...
+ to make clear that they're truncated.
+*/
+
+/// Get stable and readable names for the anonymous namespaces and the unnamed types
+pub(crate) fn normalize(name: &str) -> Cow<'_, str> {
+    lazy_static! {
+        static ref ANONYMOUS: Regex = Regex::new(
+            r"`anonymous namespace'|\?A0x[0-9a-fA-F]+|<unnamed-(tag|type|enum)(?:-([^<>]*))?>"
+        )
+        .unwrap();
+    }
+    ANONYMOUS.replace_all(name, |caps: &Captures| match (caps.get(1), caps.get(2)) {
+        (None, _) => "(anonymous namespace)".to_string(),
+        (Some(kind), Some(of)) => format!("(unnamed {} {})", kind.as_str(), of.as_str()),
+        (Some(_), None) => "(unnamed)".to_string(),
+    })
+}
+
+/// Replace the MSVC lambda names by (lambda) or (lambda at owner)
+pub(crate) fn rename_lambdas(name: &str, mode: LambdaNames) -> Cow<'_, str> {
+    lazy_static! {
+        static ref LAMBDA: Regex =
+            Regex::new(r"(?:class )?((?:[A-Za-z_]\w*(?:<[^<>]*>)?::)*)<lambda_[0-9a-fA-F]+>")
+                .unwrap();
+    }
+    match mode {
+        LambdaNames::Full => Cow::Borrowed(name),
+        LambdaNames::Short => LAMBDA.replace_all(name, "${1}(lambda)"),
+        LambdaNames::Owner => {
+            LAMBDA.replace_all(name, |caps: &Captures| match caps[1].strip_suffix("::") {
+                Some(owner) => format!("(lambda at {})", owner),
+                None => "(lambda)".to_string(),
+            })
+        }
+    }
+}
+
+const OPERATOR: &str = "operator";
+// The longest ones first
+const OPERATORS: [&str; 10] = ["<=>", "<<=", ">>=", "<<", ">>", "<=", ">=", "->", "<", ">"];
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Replace the template argument lists nested deeper than depth by <T>
+pub(crate) fn collapse_templates(name: &str, depth: usize) -> Cow<'_, str> {
+    if !name.contains('<') {
+        return Cow::Borrowed(name);
+    }
+
+    let mut collapsed = String::with_capacity(name.len());
+    let mut level = 0;
+    let mut prev = None;
+    let mut after_operator = false;
+    let mut rest = name;
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with(OPERATOR) && !matches!(prev, Some(c) if is_ident(c)) {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest[OPERATOR.len()..].starts_with(**op))
+                .copied()
+                .unwrap_or("");
+            let token = &rest[..OPERATOR.len() + op.len()];
+            if level <= depth {
+                collapsed.push_str(token);
+            }
+            rest = &rest[token.len()..];
+            prev = token.chars().last();
+            after_operator = !op.is_empty();
+            continue;
+        }
+
+        match c {
+            '<' if after_operator || matches!(prev, Some(p) if is_ident(p)) => {
+                level += 1;
+                if level == depth + 1 {
+                    collapsed.push_str("<T>");
+                } else if level <= depth {
+                    collapsed.push('<');
+                }
+            }
+            '>' if level > 0 => {
+                if level <= depth {
+                    collapsed.push('>');
+                }
+                level -= 1;
+            }
+            _ => {
+                if level <= depth {
+                    collapsed.push(c);
+                }
+            }
+        }
+        prev = Some(c);
+        // operator<< <int> is a template too
+        after_operator = after_operator && c == ' ';
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if level != 0 {
+        // Unbalanced: better to keep the name as is
+        return Cow::Borrowed(name);
+    }
+    Cow::Owned(collapsed)
+}
+
+const ELLIPSIS: &str = "...";
+
+/// Truncate the name to have at most max_len bytes (ellipsis included)
+pub(crate) fn truncate(name: &str, max_len: usize) -> Cow<'_, str> {
+    if name.len() <= max_len {
+        return Cow::Borrowed(name);
+    }
+    let mut end = max_len.saturating_sub(ELLIPSIS.len());
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}{}", &name[..end], ELLIPSIS))
+}
+
+/// Split a FUNC or a PUBLIC record in the fields before the name and the name
+pub(crate) fn split_name(record: &str) -> Option<(&str, &str)> {
+    let fields = if record.starts_with("FUNC ") {
+        4
+    } else if record.starts_with("PUBLIC ") {
+        3
+    } else {
+        return None;
+    };
+    let fields = if record.split(' ').nth(1) == Some("m") {
+        fields + 1
+    } else {
+        fields
+    };
+
+    let pos = record.match_indices(' ').nth(fields - 1)?.0;
+    Some((&record[..=pos], &record[pos + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_collapse_templates() {
+        let name = "std::map<int, std::vector<int>>::find(std::basic_string<char> const&)";
+        assert_eq!(
+            collapse_templates(name, 0),
+            "std::map<T>::find(std::basic_string<T> const&)"
+        );
+        assert_eq!(
+            collapse_templates(name, 1),
+            "std::map<int, std::vector<T>>::find(std::basic_string<char> const&)"
+        );
+        assert_eq!(collapse_templates(name, 2), name);
+
+        assert_eq!(
+            collapse_templates("bool operator<< <int>(Foo<int> const&, int)", 0),
+            "bool operator<< <T>(Foo<T> const&, int)"
+        );
+        assert_eq!(
+            collapse_templates("Foo<int>::operator<(Foo<int> const&)", 0),
+            "Foo<T>::operator<(Foo<T> const&)"
+        );
+        assert_eq!(
+            collapse_templates("Foo<int>::operator->()", 0),
+            "Foo<T>::operator->()"
+        );
+        assert_eq!(
+            collapse_templates("`anonymous namespace'::<lambda_1>::operator()", 0),
+            "`anonymous namespace'::<lambda_1>::operator()"
+        );
+        assert_eq!(collapse_templates("Foo<Bar<int>", 0), "Foo<Bar<int>");
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("foo::bar(int)"), "foo::bar(int)");
+        assert_eq!(
+            normalize("`anonymous namespace'::foo(?A0x3f2a19c1::Bar*)"),
+            "(anonymous namespace)::foo((anonymous namespace)::Bar*)"
+        );
+        assert_eq!(
+            normalize("Foo::<unnamed-tag>::get(<unnamed-type-m_data>*, <unnamed-enum-RED>)"),
+            "Foo::(unnamed)::get((unnamed type m_data)*, (unnamed enum RED))"
+        );
+    }
+
+    #[test]
+    fn test_rename_lambdas() {
+        let name =
+            "foo::bar::<lambda_1>::operator()(class <lambda_02a71323d951a238fa4826e9f186893b>)";
+        assert_eq!(rename_lambdas(name, LambdaNames::Full), name);
+        assert_eq!(
+            rename_lambdas(name, LambdaNames::Short),
+            "foo::bar::(lambda)::operator()((lambda))"
+        );
+        assert_eq!(
+            rename_lambdas(name, LambdaNames::Owner),
+            "(lambda at foo::bar)::operator()((lambda))"
+        );
+        assert_eq!(
+            rename_lambdas(
+                "<lambda_cea3005b>::<lambda_invoker_cdecl>",
+                LambdaNames::Owner
+            ),
+            "(lambda)::<lambda_invoker_cdecl>"
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("foo(int)", 8), "foo(int)");
+        assert_eq!(truncate("foo(int, char)", 8), "foo(i...");
+        // é is 2 bytes long
+        assert_eq!(truncate("fooé(int)", 8), "fooé...");
+        assert_eq!(truncate("fooé(int)", 7), "foo...");
+    }
+
+    #[test]
+    fn test_split_name() {
+        assert_eq!(
+            split_name("FUNC 1000 20 0 foo(int, char)"),
+            Some(("FUNC 1000 20 0 ", "foo(int, char)"))
+        );
+        assert_eq!(
+            split_name("FUNC m 1000 20 0 foo"),
+            Some(("FUNC m 1000 20 0 ", "foo"))
+        );
+        assert_eq!(
+            split_name("PUBLIC m 1000 0 foo bar"),
+            Some(("PUBLIC m 1000 0 ", "foo bar"))
+        );
+        assert_eq!(split_name("PUBLIC 1000 0"), None);
+        assert_eq!(split_name("1000 20 12 0"), None);
+    }
+}
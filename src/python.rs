@@ -0,0 +1,201 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::path::Path;
+
+use crate::cancel::CancelToken;
+use crate::common;
+use crate::dumper::{self, Config};
+use crate::error;
+use crate::info::{self, Info};
+use crate::mac::macho;
+use crate::utils;
+
+/*
+ The dump_syms Python module (built with the python feature, see pyproject.toml): it avoids to
+ spawn a process for each file to dump.
+ The GIL is released while a file is dumped so several files can be dumped from several threads
+ (and a dump can be cancelled from another thread with its CancelToken).
+*/
+
+create_exception!(dump_syms, DumpSymsError, PyException);
+
+fn to_py<T>(res: common::Result<T>) -> PyResult<T> {
+    res.map_err(|e| DumpSymsError::new_err(error::report(&*e)))
+}
+
+/// A token to cancel the dumps using it from another thread: they raise a DumpSymsError (only the
+/// dumps of the pdbs check it for now)
+#[pyclass(name = "CancelToken")]
+#[derive(Clone, Default)]
+struct PyCancelToken(CancelToken);
+
+#[pymethods]
+impl PyCancelToken {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn cancel(&self) {
+        self.0.cancel();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+}
+
+fn get_config(arch: Option<&str>, num_jobs: usize, cancel: Option<PyCancelToken>) -> Config<'_> {
+    let mut config = Config {
+        num_jobs: num_jobs.max(1),
+        ..Default::default()
+    };
+    if let Some(arch) = arch {
+        config.arch = arch;
+    }
+    if let Some(cancel) = cancel {
+        config.options.cancel = cancel.0;
+    }
+    config
+}
+
+/// Dump the file at path and get the records
+#[pyfunction]
+#[pyo3(signature = (path, arch = None, num_jobs = 1, cancel = None))]
+fn dump(
+    py: Python,
+    path: &str,
+    arch: Option<&str>,
+    num_jobs: usize,
+    cancel: Option<PyCancelToken>,
+) -> PyResult<String> {
+    let config = get_config(arch, num_jobs, cancel);
+    to_py(py.allow_threads(|| dumper::dump_in_memory(&config, path)))
+}
+
+/// Dump the file at path and write the records in output ("-" for stdout)
+#[pyfunction]
+#[pyo3(signature = (path, output, arch = None, num_jobs = 1, cancel = None))]
+fn dump_to_file(
+    py: Python,
+    path: &str,
+    output: &str,
+    arch: Option<&str>,
+    num_jobs: usize,
+    cancel: Option<PyCancelToken>,
+) -> PyResult<()> {
+    let config = Config {
+        output,
+        ..get_config(arch, num_jobs, cancel)
+    };
+    to_py(py.allow_threads(|| dumper::single_file(&config, path)))
+}
+
+fn info_to_dict(py: Python, info: Info) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("format", info.format)?;
+    dict.set_item("arch", info.arch)?;
+    dict.set_item("debug_file", info.debug_file)?;
+    dict.set_item("debug_id", info.debug_id)?;
+    dict.set_item("code_id", info.code_id)?;
+    dict.set_item("linker", info.linker)?;
+    dict.set_item("timestamp", info.timestamp)?;
+    dict.set_item("symbols", info.symbols)?;
+    dict.set_item("lines", info.lines)?;
+    dict.set_item("cfi", info.cfi)?;
+    dict.set_item("sources", info.sources)?;
+    Ok(dict.into())
+}
+
+/// Get the information about the objects in the file at path: a dict for each object (with the
+/// keys format, arch, debug_file, debug_id, code_id, linker, timestamp, symbols, lines, cfi and
+/// sources)
+#[pyfunction]
+#[pyo3(signature = (path, arch = None))]
+fn module_info(py: Python, path: &str, arch: Option<&str>) -> PyResult<Vec<PyObject>> {
+    let arch = arch.unwrap_or(macho::ALL_ARCHS);
+    let name = utils::get_filename(Path::new(path));
+    let infos =
+        to_py(py.allow_threads(|| {
+            utils::read(path).and_then(|buf| info::get_info(&buf, &name, arch))
+        }))?;
+    infos
+        .into_iter()
+        .map(|info| info_to_dict(py, info))
+        .collect()
+}
+
+#[pymodule]
+#[pyo3(name = "dump_syms")]
+fn python_module(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("DumpSymsError", py.get_type::<DumpSymsError>())?;
+    m.add_class::<PyCancelToken>()?;
+    m.add_function(wrap_pyfunction!(dump, m)?)?;
+    m.add_function(wrap_pyfunction!(dump_to_file, m)?)?;
+    m.add_function(wrap_pyfunction!(module_info, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "dump_syms").unwrap();
+            python_module(py, module).unwrap();
+
+            let sym: String = module
+                .getattr("dump")
+                .unwrap()
+                .call1(("./test_data/windows/basic64.pdb",))
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert!(sym.starts_with("MODULE windows x86_64 D09EA7D6D2C24C1EBFFE78B0C866BB7F2"));
+
+            let infos = module
+                .getattr("module_info")
+                .unwrap()
+                .call1(("./test_data/windows/basic64.dll",))
+                .unwrap();
+            let debug_id: String = infos
+                .get_item(0)
+                .unwrap()
+                .get_item("debug_id")
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(debug_id, "D09EA7D6D2C24C1EBFFE78B0C866BB7F2");
+
+            let error = module
+                .getattr("dump")
+                .unwrap()
+                .call1(("./test_data/windows/basic.cpp",))
+                .unwrap_err();
+            assert!(error.is_instance_of::<DumpSymsError>(py));
+
+            let token = module.getattr("CancelToken").unwrap().call0().unwrap();
+            token.call_method0("cancel").unwrap();
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("cancel", token).unwrap();
+            let error = module
+                .getattr("dump")
+                .unwrap()
+                .call(("./test_data/windows/basic64.pdb",), Some(kwargs))
+                .unwrap_err();
+            assert!(error.is_instance_of::<DumpSymsError>(py));
+            assert!(error.to_string().contains("Interrupted"));
+        });
+    }
+}
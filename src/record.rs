@@ -0,0 +1,182 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt::{self, Formatter};
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+// The buffer is written in the formatter when it's bigger than that
+const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// A reusable buffer to serialize the records.
+/// The integers are formatted by hand (without any locale and without the std::fmt machinery)
+/// since the formatting of the LINE records is a significant part of the dump time for huge modules.
+pub(crate) struct RecordWriter {
+    buf: String,
+}
+
+impl RecordWriter {
+    pub(crate) fn new() -> Self {
+        Self {
+            buf: String::with_capacity(FLUSH_THRESHOLD + 1024),
+        }
+    }
+
+    /// Lowercase hexadecimal without any prefix (like {:x})
+    pub(crate) fn hex(&mut self, mut n: u64) -> &mut Self {
+        let mut digits = [0u8; 16];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = HEX_DIGITS[(n & 0xf) as usize];
+            n >>= 4;
+            if n == 0 {
+                break;
+            }
+        }
+        self.buf.extend(digits[i..].iter().map(|d| char::from(*d)));
+        self
+    }
+
+    pub(crate) fn dec(&mut self, mut n: u64) -> &mut Self {
+        let mut digits = [0u8; 20];
+        let mut i = digits.len();
+        loop {
+            i -= 1;
+            digits[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        self.buf.extend(digits[i..].iter().map(|d| char::from(*d)));
+        self
+    }
+
+    pub(crate) fn str(&mut self, s: &str) -> &mut Self {
+        self.buf.push_str(s);
+        self
+    }
+
+    /// A name or a path: the control chars are escaped (\xNN) so the record stays on its line
+    pub(crate) fn name(&mut self, s: &str) -> &mut Self {
+        if !s.bytes().any(|b| b < 0x20 || b == 0x7f) {
+            return self.str(s);
+        }
+        for c in s.chars() {
+            if c < '\x20' || c == '\x7f' {
+                let b = c as u8;
+                self.buf.push_str("\\x");
+                self.buf.push(char::from(HEX_DIGITS[usize::from(b >> 4)]));
+                self.buf.push(char::from(HEX_DIGITS[usize::from(b & 0xf)]));
+            } else {
+                self.buf.push(c);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn space(&mut self) -> &mut Self {
+        self.buf.push(' ');
+        self
+    }
+
+    pub(crate) fn end(&mut self) -> &mut Self {
+        self.buf.push('\n');
+        self
+    }
+
+    /// Write the buffer in the formatter if it's big enough
+    pub(crate) fn flush_if_full(&mut self, f: &mut Formatter) -> fmt::Result {
+        if self.buf.len() >= FLUSH_THRESHOLD {
+            self.flush(f)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fmt::Display;
+
+    struct Records<'a>(&'a [(u32, u32, u32, u32)], bool);
+
+    impl<'a> Display for Records<'a> {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            if self.1 {
+                let mut w = RecordWriter::new();
+                for (rva, len, num, file_id) in self.0 {
+                    w.hex(u64::from(*rva))
+                        .space()
+                        .hex(u64::from(*len))
+                        .space()
+                        .dec(u64::from(*num))
+                        .space()
+                        .dec(u64::from(*file_id))
+                        .end();
+                    w.flush_if_full(f)?;
+                }
+                w.flush(f)
+            } else {
+                for (rva, len, num, file_id) in self.0 {
+                    writeln!(f, "{:x} {:x} {} {}", rva, len, num, file_id)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn get_records(n: u32) -> Vec<(u32, u32, u32, u32)> {
+        (0..n)
+            .map(|i| {
+                let x = i.wrapping_mul(2_654_435_761);
+                (x, x % 0x200, x % 100_000, i % 5000)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_record_writer() {
+        let mut w = RecordWriter::new();
+        w.hex(0)
+            .space()
+            .hex(0x1234_abcd)
+            .space()
+            .hex(u64::MAX)
+            .end();
+        w.dec(0)
+            .space()
+            .dec(1_234_567_890)
+            .space()
+            .dec(u64::MAX)
+            .end();
+        w.str("FUNC").end();
+        assert_eq!(
+            w.buf,
+            "0 1234abcd ffffffffffffffff\n0 1234567890 18446744073709551615\nFUNC\n"
+        );
+
+        let records = get_records(100_000);
+        assert_eq!(
+            Records(&records, true).to_string(),
+            Records(&records, false).to_string()
+        );
+    }
+
+    #[test]
+    fn test_name() {
+        let mut w = RecordWriter::new();
+        w.name("foo(int)").space().name("foo\nbar\t\u{7f}é").end();
+        assert_eq!(w.buf, "foo(int) foo\\x0abar\\x09\\x7fé\n");
+    }
+}
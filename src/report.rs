@@ -0,0 +1,140 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use std::io::Write;
+
+use crate::common;
+use crate::dumper::{self, Config};
+use crate::json;
+use crate::model::Module;
+use crate::utils;
+
+/*
+ The size report is built from the records: the size of a function is the one of its FUNC record and
+ the size of a source file is the sum of the sizes of its LINE records (so the inlined code is counted
+ in the file where it's defined).
+ The code without line info (e.g. from a library without debug info) is counted in "<unknown>".
+*/
+
+const UNKNOWN: &str = "<unknown>";
+
+/// The entries of the report sorted by decreasing size
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SizeReport<'a> {
+    pub total: u64,
+    pub functions: Vec<(&'a str, u64)>,
+    pub files: Vec<(&'a str, u64)>,
+}
+
+fn sort_by_size<'a>(entries: impl Iterator<Item = (&'a str, u64)>) -> Vec<(&'a str, u64)> {
+    let mut entries: Vec<_> = entries.collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    entries
+}
+
+impl<'a> SizeReport<'a> {
+    pub(crate) fn new(module: &'a Module) -> Self {
+        let files: HashMap<_, _> = module.files.iter().map(|f| (f.id, &*f.name)).collect();
+        let mut functions = HashMap::new();
+        let mut file_sizes = HashMap::new();
+        let mut total = 0;
+
+        for func in module.functions.iter() {
+            total += func.size;
+            *functions.entry(&*func.name).or_insert(0) += func.size;
+
+            let mut with_lines = 0;
+            for line in func.lines.iter() {
+                let file = files.get(&line.file).copied().unwrap_or(UNKNOWN);
+                *file_sizes.entry(file).or_insert(0) += line.size;
+                with_lines += line.size;
+            }
+            if with_lines < func.size {
+                *file_sizes.entry(UNKNOWN).or_insert(0) += func.size - with_lines;
+            }
+        }
+
+        Self {
+            total,
+            functions: sort_by_size(functions.into_iter()),
+            files: sort_by_size(file_sizes.into_iter()),
+        }
+    }
+
+    fn write_section<W: Write>(
+        &self,
+        mut writer: W,
+        title: &str,
+        entries: &[(&str, u64)],
+    ) -> common::Result<()> {
+        writeln!(writer, "{:>12} {:>7}  {}", "SIZE", "%", title)?;
+        for (name, size) in entries {
+            let percent = if self.total == 0 {
+                0.
+            } else {
+                100. * *size as f64 / self.total as f64
+            };
+            writeln!(writer, "{:>12} {:>6.2}%  {}", size, percent, name)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write<W: Write>(&self, mut writer: W) -> common::Result<()> {
+        self.write_section(&mut writer, "FUNCTION", &self.functions)?;
+        writeln!(writer)?;
+        self.write_section(&mut writer, "FILE", &self.files)?;
+        writeln!(writer)?;
+        writeln!(writer, "{:>12} {:>6.2}%  TOTAL", self.total, 100.)?;
+        Ok(())
+    }
+}
+
+/// Dump the file in memory and write the size report in the output
+pub(crate) fn write_size_report(config: &Config, filename: &str) -> common::Result<()> {
+    let sym = dumper::dump_in_memory(config, filename)?;
+    let module = json::parse(&sym)?;
+    SizeReport::new(&module).write(utils::get_writer_for_sym(config.output))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_size_report() {
+        let sym = "MODULE windows x86_64 0123456789ABCDEF1 basic.pdb
+FILE 0 c:\\basic.cpp
+FILE 1 c:\\util.h
+FUNC 1000 20 0 main
+1000 10 12 0
+1010 8 3 1
+FUNC 1020 40 0 foo
+1020 40 7 1
+FUNC 1060 10 0 bar
+";
+        let module = json::parse(sym).unwrap();
+        let report = SizeReport::new(&module);
+        assert_eq!(
+            report,
+            SizeReport {
+                total: 0x70,
+                functions: vec![("foo", 0x40), ("main", 0x20), ("bar", 0x10)],
+                files: vec![
+                    ("c:\\util.h", 0x48),
+                    ("<unknown>", 0x18),
+                    ("c:\\basic.cpp", 0x10)
+                ],
+            }
+        );
+
+        let mut output = Vec::new();
+        report.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("          64  57.14%  foo\n"));
+        assert!(output.ends_with("         112 100.00%  TOTAL\n"));
+    }
+}
@@ -0,0 +1,323 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use log::{info, warn};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use crate::common::{self, Dumpable, OutputFormat, RecordProvenance};
+use crate::dumper::{self, Config};
+use crate::json;
+
+/*
+ With --sandbox, the file is parsed in a child process: dump_syms is re-executed with the same
+ arguments and --sandboxed-child, the parent sends the content of the file on the stdin of the
+ child which writes the sym file on its stdout, and the parent stores it as usual.
+ Once the file is read, the child can't open any file or socket anymore (RLIMIT_NOFILE is 0)
+ and on Linux a seccomp filter only allows the syscalls required to compute and write the
+ result (the other ones fail with EPERM): so the files next to the input (.dwo, debug files,
+ PE, ...) aren't used.
+*/
+
+pub(crate) const SANDBOX_ARG: &str = "--sandbox";
+pub(crate) const CHILD_ARG: &str = "--sandboxed-child";
+
+/// The sym file written by the child
+#[derive(Debug)]
+pub(crate) struct ChildSym {
+    name: String,
+    debug_id: String,
+    source_files: Vec<String>,
+    sym: Vec<u8>,
+}
+
+impl ChildSym {
+    fn new(sym: Vec<u8>) -> common::Result<Self> {
+        let text = std::str::from_utf8(&sym)?;
+        let module = json::parse(text)?;
+        let name = module.name.to_string();
+        let debug_id = module.debug_id.to_string();
+        let source_files = module.files.iter().map(|f| f.name.to_string()).collect();
+
+        Ok(Self {
+            name,
+            debug_id,
+            source_files,
+            sym,
+        })
+    }
+}
+
+impl Dumpable for ChildSym {
+    fn dump<W: Write>(&self, mut writer: W) -> common::Result<()> {
+        writer.write_all(&self.sym)?;
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_debug_id(&self) -> &str {
+        &self.debug_id
+    }
+
+    fn get_source_files(&self) -> &[String] {
+        &self.source_files
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        Vec::new()
+    }
+}
+
+/// Dump the file in a child process
+pub(crate) fn dump(buf: &[u8], filename: &str) -> common::Result<ChildSym> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1).filter(|arg| arg != SANDBOX_ARG))
+        .arg(CHILD_ARG)
+        .arg(filename)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // The child reads all its input before writing anything
+    if let Err(e) = child.stdin.take().unwrap().write_all(buf) {
+        warn!("Cannot write the file to the sandboxed process: {}", e);
+    }
+    let mut sym = Vec::new();
+    child.stdout.take().unwrap().read_to_end(&mut sym)?;
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("The sandboxed process failed ({})", status).into());
+    }
+    info!("{} has been dumped in a sandboxed process", filename);
+
+    ChildSym::new(sym)
+}
+
+/// Dump the file read on stdin and write the sym file on stdout
+pub(crate) fn run_child(mut config: Config, filename: &str, name: &str) -> common::Result<()> {
+    let mut buf = Vec::new();
+    io::stdin().read_to_end(&mut buf)?;
+    restrict()?;
+
+    // The parent writes all the outputs
+    config.output = "-";
+    config.store = None;
+    config.options.formats = vec![OutputFormat::Breakpad];
+    config.options.compression = None;
+    config.options.source_bundle = None;
+    config.options.sandbox = false;
+    // The seccomp filter doesn't allow to create threads
+    config.options.module_jobs = 1;
+
+    dumper::dump_buf(
+        &config,
+        std::path::Path::new(filename),
+        name.to_string(),
+        &buf,
+    )
+}
+
+#[cfg(unix)]
+fn restrict() -> common::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(format!("Cannot set RLIMIT_NOFILE: {}", io::Error::last_os_error()).into());
+    }
+
+    #[cfg(target_os = "linux")]
+    seccomp::install()?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict() -> common::Result<()> {
+    warn!("The privileges of the sandboxed process can't be reduced on this platform");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod seccomp {
+    use std::io;
+
+    use crate::common;
+
+    // From linux/filter.h, linux/seccomp.h and linux/audit.h
+    #[repr(C)]
+    struct SockFilter {
+        code: u16,
+        jt: u8,
+        jf: u8,
+        k: u32,
+    }
+
+    #[repr(C)]
+    struct SockFprog {
+        len: u16,
+        filter: *const SockFilter,
+    }
+
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JEQ_K: u16 = 0x15;
+    const BPF_RET_K: u16 = 0x06;
+
+    // Offsets of nr and arch in struct seccomp_data
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+
+    const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// The syscalls used to allocate memory, write the result and exit
+    const ALLOWED: &[libc::c_long] = &[
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_writev,
+        libc::SYS_close,
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_mprotect,
+        libc::SYS_madvise,
+        libc::SYS_futex,
+        libc::SYS_sched_yield,
+        libc::SYS_getrandom,
+        libc::SYS_clock_gettime,
+        libc::SYS_sigaltstack,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+    ];
+
+    fn stmt(code: u16, k: u32) -> SockFilter {
+        SockFilter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump(k: u32, jt: u8, jf: u8) -> SockFilter {
+        SockFilter {
+            code: BPF_JEQ_K,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    fn get_filter(arch: u32) -> Vec<SockFilter> {
+        let mut filter = vec![
+            // A syscall from another ABI has another number
+            stmt(BPF_LD_W_ABS, ARCH_OFFSET),
+            jump(arch, 1, 0),
+            stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD_W_ABS, NR_OFFSET),
+        ];
+        for nr in ALLOWED {
+            filter.push(jump(*nr as u32, 0, 1));
+            filter.push(stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+        }
+        filter.push(stmt(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        filter
+    }
+
+    pub(super) fn install() -> common::Result<()> {
+        let arch = if let Some(arch) = AUDIT_ARCH {
+            arch
+        } else {
+            log::warn!("No seccomp filter for this architecture");
+            return Ok(());
+        };
+
+        let filter = get_filter(arch);
+        let prog = SockFprog {
+            len: filter.len() as u16,
+            filter: filter.as_ptr(),
+        };
+
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(
+                    format!("Cannot set no_new_privs: {}", io::Error::last_os_error()).into(),
+                );
+            }
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const SockFprog,
+            ) != 0
+            {
+                return Err(format!(
+                    "Cannot install the seccomp filter: {}",
+                    io::Error::last_os_error()
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+
+        use super::*;
+
+        #[test]
+        fn test_filter() {
+            let filter = get_filter(0x1234);
+            assert_eq!(filter.len(), 4 + 2 * ALLOWED.len() + 1);
+            assert_eq!(filter[1].k, 0x1234);
+            assert_eq!(filter[4].k, libc::SYS_read as u32);
+            assert_eq!(filter.last().unwrap().k, SECCOMP_RET_ERRNO | 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_child_sym() {
+        let sym = b"MODULE Linux x86_64 0123456789ABCDEF1 foo
+FILE 0 foo.c
+FILE 1 bar.h
+FUNC 1000 20 0 foo
+1000 20 1 0
+"
+        .to_vec();
+        let child_sym = ChildSym::new(sym.clone()).unwrap();
+        assert_eq!(child_sym.get_name(), "foo");
+        assert_eq!(child_sym.get_debug_id(), "0123456789ABCDEF1");
+        assert_eq!(child_sym.get_source_files(), &["foo.c", "bar.h"]);
+
+        let mut out = Vec::new();
+        child_sym.dump(&mut out).unwrap();
+        assert_eq!(out, sym);
+
+        assert!(ChildSym::new(b"garbage".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crossbeam::channel::bounded;
+use log::{error, info, warn};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::cache;
+use crate::common;
+use crate::dumper::{self, Config};
+
+/*
+ The server answers to GET /sym/<debug file>/<debug id> with the sym file: it's read from the cache
+ directory (which has the layout of a store) or the debug file (or the binary) is fetched from the symbol
+ servers, dumped in the cache and returned.
+ The connections are handled by a fixed pool of threads (the next ones wait in the listen queue)
+ and closed after the response. A client can't hold a thread forever: the reads and the writes
+ have a timeout and the sizes of the request line and of the headers are capped.
+ With a dump timeout, a watchdog thread cancels the dump (through the cancel token of its options)
+ when it isn't finished in time: the watchdog is stopped as soon as the dump ends.
+*/
+
+/// A response: the status, its reason and the body
+type Response = (u16, &'static str, Vec<u8>);
+
+const MAX_CONNECTIONS: usize = 32;
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_LINE_SIZE: u64 = 8 * 1024;
+const MAX_HEADERS_SIZE: u64 = 64 * 1024;
+
+pub(crate) struct Server {
+    cache: String,
+    symbol_server: Option<String>,
+    dump_timeout: Option<Duration>,
+}
+
+/// Get the debug file and the debug id from a request path like /sym/xul.pdb/DEBUG_ID
+fn parse_path(path: &str) -> Option<(&str, String)> {
+    let mut parts = path.strip_prefix("/sym/")?.trim_end_matches('/').split('/');
+    let (debug_file, debug_id) = (parts.next()?, parts.next()?);
+    if parts.next().is_some()
+        || debug_file.is_empty()
+        || debug_file.starts_with('.')
+        || debug_id.is_empty()
+        || !debug_id.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+    Some((debug_file, debug_id.to_uppercase()))
+}
+
+fn not_found() -> Response {
+    (404, "Not Found", b"Not Found\n".to_vec())
+}
+
+impl Server {
+    pub(crate) fn new(
+        cache: &str,
+        symbol_server: Option<&str>,
+        dump_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            cache: cache.to_string(),
+            symbol_server: symbol_server.map(|s| s.to_string()),
+            dump_timeout,
+        }
+    }
+
+    /// Fetch the file from the symbol servers and dump it in the cache
+    fn fetch_and_dump(&self, debug_file: &str, debug_id: &str) -> common::Result<bool> {
+        let servers = cache::get_sym_servers(self.symbol_server.as_deref());
+        let (buf, file) =
+            match cache::search_file(debug_file.to_string(), debug_id, servers.as_ref()) {
+                (Some(buf), file) => (buf, file),
+                _ => return Ok(false),
+            };
+
+        let config = Config::with_store(&self.cache, self.symbol_server.as_deref());
+        // The sender is dropped at the end of the dump and stops the watchdog
+        let (_done, watchdog) = mpsc::channel::<()>();
+        if let Some(timeout) = self.dump_timeout {
+            let cancel = config.options.cancel.clone();
+            thread::spawn(move || {
+                if let Err(mpsc::RecvTimeoutError::Timeout) = watchdog.recv_timeout(timeout) {
+                    cancel.cancel();
+                }
+            });
+        }
+        dumper::dump_buf(&config, Path::new(&file), file.clone(), &buf)?;
+        info!("{} ({}) dumped in {}", file, debug_id, self.cache);
+        Ok(true)
+    }
+
+    fn get_sym(&self, debug_file: &str, debug_id: &str) -> Response {
+        let sym_path = Path::new(&self.cache).join(cache::get_path_for_sym(debug_file, debug_id));
+        if !sym_path.exists() {
+            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                self.fetch_and_dump(debug_file, debug_id)
+            }));
+            match res {
+                Ok(Ok(true)) => {}
+                Ok(Ok(false)) => return not_found(),
+                Ok(Err(e)) => {
+                    warn!("Cannot dump {} ({}): {}", debug_file, debug_id, e);
+                    return (
+                        500,
+                        "Internal Server Error",
+                        format!("{}\n", e).into_bytes(),
+                    );
+                }
+                Err(_) => return (500, "Internal Server Error", b"Dump failed\n".to_vec()),
+            }
+        }
+
+        // The dumped file can have another debug id than the requested one
+        match fs::read(&sym_path) {
+            Ok(sym) => (200, "OK", sym),
+            Err(_) => not_found(),
+        }
+    }
+
+    /// Get the response to a request line
+    fn respond(&self, request: &str) -> (Response, bool) {
+        let mut parts = request.split_whitespace();
+        let (method, path) = match (parts.next(), parts.next()) {
+            (Some(method), Some(path)) => (method, path),
+            _ => return ((400, "Bad Request", Vec::new()), false),
+        };
+        let head = method == "HEAD";
+        if method != "GET" && !head {
+            return ((405, "Method Not Allowed", Vec::new()), head);
+        }
+
+        let response = match parse_path(path) {
+            Some((debug_file, debug_id)) => self.get_sym(debug_file, &debug_id),
+            None => not_found(),
+        };
+        (response, head)
+    }
+
+    /// Read the request line and skip the headers (they aren't used)
+    fn read_request<R: BufRead>(reader: &mut R) -> common::Result<Result<String, Response>> {
+        let mut request = String::new();
+        reader.take(MAX_LINE_SIZE).read_line(&mut request)?;
+        if !request.ends_with('\n') && request.len() as u64 == MAX_LINE_SIZE {
+            return Ok(Err((414, "URI Too Long", Vec::new())));
+        }
+
+        let mut headers = reader.take(MAX_HEADERS_SIZE);
+        loop {
+            let mut line = String::new();
+            if headers.read_line(&mut line)? == 0 {
+                if headers.limit() == 0 {
+                    return Ok(Err((431, "Request Header Fields Too Large", Vec::new())));
+                }
+                break;
+            }
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+        Ok(Ok(request))
+    }
+
+    fn handle(&self, stream: TcpStream) -> common::Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut reader = BufReader::new(stream);
+        let ((status, reason, body), head) = match Self::read_request(&mut reader)? {
+            Ok(request) => {
+                let (response, head) = self.respond(request.trim());
+                info!("{} -> {}", request.trim(), response.0);
+                (response, head)
+            }
+            Err(response) => {
+                warn!("Invalid request -> {}", response.0);
+                (response, false)
+            }
+        };
+
+        let stream = reader.get_mut();
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            reason,
+            body.len()
+        )?;
+        if !head {
+            stream.write_all(&body)?;
+        }
+        stream.flush()?;
+        Ok(())
+    }
+
+    /// Serve the sym files on the given address
+    pub(crate) fn run(self, listen: &str) -> common::Result<()> {
+        let listener = TcpListener::bind(listen)?;
+        info!("Listen on {}", listener.local_addr()?);
+        self.serve(listener);
+        Ok(())
+    }
+
+    fn serve(self, listener: TcpListener) {
+        let server = Arc::new(self);
+        // The accept loop blocks when all the workers are busy
+        let (sender, receiver) = bounded::<TcpStream>(0);
+        for _ in 0..MAX_CONNECTIONS {
+            let server = Arc::clone(&server);
+            let receiver = receiver.clone();
+            thread::spawn(move || {
+                for stream in receiver {
+                    if let Err(e) = server.handle(stream) {
+                        warn!("Cannot handle the request: {}", e);
+                    }
+                }
+            });
+        }
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if sender.send(stream).is_err() {
+                        error!("No more workers to handle the connections");
+                        return;
+                    }
+                }
+                Err(e) => error!("Cannot accept a connection: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Read;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("/sym/xul.pdb/44e4ec8c2f41492b9369d6b9a059577c2"),
+            Some(("xul.pdb", "44E4EC8C2F41492B9369D6B9A059577C2".to_string()))
+        );
+        assert_eq!(
+            parse_path("/sym/libxul.so/AAAA1/"),
+            Some(("libxul.so", "AAAA1".to_string()))
+        );
+        assert_eq!(parse_path("/sym/xul.pdb"), None);
+        assert_eq!(parse_path("/sym/../AAAA1"), None);
+        assert_eq!(parse_path("/sym/xul.pdb/AAAA1/xul.sym"), None);
+        assert_eq!(parse_path("/sym/xul.pdb/AA-1"), None);
+        assert_eq!(parse_path("/xul.pdb/AAAA1"), None);
+    }
+
+    #[test]
+    fn test_serve() {
+        let tmp_dir = Builder::new().prefix("serve").tempdir().unwrap();
+        let dir = tmp_dir.path().join("basic.pdb").join("AAAA1");
+        fs::create_dir_all(&dir).unwrap();
+        let sym = "MODULE windows x86_64 AAAA1 basic.pdb\n";
+        fs::write(dir.join("basic.sym"), sym).unwrap();
+
+        // No symbol server: only what is in the cache is served
+        let server = Server::new(tmp_dir.path().to_str().unwrap(), Some(""), None);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve(listener));
+
+        let get = |request: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "{}\r\nHost: localhost\r\n\r\n", request).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("GET /sym/basic.pdb/aaaa1 HTTP/1.1");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{}", sym)));
+
+        let response = get("HEAD /sym/basic.pdb/AAAA1 HTTP/1.1");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+
+        let response = get("GET /sym/basic.pdb/BBBB1 HTTP/1.1");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = get("POST /sym/basic.pdb/AAAA1 HTTP/1.1");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+
+    #[test]
+    fn test_read_request() {
+        let read = |request: &str| {
+            Server::read_request(&mut request.as_bytes())
+                .unwrap()
+                .map_err(|(status, _, _)| status)
+        };
+
+        assert_eq!(
+            read("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Ok("GET / HTTP/1.1\r\n".to_string())
+        );
+        let path = "a".repeat(MAX_LINE_SIZE as usize);
+        assert_eq!(read(&format!("GET /{} HTTP/1.1\r\n\r\n", path)), Err(414));
+        let headers = "X-Foo: bar\r\n".repeat(10_000);
+        assert_eq!(read(&format!("GET / HTTP/1.1\r\n{}", headers)), Err(431));
+    }
+}
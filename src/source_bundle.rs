@@ -0,0 +1,168 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use log::{debug, info};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::common::{self, Dumpable};
+
+const MANIFEST: &str = "manifest.json";
+
+/*
+ The archive contains the source files and a manifest.json:
+   {
+     "name": "basic.pdb",
+     "debug_id": "...",
+     "files": {
+       "files/c/build/src/foo.cpp": "c:\\build\\src\\foo.cpp",
+       "files/home/user/src/bar.cpp": "/home/user/src/bar.cpp"
+     }
+   }
+ The keys are the names of the entries in the archive and the values are the paths
+ as they're in the FILE records.
+*/
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    name: &'a str,
+    debug_id: &'a str,
+    files: HashMap<String, &'a str>,
+}
+
+/// Get the name of the archive entry for the given path:
+/// the separators are '/', the drive letters are kept without the ':'
+/// and the '.' or '..' components are removed.
+fn get_entry_name(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let components: Vec<_> = path
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+        .map(|c| c.trim_end_matches(':'))
+        .collect();
+    format!("files/{}", components.join("/"))
+}
+
+/// Write in a zip archive the source files referenced by the FILE records
+/// which are reachable on disk
+pub(crate) fn write_source_bundle<D: Dumpable>(dumpable: &D, output: &str) -> common::Result<()> {
+    let mut zip = ZipWriter::new(File::create(output)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut manifest = Manifest {
+        name: dumpable.get_name(),
+        debug_id: dumpable.get_debug_id(),
+        files: HashMap::default(),
+    };
+
+    for path in dumpable.get_source_files() {
+        if !Path::new(path).is_file() {
+            debug!("Source file {} isn't on disk", path);
+            continue;
+        }
+
+        let name = get_entry_name(path);
+        if manifest.files.contains_key(&name) {
+            continue;
+        }
+
+        let data = fs::read(path)?;
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(&data)?;
+        manifest.files.insert(name, path.as_str());
+    }
+
+    zip.start_file(MANIFEST, options)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+    zip.finish()?;
+
+    info!(
+        "Write {} source files (out of {}) at {}",
+        manifest.files.len(),
+        dumpable.get_source_files().len(),
+        output
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    struct Sources(Vec<String>);
+
+    impl Dumpable for Sources {
+        fn dump<W: Write>(&self, _writer: W) -> common::Result<()> {
+            Ok(())
+        }
+
+        fn get_name(&self) -> &str {
+            "basic.full"
+        }
+
+        fn get_debug_id(&self) -> &str {
+            "1234"
+        }
+
+        fn get_source_files(&self) -> &[String] {
+            &self.0
+        }
+
+        fn get_provenance(&self) -> Vec<common::RecordProvenance<'_>> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_entry_name() {
+        assert_eq!(
+            get_entry_name(r"c:\build\src\..\foo.cpp"),
+            "files/c/build/src/foo.cpp"
+        );
+        assert_eq!(
+            get_entry_name("/home/user/./src/bar.cpp"),
+            "files/home/user/src/bar.cpp"
+        );
+    }
+
+    #[test]
+    fn test_source_bundle() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("source_bundle")
+            .tempdir()
+            .unwrap();
+        let output = tmp_dir.path().join("sources.zip");
+        let output = output.to_str().unwrap();
+        let basic = fs::canonicalize("./test_data/linux/basic.cpp").unwrap();
+        let basic = basic.to_str().unwrap().to_string();
+        let sources = Sources(vec![basic.clone(), "/not/on/disk/foo.cpp".to_string()]);
+
+        write_source_bundle(&sources, output).unwrap();
+
+        let mut zip = ZipArchive::new(File::open(output).unwrap()).unwrap();
+        assert_eq!(zip.len(), 2);
+
+        let mut manifest = String::new();
+        zip.by_name(MANIFEST)
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+        let name = get_entry_name(&basic);
+        assert_eq!(manifest["debug_id"], "1234");
+        assert_eq!(manifest["files"][&name], basic);
+
+        let mut data = Vec::new();
+        zip.by_name(&name).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, fs::read(&basic).unwrap());
+    }
+}
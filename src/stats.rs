@@ -0,0 +1,227 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[cfg(feature = "cli")]
+use {
+    log::{Level, Log, Metadata, Record},
+    tracing::{span, Subscriber},
+    tracing_subscriber::{layer::Context, registry::LookupSpan, Layer},
+};
+
+use crate::common;
+use crate::utils;
+
+/*
+ With --stats=FILE the statistics of the run are written in json to follow the health of a symbol
+ pipeline from a release to another:
+  - for each module, the number of records of each kind and the size of its sym (before --emit),
+  - the number of times each phase (the tracing spans: open, dump, type scan, module, write, fetch)
+    has run and the time spent in it (the phases are nested: a dump contains the type scan of a pdb),
+  - the number of warnings logged (whatever the verbosity) and the duration of the run.
+ The modules can be dumped in several threads so the statistics are shared behind a mutex.
+*/
+
+/// The number of records of each kind in a sym
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(crate) struct Counts {
+    pub functions: usize,
+    pub publics: usize,
+    pub files: usize,
+    pub lines: usize,
+    pub inlines: usize,
+    pub inline_origins: usize,
+    pub cfi: usize,
+}
+
+impl Counts {
+    pub(crate) fn new(sym: &[u8]) -> Self {
+        let mut counts = Self::default();
+        for record in sym.split(|c| *c == b'\n').filter(|r| !r.is_empty()) {
+            let kind = record.split(|c| *c == b' ').next().unwrap_or_default();
+            match kind {
+                b"FUNC" => counts.functions += 1,
+                b"PUBLIC" => counts.publics += 1,
+                b"FILE" => counts.files += 1,
+                b"INLINE" => counts.inlines += 1,
+                b"INLINE_ORIGIN" => counts.inline_origins += 1,
+                b"STACK" => counts.cfi += 1,
+                b"MODULE" | b"INFO" => {}
+                _ => counts.lines += 1,
+            }
+        }
+        counts
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ModuleStats {
+    debug_file: String,
+    debug_id: String,
+    size: usize,
+    #[serde(flatten)]
+    counts: Counts,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Phase {
+    count: usize,
+    seconds: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Data {
+    seconds: f64,
+    warnings: usize,
+    phases: BTreeMap<String, Phase>,
+    modules: Vec<ModuleStats>,
+}
+
+/// The statistics of a run
+#[derive(Clone, Debug)]
+pub(crate) struct RunStats {
+    start: Instant,
+    data: Arc<Mutex<Data>>,
+}
+
+impl Default for RunStats {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            data: Arc::default(),
+        }
+    }
+}
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+impl RunStats {
+    pub(crate) fn add_module(&self, debug_file: &str, debug_id: &str, sym: &[u8]) {
+        self.data.lock().unwrap().modules.push(ModuleStats {
+            debug_file: debug_file.to_string(),
+            debug_id: debug_id.to_string(),
+            size: sym.len(),
+            counts: Counts::new(sym),
+        });
+    }
+
+    fn add_phase(&self, name: &str, seconds: f64) {
+        let mut data = self.data.lock().unwrap();
+        let phase = data.phases.entry(name.to_string()).or_default();
+        phase.count += 1;
+        phase.seconds += seconds;
+    }
+
+    /// Write the statistics in json (the modules are sorted by name)
+    pub(crate) fn write(&self, path: &str) -> common::Result<()> {
+        let mut data = self.data.lock().unwrap();
+        data.seconds = self.start.elapsed().as_secs_f64();
+        data.warnings = WARNINGS.load(Ordering::Relaxed);
+        data.modules
+            .sort_by(|a, b| (&a.debug_file, &a.debug_id).cmp(&(&b.debug_file, &b.debug_id)));
+        serde_json::to_writer_pretty(utils::get_writer_for_sym(path), &*data)?;
+        Ok(())
+    }
+}
+
+/// A logger counting the warnings before giving the records to the actual logger
+#[cfg(feature = "cli")]
+pub(crate) struct WarningCounter(pub Box<dyn Log>);
+
+#[cfg(feature = "cli")]
+impl Log for WarningCounter {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Warn || self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Warn {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.0.enabled(record.metadata()) {
+            self.0.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// A tracing layer measuring the time spent in the spans
+#[cfg(feature = "cli")]
+pub(crate) struct PhaseTimer(pub RunStats);
+
+#[cfg(feature = "cli")]
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for PhaseTimer {
+    fn on_new_span(&self, _attrs: &span::Attributes, id: &span::Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Instant::now());
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(start) = span.extensions().get::<Instant>() {
+                self.0.add_phase(span.name(), start.elapsed().as_secs_f64());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let sym = b"MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so
+INFO CODE_ID 89674523CDAB0123
+FILE 0 foo.c
+INLINE_ORIGIN 0 bar
+FUNC m 2000 20 0 foo
+INLINE 0 12 0 0 2004 8
+2000 10 12 0
+2010 10 13 0
+PUBLIC 3000 0 baz
+STACK CFI INIT 2000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 2004 .cfa: $rsp 16 +
+";
+        assert_eq!(
+            Counts::new(sym),
+            Counts {
+                functions: 1,
+                publics: 1,
+                files: 1,
+                lines: 2,
+                inlines: 1,
+                inline_origins: 1,
+                cfi: 2,
+            }
+        );
+
+        let stats = RunStats::default();
+        stats.add_module("libfoo.so", "0123456789ABCDEF1", sym);
+        stats.add_phase("dump", 0.5);
+        stats.add_phase("dump", 0.25);
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("stats.json");
+        let path = path.to_str().unwrap();
+        stats.write(path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(json["phases"]["dump"]["count"], 2);
+        assert_eq!(json["phases"]["dump"]["seconds"], 0.75);
+        assert_eq!(json["modules"][0]["debug_file"], "libfoo.so");
+        assert_eq!(json["modules"][0]["size"], sym.len());
+        assert_eq!(json["modules"][0]["lines"], 2);
+    }
+}
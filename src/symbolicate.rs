@@ -0,0 +1,152 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::io::Write;
+
+use crate::common;
+use crate::dumper::{self, Config};
+use crate::json;
+use crate::model::Module;
+
+/*
+ The file is dumped in memory and the records are parsed (as for the json output) to find the function
+ containing each address (or the closest PUBLIC before it) and the line in this function.
+ The output is like the one of addr2line: one line per address with the function, the offset in the
+ function and the source location (?? when unknown).
+*/
+
+/// The location of an address
+#[derive(Debug, PartialEq)]
+struct Location<'a> {
+    name: &'a str,
+    offset: u64,
+    file: Option<&'a str>,
+    line: u32,
+}
+
+/// Parse an address (hexadecimal with or without 0x)
+pub(crate) fn parse_address(s: &str) -> common::Result<u64> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(hex, 16).map_err(|_| format!("Invalid address: {}", s).into())
+}
+
+fn lookup<'a>(module: &'a Module, address: u64) -> Option<Location<'a>> {
+    // The records are sorted by address
+    let i = module.functions.partition_point(|f| f.address <= address);
+    if let Some(func) = i.checked_sub(1).map(|i| &module.functions[i]) {
+        if address < func.address + func.size.max(1) {
+            let line = func
+                .lines
+                .iter()
+                .find(|l| l.address <= address && address < l.address + l.size);
+            let file = line.and_then(|l| module.files.iter().find(|f| f.id == l.file));
+            return Some(Location {
+                name: &func.name,
+                offset: address - func.address,
+                file: file.map(|f| &*f.name),
+                line: line.map_or(0, |l| l.line),
+            });
+        }
+    }
+
+    let i = module.publics.partition_point(|p| p.address <= address);
+    i.checked_sub(1).map(|i| {
+        let public = &module.publics[i];
+        Location {
+            name: &public.name,
+            offset: address - public.address,
+            file: None,
+            line: 0,
+        }
+    })
+}
+
+fn format_location(address: u64, location: Option<Location>) -> String {
+    match location {
+        Some(location) => format!(
+            "0x{:x} {}+0x{:x} {}:{}",
+            address,
+            location.name,
+            location.offset,
+            location.file.unwrap_or("??"),
+            location.line
+        ),
+        None => format!("0x{:x} ?? ??:0", address),
+    }
+}
+
+/// Print the function, the file and the line of the module-relative addresses
+pub(crate) fn symbolicate<W: Write>(
+    config: &Config,
+    filename: &str,
+    addresses: &[u64],
+    mut writer: W,
+) -> common::Result<()> {
+    let sym = dumper::dump_in_memory(config, filename)?;
+    let module = json::parse(&sym)?;
+    for address in addresses {
+        writeln!(
+            writer,
+            "{}",
+            format_location(*address, lookup(&module, *address))
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_lookup() {
+        let sym = "MODULE windows x86_64 0123456789ABCDEF1 basic.pdb
+FILE 0 c:\\basic.cpp
+FUNC 1000 20 0 main
+1000 10 12 0
+1010 10 13 0
+FUNC 1040 8 0 foo
+PUBLIC 1030 0 bar
+PUBLIC 2000 0 baz
+";
+        let module = json::parse(sym).unwrap();
+        let get = |address| format_location(address, lookup(&module, address));
+
+        assert_eq!(get(0x1014), "0x1014 main+0x14 c:\\basic.cpp:13");
+        assert_eq!(get(0x1042), "0x1042 foo+0x2 ??:0");
+        assert_eq!(get(0x1034), "0x1034 bar+0x4 ??:0");
+        assert_eq!(get(0x2010), "0x2010 baz+0x10 ??:0");
+        assert_eq!(get(0x100), "0x100 ?? ??:0");
+
+        assert_eq!(parse_address("0x1a").unwrap(), 0x1a);
+        assert_eq!(parse_address("1A").unwrap(), 0x1a);
+        assert!(parse_address("0xg").is_err());
+    }
+
+    #[test]
+    fn test_symbolicate() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data/linux/basic.full");
+        let mut output = Vec::new();
+        symbolicate(
+            &Config::default(),
+            path.to_str().unwrap(),
+            &[0x112c, 0x1040],
+            &mut output,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("0x112c inline_1(int)+0x7 "));
+        assert!(lines[0].ends_with("test_data/linux/basic.cpp:3"));
+        assert_eq!(lines[1], "0x1040 _start+0x0 ??:0");
+    }
+}
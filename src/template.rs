@@ -0,0 +1,159 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::time::SystemTime;
+
+use crate::common;
+use crate::utils;
+
+/*
+ With --output-template the path of each sym file is built from the fields of its module, e.g.
+ {store}/{debug_file}/{debug_id}/{debug_file}.sym is the layout of a store and
+ {store}/{os}/{arch}/{code_file}/{code_id}.sym another one.
+ The fields are read in the MODULE and INFO CODE_ID records, so they're the same whatever the dumper:
+  - {store}: the directory given with --store,
+  - {debug_file} and {debug_id}: the name and the id in the MODULE record,
+  - {code_file} and {code_id}: the ones in the INFO CODE_ID record (the code file is the debug file
+    when the record hasn't any),
+  - {os} and {arch}: the ones in the MODULE record,
+  - {date}: the day of the dump (YYYYMMDD in UTC).
+ A field which isn't available for a module is an error rather than an empty directory.
+*/
+
+/// The fields of a module used in the templates
+#[derive(Debug, Default, PartialEq)]
+struct Fields<'a> {
+    os: &'a str,
+    arch: &'a str,
+    debug_id: &'a str,
+    debug_file: &'a str,
+    code_id: Option<&'a str>,
+    code_file: Option<&'a str>,
+}
+
+impl<'a> Fields<'a> {
+    /// Read the fields in the records at the beginning of the sym
+    fn new(sym: &'a str) -> common::Result<Self> {
+        let mut fields = Fields::default();
+        for line in sym.lines() {
+            if let Some(rest) = line.strip_prefix("MODULE ") {
+                let f: Vec<_> = rest.splitn(4, ' ').collect();
+                if f.len() != 4 {
+                    return Err(format!("Invalid record: {}", line).into());
+                }
+                fields.os = f[0];
+                fields.arch = f[1];
+                fields.debug_id = f[2];
+                fields.debug_file = f[3];
+            } else if let Some(rest) = line.strip_prefix("INFO CODE_ID ") {
+                let mut f = rest.splitn(2, ' ');
+                fields.code_id = f.next();
+                fields.code_file = f.next();
+            } else if !line.starts_with("INFO ") {
+                break;
+            }
+        }
+        if fields.debug_file.is_empty() {
+            return Err("No MODULE record in the sym".into());
+        }
+        Ok(fields)
+    }
+}
+
+/// Get the path of the sym from the template
+pub(crate) fn expand(template: &str, store: Option<&str>, sym: &[u8]) -> common::Result<String> {
+    let fields = Fields::new(std::str::from_utf8(sym)?)?;
+    let mut path = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        path.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed field in the output template: {}", template))?;
+        let name = &rest[start + 1..start + end];
+        let missing = || format!("No {} for {}", name, fields.debug_file);
+        match name {
+            "store" => match store {
+                Some(store) if store.contains("://") => {
+                    return Err("The field {store} must be a directory".into());
+                }
+                Some(store) => path.push_str(store),
+                None => return Err("The field {store} requires --store".into()),
+            },
+            "os" => path.push_str(fields.os),
+            "arch" => path.push_str(fields.arch),
+            "debug_id" => path.push_str(fields.debug_id),
+            "debug_file" => path.push_str(fields.debug_file),
+            "code_id" => path.push_str(fields.code_id.ok_or_else(missing)?),
+            "code_file" => path.push_str(fields.code_file.unwrap_or(fields.debug_file)),
+            "date" => path.push_str(&utils::get_amz_date(SystemTime::now()).0),
+            _ => {
+                return Err(format!("Unknown field {{{}}} in the output template", name).into());
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    path.push_str(rest);
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let sym = b"MODULE windows x86_64 0123456789ABCDEF1 basic.pdb
+INFO CODE_ID 5E8F1A2B3000 basic.dll
+FILE 0 c:\\basic.cpp
+";
+        assert_eq!(
+            expand(
+                "{store}/{debug_file}/{debug_id}/{debug_file}.sym",
+                Some("/tmp/store"),
+                sym
+            )
+            .unwrap(),
+            "/tmp/store/basic.pdb/0123456789ABCDEF1/basic.pdb.sym"
+        );
+        assert_eq!(
+            expand("syms/{os}/{arch}/{code_file}/{code_id}.sym", None, sym).unwrap(),
+            "syms/windows/x86_64/basic.dll/5E8F1A2B3000.sym"
+        );
+        assert_eq!(
+            expand("{date}.sym", None, sym).unwrap().len(),
+            "YYYYMMDD.sym".len()
+        );
+
+        let errors = [
+            (
+                "{store}/{debug_file}.sym",
+                "The field {store} requires --store",
+            ),
+            (
+                "{debug_name}.sym",
+                "Unknown field {debug_name} in the output template",
+            ),
+            (
+                "{debug_file.sym",
+                "Unclosed field in the output template: {debug_file.sym",
+            ),
+        ];
+        for (template, error) in errors.iter() {
+            assert_eq!(expand(template, None, sym).unwrap_err().to_string(), *error);
+        }
+
+        let sym = b"MODULE Linux x86_64 0123456789ABCDEF1 libfoo.so\nFILE 0 foo.c\n";
+        assert_eq!(
+            expand("{code_id}.sym", None, sym).unwrap_err().to_string(),
+            "No code_id for libfoo.so"
+        );
+        assert_eq!(
+            expand("{code_file}.sym", None, sym).unwrap(),
+            "libfoo.so.sym"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use log::{info, warn};
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::cancel;
+use crate::common;
+use crate::fetch;
+
+/*
+ The store is zipped (with the same layout: <name>/<debug id>/<name>.sym) and the archive is posted
+ as a multipart form to the upload endpoint of the symbol server (e.g. https://symbols.mozilla.org/upload/)
+ with the token in an Auth-Token header.
+ The upload is retried (with an exponential backoff) when the server can't be reached or answers with
+ a 5xx or a 429.
+ The same archive can be written in a file (--bundle) to be uploaded later.
+*/
+
+const BOUNDARY: &str = "dump-syms-upload-boundary";
+const MAX_ATTEMPTS: u32 = 5;
+
+/// The result of an upload
+#[derive(Debug)]
+pub(crate) struct Report {
+    pub files: usize,
+    pub size: usize,
+    pub attempts: u32,
+    pub status: u16,
+}
+
+fn add_dir(zip: &mut ZipWriter<Cursor<Vec<u8>>>, root: &Path, dir: &Path) -> common::Result<usize> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    let mut count = 0;
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            count += add_dir(zip, root, &path)?;
+            continue;
+        }
+        // Some temporary files from an interrupted dump or the checksums (they aren't symbols)
+        if path.extension() == Some(OsStr::new("tmp"))
+            || path.extension() == Some(OsStr::new("sha256"))
+        {
+            continue;
+        }
+        let name: Vec<_> = path
+            .strip_prefix(root)
+            .unwrap()
+            .iter()
+            .map(|c| c.to_string_lossy())
+            .collect();
+        zip.start_file(name.join("/"), options)?;
+        zip.write_all(&fs::read(&path)?)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Zip the content of the store and get the archive and the number of files in it
+fn zip_store(store: &Path) -> common::Result<(Vec<u8>, usize)> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let count = add_dir(&mut zip, store, store)?;
+    Ok((zip.finish()?.into_inner(), count))
+}
+
+/// Zip the store in the given bundle and get the number of files in it
+pub(crate) fn write_bundle(store: &Path, bundle: &Path) -> common::Result<usize> {
+    let (zip, files) = if store.is_dir() {
+        zip_store(store)?
+    } else {
+        (Vec::new(), 0)
+    };
+    if files == 0 {
+        return Err(format!("No sym file to put in {}", bundle.display()).into());
+    }
+    fs::write(bundle, zip)?;
+    Ok(files)
+}
+
+fn get_multipart(zip: &[u8]) -> Vec<u8> {
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"symbols.zip\"; filename=\"symbols.zip\"\r\nContent-Type: application/zip\r\n\r\n",
+        BOUNDARY
+    )
+    .into_bytes();
+    body.extend_from_slice(zip);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    body
+}
+
+/// Post the archive and retry on the errors which are likely temporary
+fn post(url: &str, token: Option<&str>, zip: &[u8], delay: Duration) -> common::Result<(u32, u16)> {
+    let client = fetch::build_blocking(Client::builder().timeout(None))?;
+    let body = get_multipart(zip);
+    let mut delay = delay;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        cancel::check()?;
+        let mut request = client
+            .post(url)
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(body.clone());
+        if let Some(token) = token {
+            request = request.header("Auth-Token", token);
+        }
+
+        let error = match request.send() {
+            Ok(resp) if resp.status().is_success() => return Ok((attempt, resp.status().as_u16())),
+            Ok(resp) if resp.status().is_server_error() || resp.status().as_u16() == 429 => {
+                format!("the server answered {}", resp.status())
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                let text = resp.text().unwrap_or_default();
+                return Err(format!("Cannot upload to {}: {} {}", url, status, text.trim()).into());
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if attempt == MAX_ATTEMPTS {
+            return Err(format!(
+                "Cannot upload to {} after {} attempts: {}",
+                url, MAX_ATTEMPTS, error
+            )
+            .into());
+        }
+        warn!(
+            "Upload attempt {} to {} failed ({}), retry in {:?}",
+            attempt, url, error, delay
+        );
+        thread::sleep(delay);
+        delay *= 2;
+    }
+
+    unreachable!()
+}
+
+/// Zip the store and upload it
+pub(crate) fn upload(store: &Path, url: &str, token: Option<&str>) -> common::Result<Report> {
+    upload_with_delay(store, url, token, Duration::from_secs(1))
+}
+
+fn upload_with_delay(
+    store: &Path,
+    url: &str,
+    token: Option<&str>,
+    delay: Duration,
+) -> common::Result<Report> {
+    let (zip, files) = zip_store(store)?;
+    if files == 0 {
+        return Err(format!("Nothing to upload in {}", store.display()).into());
+    }
+    info!("Upload {} files ({} bytes) to {}", files, zip.len(), url);
+
+    let (attempts, status) = post(url, token, &zip, delay)?;
+    Ok(Report {
+        files,
+        size: zip.len(),
+        attempts,
+        status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use tempfile::Builder;
+    use zip::ZipArchive;
+
+    /// The headers and the body of the received requests
+    type Requests = Vec<(Vec<String>, Vec<u8>)>;
+
+    /// A server answering with the given statuses and returning the received requests
+    fn serve(statuses: Vec<u16>) -> (String, thread::JoinHandle<Requests>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/upload/", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    headers.push(line.trim().to_lowercase());
+                }
+                let len = headers
+                    .iter()
+                    .find_map(|h| h.strip_prefix("content-length: "))
+                    .map_or(0, |l| l.parse().unwrap());
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                requests.push((headers, body));
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn get_store() -> tempfile::TempDir {
+        let tmp_dir = Builder::new().prefix("upload").tempdir().unwrap();
+        let dir = tmp_dir.path().join("basic.pdb").join("AAAA1");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("basic.sym"),
+            "MODULE windows x86_64 AAAA1 basic.pdb\n",
+        )
+        .unwrap();
+        fs::write(dir.join("basic.sym.123.tmp"), "MODULE").unwrap();
+        fs::write(dir.join("basic.sym.sha256"), "0123  basic.sym\n").unwrap();
+        tmp_dir
+    }
+
+    #[test]
+    fn test_zip_store() {
+        let store = get_store();
+        let (zip, count) = zip_store(store.path()).unwrap();
+        assert_eq!(count, 1);
+
+        let mut zip = ZipArchive::new(Cursor::new(zip)).unwrap();
+        assert_eq!(zip.len(), 1);
+        let mut sym = String::new();
+        zip.by_name("basic.pdb/AAAA1/basic.sym")
+            .unwrap()
+            .read_to_string(&mut sym)
+            .unwrap();
+        assert_eq!(sym, "MODULE windows x86_64 AAAA1 basic.pdb\n");
+    }
+
+    #[test]
+    fn test_write_bundle() {
+        let store = get_store();
+        let bundle = store.path().join("symbols.zip");
+        assert_eq!(write_bundle(store.path(), &bundle).unwrap(), 1);
+        let zip = ZipArchive::new(fs::File::open(&bundle).unwrap()).unwrap();
+        assert_eq!(zip.len(), 1);
+
+        assert!(write_bundle(&store.path().join("missing"), &bundle).is_err());
+    }
+
+    #[test]
+    fn test_upload() {
+        let store = get_store();
+
+        // A 503 and then a success
+        let (url, handle) = serve(vec![503, 201]);
+        let report =
+            upload_with_delay(store.path(), &url, Some("secret"), Duration::from_millis(1))
+                .unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(report.attempts, 2);
+        assert_eq!(report.status, 201);
+
+        let requests = handle.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        let (headers, body) = &requests[1];
+        assert!(headers.contains(&"post /upload/ http/1.1".to_string()));
+        assert!(headers.contains(&"auth-token: secret".to_string()));
+        let (zip, _) = zip_store(store.path()).unwrap();
+        assert_eq!(body, &get_multipart(&zip));
+
+        // A client error isn't retried
+        let (url, handle) = serve(vec![403]);
+        assert!(upload_with_delay(store.path(), &url, None, Duration::from_millis(1)).is_err());
+        assert_eq!(handle.join().unwrap().len(), 1);
+    }
+}
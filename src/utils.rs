@@ -7,6 +7,7 @@ use cab::Cabinet;
 use std::fs::{self, File, Metadata};
 use std::io::{self, BufWriter, Cursor, Read, Write};
 use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::common;
 
@@ -130,15 +131,18 @@ fn get_cabinet_files(cab: &Cabinet<Cursor<&Vec<u8>>>, path: PathBuf) -> Option<(
 }
 
 pub fn get_writer_for_sym(file_name: &str) -> BufWriter<Box<dyn Write>> {
+    try_get_writer_for_sym(file_name)
+        .unwrap_or_else(|_| panic!("Cannot open file {} for writing", file_name))
+}
+
+/// Same as get_writer_for_sym but the error is returned when the file can't be created
+pub fn try_get_writer_for_sym(file_name: &str) -> io::Result<BufWriter<Box<dyn Write>>> {
     let output: Box<dyn Write> = if file_name.is_empty() || file_name == "-" {
         Box::new(io::stdout())
     } else {
-        let path = PathBuf::from(file_name);
-        let output = File::create(&path)
-            .unwrap_or_else(|_| panic!("Cannot open file {} for writing", path.to_str().unwrap()));
-        Box::new(output)
+        Box::new(File::create(file_name)?)
     };
-    BufWriter::new(output)
+    Ok(BufWriter::new(output))
 }
 
 pub fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
@@ -193,3 +197,29 @@ pub fn read<P: AsRef<Path>>(path: P) -> common::Result<Vec<u8>> {
 
     Ok(buf)
 }
+/// Get the date (YYYYMMDD) and the time (YYYYMMDDTHHMMSSZ) in UTC
+pub(crate) fn get_amz_date(time: SystemTime) -> (String, String) {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // From the days since the epoch to the civil date (proleptic Gregorian calendar)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    );
+    (date, time)
+}
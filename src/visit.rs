@@ -0,0 +1,347 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::common;
+use crate::dumper::{self, Config};
+use crate::model::{CfiKind, LineRecord, Public, SourceFile, StackCfi};
+
+/*
+ The records of a sym file are given one by one to a visitor as typed items (the ones of the data
+ model), so a consumer can build what it needs (an index, a json, some checks) without parsing the
+ records itself.
+ The pdbs, the ELFs and the Mach-Os give their items right from the collected symbols (see
+ Dumpable::visit) and the other files are dumped and their records parsed back: in both cases the
+ items borrow what they can (the symbols or the text of the records).
+ The items come in the order of the records: the LINE ones are the lines of the last visited function.
+ A visitor can stop the visit by returning an error.
+*/
+
+/// The MODULE record
+#[derive(Debug, PartialEq)]
+pub struct ModuleHeader<'a> {
+    pub os: &'a str,
+    pub arch: &'a str,
+    pub debug_id: &'a str,
+    pub name: &'a str,
+}
+
+/// A FUNC record (without its lines)
+#[derive(Debug, PartialEq)]
+pub struct FunctionHeader<'a> {
+    pub address: u64,
+    pub size: u64,
+    pub parameter_size: u64,
+    pub name: &'a str,
+    pub multiple: bool,
+}
+
+/// The items of a sym file: all the methods do nothing by default
+pub trait SymbolVisitor<'a> {
+    fn module(&mut self, _module: ModuleHeader<'a>) -> common::Result<()> {
+        Ok(())
+    }
+
+    /// An INFO record (without the INFO prefix)
+    fn info(&mut self, _info: Cow<'a, str>) -> common::Result<()> {
+        Ok(())
+    }
+
+    fn file(&mut self, _file: SourceFile<'a>) -> common::Result<()> {
+        Ok(())
+    }
+
+    fn function(&mut self, _function: FunctionHeader<'a>) -> common::Result<()> {
+        Ok(())
+    }
+
+    /// A line of the last visited function
+    fn line(&mut self, _line: LineRecord) -> common::Result<()> {
+        Ok(())
+    }
+
+    fn public(&mut self, _public: Public<'a>) -> common::Result<()> {
+        Ok(())
+    }
+
+    fn cfi(&mut self, _record: StackCfi<'a>) -> common::Result<()> {
+        Ok(())
+    }
+}
+
+fn hex(s: &str) -> common::Result<u64> {
+    u64::from_str_radix(s, 16).map_err(|_| format!("Invalid hexadecimal number: {}", s).into())
+}
+
+/// Split the n first fields and get the rest of the line (which may contain spaces)
+fn fields(line: &str, n: usize) -> common::Result<Vec<&str>> {
+    let fields: Vec<_> = line.splitn(n + 1, ' ').collect();
+    if fields.len() != n + 1 {
+        return Err(format!("Invalid record: {}", line).into());
+    }
+    Ok(fields)
+}
+
+fn strip_multiple(line: &str) -> (&str, bool) {
+    match line.strip_prefix("m ") {
+        Some(line) => (line, true),
+        None => (line, false),
+    }
+}
+
+// The checksums added with --file-checksums are kind:hex
+fn split_checksum(name: &str) -> (&str, Option<&str>) {
+    if let Some((path, checksum)) = name.rsplit_once(' ') {
+        if let Some((kind, digest)) = checksum.split_once(':') {
+            if matches!(kind, "md5" | "sha1" | "sha256")
+                && !digest.is_empty()
+                && digest.bytes().all(|c| c.is_ascii_hexdigit())
+            {
+                return (path, Some(checksum));
+            }
+        }
+    }
+    (name, None)
+}
+
+fn parse_stack(line: &str) -> common::Result<StackCfi<'_>> {
+    let (kind, frame_type, address, size, rules) =
+        if let Some(rest) = line.strip_prefix("STACK CFI INIT ") {
+            let f = fields(rest, 2)?;
+            (CfiKind::Init, None, f[0], Some(f[1]), f[2])
+        } else if let Some(rest) = line.strip_prefix("STACK CFI ") {
+            let f = fields(rest, 1)?;
+            (CfiKind::Delta, None, f[0], None, f[1])
+        } else if let Some(rest) = line.strip_prefix("STACK WIN ") {
+            // The type of the frame data is before the address
+            let f = fields(rest, 3)?;
+            (CfiKind::Win, Some(f[0].parse()?), f[1], Some(f[2]), f[3])
+        } else {
+            return Err(format!("Invalid record: {}", line).into());
+        };
+
+    Ok(StackCfi {
+        kind,
+        frame_type,
+        address: hex(address)?,
+        size: size.map(hex).transpose()?,
+        rules: Cow::Borrowed(rules),
+    })
+}
+
+/// Give the STACK records (the text of the ones of a collector) to the visitor
+pub(crate) fn visit_stack<'a, V: SymbolVisitor<'a>>(
+    stack: &'a str,
+    visitor: &mut V,
+) -> common::Result<()> {
+    for line in stack.lines().filter(|line| !line.is_empty()) {
+        visitor.cfi(parse_stack(line)?)?;
+    }
+    Ok(())
+}
+
+/// Dump the file at path and give its records to the visitor: the ones of a fat binary are the
+/// ones of the architecture of the build
+pub fn visit_file<P, V>(path: P, visitor: &mut V) -> common::Result<()>
+where
+    P: AsRef<Path>,
+    V: for<'a> SymbolVisitor<'a>,
+{
+    dumper::visit_in_memory(&Config::default(), path.as_ref(), visitor)
+}
+
+/// Give the records of a sym file to the visitor
+pub(crate) fn visit<'a, V: SymbolVisitor<'a>>(sym: &'a str, visitor: &mut V) -> common::Result<()> {
+    let mut has_function = false;
+    for line in sym.lines() {
+        if let Some(rest) = line.strip_prefix("MODULE ") {
+            let f = fields(rest, 3)?;
+            visitor.module(ModuleHeader {
+                os: f[0],
+                arch: f[1],
+                debug_id: f[2],
+                name: f[3],
+            })?;
+        } else if let Some(rest) = line.strip_prefix("INFO ") {
+            visitor.info(Cow::Borrowed(rest))?;
+        } else if let Some(rest) = line.strip_prefix("FILE ") {
+            let f = fields(rest, 1)?;
+            let (name, checksum) = split_checksum(f[1]);
+            visitor.file(SourceFile {
+                id: f[0].parse()?,
+                name: Cow::Borrowed(name),
+                checksum: checksum.map(Cow::Borrowed),
+            })?;
+        } else if let Some(rest) = line.strip_prefix("FUNC ") {
+            let (rest, multiple) = strip_multiple(rest);
+            let f = fields(rest, 3)?;
+            has_function = true;
+            visitor.function(FunctionHeader {
+                address: hex(f[0])?,
+                size: hex(f[1])?,
+                parameter_size: hex(f[2])?,
+                name: f[3],
+                multiple,
+            })?;
+        } else if let Some(rest) = line.strip_prefix("PUBLIC ") {
+            let (rest, multiple) = strip_multiple(rest);
+            let f = fields(rest, 2)?;
+            visitor.public(Public {
+                address: hex(f[0])?,
+                parameter_size: hex(f[1])?,
+                name: Cow::Borrowed(f[2]),
+                multiple,
+            })?;
+        } else if line.starts_with("STACK ") {
+            visit_stack(line, visitor)?;
+        } else if !line.is_empty() {
+            // A LINE record: address size line file (and column with --line-columns)
+            if !has_function {
+                return Err(format!("Line record without function: {}", line).into());
+            }
+            let f: Vec<_> = line.split(' ').collect();
+            if f.len() != 4 && f.len() != 5 {
+                return Err(format!("Invalid record: {}", line).into());
+            }
+            visitor.line(LineRecord {
+                address: hex(f[0])?,
+                size: hex(f[1])?,
+                line: f[2].parse()?,
+                file: f[3].parse()?,
+                column: f.get(4).map(|c| c.parse()).transpose()?,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Count the lines of each function and keep the addresses of the CFI records
+    #[derive(Default)]
+    struct Index {
+        functions: Vec<(u64, usize)>,
+        cfi: Vec<(CfiKind, u64, Option<u64>)>,
+    }
+
+    impl<'a> SymbolVisitor<'a> for Index {
+        fn function(&mut self, function: FunctionHeader<'a>) -> common::Result<()> {
+            self.functions.push((function.address, 0));
+            Ok(())
+        }
+
+        fn line(&mut self, _line: LineRecord) -> common::Result<()> {
+            self.functions.last_mut().unwrap().1 += 1;
+            Ok(())
+        }
+
+        fn cfi(&mut self, record: StackCfi<'a>) -> common::Result<()> {
+            self.cfi.push((record.kind, record.address, record.size));
+            Ok(())
+        }
+    }
+
+    /// Keep the items as they're printed
+    #[derive(Default)]
+    struct Items(Vec<String>);
+
+    impl<'a> SymbolVisitor<'a> for Items {
+        fn module(&mut self, module: ModuleHeader<'a>) -> common::Result<()> {
+            self.0.push(format!("{:?}", module));
+            Ok(())
+        }
+
+        fn info(&mut self, info: Cow<'a, str>) -> common::Result<()> {
+            self.0.push(format!("{:?}", info));
+            Ok(())
+        }
+
+        fn file(&mut self, file: SourceFile<'a>) -> common::Result<()> {
+            self.0.push(format!("{:?}", file));
+            Ok(())
+        }
+
+        fn function(&mut self, function: FunctionHeader<'a>) -> common::Result<()> {
+            self.0.push(format!("{:?}", function));
+            Ok(())
+        }
+
+        fn line(&mut self, line: LineRecord) -> common::Result<()> {
+            self.0.push(format!("{:?}", line));
+            Ok(())
+        }
+
+        fn public(&mut self, public: Public<'a>) -> common::Result<()> {
+            self.0.push(format!("{:?}", public));
+            Ok(())
+        }
+
+        fn cfi(&mut self, record: StackCfi<'a>) -> common::Result<()> {
+            self.0.push(format!("{:?}", record));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_visit_file() {
+        // The collectors give the same items as the records they write
+        for path in [
+            "./test_data/windows/basic64.pdb",
+            "./test_data/linux/basic.full",
+        ] {
+            let mut from_collector = Items::default();
+            visit_file(path, &mut from_collector).unwrap();
+
+            let sym = dumper::dump_in_memory(&Config::default(), path).unwrap();
+            let mut from_sym = Items::default();
+            visit(&sym, &mut from_sym).unwrap();
+
+            assert!(from_collector
+                .0
+                .iter()
+                .any(|i| i.starts_with("FunctionHeader")));
+            assert_eq!(from_collector.0, from_sym.0);
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let sym = "MODULE Linux x86_64 0123456789ABCDEF1 foo
+FILE 0 foo.c
+FUNC 1000 20 0 foo
+1000 10 1 0
+1010 10 2 0
+PUBLIC 1100 0 bar
+FUNC m 1200 8 0 baz
+STACK CFI INIT 1000 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^
+STACK CFI 1004 .cfa: $rsp 16 +
+STACK WIN 4 1200 8 0 0 0 0 0 0 1 $T0 .raSearch =
+";
+        let mut index = Index::default();
+        visit(sym, &mut index).unwrap();
+        assert_eq!(index.functions, vec![(0x1000, 2), (0x1200, 0)]);
+        assert_eq!(
+            index.cfi,
+            vec![
+                (CfiKind::Init, 0x1000, Some(0x20)),
+                (CfiKind::Delta, 0x1004, None),
+                (CfiKind::Win, 0x1200, Some(8)),
+            ]
+        );
+
+        assert_eq!(
+            visit("STACK CFI INIT 10\n", &mut index)
+                .unwrap_err()
+                .to_string(),
+            "Invalid record: 10"
+        );
+    }
+}
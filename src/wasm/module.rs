@@ -0,0 +1,291 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use failure::Fail;
+use log::{info, warn};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use symbolic::debuginfo::dwarf::DwarfDebugSession;
+use symbolic::debuginfo::wasm::WasmObject;
+use symbolic::debuginfo::{Object, SymbolMap};
+
+use crate::common::{self, Dumpable, Mergeable, RecordProvenance};
+use crate::debug_id::DebugIdFallback;
+use crate::linux::elf::{ElfInfo, Platform};
+use crate::mapping::PathMappings;
+use crate::utils;
+
+/*
+ The addresses in the sym files of the WebAssembly modules are offsets in the code section
+ (the addresses used in the DWARF) and not offsets in the file.
+ The DWARF can be in a separate file (e.g. emscripten -gseparate-dwarf): its path or url is
+ in the external_debug_info section and it's searched next to the module.
+*/
+
+const CUSTOM_SECTION: u8 = 0;
+pub(crate) const CODE_SECTION: u8 = 10;
+const EXTERNAL_DEBUG_INFO: &str = "external_debug_info";
+
+/// A section of a WebAssembly module
+pub(crate) struct Section<'a> {
+    pub id: u8,
+    /// The name of a custom section
+    pub name: Option<&'a str>,
+    pub data: &'a [u8],
+}
+
+fn read_uleb(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        if shift >= 64 {
+            return None;
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+fn read_name<'a>(data: &'a [u8], pos: &mut usize) -> Option<&'a str> {
+    let len = read_uleb(data, pos)? as usize;
+    let name = data.get(*pos..pos.checked_add(len)?)?;
+    *pos += len;
+    std::str::from_utf8(name).ok()
+}
+
+/// Get the sections of a module: magic, version and then (id, size, content) for each section
+pub(crate) fn get_sections(buf: &[u8]) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    if !WasmObject::test(buf) {
+        return sections;
+    }
+
+    let mut pos = 8;
+    while pos < buf.len() {
+        let id = buf[pos];
+        pos += 1;
+        let size = match read_uleb(buf, &mut pos) {
+            Some(size) => size as usize,
+            _ => break,
+        };
+        let data = match pos.checked_add(size).and_then(|end| buf.get(pos..end)) {
+            Some(data) => data,
+            _ => break,
+        };
+        pos += size;
+
+        if id == CUSTOM_SECTION {
+            let mut name_pos = 0;
+            if let Some(name) = read_name(data, &mut name_pos) {
+                sections.push(Section {
+                    id,
+                    name: Some(name),
+                    data: &data[name_pos..],
+                });
+            }
+        } else {
+            sections.push(Section {
+                id,
+                name: None,
+                data,
+            });
+        }
+    }
+
+    sections
+}
+
+fn get_external_debug_info(buf: &[u8]) -> Option<String> {
+    let sections = get_sections(buf);
+    let section = sections
+        .iter()
+        .find(|s| s.name == Some(EXTERNAL_DEBUG_INFO))?;
+    let mut pos = 0;
+    read_name(section.data, &mut pos).map(|s| s.to_string())
+}
+
+#[derive(Debug)]
+pub struct WasmInfo {
+    elf: ElfInfo,
+}
+
+impl Display for WasmInfo {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.elf)
+    }
+}
+
+impl WasmInfo {
+    pub fn new(
+        buf: &[u8],
+        path: &Path,
+        file_name: &str,
+        mapping: Option<Arc<PathMappings>>,
+    ) -> common::Result<Self> {
+        let wasm = WasmObject::parse(buf).map_err(|e| e.compat())?;
+        if !wasm.has_debug_info() {
+            if let Some(debug_file) = get_external_debug_info(buf) {
+                // The url or the path is the one used when the module has been built
+                let debug_name = debug_file.rsplit(['/', '\\']).next().unwrap_or(&debug_file);
+                let debug_path = path.with_file_name(debug_name);
+                if debug_path.is_file() {
+                    info!("Use the DWARF in {}", debug_path.display());
+                    let debug_buf = utils::read(&debug_path)?;
+                    return Self::from_buf(&debug_buf, file_name, mapping);
+                }
+                warn!("Cannot find the DWARF file {}", debug_file);
+            }
+        }
+
+        Self::from_buf(buf, file_name, mapping)
+    }
+
+    fn from_buf(
+        buf: &[u8],
+        file_name: &str,
+        mapping: Option<Arc<PathMappings>>,
+    ) -> common::Result<Self> {
+        let wasm = WasmObject::parse(buf).map_err(|e| e.compat())?;
+
+        // The function bodies are at offsets in the file
+        let code_offset = wasm.code_offset();
+        let get_symbols = || -> SymbolMap {
+            wasm.symbols()
+                .map(|mut sym| {
+                    sym.address = sym.address.saturating_sub(code_offset);
+                    sym
+                })
+                .collect()
+        };
+        let ds = DwarfDebugSession::parse(&wasm, get_symbols(), 0, wasm.kind())
+            .map_err(|e| e.compat())?;
+        let symbols = get_symbols();
+        let has_debug_info = wasm.has_debug_info();
+        let o = Object::Wasm(wasm);
+
+        Ok(Self {
+            elf: ElfInfo::from_session(
+                &o,
+                &ds,
+                &symbols,
+                has_debug_info,
+                file_name,
+                Platform::Unknown,
+                None,
+                mapping,
+            )?,
+        })
+    }
+
+    pub(crate) fn set_fallback_debug_id(&mut self, buf: &[u8], fallback: DebugIdFallback) {
+        self.elf.set_fallback_debug_id(buf, fallback);
+    }
+}
+
+impl Mergeable for WasmInfo {
+    fn merge(left: WasmInfo, right: WasmInfo) -> common::Result<WasmInfo> {
+        Ok(WasmInfo {
+            elf: ElfInfo::merge(left.elf, right.elf)?,
+        })
+    }
+}
+
+impl Dumpable for WasmInfo {
+    fn dump<W: Write>(&self, mut writer: W) -> common::Result<()> {
+        write!(writer, "{}", self.elf)?;
+        Ok(())
+    }
+
+    fn get_debug_id(&self) -> &str {
+        self.elf.get_debug_id()
+    }
+
+    fn get_name(&self) -> &str {
+        self.elf.get_name()
+    }
+
+    fn get_source_files(&self) -> &[String] {
+        self.elf.get_source_files()
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        self.elf.get_provenance()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn get_module() -> Vec<u8> {
+        let mut buf = b"\0asm\x01\0\0\0".to_vec();
+        // A type section: one function () -> ()
+        buf.extend_from_slice(&[1, 4, 1, 0x60, 0, 0]);
+        // A function section: one function of type 0
+        buf.extend_from_slice(&[3, 2, 1, 0]);
+        // A code section: one body (no locals, i32.const 0, drop, end)
+        buf.extend_from_slice(&[CODE_SECTION, 7, 1, 5, 0, 0x41, 0, 0x1a, 0x0b]);
+        // A name section: the function 0 is foo (symbolic gets the symbols from there)
+        buf.extend_from_slice(&[CUSTOM_SECTION, 13, 4]);
+        buf.extend_from_slice(b"name");
+        buf.extend_from_slice(&[1, 6, 1, 0, 3]);
+        buf.extend_from_slice(b"foo");
+        // The external debug info
+        let name = EXTERNAL_DEBUG_INFO.as_bytes();
+        let url = b"http://foo/bar.debug.wasm";
+        buf.push(CUSTOM_SECTION);
+        buf.push((2 + name.len() + url.len()) as u8);
+        buf.push(name.len() as u8);
+        buf.extend_from_slice(name);
+        buf.push(url.len() as u8);
+        buf.extend_from_slice(url);
+        buf
+    }
+
+    #[test]
+    fn test_sections() {
+        let buf = get_module();
+        let sections = get_sections(&buf);
+        assert_eq!(
+            sections.iter().map(|s| s.id).collect::<Vec<_>>(),
+            vec![1, 3, CODE_SECTION, CUSTOM_SECTION, CUSTOM_SECTION]
+        );
+        assert_eq!(sections[2].data, &[1, 5, 0, 0x41, 0, 0x1a, 0x0b]);
+        assert_eq!(
+            get_external_debug_info(&buf).unwrap(),
+            "http://foo/bar.debug.wasm"
+        );
+
+        let mut pos = 0;
+        assert_eq!(read_uleb(&[0xe5, 0x8e, 0x26], &mut pos), Some(624_485));
+        assert_eq!(pos, 3);
+        assert!(get_sections(b"\x7fELF").is_empty());
+    }
+
+    #[test]
+    fn test_wasm() {
+        let buf = get_module();
+        let wasm = WasmInfo::new(&buf, Path::new("foo.wasm"), "foo.wasm", None).unwrap();
+        let mut out = Vec::new();
+        wasm.dump(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "MODULE unknown wasm32 000000000000000000000000000000000 foo.wasm"
+        );
+        // The first instruction of the function (after the number of bodies, the size of the body
+        // and its locals) is at offset 3 in the code section
+        assert!(out.contains("PUBLIC 3 0 foo\n"));
+    }
+}
@@ -0,0 +1,241 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use log::{info, warn};
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::cancel;
+use crate::common::{self, FileType};
+use crate::dumper::{self, Config};
+
+/*
+ The drop directory is polled: a new (or modified) file is dumped once its size and its modification
+ time haven't changed during the de-bounce delay (the file is likely still being written else).
+ The processed files are appended to a journal (path, size and mtime separated by tabs) so they aren't
+ dumped again when the watcher is restarted, unless they've been modified.
+ The files which aren't binaries or debug files are recorded in the journal too.
+*/
+
+/// The size and the modification time (in seconds) of a file
+type Stamp = (u64, u64);
+
+pub(crate) struct Watcher {
+    dir: PathBuf,
+    journal: PathBuf,
+    debounce: Duration,
+    processed: HashMap<PathBuf, Stamp>,
+    pending: HashMap<PathBuf, (Stamp, Instant)>,
+}
+
+fn get_stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some((metadata.len(), mtime))
+}
+
+fn read_journal(journal: &Path) -> common::Result<HashMap<PathBuf, Stamp>> {
+    let mut processed = HashMap::new();
+    if !journal.exists() {
+        return Ok(processed);
+    }
+    for line in BufReader::new(File::open(journal)?).lines() {
+        let line = line?;
+        let mut fields = line.rsplitn(3, '\t');
+        let (mtime, size, path) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(mtime), Some(size), Some(path)) => (mtime, size, path),
+            _ => continue,
+        };
+        if let (Ok(size), Ok(mtime)) = (size.parse(), mtime.parse()) {
+            processed.insert(PathBuf::from(path), (size, mtime));
+        }
+    }
+    Ok(processed)
+}
+
+/// Collect the files in the directory (the hidden and temporary ones are skipped)
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> common::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.')
+            || path.extension() == Some(OsStr::new("tmp"))
+        {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Check the first bytes of the file to know if it can be dumped
+fn is_dumpable(path: &Path) -> bool {
+    let mut buf = Vec::new();
+    File::open(path)
+        .and_then(|f| f.take(4096).read_to_end(&mut buf))
+        .is_ok()
+        && !matches!(FileType::from_buf(&buf), FileType::Unknown)
+}
+
+impl Watcher {
+    pub(crate) fn new(
+        dir: &str,
+        journal: Option<&str>,
+        debounce: Duration,
+    ) -> common::Result<Self> {
+        let dir = PathBuf::from(dir);
+        let journal = journal.map_or_else(|| dir.join(".dump_syms.journal"), PathBuf::from);
+        Ok(Self {
+            processed: read_journal(&journal)?,
+            dir,
+            journal,
+            debounce,
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Get the new files which haven't been modified during the de-bounce delay
+    fn get_ready_files(&mut self, now: Instant) -> common::Result<Vec<(PathBuf, Stamp)>> {
+        let mut files = Vec::new();
+        collect_files(&self.dir, &mut files)?;
+        files.sort();
+
+        let mut ready = Vec::new();
+        for path in files {
+            let stamp = match get_stamp(&path) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            if self.processed.get(&path) == Some(&stamp) {
+                continue;
+            }
+            match self.pending.get(&path) {
+                Some((pending, since)) if *pending == stamp => {
+                    if now.duration_since(*since) >= self.debounce {
+                        self.pending.remove(&path);
+                        ready.push((path, stamp));
+                    }
+                }
+                _ => {
+                    self.pending.insert(path, (stamp, now));
+                }
+            }
+        }
+        Ok(ready)
+    }
+
+    fn add_to_journal(&mut self, path: PathBuf, stamp: Stamp) -> common::Result<()> {
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.journal)?;
+        writeln!(journal, "{}\t{}\t{}", path.display(), stamp.0, stamp.1)?;
+        self.processed.insert(path, stamp);
+        Ok(())
+    }
+
+    /// Dump the files which are ready and get the number of dumped files
+    pub(crate) fn poll(&mut self, config: &Config, now: Instant) -> common::Result<usize> {
+        let mut dumped = 0;
+        for (path, stamp) in self.get_ready_files(now)? {
+            cancel::check()?;
+            if is_dumpable(&path) {
+                match dumper::single_file(config, &path.to_string_lossy()) {
+                    Ok(_) => {
+                        info!("{} dumped", path.display());
+                        dumped += 1;
+                    }
+                    Err(e) => warn!("Cannot dump {}: {}", path.display(), e),
+                }
+            }
+            self.add_to_journal(path, stamp)?;
+        }
+        Ok(dumped)
+    }
+
+    /// Poll the directory until the process is interrupted
+    pub(crate) fn run(&mut self, config: &Config, interval: Duration) -> common::Result<()> {
+        info!("Watch {}", self.dir.display());
+        loop {
+            self.poll(config, Instant::now())?;
+            thread::sleep(interval);
+            cancel::check()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_watch() {
+        let tmp_dir = Builder::new().prefix("watch").tempdir().unwrap();
+        let dir = tmp_dir.path().join("drop");
+        let store = tmp_dir.path().join("store");
+        let journal = tmp_dir.path().join("journal");
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data/linux/basic.full"),
+            dir.join("basic.full"),
+        )
+        .unwrap();
+        fs::write(dir.join("README"), "Not a binary").unwrap();
+        fs::write(dir.join("basic.full.123.tmp"), "Being copied").unwrap();
+
+        let config = Config::with_store(store.to_str().unwrap(), None);
+        let mut watcher = Watcher::new(
+            dir.to_str().unwrap(),
+            journal.to_str(),
+            Duration::from_secs(2),
+        )
+        .unwrap();
+
+        // The files are dumped once they're unchanged during the de-bounce delay
+        let now = Instant::now();
+        assert_eq!(watcher.poll(&config, now).unwrap(), 0);
+        assert_eq!(
+            watcher.poll(&config, now + Duration::from_secs(1)).unwrap(),
+            0
+        );
+        assert_eq!(
+            watcher.poll(&config, now + Duration::from_secs(2)).unwrap(),
+            1
+        );
+        assert!(store
+            .join("basic.full/20AD60B0B4C68177552708AA192E77390/basic.sym")
+            .exists());
+        assert_eq!(
+            watcher.poll(&config, now + Duration::from_secs(4)).unwrap(),
+            0
+        );
+
+        // Nothing is dumped again after a restart
+        let mut watcher = Watcher::new(
+            dir.to_str().unwrap(),
+            journal.to_str(),
+            Duration::from_secs(0),
+        )
+        .unwrap();
+        assert_eq!(watcher.processed.len(), 2);
+        assert!(watcher.get_ready_files(now).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,550 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt::Write;
+use symbolic::debuginfo::pe::PeObject;
+
+/*
+ On Windows ARM64 the .pdata contains an entry of two words for each function: its rva and either the
+ rva of its .xdata record or a packed unwind data describing a canonical prologue.
+ The .xdata record contains the unwind codes of the prologue in the reverse order of the execution
+ (one code per instruction), so once put back in order they're replayed to get the rules after each
+ instruction of the prologue. The epilogues aren't described.
+ See https://docs.microsoft.com/en-us/cpp/build/arm64-exception-handling
+*/
+
+const FP: u8 = 29;
+const LR: u8 = 30;
+/// The FP registers d8-d15 are numbered from 64 (they aren't restored by the stackwalkers)
+const D8: u8 = 64;
+
+/// An instruction of the prologue
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    /// sub sp, sp, #size
+    Alloc(u32),
+    /// Store one or two registers at [sp + offset] (after having decremented sp by pre)
+    Save {
+        first: u8,
+        second: Option<u8>,
+        offset: u32,
+        pre: u32,
+    },
+    /// Store the next pair of registers after the previous one
+    SaveNext,
+    /// add x29, sp, #offset
+    SetFp(u32),
+    Nop,
+}
+
+/// The location of the CFA and of the saved registers after some instructions of the prologue
+#[derive(Debug, Default)]
+struct State {
+    /// sp = CFA - alloc
+    alloc: u32,
+    /// x29 = CFA - fp
+    fp: Option<u32>,
+    /// The registers saved at CFA - offset
+    saved: Vec<(u8, u32)>,
+    /// The previous store (for save_next)
+    last: Option<(u8, u32)>,
+}
+
+impl State {
+    fn cfa_rule(&self) -> String {
+        match self.fp {
+            Some(fp) => format!(".cfa: x29 {} +", fp),
+            None => format!(".cfa: sp {} +", self.alloc),
+        }
+    }
+
+    fn save(&mut self, reg: u8, offset: u32, rules: &mut Vec<String>) -> Option<()> {
+        let offset = self.alloc.checked_sub(offset)?;
+        self.saved.push((reg, offset));
+        if reg == LR {
+            rules.push(format!(".ra: .cfa -{} + ^", offset));
+        }
+        if reg < D8 {
+            rules.push(format!("x{}: .cfa -{} + ^", reg, offset));
+        }
+        Some(())
+    }
+
+    /// Execute an instruction and get the rules which have changed
+    fn apply(&mut self, op: Op) -> Option<Vec<String>> {
+        let mut rules = Vec::new();
+        match op {
+            Op::Alloc(size) => {
+                self.alloc += size;
+                if self.fp.is_none() {
+                    rules.push(self.cfa_rule());
+                }
+            }
+            Op::Save {
+                first,
+                second,
+                offset,
+                pre,
+            } => {
+                if pre != 0 {
+                    self.alloc += pre;
+                    if self.fp.is_none() {
+                        rules.push(self.cfa_rule());
+                    }
+                }
+                self.save(first, offset, &mut rules)?;
+                if let Some(second) = second {
+                    self.save(second, offset + 8, &mut rules)?;
+                }
+                self.last = Some((second.unwrap_or(first), offset));
+            }
+            Op::SaveNext => {
+                let (reg, offset) = self.last?;
+                self.save(reg + 1, offset + 16, &mut rules)?;
+                self.save(reg + 2, offset + 24, &mut rules)?;
+                self.last = Some((reg + 2, offset + 16));
+            }
+            Op::SetFp(offset) => {
+                self.fp = Some(self.alloc.checked_sub(offset)?);
+                rules.push(self.cfa_rule());
+            }
+            Op::Nop => {}
+        }
+        Some(rules)
+    }
+}
+
+/// Get the prologue described by a packed unwind data
+fn get_packed_ops(data: u32) -> Vec<Op> {
+    let reg_f = (data >> 13) & 0x7;
+    let reg_i = (data >> 16) & 0xf;
+    let homing = (data >> 20) & 0x1 == 1;
+    let cr = (data >> 21) & 0x3;
+    let frame_size = ((data >> 23) & 0x1ff) * 16;
+
+    let int_size = reg_i * 8 + if cr == 1 { 8 } else { 0 };
+    let fp_size = if reg_f == 0 { 0 } else { (reg_f + 1) * 8 };
+    let save_size = (int_size + fp_size + if homing { 64 } else { 0 } + 0xf) & !0xf;
+    let local_size = frame_size.saturating_sub(save_size);
+
+    let mut ops = Vec::new();
+    // pacibsp
+    if cr == 2 {
+        ops.push(Op::Nop);
+    }
+
+    // The first store allocates the save area
+    let mut pre = save_size;
+    let mut offset = 0;
+    let mut int_regs: Vec<u8> = (0..reg_i as u8).map(|i| 19 + i).collect();
+    if cr == 1 {
+        int_regs.push(LR);
+    }
+    let fp_regs: Vec<u8> = (0..if reg_f == 0 { 0 } else { reg_f as u8 + 1 })
+        .map(|i| D8 + i)
+        .collect();
+    for regs in [int_regs, fp_regs] {
+        for pair in regs.chunks(2) {
+            ops.push(Op::Save {
+                first: pair[0],
+                second: pair.get(1).copied(),
+                offset,
+                pre,
+            });
+            pre = 0;
+            offset += 8 * pair.len() as u32;
+        }
+    }
+    // The parameters stored in the home area are volatile
+    if homing {
+        ops.push(if pre == 0 { Op::Nop } else { Op::Alloc(pre) });
+        ops.extend([Op::Nop; 3]);
+    }
+
+    let mut alloc = |size: u32| {
+        if size > 4080 {
+            ops.push(Op::Alloc(4080));
+            ops.push(Op::Alloc(size - 4080));
+        } else if size != 0 {
+            ops.push(Op::Alloc(size));
+        }
+    };
+    if cr >= 2 {
+        // A chained frame
+        if local_size <= 512 {
+            ops.push(Op::Save {
+                first: FP,
+                second: Some(LR),
+                offset: 0,
+                pre: local_size,
+            });
+        } else {
+            alloc(local_size);
+            ops.push(Op::Save {
+                first: FP,
+                second: Some(LR),
+                offset: 0,
+                pre: 0,
+            });
+        }
+        ops.push(Op::SetFp(0));
+    } else {
+        alloc(local_size);
+    }
+
+    ops
+}
+
+/// Get the unwind codes and the function length in a .xdata record
+fn get_xdata(data: &[u8]) -> Option<(&[u8], u32)> {
+    let word = read_u32(data, 0)?;
+    let function_length = (word & 0x3_ffff) * 4;
+    let single_epilog = (word >> 21) & 0x1 == 1;
+    let mut epilog_count = (word >> 22) & 0x1f;
+    let mut code_words = (word >> 27) & 0x1f;
+    let mut offset = 4;
+    if epilog_count == 0 && code_words == 0 {
+        let word = read_u32(data, 4)?;
+        epilog_count = word & 0xffff;
+        code_words = (word >> 16) & 0xff;
+        offset = 8;
+    }
+    if !single_epilog {
+        offset += epilog_count as usize * 4;
+    }
+
+    let codes = data.get(offset..offset + code_words as usize * 4)?;
+    Some((codes, function_length))
+}
+
+/// Decode the unwind codes of the prologue (and put them in execution order)
+fn get_ops(codes: &[u8]) -> Option<Vec<Op>> {
+    let mut ops = Vec::new();
+    let mut i = 0;
+    while i < codes.len() {
+        let byte = codes[i];
+        // The number of bytes of the code
+        let len = match byte {
+            0x00..=0xbf => 1,
+            0xc0..=0xdf => 2,
+            0xe0 => 4,
+            0xe2 | 0xe7 => 3,
+            _ => 1,
+        };
+        let code = codes.get(i..i + len)?;
+        let word = code.iter().fold(0u32, |w, b| (w << 8) | u32::from(*b));
+        let z = word & 0x3f;
+        let reg = |shift: u32, mask: u32| ((word >> shift) & mask) as u8;
+
+        let op = match byte {
+            // alloc_s
+            0x00..=0x1f => Op::Alloc(z * 16),
+            // save_r19r20_x
+            0x20..=0x3f => Op::Save {
+                first: 19,
+                second: Some(20),
+                offset: 0,
+                pre: (word & 0x1f) * 8,
+            },
+            // save_fplr
+            0x40..=0x7f => Op::Save {
+                first: FP,
+                second: Some(LR),
+                offset: z * 8,
+                pre: 0,
+            },
+            // save_fplr_x
+            0x80..=0xbf => Op::Save {
+                first: FP,
+                second: Some(LR),
+                offset: 0,
+                pre: (z + 1) * 8,
+            },
+            // alloc_m
+            0xc0..=0xc7 => Op::Alloc((word & 0x7ff) * 16),
+            // save_regp, save_regp_x, save_reg
+            0xc8..=0xd3 => {
+                let first = 19 + reg(6, 0xf);
+                let (second, pre) = match byte {
+                    0xc8..=0xcb => (Some(first + 1), 0),
+                    0xcc..=0xcf => (Some(first + 1), (z + 1) * 8),
+                    _ => (None, 0),
+                };
+                Op::Save {
+                    first,
+                    second,
+                    offset: if pre == 0 { z * 8 } else { 0 },
+                    pre,
+                }
+            }
+            // save_reg_x
+            0xd4 | 0xd5 => Op::Save {
+                first: 19 + reg(5, 0xf),
+                second: None,
+                offset: 0,
+                pre: ((word & 0x1f) + 1) * 8,
+            },
+            // save_lrpair
+            0xd6 | 0xd7 => Op::Save {
+                first: 19 + 2 * reg(6, 0x7),
+                second: Some(LR),
+                offset: z * 8,
+                pre: 0,
+            },
+            // save_fregp, save_fregp_x, save_freg
+            0xd8..=0xdd => {
+                let first = D8 + reg(6, 0x7);
+                let (second, pre) = match byte {
+                    0xd8 | 0xd9 => (Some(first + 1), 0),
+                    0xda | 0xdb => (Some(first + 1), (z + 1) * 8),
+                    _ => (None, 0),
+                };
+                Op::Save {
+                    first,
+                    second,
+                    offset: if pre == 0 { z * 8 } else { 0 },
+                    pre,
+                }
+            }
+            // save_freg_x
+            0xde => Op::Save {
+                first: D8 + reg(5, 0x7),
+                second: None,
+                offset: 0,
+                pre: ((word & 0x1f) + 1) * 8,
+            },
+            // alloc_l
+            0xe0 => Op::Alloc((word & 0xff_ffff) * 16),
+            // set_fp
+            0xe1 => Op::SetFp(0),
+            // add_fp
+            0xe2 => Op::SetFp((word & 0xff) * 8),
+            // nop, pac_sign_lr
+            0xe3 | 0xfc => Op::Nop,
+            // end, end_c
+            0xe4 | 0xe5 => break,
+            // save_next
+            0xe6 => Op::SaveNext,
+            // alloc_z (SVE), save_any_reg and the reserved codes
+            _ => return None,
+        };
+        ops.push(op);
+        i += len;
+    }
+    ops.reverse();
+
+    Some(ops)
+}
+
+/// Write the STACK CFI records of a function
+fn write_records(records: &mut String, rva: u32, len: u32, ops: &[Op], has_prologue: bool) {
+    let mut state = State::default();
+    let mut rows = Vec::new();
+    for (i, op) in ops.iter().enumerate() {
+        match state.apply(*op) {
+            Some(rules) if !rules.is_empty() => rows.push((rva + 4 * (i as u32 + 1), rules)),
+            Some(_) => {}
+            None => return,
+        }
+    }
+
+    if has_prologue {
+        writeln!(
+            records,
+            "STACK CFI INIT {:x} {:x} .cfa: sp 0 + .ra: x30",
+            rva, len
+        )
+        .unwrap();
+        for (address, rules) in rows {
+            writeln!(records, "STACK CFI {:x} {}", address, rules.join(" ")).unwrap();
+        }
+    } else {
+        // A fragment of a function which starts once the prologue has been executed
+        let mut rules = vec![state.cfa_rule()];
+        if !state.saved.iter().any(|(reg, _)| *reg == LR) {
+            rules.push(".ra: x30".to_string());
+        }
+        for (reg, offset) in state.saved.iter() {
+            if *reg == LR {
+                rules.push(format!(".ra: .cfa -{} + ^", offset));
+            }
+            if *reg < D8 {
+                rules.push(format!("x{}: .cfa -{} + ^", reg, offset));
+            }
+        }
+        writeln!(
+            records,
+            "STACK CFI INIT {:x} {:x} {}",
+            rva,
+            len,
+            rules.join(" ")
+        )
+        .unwrap();
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Get the STACK CFI records from the entries of a .pdata
+/// (`get_data` gives the data at an rva)
+fn get_records<'a>(pdata: &[u8], get_data: impl Fn(u32) -> Option<&'a [u8]>) -> String {
+    let mut records = String::new();
+    for entry in pdata.chunks_exact(8) {
+        let rva = read_u32(entry, 0).unwrap();
+        let data = read_u32(entry, 4).unwrap();
+        match data & 0x3 {
+            0 => {
+                let ops = get_data(data)
+                    .and_then(get_xdata)
+                    .and_then(|(codes, len)| Some((get_ops(codes)?, len)));
+                if let Some((ops, len)) = ops {
+                    write_records(&mut records, rva, len, &ops, true);
+                }
+            }
+            flag => {
+                let len = ((data >> 2) & 0x7ff) * 4;
+                write_records(&mut records, rva, len, &get_packed_ops(data), flag == 1);
+            }
+        }
+    }
+
+    records
+}
+
+/// Get the data from an rva to the end of its section
+fn get_data<'a>(pe: &PeObject<'a>, rva: u32) -> Option<&'a [u8]> {
+    let data = pe.data();
+    pe.sections().iter().find_map(|section| {
+        let start = section.virtual_address;
+        if rva < start || rva - start >= section.size_of_raw_data {
+            return None;
+        }
+        let offset = section.pointer_to_raw_data as usize;
+        data.get(offset + (rva - start) as usize..offset + section.size_of_raw_data as usize)
+    })
+}
+
+/// Get the STACK CFI records from the ARM64 exception data
+pub(super) fn get_stack_info(pe: &PeObject) -> String {
+    let pdata = pe.sections().iter().find_map(|section| {
+        if section.name().ok()? != ".pdata" {
+            return None;
+        }
+        let data = get_data(pe, section.virtual_address)?;
+        data.get(..section.virtual_size.min(data.len() as u32) as usize)
+    });
+
+    match pdata {
+        Some(pdata) => get_records(pdata, |rva| get_data(pe, rva)),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_ops() {
+        // stp x29, lr, [sp, #-32]!; stp x19, x20, [sp, #16]; mov x29, sp; end
+        let codes = [0xe1, 0xc8, 0x02, 0x83, 0xe4, 0xe3, 0xe3, 0xe3];
+        let ops = get_ops(&codes).unwrap();
+        assert_eq!(
+            ops,
+            vec![
+                Op::Save {
+                    first: FP,
+                    second: Some(LR),
+                    offset: 0,
+                    pre: 32
+                },
+                Op::Save {
+                    first: 19,
+                    second: Some(20),
+                    offset: 16,
+                    pre: 0
+                },
+                Op::SetFp(0),
+            ]
+        );
+        // save_r19r20_x then save_next
+        let ops = get_ops(&[0xe6, 0x24, 0xe4]).unwrap();
+        assert_eq!(ops[1], Op::SaveNext);
+        // alloc_l, alloc_m and a reserved code
+        assert_eq!(
+            get_ops(&[0xe0, 0x00, 0x01, 0x00, 0xc1, 0x00, 0xe4]).unwrap(),
+            vec![Op::Alloc(0x1000), Op::Alloc(0x1000)]
+        );
+        assert!(get_ops(&[0xe8, 0xe4]).is_none());
+    }
+
+    #[test]
+    fn test_packed() {
+        // RegI = 2, CR = 3 (chained), FrameSize = 48
+        let data = 1 | (8 << 2) | (2 << 16) | (3 << 21) | (3 << 23);
+        assert_eq!(
+            get_packed_ops(data),
+            vec![
+                Op::Save {
+                    first: 19,
+                    second: Some(20),
+                    offset: 0,
+                    pre: 16
+                },
+                Op::Save {
+                    first: FP,
+                    second: Some(LR),
+                    offset: 0,
+                    pre: 32
+                },
+                Op::SetFp(0),
+            ]
+        );
+
+        // RegI = 1, CR = 1 (lr saved with x19), RegF = 1 (d8, d9), FrameSize = 8176
+        let data = 1 | (8 << 2) | (1 << 13) | (1 << 16) | (1 << 21) | (511 << 23);
+        let ops = get_packed_ops(data);
+        assert_eq!(ops.len(), 4);
+        assert_eq!(ops[2], Op::Alloc(4080));
+        assert_eq!(ops[3], Op::Alloc(8176 - 32 - 4080));
+    }
+
+    #[test]
+    fn test_records() {
+        let xdata = [
+            // FunctionLength = 0x10, one epilog in the header, one code word
+            0x04, 0x00, 0x20, 0x08, 0xe1, 0x81, 0xe4, 0xe4,
+        ];
+        let pdata = [
+            // 0x1000 with the .xdata at 0x4000
+            0x00, 0x10, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00,
+            // 0x2000 packed: 0x20 bytes, RegI = 2, CR = 0, FrameSize = 0x30
+            0x00, 0x20, 0x00, 0x00, 0x21, 0x00, 0x82, 0x01,
+            // 0x3000 fragment of the same function
+            0x00, 0x30, 0x00, 0x00, 0x22, 0x00, 0x82, 0x01,
+        ];
+        let records = get_records(&pdata, |rva| {
+            if rva == 0x4000 {
+                Some(&xdata[..])
+            } else {
+                None
+            }
+        });
+        assert_eq!(
+            records,
+            "STACK CFI INIT 1000 10 .cfa: sp 0 + .ra: x30\n\
+             STACK CFI 1004 .cfa: sp 16 + x29: .cfa -16 + ^ .ra: .cfa -8 + ^ x30: .cfa -8 + ^\n\
+             STACK CFI 1008 .cfa: x29 16 +\n\
+             STACK CFI INIT 2000 20 .cfa: sp 0 + .ra: x30\n\
+             STACK CFI 2004 .cfa: sp 16 + x19: .cfa -16 + ^ x20: .cfa -8 + ^\n\
+             STACK CFI 2008 .cfa: sp 48 +\n\
+             STACK CFI INIT 3000 20 .cfa: sp 48 + .ra: x30 x19: .cfa -16 + ^ x20: .cfa -8 + ^\n"
+        );
+    }
+}
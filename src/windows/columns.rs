@@ -0,0 +1,205 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use pdb::{Result, Source, StreamIndex, PDB};
+use std::convert::TryInto;
+
+/*
+ The pdb crate (0.7) reads the column entries of the line blocks from the line entries themselves,
+ so the columns it gives are the offsets and the line numbers of the lines. The columns are read here
+ from the C13 lines subsections of the module streams:
+  - the DBI stream gives, for each module, its stream and the sizes of its symbols, C11 and C13 lines;
+  - a lines subsection (0xf2) starts with the section offset and the section of the lines, and the
+    flag 0x1 is set when each block of lines is followed by the columns (start and end, u16 each).
+ The modules are in the same order as the ones given by DebugInformation::modules.
+*/
+
+/// The start columns of the lines by (section, offset)
+pub(super) type Columns = HashMap<(u16, u32), u32>;
+
+const DEBUG_S_LINES: u32 = 0xf2;
+const CV_LINES_HAVE_COLUMNS: u16 = 0x1;
+const DBI_HEADER_SIZE: usize = 64;
+const MODULE_HEADER_SIZE: usize = 64;
+
+fn read_u16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(buf.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// The stream and the range of the C13 lines of a module
+#[derive(Debug, PartialEq)]
+pub(super) struct ModuleLines {
+    stream: u16,
+    start: usize,
+    len: usize,
+}
+
+/// Get the C13 lines of all the modules listed in the DBI stream
+fn parse_modules(dbi: &[u8]) -> Option<Vec<ModuleLines>> {
+    let modules_size = read_u32(dbi, 24)? as usize;
+    let modules = dbi.get(DBI_HEADER_SIZE..DBI_HEADER_SIZE + modules_size)?;
+    let mut pos = 0;
+    let mut lines = Vec::new();
+
+    while pos + MODULE_HEADER_SIZE <= modules.len() {
+        let symbols_size = read_u32(modules, pos + 36)? as usize;
+        let c11_size = read_u32(modules, pos + 40)? as usize;
+        lines.push(ModuleLines {
+            stream: read_u16(modules, pos + 34)?,
+            start: symbols_size + c11_size,
+            len: read_u32(modules, pos + 44)? as usize,
+        });
+
+        // The module and the object names follow the header
+        pos += MODULE_HEADER_SIZE;
+        for _ in 0..2 {
+            pos += modules[pos..].iter().position(|b| *b == 0)? + 1;
+        }
+        pos = (pos + 3) & !3;
+    }
+
+    Some(lines)
+}
+
+/// Collect the columns of a lines subsection
+fn parse_lines(data: &[u8], columns: &mut Columns) -> Option<()> {
+    let offset = read_u32(data, 0)?;
+    let section = read_u16(data, 4)?;
+    if read_u16(data, 6)? & CV_LINES_HAVE_COLUMNS == 0 {
+        return Some(());
+    }
+
+    let mut pos = 12;
+    while pos < data.len() {
+        let num_lines = read_u32(data, pos + 4)? as usize;
+        let block_size = read_u32(data, pos + 8)? as usize;
+        let lines = pos + 12;
+        let cols = lines + 8 * num_lines;
+        for i in 0..num_lines {
+            let line_offset = read_u32(data, lines + 8 * i)?;
+            let start = read_u16(data, cols + 4 * i)?;
+            if start != 0 {
+                columns.insert((section, offset + line_offset), u32::from(start));
+            }
+        }
+        pos += block_size.max(12);
+    }
+
+    Some(())
+}
+
+/// Collect the columns of the C13 lines of a module stream
+pub(super) fn parse_subsections(data: &[u8], columns: &mut Columns) -> Option<()> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let kind = read_u32(data, pos)?;
+        let len = read_u32(data, pos + 4)? as usize;
+        let subsection = data.get(pos + 8..pos + 8 + len)?;
+        if kind == DEBUG_S_LINES {
+            parse_lines(subsection, columns)?;
+        }
+        pos = (pos + 8 + len + 3) & !3;
+    }
+    Some(())
+}
+
+/// Get the C13 lines of the modules (in the order of DebugInformation::modules)
+pub(super) fn get_module_lines<'a, S: 'a + Source<'a>>(
+    pdb: &mut PDB<'a, S>,
+) -> Result<Vec<ModuleLines>> {
+    let dbi = match pdb.raw_stream(StreamIndex(3))? {
+        Some(dbi) => dbi,
+        None => return Ok(Vec::new()),
+    };
+    Ok(parse_modules(dbi.as_slice()).unwrap_or_default())
+}
+
+/// Get the columns of the lines of a module
+pub(super) fn get_columns<'a, S: 'a + Source<'a>>(
+    pdb: &mut PDB<'a, S>,
+    module: Option<&ModuleLines>,
+) -> Result<Columns> {
+    let mut columns = Columns::new();
+    let module = match module {
+        Some(module) if module.len != 0 => module,
+        _ => return Ok(columns),
+    };
+    if let Some(stream) = pdb.raw_stream(StreamIndex(module.stream))? {
+        if let Some(data) = stream
+            .as_slice()
+            .get(module.start..module.start + module.len)
+        {
+            // A broken subsection only loses its columns
+            let _ = parse_subsections(data, &mut columns);
+        }
+    }
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use pdb::FallibleIterator;
+
+    #[test]
+    fn test_parse_subsections() {
+        let mut data = Vec::new();
+        // A subsection which isn't about the lines
+        data.extend_from_slice(&0xf4u32.to_le_bytes());
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut lines = Vec::new();
+        lines.extend_from_slice(&0x100u32.to_le_bytes());
+        lines.extend_from_slice(&1u16.to_le_bytes());
+        lines.extend_from_slice(&CV_LINES_HAVE_COLUMNS.to_le_bytes());
+        lines.extend_from_slice(&0x20u32.to_le_bytes());
+        // A block with two lines
+        lines.extend_from_slice(&0u32.to_le_bytes());
+        lines.extend_from_slice(&2u32.to_le_bytes());
+        lines.extend_from_slice(&(12u32 + 2 * 8 + 2 * 4).to_le_bytes());
+        for (offset, line) in &[(0u32, 12u32), (0x10, 13)] {
+            lines.extend_from_slice(&offset.to_le_bytes());
+            lines.extend_from_slice(&(line | 0x8000_0000).to_le_bytes());
+        }
+        for (start, end) in &[(5u16, 9u16), (0, 0)] {
+            lines.extend_from_slice(&start.to_le_bytes());
+            lines.extend_from_slice(&end.to_le_bytes());
+        }
+        data.extend_from_slice(&DEBUG_S_LINES.to_le_bytes());
+        data.extend_from_slice(&(lines.len() as u32).to_le_bytes());
+        data.extend_from_slice(&lines);
+
+        let mut columns = Columns::new();
+        assert_eq!(parse_subsections(&data, &mut columns), Some(()));
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns.get(&(1, 0x100)), Some(&5));
+    }
+
+    #[test]
+    fn test_get_module_lines() {
+        let path = PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = PDB::open(Cursor::new(&buf)).unwrap();
+        let modules = get_module_lines(&mut pdb).unwrap();
+        let count = pdb.debug_information().unwrap().modules().unwrap().count();
+        assert_eq!(modules.len(), count.unwrap());
+        assert!(modules.iter().any(|m| m.len != 0));
+
+        // No column info in this pdb
+        for module in &modules {
+            assert!(get_columns(&mut pdb, Some(module)).unwrap().is_empty());
+        }
+    }
+}
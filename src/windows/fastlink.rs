@@ -0,0 +1,122 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use pdb::{Source, StreamIndex, PDB};
+
+// The pdb information stream
+const PDB_STREAM: StreamIndex = StreamIndex(1);
+// The feature signature added by /DEBUG:FASTLINK (PdbRaw_FeatureSig::MinimalDebugInfo)
+const MINIMAL_DEBUG_INFO: u32 = 0x494E_494D;
+
+/*
+ The pdb information stream is:
+   version, signature, age (u32), guid (16 bytes),
+   names size (u32), names,
+   the hash table of the named streams:
+     count, capacity (u32),
+     present bit vector, deleted bit vector: words count (u32) and the words,
+     count * (name offset, stream index) (u32, u32),
+   and the feature signatures (u32) until the end.
+*/
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn skip(&mut self, n: usize) -> Option<()> {
+        let pos = self.pos.checked_add(n)?;
+        if pos > self.data.len() {
+            return None;
+        }
+        self.pos = pos;
+        Some(())
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.data.get(self.pos..self.pos + 4)?;
+        self.pos += 4;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn skip_bit_vector(&mut self) -> Option<()> {
+        let words = self.u32()? as usize;
+        self.skip(words.checked_mul(4)?)
+    }
+}
+
+fn get_features(data: &[u8]) -> Option<Vec<u32>> {
+    let mut reader = Reader { data, pos: 0 };
+    reader.skip(28)?;
+    let names_size = reader.u32()? as usize;
+    reader.skip(names_size)?;
+
+    let count = reader.u32()? as usize;
+    reader.u32()?;
+    reader.skip_bit_vector()?;
+    reader.skip_bit_vector()?;
+    reader.skip(count.checked_mul(8)?)?;
+
+    let mut features = Vec::new();
+    while let Some(feature) = reader.u32() {
+        features.push(feature);
+    }
+
+    Some(features)
+}
+
+/// Check if the pdb has been generated with /DEBUG:FASTLINK: in this case the
+/// types and the symbols of the functions are in the object files and not in the pdb.
+pub(super) fn is_fastlink<'a, S: 'a + Source<'a>>(pdb: &mut PDB<'a, S>) -> bool {
+    let stream = match pdb.raw_stream(PDB_STREAM) {
+        Ok(Some(stream)) => stream,
+        _ => return false,
+    };
+
+    matches!(get_features(stream.as_slice()), Some(f) if f.contains(&MINIMAL_DEBUG_INFO))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_fastlink() {
+        let mut data = vec![0; 28];
+        let words: &[u32] = &[
+            // names: "/names\0\0"
+            8,
+            0x6d61_6e2f,
+            0x0000_7365,
+            // 1 entry, capacity, present bits, no deleted bits
+            1,
+            2,
+            1,
+            1,
+            0,
+            // the entry
+            1,
+            10,
+            // VC140 and MINI
+            20_140_508,
+            MINIMAL_DEBUG_INFO,
+        ];
+        for word in words {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+
+        let features = get_features(&data).unwrap();
+        assert_eq!(features, vec![20_140_508, MINIMAL_DEBUG_INFO]);
+        assert!(get_features(&data[..40]).is_none());
+
+        let path = PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = PDB::open(Cursor::new(&buf)).unwrap();
+        assert!(!is_fastlink(&mut pdb));
+    }
+}
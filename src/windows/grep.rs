@@ -0,0 +1,139 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use pdb::{AddressMap, FallibleIterator, PdbInternalSectionOffset, SymbolData, PDB};
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+use std::io::Cursor;
+
+use crate::common;
+
+/// A symbol whose name matches
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Match {
+    pub rva: u32,
+    pub kind: &'static str,
+    pub name: String,
+}
+
+impl Display for Match {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{:x} {} {}", self.rva, self.kind, self.name)
+    }
+}
+
+fn push_match(
+    matches: &mut Vec<Match>,
+    re: &Regex,
+    address_map: &AddressMap,
+    offset: PdbInternalSectionOffset,
+    kind: &'static str,
+    name: &str,
+) {
+    if !re.is_match(name) {
+        return;
+    }
+    if let Some(rva) = offset.to_rva(address_map) {
+        matches.push(Match {
+            rva: rva.0,
+            kind,
+            name: name.to_string(),
+        });
+    }
+}
+
+/// Search the publics, the global data and the procedures whose (raw) name matches the regex:
+/// the types and the line information aren't read.
+pub fn grep_pdb(buf: &[u8], re: &Regex) -> common::Result<Vec<Match>> {
+    let mut pdb = PDB::open(Cursor::new(buf))?;
+    let address_map = pdb.address_map()?;
+    let mut matches = Vec::new();
+
+    let globals = pdb.global_symbols()?;
+    let mut symbols = globals.iter();
+    while let Some(symbol) = symbols.next()? {
+        match symbol.parse() {
+            Ok(SymbolData::Public(public)) => push_match(
+                &mut matches,
+                re,
+                &address_map,
+                public.offset,
+                "PUBLIC",
+                &public.name.to_string(),
+            ),
+            Ok(SymbolData::Data(data)) => push_match(
+                &mut matches,
+                re,
+                &address_map,
+                data.offset,
+                "DATA",
+                &data.name.to_string(),
+            ),
+            _ => {}
+        }
+    }
+
+    let dbi = pdb.debug_information()?;
+    let mut modules = dbi.modules()?;
+    while let Some(module) = modules.next()? {
+        let module_info = match pdb.module_info(&module)? {
+            Some(info) => info,
+            _ => continue,
+        };
+        let mut symbols = module_info.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            if let Ok(SymbolData::Procedure(procedure)) = symbol.parse() {
+                push_match(
+                    &mut matches,
+                    re,
+                    &address_map,
+                    procedure.offset,
+                    "FUNC",
+                    &procedure.name.to_string(),
+                );
+            }
+        }
+    }
+
+    matches.sort();
+    matches.dedup();
+
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_grep() {
+        let path = PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+
+        let re = Regex::new("^DllMain$").unwrap();
+        let matches = grep_pdb(&buf, &re).unwrap();
+        assert_eq!(
+            matches,
+            vec![
+                Match {
+                    rva: 0xa490,
+                    kind: "FUNC",
+                    name: "DllMain".to_string(),
+                },
+                Match {
+                    rva: 0xa490,
+                    kind: "PUBLIC",
+                    name: "DllMain".to_string(),
+                },
+            ]
+        );
+        assert_eq!(matches[0].to_string(), "a490 FUNC DllMain");
+
+        let re = Regex::new("no_such_function").unwrap();
+        assert!(grep_pdb(&buf, &re).unwrap().is_empty());
+    }
+}
@@ -39,6 +39,7 @@ impl LineFinalizer<AddressMap<'_>> for Lines {
                         len: rg.end - rg.start,
                         num: line.num,
                         file_id: line.file_id,
+                        col: line.col,
                     });
 
                     is_sorted = is_sorted && last_rva <= rg.start.0;
@@ -0,0 +1,238 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use pdb::{AddressMap, FallibleIterator, Result, Source, SymbolData, PDB};
+use serde::Serialize;
+
+use super::types::TypeDumper;
+
+/*
+ The symbols of a function are between its S_GPROC32 (or S_LPROC32) and the matching S_END: the
+ S_BLOCK32 are the nested scopes (they end with a S_END too) and the S_LOCAL, S_REGREL32, S_REGISTER
+ and S_LDATA32 are the variables of the innermost scope.
+ The variables of the inlined functions (between a S_INLINESITE and its S_INLINESITE_END) aren't the
+ ones of the enclosing scope so they're skipped.
+ The ranges are rvas and the types are dumped with the TypeDumper (as for the function signatures).
+*/
+
+/// A local variable or a parameter
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct LocalVariable {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    /// Only known for the S_LOCAL ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_param: Option<bool>,
+    /// The register (CodeView numbering) holding the variable or its base address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub register: Option<u16>,
+    /// The offset from the register
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i32>,
+    /// The rva of a static local
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rva: Option<u32>,
+}
+
+/// A scope (a function or a block) with its variables and its nested blocks
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(crate) struct FunctionScope {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub rva: u32,
+    pub size: u32,
+    pub locals: Vec<LocalVariable>,
+    pub blocks: Vec<FunctionScope>,
+}
+
+enum Scope {
+    Kept(FunctionScope),
+    Inlined,
+    Other,
+}
+
+fn get_scope(data: Option<SymbolData>, address_map: &AddressMap, inlined: bool) -> Scope {
+    match data {
+        Some(SymbolData::InlineSite(_)) => Scope::Inlined,
+        _ if inlined => Scope::Other,
+        Some(SymbolData::Procedure(procedure)) => match procedure.offset.to_rva(address_map) {
+            Some(rva) => Scope::Kept(FunctionScope {
+                name: procedure.name.to_string().into_owned(),
+                rva: rva.0,
+                size: procedure.len,
+                ..Default::default()
+            }),
+            None => Scope::Other,
+        },
+        Some(SymbolData::Block(block)) => match block.offset.to_rva(address_map) {
+            Some(rva) => Scope::Kept(FunctionScope {
+                name: block.name.to_string().into_owned(),
+                rva: rva.0,
+                size: block.len,
+                ..Default::default()
+            }),
+            None => Scope::Other,
+        },
+        _ => Scope::Other,
+    }
+}
+
+fn get_variable(
+    data: SymbolData,
+    address_map: &AddressMap,
+    type_dumper: &TypeDumper,
+) -> Option<LocalVariable> {
+    let (name, type_index, is_param, register, offset, rva) = match data {
+        SymbolData::Local(local) => (
+            local.name,
+            local.type_index,
+            Some(local.flags.isparam),
+            None,
+            None,
+            None,
+        ),
+        SymbolData::RegisterRelative(local) => (
+            local.name,
+            local.type_index,
+            None,
+            Some(local.register.0),
+            Some(local.offset),
+            None,
+        ),
+        SymbolData::RegisterVariable(local) => (
+            local.name,
+            local.type_index,
+            None,
+            Some(local.register.0),
+            None,
+            None,
+        ),
+        SymbolData::Data(data) if !data.global => (
+            data.name,
+            data.type_index,
+            None,
+            None,
+            None,
+            Some(data.offset.to_rva(address_map)?.0),
+        ),
+        _ => return None,
+    };
+
+    Some(LocalVariable {
+        name: name.to_string().into_owned(),
+        typ: type_dumper.dump_type(type_index),
+        is_param,
+        register,
+        offset,
+        rva,
+    })
+}
+
+/// Collect the scopes and the local variables of all the functions
+pub(super) fn collect_locals<'a, S: 'a + Source<'a>>(
+    pdb: &mut PDB<'a, S>,
+    address_map: &AddressMap,
+    type_dumper: &TypeDumper,
+) -> Result<Vec<FunctionScope>> {
+    let dbi = pdb.debug_information()?;
+    let mut modules = dbi.modules()?;
+    let mut functions = Vec::new();
+
+    while let Some(module) = modules.next()? {
+        type_dumper.check_cancelled()?;
+        let module_info = match pdb.module_info(&module)? {
+            Some(module_info) => module_info,
+            None => continue,
+        };
+
+        let mut stack = Vec::new();
+        let mut inlined = 0;
+        let mut symbols = module_info.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            let data = symbol.parse().ok();
+            if symbol.starts_scope() {
+                let scope = get_scope(data, address_map, inlined > 0);
+                if let Scope::Inlined = scope {
+                    inlined += 1;
+                }
+                stack.push(scope);
+            } else if symbol.ends_scope() {
+                match stack.pop() {
+                    Some(Scope::Kept(scope)) => {
+                        let parent = stack.iter_mut().rev().find_map(|s| match s {
+                            Scope::Kept(parent) => Some(parent),
+                            _ => None,
+                        });
+                        match parent {
+                            Some(parent) => parent.blocks.push(scope),
+                            None => functions.push(scope),
+                        }
+                    }
+                    Some(Scope::Inlined) => inlined -= 1,
+                    _ => {}
+                }
+            } else if inlined == 0 {
+                let variable = data.and_then(|data| get_variable(data, address_map, type_dumper));
+                if let (Some(variable), Some(Scope::Kept(scope))) = (variable, stack.last_mut()) {
+                    scope.locals.push(variable);
+                }
+            }
+        }
+    }
+
+    functions.sort_by_key(|f| f.rva);
+    Ok(functions)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use crate::cancel::CancelToken;
+    use crate::windows::types::DumperFlags;
+
+    #[test]
+    fn test_collect_locals() {
+        let path = PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = PDB::open(Cursor::new(&buf)).unwrap();
+        let address_map = pdb.address_map().unwrap();
+        let type_info = pdb.type_information().unwrap();
+        let type_dumper = TypeDumper::new(
+            &type_info,
+            &[],
+            8,
+            DumperFlags::default(),
+            CancelToken::default(),
+        )
+        .unwrap();
+        let functions = collect_locals(&mut pdb, &address_map, &type_dumper).unwrap();
+
+        let test2 = functions.iter().find(|f| f.name == "test2").unwrap();
+        assert_eq!(test2.size, 10);
+        let params: Vec<_> = test2
+            .locals
+            .iter()
+            .map(|l| (l.name.as_str(), l.typ.as_str(), l.is_param))
+            .collect();
+        assert_eq!(
+            params,
+            vec![("x", "int", Some(true)), ("y", "unsigned int", Some(true))]
+        );
+
+        let dealloc = functions
+            .iter()
+            .find(|f| f.name.ends_with("::_Tidy_deallocate"))
+            .unwrap();
+        let block = &dealloc.blocks[0];
+        assert!(block.rva > dealloc.rva && block.rva + block.size <= dealloc.rva + dealloc.size);
+        assert_eq!(block.locals[0].name, "_Ptr");
+        assert_eq!(block.locals[0].typ, "char const*");
+    }
+}
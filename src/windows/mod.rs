@@ -3,9 +3,21 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+mod arm64_unwind;
+mod columns;
+mod fastlink;
+pub mod grep;
 mod lines;
+mod locals;
+mod objects;
 pub mod pdb;
+pub(crate) mod pe_dwarf;
+mod prologue;
 mod source;
+mod sourcelink;
+mod srcsrv;
 mod symbol;
+mod type_server;
 mod types;
 pub mod utils;
+pub mod verify;
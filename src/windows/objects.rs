@@ -0,0 +1,160 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use pdb::{FallibleIterator, IdData, IdFinder, IdIndex, Result, Source, SymbolData, PDB};
+use serde::Serialize;
+
+use crate::cancel;
+
+// The arguments of a LF_BUILDINFO record
+const BUILD_INFO_CWD: usize = 0;
+const BUILD_INFO_TOOL: usize = 1;
+const BUILD_INFO_SOURCE: usize = 2;
+const BUILD_INFO_COMMAND_LINE: usize = 4;
+
+/// The build information of a module (compiland)
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(crate) struct ObjectInfo {
+    /// The module name (the object file or the import library)
+    pub module: String,
+    /// The object file name (the static library for the modules coming from one)
+    pub object: String,
+    /// The path of the object when it has been compiled (S_OBJNAME)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obj_name: Option<String>,
+    /// The working directory of the compiler (S_BUILDINFO)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compile_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_line: Option<String>,
+}
+
+fn get_string(finder: &IdFinder, index: IdIndex) -> Option<String> {
+    if index.0 == 0 {
+        return None;
+    }
+
+    match finder.find(index).ok()?.parse().ok()? {
+        IdData::String(s) => {
+            // Long strings are split: the beginning is in a list of substrings
+            let mut buf = String::new();
+            if let Some(substrings) = s.substrings {
+                if let IdData::StringList(list) = finder.find(substrings).ok()?.parse().ok()? {
+                    for sub in list.substrings {
+                        buf.push_str(&get_string(finder, IdIndex(sub.0))?);
+                    }
+                }
+            }
+            buf.push_str(&s.name.to_string());
+            Some(buf)
+        }
+        _ => None,
+    }
+}
+
+fn fill_build_info(info: &mut ObjectInfo, finder: &IdFinder, index: IdIndex) {
+    let args = match finder.find(index).and_then(|id| id.parse()) {
+        Ok(IdData::BuildInfo(build_info)) => build_info.arguments,
+        _ => return,
+    };
+    let get = |i: usize| {
+        args.get(i)
+            .and_then(|index| get_string(finder, *index))
+            .filter(|s| !s.is_empty())
+    };
+
+    info.compile_dir = get(BUILD_INFO_CWD);
+    info.tool = get(BUILD_INFO_TOOL);
+    info.source = get(BUILD_INFO_SOURCE);
+    info.command_line = get(BUILD_INFO_COMMAND_LINE);
+}
+
+/// Collect the object paths and the build information of all the modules
+pub(super) fn collect_objects<'a, S: 'a + Source<'a>>(
+    pdb: &mut PDB<'a, S>,
+) -> Result<Vec<ObjectInfo>> {
+    let id_info = pdb.id_information()?;
+    let mut finder = id_info.finder();
+    let mut ids = id_info.iter();
+    while ids.next()?.is_some() {
+        finder.update(&ids);
+    }
+
+    let dbi = pdb.debug_information()?;
+    let mut modules = dbi.modules()?;
+    let mut objects = Vec::new();
+
+    while let Some(module) = modules.next()? {
+        cancel::check_pdb()?;
+        let mut info = ObjectInfo {
+            module: module.module_name().into_owned(),
+            object: module.object_file_name().into_owned(),
+            ..Default::default()
+        };
+
+        if let Some(module_info) = pdb.module_info(&module)? {
+            let mut symbols = module_info.symbols()?;
+            while let Some(symbol) = symbols.next()? {
+                match symbol.parse() {
+                    Ok(SymbolData::ObjName(obj)) => {
+                        info.obj_name = Some(obj.name.to_string().into_owned());
+                    }
+                    Ok(SymbolData::BuildInfo(build_info)) => {
+                        fill_build_info(&mut info, &finder, build_info.id);
+                    }
+                    _ => {}
+                }
+
+                if info.obj_name.is_some() && info.compile_dir.is_some() {
+                    // Both are at the beginning of the stream
+                    break;
+                }
+            }
+        }
+
+        objects.push(info);
+    }
+
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_collect_objects() {
+        let path = PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = PDB::open(Cursor::new(&buf)).unwrap();
+        let objects = collect_objects(&mut pdb).unwrap();
+
+        let basic = objects
+            .iter()
+            .find(|o| o.module.ends_with("basic64.obj"))
+            .unwrap();
+        assert_eq!(
+            basic.compile_dir.as_ref().unwrap(),
+            r"C:\Users\Calixte\dump_syms\test_data"
+        );
+        assert_eq!(basic.source.as_ref().unwrap(), "basic.cpp");
+
+        let cookie = objects
+            .iter()
+            .find(|o| o.module.ends_with("gs_cookie.obj"))
+            .unwrap();
+        assert!(cookie.object.ends_with("libcmt.lib"));
+        assert_eq!(cookie.obj_name.as_ref().unwrap(), &cookie.module);
+        assert!(cookie.tool.as_ref().unwrap().ends_with("CL.EXE"));
+        assert!(cookie.command_line.as_ref().unwrap().starts_with("-c "));
+    }
+}
@@ -3,32 +3,58 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use failure::Fail;
-use hashbrown::HashSet;
+use hashbrown::{HashMap, HashSet};
+use log::warn;
 use pdb::{
-    AddressMap, BlockSymbol, DebugInformation, FallibleIterator, MachineType, ModuleInfo,
-    PDBInformation, ProcedureSymbol, PublicSymbol, Register, RegisterRelativeSymbol, Result,
-    SeparatedCodeSymbol, Source, SymbolData, SymbolTable, PDB,
+    AddressMap, BlockSymbol, DebugInformation, FallibleIterator, FrameTable, LocalSymbol,
+    MachineType, Module, ModuleInfo, PDBInformation, PdbInternalSectionOffset, ProcedureSymbol,
+    PublicSymbol, RawString, Register, RegisterRelativeSymbol, Result, SeparatedCodeSymbol, Source,
+    SymbolData, SymbolTable, ThunkSymbol, PDB,
 };
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
 use std::io::{Cursor, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use symbolic::common::Arch;
+use std::time::Instant;
+use symbolic::common::{Arch, CpuFamily};
 use symbolic::debuginfo::{pdb::PdbObject, pe::PeObject, Object};
-use symbolic::minidump::cfi::AsciiCfiWriter;
+use tracing::{debug_span, info_span};
 use uuid::Uuid;
 
-use super::source::{SourceFiles, SourceLineCollector};
-use super::symbol::{BlockInfo, PDBSymbols, RvaSymbols, SelectedSymbol};
+use super::arm64_unwind;
+use super::columns::{self, Columns, ModuleLines};
+use super::fastlink;
+use super::locals::{self, FunctionScope};
+use super::objects::{self, ObjectInfo};
+use super::prologue;
+use super::source::{RefToIds, SourceFiles, SourceLineCollector};
+use super::sourcelink::SourceLink;
+use super::srcsrv::SrcSrv;
+use super::symbol::{self, AddressReport, BlockInfo, PDBSymbols, RvaSymbols, SelectedSymbol};
+use super::type_server;
 use super::types::{DumperFlags, TypeDumper};
 use super::utils::get_pe_debug_id;
-use crate::common::{self, Dumpable, Mergeable};
+use crate::cancel::CancelToken;
+use crate::common::{
+    self, DumpOptions, Dumpable, Mergeable, Provenance, PublicPrecedence, RecordProvenance,
+    SourceLinkMode, ThunkNames, TypeNames,
+};
+use crate::compat::Compat;
+use crate::diagnostics::{self, Category};
+use crate::error::{Error, PdbContext};
 use crate::mapping::PathMappings;
+use crate::model::SourceFile;
+use crate::record::RecordWriter;
+use crate::visit::{self, ModuleHeader, SymbolVisitor};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum Cpu {
     X86,
     X86_64,
+    Arm64,
     Unknown,
 }
 
@@ -39,24 +65,26 @@ impl Cpu {
             _ => 8,
         }
     }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Cpu::X86 => "x86",
+            Cpu::X86_64 => "x86_64",
+            Cpu::Arm64 => "arm64",
+            Cpu::Unknown => "unknown",
+        }
+    }
 }
 
 impl Display for Cpu {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Cpu::X86 => "x86",
-                Cpu::X86_64 => "x86_64",
-                Cpu::Unknown => "unknown",
-            }
-        )
+        write!(f, "{}", self.as_str())
     }
 }
 
 const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
 const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const S_FRAMEPROC: u16 = 0x1012;
 
 #[derive(Debug)]
 pub(super) struct PDBSections {
@@ -94,44 +122,60 @@ impl PDBSections {
 #[derive(Debug)]
 pub(super) struct PDBContributions {
     contributions: Option<Vec<HashSet<u32>>>,
+    // The offsets and the sizes of the code contributions
+    code: Vec<(PdbInternalSectionOffset, u32)>,
 }
 
 // Some executable sections may contain symbols which are not executable (e.g. string constants)
 // So here we collect all the symbols which are in an exec section and which aren't exec
 impl PDBContributions {
     fn new(dbi: &DebugInformation, pdb_sections: &PDBSections) -> Self {
-        PDBContributions {
-            contributions: dbi
-                .section_contributions()
-                .ok()
-                .and_then(|mut contributions| {
-                    let mut contribs: Vec<HashSet<u32>> =
-                        vec![HashSet::default(); pdb_sections.len()];
-                    loop {
-                        if let Ok(c) = contributions.next() {
-                            if let Some(contribution) = c {
-                                if pdb_sections.is_code(contribution.offset.section)
-                                    && !PDBSections::has_code(contribution.characteristics)
-                                {
-                                    let section = (contribution.offset.section - 1) as usize;
-                                    contribs[section].insert(contribution.offset.offset);
-                                }
-                            } else {
-                                break;
-                            }
-                        } else {
-                            return None;
+        let mut contribs: Vec<HashSet<u32>> = vec![HashSet::default(); pdb_sections.len()];
+        let mut code = Vec::new();
+        let complete = match dbi.section_contributions() {
+            Ok(mut contributions) => loop {
+                match contributions.next() {
+                    Ok(Some(contribution)) => {
+                        if PDBSections::has_code(contribution.characteristics) {
+                            code.push((contribution.offset, contribution.size));
+                        } else if pdb_sections.is_code(contribution.offset.section) {
+                            let section = (contribution.offset.section - 1) as usize;
+                            contribs[section].insert(contribution.offset.offset);
                         }
                     }
-                    if contribs.is_empty() {
-                        None
-                    } else {
-                        Some(contribs)
-                    }
-                }),
+                    Ok(None) => break true,
+                    Err(_) => break false,
+                }
+            },
+            Err(_) => false,
+        };
+
+        if complete && !contribs.is_empty() {
+            PDBContributions {
+                contributions: Some(contribs),
+                code,
+            }
+        } else {
+            PDBContributions {
+                contributions: None,
+                code: Vec::new(),
+            }
         }
     }
 
+    /// Get the sorted rva ranges of the code contributions
+    pub(super) fn get_code_ranges(&self, address_map: &AddressMap) -> Vec<(u32, u32)> {
+        let mut ranges: Vec<_> = self
+            .code
+            .iter()
+            .filter_map(|(offset, size)| {
+                offset.to_rva(address_map).map(|rva| (rva.0, rva.0 + size))
+            })
+            .collect();
+        ranges.sort_unstable();
+        ranges
+    }
+
     pub(super) fn is_code(&self, section: u16, offset: u32) -> bool {
         let section = (section - 1) as usize;
         self.contributions.as_ref().map_or(true, |v| {
@@ -142,22 +186,99 @@ impl PDBContributions {
 
 struct PDBData<'s> {
     address_map: AddressMap<'s>,
+    line_columns: bool,
+    cancel: CancelToken,
 }
 
 struct Collector {
     cpu: Cpu,
+    public_precedence: PublicPrecedence,
+    parameter_names: bool,
+    data_publics: bool,
+    thunk_names: ThunkNames,
+    // The rvas of the thunks (when they're annotated)
+    thunks: HashSet<u32>,
+    // The register addressing the parameters of the current procedure (from its S_FRAMEPROC)
+    param_base: Option<Register>,
+    x86_param_sizes: bool,
+    strict_names: bool,
+    // The first name which isn't valid UTF-8 (with --strict-names)
+    invalid_name: Option<String>,
     symbols: RvaSymbols,
-    pdb_sections: PDBSections,
-    pdb_contributions: PDBContributions,
 }
 
 impl Collector {
-    fn add_public_symbol(&mut self, symbol: PublicSymbol, address_map: &AddressMap) {
+    fn new(cpu: Cpu, options: &DumpOptions) -> Self {
+        Self {
+            cpu,
+            public_precedence: options.public_precedence,
+            parameter_names: options.parameter_names,
+            data_publics: options.data_publics,
+            thunk_names: options.thunk_names,
+            thunks: HashSet::new(),
+            param_base: None,
+            x86_param_sizes: options.x86_param_sizes,
+            strict_names: options.strict_names,
+            invalid_name: None,
+            symbols: RvaSymbols::default(),
+        }
+    }
+
+    /// Merge the collector of a module (the modules must be merged in order)
+    fn merge(&mut self, other: Collector) {
+        self.thunks.extend(other.thunks);
+        if self.invalid_name.is_none() {
+            self.invalid_name = other.invalid_name;
+        }
+        self.symbols.merge(other.symbols);
+    }
+
+    /// The names which aren't valid UTF-8 are decoded with some U+FFFD (as RawString::to_string does)
+    /// and reported, or they're an error with --strict-names
+    fn check_name(&mut self, name: RawString) {
+        if std::str::from_utf8(name.as_bytes()).is_ok() {
+            return;
+        }
+        let name = name.to_string().into_owned();
+        if self.strict_names {
+            self.invalid_name.get_or_insert(name);
+        } else {
+            diagnostics::report(
+                Category::InvalidName,
+                format!("The name {} isn't valid UTF-8", name),
+            );
+        }
+    }
+
+    fn check_names(&self) -> common::Result<()> {
+        match self.invalid_name.as_ref() {
+            Some(name) => Err(Error::InvalidName { name: name.clone() }.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn add_public_symbol(
+        &mut self,
+        mut symbol: PublicSymbol,
+        pdb_sections: &PDBSections,
+        pdb_contributions: &PDBContributions,
+        address_map: &AddressMap,
+    ) {
+        self.check_name(symbol.name);
+        if let Some(target) = symbol::get_ilt_target(symbol.name.as_bytes()) {
+            match self.thunk_names {
+                ThunkNames::Keep => {}
+                ThunkNames::Annotate => self.add_thunk_rva(symbol.offset, address_map),
+                ThunkNames::Target => symbol.name = RawString::from(target),
+            }
+        }
         self.symbols.add_public_symbol(
             symbol,
-            &self.pdb_sections,
-            &self.pdb_contributions,
+            pdb_sections,
+            pdb_contributions,
             address_map,
+            self.public_precedence,
+            self.data_publics,
         )
     }
 
@@ -167,22 +288,72 @@ impl Collector {
         info: BlockInfo,
         lines: &SourceLineCollector,
     ) {
+        self.param_base = None;
+        self.check_name(symbol.name);
         self.symbols.add_procedure_symbol(lines, symbol, info);
     }
 
+    fn add_fragment(
+        &mut self,
+        symbol: ProcedureSymbol,
+        info: BlockInfo,
+        lines: &SourceLineCollector,
+    ) {
+        self.check_name(symbol.name);
+        self.symbols.add_fragment(lines, symbol, info);
+    }
+
     fn add_symbol(&mut self, symbol: SelectedSymbol, info: BlockInfo) {
         self.symbols.add_symbol(symbol, info);
     }
 
+    fn add_thunk_rva(&mut self, offset: PdbInternalSectionOffset, address_map: &AddressMap) {
+        if let Some(rva) = offset.to_rva(address_map) {
+            self.thunks.insert(rva.0);
+        }
+    }
+
+    /// The name of a S_THUNK32 is the one of its target (for the import thunks)
+    /// and there's a public at the same address
+    fn add_thunk(&mut self, symbol: ThunkSymbol, address_map: &AddressMap) {
+        if self.thunk_names == ThunkNames::Annotate {
+            self.add_thunk_rva(symbol.offset, address_map);
+        }
+    }
+
+    fn add_local(&mut self, symbol: LocalSymbol) {
+        if self.parameter_names && symbol.flags.isparam {
+            self.check_name(symbol.name);
+            self.symbols.add_param_name(symbol.name);
+        }
+    }
+
     fn add_reg_rel(&mut self, symbol: RegisterRelativeSymbol) {
+        // The S_REGREL32 of the parameters are before the ones of the other locals:
+        // the extra names are ignored when the signature is written
+        if self.parameter_names {
+            self.check_name(symbol.name);
+            self.symbols.add_param_name(symbol.name);
+        }
         // TODO: check that's the correct way to know if we've a parameter here
         // 22 comes from https://github.com/microsoft/microsoft-pdb/blob/master/include/cvconst.h#L436
-        if self.cpu == Cpu::X86 && symbol.register == Register(22 /* EBP */) && symbol.offset > 0 {
+        // The optimized functions address their parameters with the virtual frame (or ebx when the
+        // stack is realigned) rather than with ebp: the S_FRAMEPROC tells which one is used
+        let param_base = self.param_base.unwrap_or(Register(22 /* EBP */));
+        if self.cpu == Cpu::X86 && symbol.register == param_base && symbol.offset > 0 {
             self.symbols.add_ebp(symbol);
         }
     }
 
+    fn add_frame_proc(&mut self, data: &[u8]) {
+        // Google's dump_syms only uses the ebp-relative parameters
+        if self.cpu == Cpu::X86 && self.x86_param_sizes {
+            self.param_base = get_param_base(data);
+        }
+    }
+
     fn close_procedure(&mut self) {
+        self.param_base = None;
         self.symbols.close_procedure();
     }
 
@@ -194,12 +365,21 @@ impl Collector {
 pub(crate) struct PDBInfo {
     symbols: PDBSymbols,
     files: Vec<String>,
+    source_urls: Vec<(u32, String)>,
+    checksums: Vec<Option<String>>,
     cpu: Cpu,
     debug_id: String,
     pdb_name: String,
     pe_name: String,
     code_id: Option<String>,
     stack: String,
+    // The functions whose STACK records come from a prologue analysis
+    heuristic_stack: Option<Vec<(u32, u32)>>,
+    // The unwind info isn't computed when the STACK records aren't emitted
+    cfi: bool,
+    address_report: AddressReport,
+    objects: Vec<ObjectInfo>,
+    locals: Vec<FunctionScope>,
 }
 
 impl Display for PDBInfo {
@@ -214,13 +394,27 @@ impl Display for PDBInfo {
             writeln!(f, "INFO CODE_ID {} {}", code_id, self.pe_name)?;
         }
 
+        for (n, url) in self.source_urls.iter() {
+            writeln!(f, "INFO URL {} {}", n, url)?;
+        }
+
+        for (rva, len) in self.heuristic_stack.iter().flatten() {
+            writeln!(f, "INFO HEURISTIC_STACK {:x} {:x}", rva, len)?;
+        }
+
+        let mut writer = RecordWriter::new();
         for (n, file_name) in self.files.iter().enumerate() {
-            writeln!(f, "FILE {} {}", n, file_name)?;
+            writer.str("FILE ").dec(n as u64).space().name(file_name);
+            if let Some(Some(checksum)) = self.checksums.get(n) {
+                writer.space().str(checksum);
+            }
+            writer.end().flush_if_full(f)?;
         }
 
         for (_, sym) in self.symbols.iter() {
-            write!(f, "{}", sym)?;
+            sym.write_records(&mut writer, f)?;
         }
+        writer.flush(f)?;
 
         write!(f, "{}", self.stack)?;
 
@@ -236,6 +430,7 @@ fn get_cpu(dbi: &DebugInformation) -> Cpu {
             // https://docs.rs/pdb/0.5.0/pdb/enum.MachineType.html
             MachineType::X86 => Cpu::X86,
             MachineType::Amd64 | MachineType::Ia64 => Cpu::X86_64,
+            MachineType::Arm64 => Cpu::Arm64,
             _ => Cpu::Unknown,
         }
     } else {
@@ -243,6 +438,20 @@ fn get_cpu(dbi: &DebugInformation) -> Cpu {
     }
 }
 
+/// Get the register used by an x86 function to address its parameters from its S_FRAMEPROC:
+/// https://github.com/microsoft/microsoft-pdb/blob/master/include/cvinfo.h (FRAMEPROCSYM)
+fn get_param_base(data: &[u8]) -> Option<Register> {
+    // The kind, five u32 (frame, padding and saved registers sizes, ...), a u16 and the flags
+    let flags = data.get(24..28)?;
+    let flags = u32::from_le_bytes([flags[0], flags[1], flags[2], flags[3]]);
+    match (flags >> 16) & 0x3 {
+        1 => Some(Register(30006 /* VFRAME */)),
+        2 => Some(Register(22 /* EBP */)),
+        3 => Some(Register(20 /* EBX */)),
+        _ => None,
+    }
+}
+
 fn get_debug_id(dbi: &DebugInformation, pi: PDBInformation) -> String {
     // Here the guid is treated like a 128-bit uuid (PDB >=7.0)
     let mut buf = Uuid::encode_buffer();
@@ -251,18 +460,73 @@ fn get_debug_id(dbi: &DebugInformation, pi: PDBInformation) -> String {
     format!("{}{:x}", guid, age)
 }
 
+/// Sort the files by name and merge the ones with the same name if required
+/// (their paths have been normalized): the old ids are mapped to the new ones.
+/// Google's dump_syms keeps the files in the order of the pdb so they aren't sorted with
+/// --compat breakpad: only the duplicates are merged in the first one.
+fn sort_and_dedup_files(files: &mut Vec<String>, options: &DumpOptions) -> Vec<u32> {
+    if options.compat == Some(Compat::Breakpad) {
+        let mut ids = HashMap::with_capacity(files.len());
+        let mut remapping = Vec::with_capacity(files.len());
+        for file in std::mem::take(files) {
+            let next = ids.len() as u32;
+            remapping.push(*ids.entry(file.clone()).or_insert_with(|| {
+                files.push(file);
+                next
+            }));
+        }
+        return remapping;
+    }
+
+    let mut remapping = common::sort_files(files);
+    if options.normalize_paths {
+        let dedup_remapping = common::dedup_files(files);
+        for id in remapping.iter_mut() {
+            *id = dedup_remapping[*id as usize];
+        }
+    }
+    remapping
+}
+
+fn get_dumper_flags(options: &DumpOptions) -> DumperFlags {
+    let mut flags = DumperFlags::default();
+    flags.set(
+        DumperFlags::NO_DEMANGLE_PUBLICS,
+        options.no_demangle_publics,
+    );
+    flags.set(
+        DumperFlags::MSVC_TYPE_NAMES,
+        options.type_names == TypeNames::Msvc,
+    );
+    flags.set(DumperFlags::X86_PARAM_SIZES, options.x86_param_sizes);
+    if options.compat == Some(Compat::Breakpad) {
+        // DIA writes "A::f(int * *,unsigned __int64)" for "static A::f(int**, unsigned long long)"
+        flags.remove(DumperFlags::SPACE_AFTER_COMMA);
+        flags.insert(
+            DumperFlags::SPACE_BEFORE_POINTER
+                | DumperFlags::MSVC_TYPE_NAMES
+                | DumperFlags::NO_METHOD_QUALIFIERS,
+        );
+    }
+    flags
+}
+
 fn get_stack_info(pdb_buf: Option<&[u8]>, pe: Option<PeObject>) -> String {
     let mut found_unwind_info = false;
     let mut buf = Vec::new();
-    let writer = Cursor::new(&mut buf);
 
-    let mut cfi_writer = AsciiCfiWriter::new(writer);
+    // symbolic only reads the x64 exception data
+    let arm64_stack = pe
+        .as_ref()
+        .filter(|pe| pe.arch().cpu_family() == CpuFamily::Arm64)
+        .map(arm64_unwind::get_stack_info);
+
+    // Broken unwind info only loses the STACK records
     if let Some(pe) = pe {
         if pe.has_unwind_info() {
-            cfi_writer
-                .process(&Object::Pe(pe))
-                .map_err(|e| e.compat())
-                .unwrap();
+            if let Err(e) = common::write_cfi(&mut buf, &Object::Pe(pe)) {
+                warn!("Cannot read the unwind info of the PE file: {}", e);
+            }
             found_unwind_info = true;
         }
     }
@@ -271,22 +535,59 @@ fn get_stack_info(pdb_buf: Option<&[u8]>, pe: Option<PeObject>) -> String {
         if let Some(pdb_buf) = pdb_buf {
             if let Ok(pdb) = PdbObject::parse(pdb_buf) {
                 if pdb.has_unwind_info() {
-                    cfi_writer
-                        .process(&Object::Pdb(pdb))
-                        .map_err(|e| e.compat())
-                        .unwrap();
+                    if let Err(e) = common::write_cfi(&mut buf, &Object::Pdb(pdb)) {
+                        warn!("Cannot read the frame data of the pdb: {}", e);
+                    }
                 }
             }
         }
     }
 
-    String::from_utf8(buf).unwrap()
+    let mut stack = String::from_utf8_lossy(&buf).into_owned();
+    stack.push_str(&arm64_stack.unwrap_or_default());
+    stack
+}
+
+/// Get the provenance of the symbols and of the STACK records: the ones in `heuristic`
+/// come from a prologue analysis, the STACK WIN from the frame data of the pdb
+/// and the STACK CFI from the exception data of the PE.
+fn get_provenance<'a>(
+    symbols: &'a PDBSymbols,
+    stack: &str,
+    heuristic: &[(u32, u32)],
+) -> Vec<RecordProvenance<'a>> {
+    let heuristic: HashSet<_> = heuristic.iter().map(|(rva, _)| *rva).collect();
+    let symbols = symbols.values().map(|sym| RecordProvenance {
+        record: if sym.is_public { "PUBLIC" } else { "FUNC" },
+        address: sym.rva,
+        size: sym.len,
+        name: Some(&sym.name),
+        provenance: sym.provenance,
+    });
+    let stack = common::get_stack_ranges(stack)
+        .into_iter()
+        .map(|(record, address, size)| RecordProvenance {
+            record,
+            address,
+            size,
+            name: None,
+            provenance: if heuristic.contains(&address) {
+                Provenance::Heuristic
+            } else if record == "STACK WIN" {
+                Provenance::PdbFrameData
+            } else {
+                Provenance::PeExceptionData
+            },
+        });
+    symbols.chain(stack).collect()
 }
 
 impl<'s> PDBData<'s> {
     fn collect_public_symbols(
         &self,
         globals: SymbolTable,
+        pdb_sections: &PDBSections,
+        pdb_contributions: &PDBContributions,
         collector: &mut Collector,
     ) -> Result<()> {
         let mut symbols = globals.iter();
@@ -297,7 +598,12 @@ impl<'s> PDBData<'s> {
             };
 
             if let SymbolData::Public(symbol) = symbol {
-                collector.add_public_symbol(symbol, &self.address_map);
+                collector.add_public_symbol(
+                    symbol,
+                    pdb_sections,
+                    pdb_contributions,
+                    &self.address_map,
+                );
             }
         }
 
@@ -345,7 +651,7 @@ impl<'s> PDBData<'s> {
 
         if block_rva < parent_rva || block_rva > parent_rva + parent.len {
             // So the block is outside of its parent procedure
-            collector.add_procedure_symbol(
+            collector.add_fragment(
                 parent,
                 BlockInfo {
                     rva: block_rva.0,
@@ -394,7 +700,9 @@ impl<'s> PDBData<'s> {
                     parameter_size: parent.parameter_size,
                     source,
                     ebp: parent.ebp.clone(),
+                    param_names: parent.param_names.clone(),
                     id: parent.id,
+                    folded: Vec::new(),
                 };
                 collector.add_symbol(
                     sym,
@@ -438,6 +746,12 @@ impl<'s> PDBData<'s> {
             SymbolData::SeparatedCode(block) => {
                 self.add_sepcode(block, collector, lines);
             }
+            SymbolData::Local(local) => {
+                collector.add_local(local);
+            }
+            SymbolData::Thunk(thunk) => {
+                collector.add_thunk(thunk, &self.address_map);
+            }
             SymbolData::RegisterRelative(regrel) => {
                 collector.add_reg_rel(regrel);
             }
@@ -450,44 +764,184 @@ impl<'s> PDBData<'s> {
         Ok(())
     }
 
-    fn collect_functions<'a, S: 'a + Source<'a>>(
+    /// Get all the procedures and the labels of a module
+    /// Labels correspond to some labelled code we can map with some public symbols (assembly)
+    fn collect_module<'a, S: 'a + Source<'a>>(
         &self,
         pdb: &mut PDB<'a, S>,
-        dbi: &DebugInformation,
+        module: &Module,
+        module_lines: Option<&ModuleLines>,
         collector: &mut Collector,
-        source_files: &SourceFiles<'s>,
-    ) -> Result<()> {
-        let mut modules = dbi.modules()?;
-
-        // We get all the procedures and the labels
-        // Labels correspond to some labelled code we can map with some public symbols (assembly)
-        while let Some(module) = modules.next()? {
-            let module_info = match pdb.module_info(&module)? {
-                Some(info) => info,
-                _ => continue,
+        file_ids: &RefToIds,
+    ) -> common::Result<()> {
+        let _span = debug_span!("module", name = %module.module_name()).entered();
+        let module_info = match pdb.module_info(module).stream("module stream")? {
+            Some(info) => info,
+            _ => return Ok(()),
+        };
+
+        let columns = if self.line_columns {
+            columns::get_columns(pdb, module_lines)?
+        } else {
+            Columns::new()
+        };
+        let lines = SourceLineCollector::new(
+            &self.address_map,
+            file_ids,
+            module_info.line_program()?,
+            columns,
+        )?;
+
+        let symbol_error = |offset, source| Error::PdbSymbol {
+            module: module.module_name().into_owned(),
+            offset,
+            source,
+        };
+        let mut symbols = module_info.symbols().stream("module stream")?;
+        // The symbols start after the signature of the stream
+        let mut offset = 4;
+        loop {
+            let symbol = match symbols.next() {
+                Ok(Some(symbol)) => symbol,
+                Ok(None) => break,
+                Err(source) => return Err(symbol_error(offset, source).into()),
+            };
+            offset = symbol.index().0;
+            let next_offset = offset + 2 + symbol.raw_bytes().len() as u32;
+            // The pdb crate doesn't parse the S_FRAMEPROC records
+            if symbol.raw_kind() == S_FRAMEPROC {
+                collector.add_frame_proc(symbol.raw_bytes());
+                offset = next_offset;
+                continue;
+            }
+            let symbol = match symbol.parse() {
+                Ok(s) => s,
+                _ => {
+                    offset = next_offset;
+                    continue;
+                }
             };
 
-            let lines = SourceLineCollector::new(
-                &self.address_map,
-                source_files,
-                module_info.line_program()?,
-            )?;
+            self.handle_symbol(symbol, collector, &lines, &module_info)
+                .map_err(|source| symbol_error(offset, source))?;
+            offset = next_offset;
+        }
 
-            let mut symbols = module_info.symbols()?;
-            while let Some(symbol) = symbols.next()? {
-                let symbol = match symbol.parse() {
-                    Ok(s) => s,
-                    _ => continue,
-                };
+        Ok(())
+    }
 
-                self.handle_symbol(symbol, collector, &lines, &module_info)?;
-            }
+    fn collect_functions<'a, S: 'a + Source<'a>>(
+        &self,
+        pdb: &mut PDB<'a, S>,
+        dbi: &DebugInformation,
+        collector: &mut Collector,
+        file_ids: &RefToIds,
+    ) -> common::Result<()> {
+        let modules: Vec<_> = dbi.modules().stream("DBI stream")?.collect()?;
+        let module_lines = if self.line_columns {
+            columns::get_module_lines(pdb)?
+        } else {
+            Vec::new()
+        };
+
+        for (index, module) in modules.iter().enumerate() {
+            self.cancel.check_pdb()?;
+            self.collect_module(pdb, module, module_lines.get(index), collector, file_ids)?;
         }
 
         Ok(())
     }
 }
 
+/// Collect the functions of the modules taken from the shared queue: each worker has its own pdb
+/// and each module its own collector
+fn collect_modules(
+    buf: &[u8],
+    next: &AtomicUsize,
+    cpu: Cpu,
+    options: &DumpOptions,
+    file_ids: &RefToIds,
+) -> common::Result<Vec<(usize, Collector)>> {
+    let mut pdb = PDB::open(Cursor::new(buf))?;
+    let dbi = pdb.debug_information().stream("DBI stream")?;
+    let modules: Vec<_> = dbi.modules()?.collect()?;
+    let module_lines = if options.line_columns {
+        columns::get_module_lines(&mut pdb)?
+    } else {
+        Vec::new()
+    };
+    let pdb_data = PDBData {
+        address_map: pdb.address_map().stream("address map")?,
+        line_columns: options.line_columns,
+        cancel: options.cancel.clone(),
+    };
+
+    let mut collectors = Vec::new();
+    loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let module = match modules.get(index) {
+            Some(module) => module,
+            None => break,
+        };
+        options.cancel.check_pdb()?;
+        let mut collector = Collector::new(cpu, options);
+        pdb_data.collect_module(
+            &mut pdb,
+            module,
+            module_lines.get(index),
+            &mut collector,
+            file_ids,
+        )?;
+        collectors.push((index, collector));
+    }
+
+    Ok(collectors)
+}
+
+/// Collect the functions of the modules on several threads and merge them in the order
+/// of the modules (so the output is the one of a sequential collection)
+fn collect_functions_in_parallel(
+    buf: &[u8],
+    num_jobs: usize,
+    cpu: Cpu,
+    options: &DumpOptions,
+    collector: &mut Collector,
+    file_ids: &RefToIds,
+) -> common::Result<()> {
+    const PANICKED: &str = "A thread reading the modules of the pdb panicked";
+
+    let next = AtomicUsize::new(0);
+    let results = crossbeam::scope(|scope| -> common::Result<Vec<_>> {
+        // The threads can't be created in a sandbox for example
+        let handles = (0..num_jobs)
+            .map(|_| {
+                scope
+                    .builder()
+                    .spawn(|_| collect_modules(buf, &next, cpu, options, file_ids))
+            })
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| format!("Cannot create the threads reading the modules: {}", e))?;
+
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(handle.join().map_err(|_| PANICKED)?);
+        }
+        Ok(results)
+    })
+    .map_err(|_| PANICKED)??;
+
+    let mut collectors = Vec::new();
+    for result in results {
+        collectors.extend(result?);
+    }
+    collectors.sort_unstable_by_key(|(index, _)| *index);
+    for (_, module) in collectors {
+        collector.merge(module);
+    }
+
+    Ok(())
+}
+
 impl PDBInfo {
     pub fn new(
         buf: &[u8],
@@ -495,68 +949,264 @@ impl PDBInfo {
         pe_name: &str,
         pe: Option<PeObject>,
         mapping: Option<Arc<PathMappings>>,
-    ) -> Result<Self> {
+        options: &DumpOptions,
+    ) -> common::Result<Self> {
+        let start = Instant::now();
         let cursor = Cursor::new(buf);
         let mut pdb = PDB::open(cursor)?;
-        let dbi = pdb.debug_information()?;
-        let pi = pdb.pdb_information()?;
-        let frame_table = pdb.frame_table()?;
-        let globals = pdb.global_symbols()?;
+        let dbi = pdb.debug_information().stream("DBI stream")?;
+        let pi = pdb.pdb_information().stream("PDB stream")?;
+        let frame_table = pdb.frame_table().stream("frame data")?;
+        let globals = pdb.global_symbols().stream("global symbols")?;
         let pdb_sections = PDBSections::new(&mut pdb);
         let pdb_contributions = PDBContributions::new(&dbi, &pdb_sections);
 
+        if fastlink::is_fastlink(&mut pdb) {
+            let error = Error::Fastlink {
+                pdb: pdb_name.to_string(),
+            };
+            if options.reject_fastlink {
+                return Err(error.into());
+            }
+            warn!("{}", error);
+        }
+
         let cpu = get_cpu(&dbi);
         let debug_id = get_debug_id(&dbi, pi);
-        let source_files = SourceFiles::new(&mut pdb, mapping)?;
+        let source_files = SourceFiles::new(
+            &mut pdb,
+            mapping,
+            options.normalize_paths,
+            options.fold_path_case,
+        )?;
+        let mut files = source_files.get_mapping();
+        let mut source_urls = if options.srcsrv {
+            SrcSrv::new(&mut pdb)
+                .map_or_else(Vec::new, |s| source_files.get_source_urls(|p| s.get_url(p)))
+        } else {
+            Vec::new()
+        };
 
-        let pdb_data = PDBData {
-            address_map: pdb.address_map()?,
+        if let Some(mode) = options.sourcelink {
+            if let Some(sourcelink) = SourceLink::new(&mut pdb) {
+                let urls = source_files.get_source_urls(|p| sourcelink.get_url(p));
+                if mode == SourceLinkMode::Replace {
+                    for (n, url) in urls {
+                        files[n as usize] = url;
+                    }
+                } else {
+                    // The urls from srcsrv (if any) have the precedence
+                    let known: HashSet<_> = source_urls.iter().map(|(n, _)| *n).collect();
+                    source_urls.extend(urls.into_iter().filter(|(n, _)| !known.contains(n)));
+                    source_urls.sort_by_key(|(n, _)| *n);
+                }
+            }
+        }
+
+        let objects = if options.objects.is_some() {
+            objects::collect_objects(&mut pdb)?
+        } else {
+            Vec::new()
         };
 
-        let mut collector = Collector {
-            cpu,
-            symbols: RvaSymbols::default(),
-            pdb_sections,
-            pdb_contributions,
+        let checksums = if options.file_checksums {
+            source_files.get_checksums()
+        } else {
+            Vec::new()
         };
 
-        pdb_data.collect_functions(&mut pdb, &dbi, &mut collector, &source_files)?;
-        pdb_data.collect_public_symbols(globals, &mut collector)?;
+        let pdb_data = PDBData {
+            address_map: pdb.address_map().stream("address map")?,
+            line_columns: options.line_columns,
+            cancel: options.cancel.clone(),
+        };
+
+        let mut collector = Collector::new(cpu, options);
+        if options.module_jobs > 1 {
+            collect_functions_in_parallel(
+                buf,
+                options.module_jobs,
+                cpu,
+                options,
+                &mut collector,
+                source_files.get_ids(),
+            )?;
+        } else {
+            pdb_data.collect_functions(&mut pdb, &dbi, &mut collector, source_files.get_ids())?;
+        }
+        pdb_data.collect_public_symbols(
+            globals,
+            &pdb_sections,
+            &pdb_contributions,
+            &mut collector,
+        )?;
+        collector.check_names()?;
 
-        let type_info = pdb.type_information()?;
+        let type_span = info_span!("type scan").entered();
+        let type_info = pdb.type_information().stream("TPI stream")?;
+        let type_servers = type_server::load_type_servers(&mut pdb, &options.type_server_paths);
         // Demangler or dumper (for type info we've for private symbols)
-        let type_dumper = TypeDumper::new(&type_info, cpu.get_ptr_size(), DumperFlags::default())?;
+        let type_dumper = TypeDumper::new(
+            &type_info,
+            &type_servers,
+            cpu.get_ptr_size(),
+            get_dumper_flags(options),
+            options.cancel.clone(),
+        )?;
+        type_span.exit();
+        let locals = if options.locals.is_some() {
+            locals::collect_locals(&mut pdb, &pdb_data.address_map, &type_dumper)?
+        } else {
+            Vec::new()
+        };
 
         let code_id = pe
             .as_ref()
-            .map(|pe| pe.code_id().unwrap().as_str().to_uppercase());
+            .and_then(|pe| pe.code_id())
+            .map(|code_id| code_id.as_str().to_uppercase());
 
-        let stack = get_stack_info(Some(buf), pe);
-        let symbols =
-            collector
-                .symbols
-                .mv_to_pdb_symbols(type_dumper, &pdb_data.address_map, frame_table);
+        // The pe is consumed by the cfi writer
+        let pe_buf = pe.as_ref().map(|pe| pe.data());
+        let stack = if options.emit.cfi {
+            get_stack_info(Some(buf), pe)
+        } else {
+            String::new()
+        };
+        let mut address_report = AddressReport::default();
+        let names_start = Instant::now();
+        let mut symbols = collector.symbols.mv_to_pdb_symbols(
+            &type_dumper,
+            &pdb_data.address_map,
+            &frame_table,
+            options.address_report.as_ref().map(|_| &mut address_report),
+        )?;
+        let names_time = names_start.elapsed();
+        address_report.sort_by_key(|e| e.original_rva);
+        symbol::fix_sizes(
+            &mut symbols,
+            &pdb_contributions.get_code_ranges(&pdb_data.address_map),
+        );
+        for rva in collector.thunks.iter() {
+            if let Some(sym) = symbols.get_mut(rva) {
+                sym.name.push_str(" [thunk]");
+            }
+        }
         let symbols = crate::windows::symbol::append_dummy_symbol(symbols, pe_name);
 
-        Ok(PDBInfo {
+        let mut pdb_info = PDBInfo {
             symbols,
-            files: source_files.get_mapping(),
+            files,
+            source_urls,
+            checksums,
             cpu,
             debug_id,
             pdb_name: String::from(pdb_name),
             pe_name: String::from(pe_name),
             code_id,
             stack,
-        })
+            heuristic_stack: if options.scan_prologues && options.emit.cfi {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            cfi: options.emit.cfi,
+            address_report,
+            objects,
+            locals,
+        };
+
+        pdb_info.sort_files(options);
+        if let Some(pe_buf) = pe_buf {
+            pdb_info.scan_prologues(pe_buf);
+        }
+
+        if options.stats {
+            crate::windows::symbol::print_stats(
+                pdb_name,
+                &pdb_info.symbols,
+                !options.no_demangle_publics,
+                names_time,
+                start.elapsed(),
+            );
+        }
+
+        Ok(pdb_info)
+    }
+
+    /// Sort the FILE records by name to have a reproducible output
+    fn sort_files(&mut self, options: &DumpOptions) {
+        let remapping = sort_and_dedup_files(&mut self.files, options);
+        for sym in self.symbols.values_mut() {
+            if !sym.source.lines.is_empty() {
+                Rc::make_mut(&mut sym.source).remap_files(&remapping);
+            }
+        }
+
+        for (n, _) in self.source_urls.iter_mut() {
+            *n = remapping[*n as usize];
+        }
+        self.source_urls.sort_by_key(|(n, _)| *n);
+        self.source_urls.dedup_by_key(|(n, _)| *n);
+
+        if !self.checksums.is_empty() {
+            let mut checksums = vec![None; self.files.len()];
+            for (old_id, checksum) in self.checksums.drain(..).enumerate() {
+                let new_id = remapping[old_id] as usize;
+                if checksums[new_id].is_none() {
+                    checksums[new_id] = checksum;
+                }
+            }
+            self.checksums = checksums;
+        }
+    }
+
+    /// Add some STACK records for the functions without unwind info
+    /// but with a frame pointer setup (if --scan-prologues is on)
+    fn scan_prologues(&mut self, pe_buf: &[u8]) {
+        if let (Some(heuristic), Cpu::X86 | Cpu::X86_64) = (self.heuristic_stack.as_mut(), self.cpu)
+        {
+            *heuristic = prologue::scan_prologues(
+                pe_buf,
+                &self.symbols,
+                &mut self.stack,
+                self.cpu == Cpu::X86_64,
+            );
+        }
+    }
+
+    /// Write the functions which have been moved by OMAP or folded by ICF
+    pub fn dump_address_report<W: Write>(&self, mut writer: W) -> common::Result<()> {
+        for entry in self.address_report.iter() {
+            write!(writer, "{}", entry)?;
+        }
+        Ok(())
+    }
+
+    /// Write the object paths and the build information of the modules in json
+    pub fn dump_objects<W: Write>(&self, writer: W) -> common::Result<()> {
+        serde_json::to_writer_pretty(writer, &self.objects)?;
+        Ok(())
+    }
+
+    /// Write the scopes and the local variables of the functions in json
+    pub fn dump_locals<W: Write>(&self, writer: W) -> common::Result<()> {
+        serde_json::to_writer_pretty(writer, &self.locals)?;
+        Ok(())
+    }
+
+    pub(crate) fn has_pe(&self) -> bool {
+        self.code_id.is_some()
     }
 
     pub fn set_pe(&mut self, pe_name: String, pe: PeObject, pdb_buf: &[u8]) -> bool {
-        if get_pe_debug_id(Some(&pe)).unwrap() == self.debug_id {
-            self.code_id = Some(pe.code_id().unwrap().as_str().to_uppercase());
+        if get_pe_debug_id(Some(&pe)).as_ref() == Some(&self.debug_id) {
+            self.code_id = pe.code_id().map(|code_id| code_id.as_str().to_uppercase());
             self.pe_name = pe_name;
-            if self.stack.is_empty() {
+            let pe_buf = pe.data();
+            if self.cfi && self.stack.is_empty() {
                 self.stack = get_stack_info(Some(pdb_buf), Some(pe));
             }
+            self.scan_prologues(pe_buf);
             true
         } else {
             false
@@ -577,6 +1227,57 @@ impl Dumpable for PDBInfo {
     fn get_name(&self) -> &str {
         &self.pdb_name
     }
+
+    fn get_source_files(&self) -> &[String] {
+        &self.files
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        get_provenance(
+            &self.symbols,
+            &self.stack,
+            self.heuristic_stack.as_deref().unwrap_or_default(),
+        )
+    }
+
+    /// Give the records as Display writes them
+    fn visit<V: for<'a> SymbolVisitor<'a>>(&self, visitor: &mut V) -> common::Result<()> {
+        visitor.module(ModuleHeader {
+            os: "windows",
+            arch: self.cpu.as_str(),
+            debug_id: &self.debug_id,
+            name: &self.pdb_name,
+        })?;
+
+        if let Some(code_id) = self.code_id.as_ref() {
+            visitor.info(format!("CODE_ID {} {}", code_id, self.pe_name).into())?;
+        }
+
+        for (n, url) in self.source_urls.iter() {
+            visitor.info(format!("URL {} {}", n, url).into())?;
+        }
+
+        for (rva, len) in self.heuristic_stack.iter().flatten() {
+            visitor.info(format!("HEURISTIC_STACK {:x} {:x}", rva, len).into())?;
+        }
+
+        for (n, file_name) in self.files.iter().enumerate() {
+            visitor.file(SourceFile {
+                id: n as u32,
+                name: Cow::Borrowed(file_name),
+                checksum: self
+                    .checksums
+                    .get(n)
+                    .and_then(|c| c.as_deref().map(Cow::Borrowed)),
+            })?;
+        }
+
+        for sym in self.symbols.values() {
+            sym.visit(visitor)?;
+        }
+
+        visit::visit_stack(&self.stack, visitor)
+    }
 }
 
 impl Mergeable for PDBInfo {
@@ -585,6 +1286,325 @@ impl Mergeable for PDBInfo {
     }
 }
 
+/// The records of a part of a streamed pdb
+struct StreamedSymbols<'a>(&'a PDBSymbols);
+
+impl Display for StreamedSymbols<'_> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let mut writer = RecordWriter::new();
+        for sym in self.0.values() {
+            sym.write_records(&mut writer, f)?;
+        }
+        writer.flush(f)
+    }
+}
+
+struct StreamedFiles<'a>(&'a [String], &'a [Option<String>]);
+
+impl Display for StreamedFiles<'_> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let mut writer = RecordWriter::new();
+        for (n, file_name) in self.0.iter().enumerate() {
+            writer.str("FILE ").dec(n as u64).space().name(file_name);
+            if let Some(Some(checksum)) = self.1.get(n) {
+                writer.space().str(checksum);
+            }
+            writer.end().flush_if_full(f)?;
+        }
+        writer.flush(f)
+    }
+}
+
+/// A pdb whose records are collected and written module by module when it's dumped (--stream):
+/// only the symbols of one module are in memory at a time so the FUNC records are in the order
+/// of the modules (and not sorted by address) and the reports aren't available
+pub(crate) struct PDBStream<'a> {
+    buf: &'a [u8],
+    cpu: Cpu,
+    debug_id: String,
+    pdb_name: String,
+    pe_name: String,
+    code_id: Option<String>,
+    pe_buf: Option<Vec<u8>>,
+    files: Vec<String>,
+    // The new ids of the files once sorted
+    remapping: Vec<u32>,
+    mapping: Option<Arc<PathMappings>>,
+    options: &'a DumpOptions,
+}
+
+/// What has already been written by a PDBStream
+#[derive(Default)]
+struct StreamState {
+    rvas: HashSet<u32>,
+    last: Option<symbol::PDBSymbol>,
+}
+
+impl<'a> PDBStream<'a> {
+    pub fn new(
+        buf: &'a [u8],
+        path: &Path,
+        pdb_name: &str,
+        mapping: Option<Arc<PathMappings>>,
+        options: &'a DumpOptions,
+    ) -> common::Result<Self> {
+        let mut pdb = PDB::open(Cursor::new(buf))?;
+        let dbi = pdb.debug_information().stream("DBI stream")?;
+        let pi = pdb.pdb_information().stream("PDB stream")?;
+        let mut files = SourceFiles::new(
+            &mut pdb,
+            mapping.clone(),
+            options.normalize_paths,
+            options.fold_path_case,
+        )?
+        .get_mapping();
+        let remapping = sort_and_dedup_files(&mut files, options);
+
+        let mut stream = Self {
+            buf,
+            cpu: get_cpu(&dbi),
+            debug_id: get_debug_id(&dbi, pi),
+            pdb_name: pdb_name.to_string(),
+            pe_name: String::new(),
+            code_id: None,
+            pe_buf: None,
+            files,
+            remapping,
+            mapping,
+            options,
+        };
+        super::utils::try_to_set_pe(path, &options.binary_paths, |pe_name, pe| {
+            stream.set_pe(pe_name, pe)
+        });
+
+        Ok(stream)
+    }
+
+    pub(crate) fn has_pe(&self) -> bool {
+        self.code_id.is_some()
+    }
+
+    pub(crate) fn set_pe(&mut self, pe_name: String, pe: PeObject) -> bool {
+        if get_pe_debug_id(Some(&pe)).as_ref() == Some(&self.debug_id) {
+            self.code_id = pe.code_id().map(|code_id| code_id.as_str().to_uppercase());
+            self.pe_name = pe_name;
+            // The pe is only needed for the STACK records
+            self.pe_buf = Some(pe.data().to_vec());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Write the symbols of a collector which haven't been written yet
+    fn write_symbols<W: Write>(
+        &self,
+        writer: &mut W,
+        mut collector: Collector,
+        state: &mut StreamState,
+        pdb_data: &PDBData,
+        type_dumper: &TypeDumper,
+        frame_table: &FrameTable,
+    ) -> common::Result<()> {
+        let thunks = std::mem::take(&mut collector.thunks);
+        let mut symbols = collector.symbols.mv_to_pdb_symbols(
+            type_dumper,
+            &pdb_data.address_map,
+            frame_table,
+            None,
+        )?;
+        // A function can be in several modules
+        symbols.retain(|rva, _| state.rvas.insert(*rva));
+        for rva in thunks.iter() {
+            if let Some(sym) = symbols.get_mut(rva) {
+                sym.name.push_str(" [thunk]");
+            }
+        }
+        for sym in symbols.values_mut() {
+            if !sym.source.lines.is_empty() {
+                Rc::make_mut(&mut sym.source).remap_files(&self.remapping);
+            }
+        }
+        if let Some((rva, sym)) = symbols.iter().next_back() {
+            if !matches!(&state.last, Some(last) if last.rva >= *rva) {
+                state.last = Some(sym.clone());
+            }
+        }
+        write!(writer, "{}", StreamedSymbols(&symbols))?;
+        Ok(())
+    }
+
+    fn write_records<W: Write>(&self, mut writer: W) -> common::Result<()> {
+        let options = self.options;
+        let mut pdb = PDB::open(Cursor::new(self.buf))?;
+        let dbi = pdb.debug_information().stream("DBI stream")?;
+        let frame_table = pdb.frame_table().stream("frame data")?;
+        let globals = pdb.global_symbols().stream("global symbols")?;
+        let pdb_sections = PDBSections::new(&mut pdb);
+        let pdb_contributions = PDBContributions::new(&dbi, &pdb_sections);
+        let source_files = SourceFiles::new(
+            &mut pdb,
+            self.mapping.clone(),
+            options.normalize_paths,
+            options.fold_path_case,
+        )?;
+        let type_info = pdb.type_information().stream("TPI stream")?;
+        let type_servers = type_server::load_type_servers(&mut pdb, &options.type_server_paths);
+        let type_dumper = TypeDumper::new(
+            &type_info,
+            &type_servers,
+            self.cpu.get_ptr_size(),
+            get_dumper_flags(options),
+            options.cancel.clone(),
+        )?;
+        let pdb_data = PDBData {
+            address_map: pdb.address_map().stream("address map")?,
+            line_columns: options.line_columns,
+            cancel: options.cancel.clone(),
+        };
+
+        writeln!(
+            writer,
+            "MODULE windows {} {} {}",
+            self.cpu, self.debug_id, self.pdb_name
+        )?;
+        if let Some(code_id) = self.code_id.as_ref() {
+            writeln!(writer, "INFO CODE_ID {} {}", code_id, self.pe_name)?;
+        }
+        let mut checksums = vec![None; self.files.len()];
+        if options.file_checksums {
+            for (old_id, checksum) in source_files.get_checksums().into_iter().enumerate() {
+                let new_id = self.remapping[old_id] as usize;
+                if checksums[new_id].is_none() {
+                    checksums[new_id] = checksum;
+                }
+            }
+        }
+        write!(writer, "{}", StreamedFiles(&self.files, &checksums))?;
+
+        // The publics are merged in the functions of the module they're in (as when all the
+        // modules are collected): the remaining ones are written at the end
+        let mut publics = Vec::new();
+        let mut public_indices: HashMap<u32, Vec<usize>> = HashMap::new();
+        let mut symbols = globals.iter();
+        while let Some(symbol) = symbols.next()? {
+            match symbol.parse() {
+                Ok(SymbolData::Public(symbol)) => {
+                    if let Some(rva) = symbol.offset.to_rva(&pdb_data.address_map) {
+                        public_indices.entry(rva.0).or_default().push(publics.len());
+                        publics.push(Some(symbol));
+                    }
+                }
+                Ok(_) => {}
+                _ => break,
+            }
+        }
+
+        let modules: Vec<_> = dbi.modules()?.collect()?;
+        let module_lines = if options.line_columns {
+            columns::get_module_lines(&mut pdb)?
+        } else {
+            Vec::new()
+        };
+        let mut state = StreamState::default();
+        for (index, module) in modules.iter().enumerate() {
+            options.cancel.check_pdb()?;
+            let mut collector = Collector::new(self.cpu, options);
+            pdb_data.collect_module(
+                &mut pdb,
+                module,
+                module_lines.get(index),
+                &mut collector,
+                source_files.get_ids(),
+            )?;
+            collector.close_procedure();
+            for rva in collector.symbols.get_rvas() {
+                for index in public_indices.remove(&rva).unwrap_or_default() {
+                    if let Some(symbol) = publics[index].take() {
+                        collector.add_public_symbol(
+                            symbol,
+                            &pdb_sections,
+                            &pdb_contributions,
+                            &pdb_data.address_map,
+                        );
+                    }
+                }
+            }
+            collector.check_names()?;
+            self.write_symbols(
+                &mut writer,
+                collector,
+                &mut state,
+                &pdb_data,
+                &type_dumper,
+                &frame_table,
+            )?;
+        }
+
+        let mut collector = Collector::new(self.cpu, options);
+        for symbol in publics.drain(..).flatten() {
+            collector.add_public_symbol(
+                symbol,
+                &pdb_sections,
+                &pdb_contributions,
+                &pdb_data.address_map,
+            );
+        }
+        collector.check_names()?;
+        self.write_symbols(
+            &mut writer,
+            collector,
+            &mut state,
+            &pdb_data,
+            &type_dumper,
+            &frame_table,
+        )?;
+
+        if let Some(last) = state.last {
+            let rva = last.rva;
+            let mut symbols =
+                symbol::append_dummy_symbol(std::iter::once((rva, last)).collect(), "");
+            write!(
+                writer,
+                "{}",
+                StreamedSymbols(&symbols.split_off(&(rva + 1)))
+            )?;
+        }
+
+        if options.emit.cfi {
+            let pe = self
+                .pe_buf
+                .as_deref()
+                .and_then(|buf| PeObject::parse(buf).ok());
+            write!(writer, "{}", get_stack_info(Some(self.buf), pe))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Dumpable for PDBStream<'_> {
+    fn dump<W: Write>(&self, writer: W) -> common::Result<()> {
+        self.write_records(writer)
+    }
+
+    fn get_debug_id(&self) -> &str {
+        &self.debug_id
+    }
+
+    fn get_name(&self) -> &str {
+        &self.pdb_name
+    }
+
+    fn get_source_files(&self) -> &[String] {
+        &self.files
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        Vec::new()
+    }
+}
+
 pub(crate) struct PEInfo {
     symbols: PDBSymbols,
     cpu: Cpu,
@@ -618,27 +1638,45 @@ impl Display for PEInfo {
 }
 
 impl PEInfo {
-    pub fn new(pe_name: &str, pe: PeObject) -> Result<Self> {
+    pub fn new(pe_name: &str, pe: PeObject, options: &DumpOptions) -> Result<Self> {
+        let start = Instant::now();
         let cpu = match pe.arch() {
             Arch::X86 => Cpu::X86,
             Arch::X86Unknown => Cpu::X86,
             Arch::Amd64 => Cpu::X86_64,
             Arch::Amd64h => Cpu::X86_64,
             Arch::Amd64Unknown => Cpu::X86_64,
+            Arch::Arm64 => Cpu::Arm64,
             _ => Cpu::Unknown,
         };
         let pdb_name = pe.debug_file_name().unwrap_or_default().to_string();
         let pdb_name = PEInfo::file_name_only(&pdb_name).to_string();
 
-        let debug_id = get_pe_debug_id(Some(&pe)).unwrap();
-        let code_id = Some(pe.code_id().unwrap().as_str().to_uppercase());
+        let debug_id = get_pe_debug_id(Some(&pe)).unwrap_or_default();
+        let code_id = pe.code_id().map(|code_id| code_id.as_str().to_uppercase());
         let symbols = crate::windows::symbol::symbolic_to_pdb_symbols(
             pe.symbols(),
             pe.exception_data(),
             pe_name,
+            !options.no_demangle_publics,
         );
+        let names_time = start.elapsed();
         let symbols = crate::windows::symbol::append_dummy_symbol(symbols, pe_name);
-        let stack = get_stack_info(None, Some(pe));
+        let stack = if options.emit.cfi {
+            get_stack_info(None, Some(pe))
+        } else {
+            String::new()
+        };
+
+        if options.stats {
+            crate::windows::symbol::print_stats(
+                pe_name,
+                &symbols,
+                !options.no_demangle_publics,
+                names_time,
+                start.elapsed(),
+            );
+        }
 
         Ok(PEInfo {
             symbols,
@@ -674,6 +1712,15 @@ impl Dumpable for PEInfo {
 
         &self.pdb_name
     }
+
+    fn get_source_files(&self) -> &[String] {
+        // No FILE records
+        &[]
+    }
+
+    fn get_provenance(&self) -> Vec<RecordProvenance<'_>> {
+        get_provenance(&self.symbols, &self.stack, &[])
+    }
 }
 
 impl Mergeable for PEInfo {
@@ -738,11 +1785,20 @@ mod tests {
             &pe_buf,
             crate::cache::get_sym_servers(Some(&format!("SRV*~/symcache*{}", MS))).as_ref(),
         )
+        .unwrap()
         .unwrap();
 
         let mut output = Vec::new();
         let cursor = Cursor::new(&mut output);
-        let pdb = PDBInfo::new(&pdb_buf, &pdb_name, name, Some(pe), None).unwrap();
+        let pdb = PDBInfo::new(
+            &pdb_buf,
+            &pdb_name,
+            name,
+            Some(pe),
+            None,
+            &DumpOptions::default(),
+        )
+        .unwrap();
         pdb.dump(cursor).unwrap();
 
         let toks: Vec<_> = name.rsplitn(2, '.').collect();
@@ -751,6 +1807,14 @@ mod tests {
     }
 
     fn get_new_bp(file_name: &str, mapping: Option<Arc<PathMappings>>) -> Vec<u8> {
+        get_new_bp_with_options(file_name, mapping, &DumpOptions::default())
+    }
+
+    fn get_new_bp_with_options(
+        file_name: &str,
+        mapping: Option<Arc<PathMappings>>,
+        options: &DumpOptions,
+    ) -> Vec<u8> {
         let path = PathBuf::from("./test_data/windows");
         let mut path = path.join(file_name);
 
@@ -764,22 +1828,33 @@ mod tests {
             &pe_buf,
             crate::cache::get_sym_servers(Some(&format!("SRV*~/symcache*{}", MS))).as_ref(),
         )
+        .unwrap()
         .unwrap_or_else(|| (PeObject::parse(&pe_buf).unwrap(), vec![], "".to_string()));
 
         let mut output = Vec::new();
         let cursor = Cursor::new(&mut output);
 
         if pdb_buf.is_empty() {
-            let pe = PEInfo::new(file_name, pe).unwrap();
+            let pe = PEInfo::new(file_name, pe, &DumpOptions::default()).unwrap();
             pe.dump(cursor).unwrap();
         } else {
-            let pdb = PDBInfo::new(&pdb_buf, &pdb_name, file_name, Some(pe), mapping).unwrap();
+            let pdb =
+                PDBInfo::new(&pdb_buf, &pdb_name, file_name, Some(pe), mapping, options).unwrap();
             pdb.dump(cursor).unwrap();
         }
 
         output
     }
 
+    /// Dump basic64.pdb alone (without its dll)
+    fn dump_basic64(options: &DumpOptions) -> String {
+        let pdb_buf = crate::utils::read_file(PathBuf::from("./test_data/windows/basic64.pdb"));
+        let pdb = PDBInfo::new(&pdb_buf, "basic64.pdb", "", None, None, options).unwrap();
+        let mut output = Vec::new();
+        pdb.dump(Cursor::new(&mut output)).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
     fn get_data(file_name: &str) -> Vec<u8> {
         let path = PathBuf::from("./test_data/windows");
         let mut path = path.join(file_name);
@@ -993,7 +2068,9 @@ mod tests {
 
         let file_map_old = old.file_map();
         let file_map_new = new.file_map();
-        let files_old: Vec<_> = file_map_old.values().collect();
+        // The FILE records are sorted by name
+        let mut files_old: Vec<_> = file_map_old.values().collect();
+        files_old.sort();
         let files_new: Vec<_> = file_map_new.values().collect();
 
         assert_eq!(files_new, files_old, "Not the same files");
@@ -1075,11 +2152,126 @@ mod tests {
         test_file("basic64", TestFlags::ALL);
     }
 
+    #[test]
+    fn test_compat_breakpad() {
+        let options = DumpOptions {
+            compat: Some(Compat::Breakpad),
+            ..Default::default()
+        };
+        let out = dump_basic64(&options);
+        let new = BreakpadObject::parse(out.as_bytes()).unwrap();
+        let out = get_data("basic64");
+        let old = BreakpadObject::parse(&out).unwrap();
+
+        // The FILE records are in the same order (but DIA's ids start at 1 and have some holes)
+        let files_new: Vec<_> = new.file_records().map(|f| f.unwrap().name).collect();
+        let files_old: Vec<_> = old.file_records().map(|f| f.unwrap().name).collect();
+        assert_eq!(files_new, files_old);
+
+        // DIA drops the top-level const of a parameter passed by value, writes the arrays with
+        // their dimensions and the destructors of the templates without their arguments
+        let known_diffs = [
+            "test5(",
+            "test_array(",
+            "test_array_empty_struct(",
+            "::~basic_string(",
+        ];
+        let names_old: HashMap<_, _> = old
+            .func_records()
+            .map(|f| f.unwrap())
+            .map(|f| (f.address, f.name))
+            .collect();
+        let mut count = 0;
+        for func in new.func_records().map(|f| f.unwrap()) {
+            // The functions of basic.cpp: the other ones are from the CRT
+            if func.address >= 0x6e50 || known_diffs.iter().any(|d| func.name.contains(d)) {
+                continue;
+            }
+            assert_eq!(Some(&func.name), names_old.get(&func.address));
+            count += 1;
+        }
+        assert_eq!(count, 19);
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let name = RawString::from(&b"foo\xffbar"[..]);
+        let mut collector = Collector::new(Cpu::X86, &DumpOptions::default());
+        collector.check_name(RawString::from("foo"));
+        collector.check_name(name);
+        assert!(collector.check_names().is_ok());
+
+        let options = DumpOptions {
+            strict_names: true,
+            ..Default::default()
+        };
+        let mut collector = Collector::new(Cpu::X86, &options);
+        collector.check_name(RawString::from("foo"));
+        assert!(collector.check_names().is_ok());
+        collector.check_name(name);
+        assert_eq!(
+            collector.check_names().unwrap_err().to_string(),
+            "the name foo\u{fffd}bar in the pdb isn't valid UTF-8"
+        );
+    }
+
+    #[test]
+    fn test_param_base() {
+        let mut data = vec![0u8; 28];
+        data[..2].copy_from_slice(&S_FRAMEPROC.to_le_bytes());
+        assert_eq!(get_param_base(&data), None);
+        data[26] = 0x2;
+        assert_eq!(get_param_base(&data), Some(Register(22)));
+        data[26] = 0x3;
+        assert_eq!(get_param_base(&data), Some(Register(20)));
+        assert_eq!(get_param_base(&data[..20]), None);
+    }
+
     #[test]
     fn test_basic_opt32() {
         test_file("basic-opt32", TestFlags::ALL);
     }
 
+    #[test]
+    fn test_param_size() {
+        let get_param_sizes = |options: &DumpOptions| {
+            let out = get_new_bp_with_options("basic-opt32.dll", None, options);
+            let sym = BreakpadObject::parse(&out).unwrap();
+            sym.func_records()
+                .map(|f| f.unwrap())
+                .map(|f| {
+                    (
+                        f.name.split('(').next().unwrap().to_string(),
+                        f.parameter_size,
+                    )
+                })
+                .collect::<FxHashMap<_, _>>()
+        };
+
+        // No ebp-relative parameter: Google's dump_syms writes 0
+        let sizes = get_param_sizes(&DumpOptions::default());
+        for name in &["_87except", "_except1", "_except2", "wcschr", "test1"] {
+            assert_eq!(sizes[*name], 0);
+        }
+
+        let sizes = get_param_sizes(&DumpOptions {
+            x86_param_sizes: true,
+            ..Default::default()
+        });
+        // The parameters are above the base register given in S_FRAMEPROC
+        assert_eq!(sizes["_87except"], 0xc);
+        assert_eq!(sizes["_except1"], 0x1c);
+        assert_eq!(sizes["_except2"], 0x24);
+        assert_eq!(sizes["wcschr"], 0x8);
+        // __cdecl: test1(int *), test2(int, unsigned int), test3(int, unsigned int, short) and
+        // test5(int *, unsigned int, unsigned short, double const *, std::string) with a 24 bytes
+        // std::string
+        assert_eq!(sizes["test1"], 0x4);
+        assert_eq!(sizes["test2"], 0x8);
+        assert_eq!(sizes["test3"], 0xc);
+        assert_eq!(sizes["test5"], 0x28);
+    }
+
     #[test]
     fn test_basic_opt64() {
         test_file("basic-opt64", TestFlags::ALL);
@@ -1111,6 +2303,455 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_address_report() {
+        let path = PathBuf::from("./test_data/windows/basic32-min.pdb");
+        let pdb_buf = crate::utils::read_file(&path);
+        let options = DumpOptions {
+            address_report: Some("-".to_string()),
+            ..Default::default()
+        };
+        let pdb = PDBInfo::new(&pdb_buf, "basic32-min.pdb", "", None, None, &options).unwrap();
+
+        let mut output = Vec::new();
+        pdb.dump_address_report(Cursor::new(&mut output)).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[0], "ab4f -> ab4f __scrt_exe_initialize_mta");
+        assert_eq!(lines[1], "  folded ___scrt_stub_for_initialize_mta");
+        assert_eq!(lines[10], "534f0 -> 534f0 _alloca_probe");
+        assert_eq!(lines[11], "  folded __chkstk");
+    }
+
+    #[test]
+    fn test_public_precedence() {
+        let path = PathBuf::from("./test_data/windows/basic-opt32.pdb");
+        let pdb_buf = crate::utils::read_file(&path);
+        let dump = |public_precedence| {
+            let options = DumpOptions {
+                public_precedence,
+                ..Default::default()
+            };
+            let pdb = PDBInfo::new(&pdb_buf, "basic-opt32.pdb", "", None, None, &options).unwrap();
+            let mut output = Vec::new();
+            pdb.dump(Cursor::new(&mut output)).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let get_rvas = |output: &str, kind: &str| -> HashSet<u32> {
+            output
+                .lines()
+                .filter_map(|l| l.strip_prefix(kind))
+                .map(|l| l.trim_start_matches("m "))
+                .map(|l| u32::from_str_radix(l.split(' ').next().unwrap(), 16).unwrap())
+                .collect()
+        };
+
+        let func = dump(PublicPrecedence::Func);
+        let public = dump(PublicPrecedence::Public);
+        let func_funcs = get_rvas(&func, "FUNC ");
+        let func_publics = get_rvas(&func, "PUBLIC ");
+        let public_funcs = get_rvas(&public, "FUNC ");
+        let public_publics = get_rvas(&public, "PUBLIC ");
+
+        // No duplicates in both cases
+        assert!(func_funcs.is_disjoint(&func_publics));
+        assert!(public_funcs.is_disjoint(&public_publics));
+
+        // The functions with a public have been replaced
+        let replaced: HashSet<_> = public_publics.difference(&func_publics).collect();
+        assert!(!replaced.is_empty());
+        assert!(replaced.iter().all(|rva| func_funcs.contains(rva)));
+        assert_eq!(public_funcs.len() + replaced.len(), func_funcs.len());
+
+        // A decorated stdcall name gives the parameter size
+        assert!(public.contains("PUBLIC a03b 4 __scrt_unhandled_exception_filter\n"));
+    }
+
+    #[test]
+    fn test_data_publics() {
+        let dump = |data_publics| {
+            dump_basic64(&DumpOptions {
+                data_publics,
+                ..Default::default()
+            })
+        };
+
+        // The vftable of DNameNode in .rdata
+        assert!(!dump(false).contains("PUBLIC 6dc80 0 "));
+        assert!(dump(true).contains("PUBLIC 6dc80 0 "));
+    }
+
+    #[test]
+    fn test_fragments() {
+        // The fields of a procedure (and its flags) are taken from a real one
+        fn get_procedure<'a>(module_info: &'a ModuleInfo) -> Option<ProcedureSymbol<'a>> {
+            module_info
+                .symbols()
+                .unwrap()
+                .find_map(|s| {
+                    Ok(match s.parse() {
+                        Ok(SymbolData::Procedure(procedure)) => Some(procedure),
+                        _ => None,
+                    })
+                })
+                .unwrap()
+        }
+
+        let file = File::open("./test_data/windows/basic64.pdb").unwrap();
+        let mut pdb = PDB::open(file).unwrap();
+        let address_map = pdb.address_map().unwrap();
+        let dbi = pdb.debug_information().unwrap();
+        let mut modules = dbi.modules().unwrap();
+        let module_info = loop {
+            let module = modules.next().unwrap().unwrap();
+            if let Some(info) = pdb.module_info(&module).unwrap() {
+                if get_procedure(&info).is_some() {
+                    break info;
+                }
+            }
+        };
+        let procedure = get_procedure(&module_info).unwrap();
+
+        // No files so no lines
+        let file_ids = RefToIds::new();
+        let lines = SourceLineCollector::new(
+            &address_map,
+            &file_ids,
+            module_info.line_program().unwrap(),
+            Columns::new(),
+        )
+        .unwrap();
+
+        let proc = |name| ProcedureSymbol {
+            name: RawString::from(name),
+            ..procedure
+        };
+        let block = |rva, len| BlockInfo {
+            rva,
+            offset: PdbInternalSectionOffset::new(1, rva),
+            len,
+        };
+
+        let mut symbols = RvaSymbols::default();
+        symbols.add_procedure_symbol(&lines, proc("foo"), block(0x1000, 0x20));
+        // Out of order
+        symbols.add_fragment(&lines, proc("foo"), block(0x3000, 0x10));
+        symbols.add_fragment(&lines, proc("foo"), block(0x2000, 0x10));
+        // Adjacent
+        symbols.add_fragment(&lines, proc("foo"), block(0x2010, 0x8));
+        // Overlapping: the same part of two folded functions
+        symbols.add_fragment(&lines, proc("bar"), block(0x2000, 0x10));
+
+        // The parent is still open
+        assert_eq!(symbols.get_symbol_at(0x1000).unwrap().name, "foo");
+        symbols.close_procedure();
+
+        let mut rvas = symbols.get_rvas();
+        rvas.sort_unstable();
+        assert_eq!(rvas, vec![0x1000, 0x2000, 0x2010, 0x3000]);
+
+        let get = |rva| {
+            let sym = symbols.get_symbol_at(rva).unwrap();
+            (sym.name.as_str(), sym.len, sym.folded.clone())
+        };
+        assert_eq!(get(0x1000), ("foo", 0x20, vec![]));
+        assert_eq!(get(0x2000), ("bar", 0x10, vec!["foo".to_string()]));
+        assert_eq!(get(0x2010), ("foo", 0x8, vec![]));
+        assert_eq!(get(0x3000), ("foo", 0x10, vec![]));
+        assert!(symbols.get_symbol_at(0x2008).is_none());
+    }
+
+    #[test]
+    fn test_fix_sizes() {
+        let func = |rva, len| symbol::PDBSymbol {
+            rva,
+            len,
+            ..Default::default()
+        };
+        let mut symbols: PDBSymbols = vec![
+            (0x1000, func(0x1000, 0)),
+            (0x1020, func(0x1020, 0)),
+            (0x1100, func(0x1100, 0x1000)),
+            (0x1200, func(0x1200, 0x10)),
+            (0x2000, func(0x2000, 0)),
+        ]
+        .into_iter()
+        .collect();
+        symbol::fix_sizes(&mut symbols, &[(0x1000, 0x1040), (0x1100, 0x1180)]);
+
+        let sizes: Vec<_> = symbols.values().map(|s| (s.rva, s.len)).collect();
+        assert_eq!(
+            sizes,
+            vec![
+                // Up to the next symbol
+                (0x1000, 0x20),
+                // Up to the end of the contribution
+                (0x1020, 0x20),
+                // Beyond the next symbol and the contribution
+                (0x1100, 0x80),
+                (0x1200, 0x10),
+                // Nothing after
+                (0x2000, 0),
+            ]
+        );
+
+        // The sizes going beyond the address space are clamped
+        let mut symbols: PDBSymbols = vec![
+            (0x3000, func(0x3000, u32::MAX)),
+            (0x4000, func(0x4000, u32::MAX)),
+            (0xffff_f000, func(0xffff_f000, u32::MAX)),
+        ]
+        .into_iter()
+        .collect();
+        symbol::fix_sizes(&mut symbols, &[(0x4000, 0x4100)]);
+
+        let sizes: Vec<_> = symbols.values().map(|s| (s.rva, s.len)).collect();
+        assert_eq!(
+            sizes,
+            vec![(0x3000, 0x1000), (0x4000, 0x100), (0xffff_f000, 0xfff)]
+        );
+    }
+
+    #[test]
+    fn test_module_jobs() {
+        for name in &["basic64.pdb", "basic-opt32.pdb"] {
+            let path = PathBuf::from("./test_data/windows").join(name);
+            let pdb_buf = crate::utils::read_file(&path);
+            let dump = |module_jobs| {
+                let options = DumpOptions {
+                    module_jobs,
+                    ..Default::default()
+                };
+                let pdb = PDBInfo::new(&pdb_buf, name, "", None, None, &options).unwrap();
+                let mut output = Vec::new();
+                pdb.dump(Cursor::new(&mut output)).unwrap();
+                output
+            };
+
+            // The modules collected apart are merged in order
+            assert_eq!(dump(1), dump(4));
+        }
+    }
+
+    #[test]
+    fn test_cancel_token() {
+        let path = PathBuf::from("./test_data/windows/basic64.pdb");
+        let pdb_buf = crate::utils::read_file(&path);
+        let options = DumpOptions {
+            module_jobs: 2,
+            ..Default::default()
+        };
+        options.cancel.cancel();
+
+        let error = PDBInfo::new(&pdb_buf, "basic64.pdb", "", None, None, &options)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Interrupted"));
+        // Only the dumps with this token are cancelled
+        assert!(!crate::cancel::is_cancelled());
+        assert!(PDBInfo::new(
+            &pdb_buf,
+            "basic64.pdb",
+            "",
+            None,
+            None,
+            &DumpOptions::default()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_stream() {
+        for name in &["basic64.pdb", "basic-opt32.pdb"] {
+            let path = PathBuf::from("./test_data/windows").join(name);
+            let pdb_buf = crate::utils::read_file(&path);
+            let options = DumpOptions::default();
+            let sorted = |mut output: Vec<u8>| {
+                output.pop();
+                let mut lines: Vec<_> = output.split(|c| *c == b'\n').map(Vec::from).collect();
+                lines.sort();
+                lines
+            };
+
+            let mut pdb = PDBInfo::new(&pdb_buf, name, "", None, None, &options).unwrap();
+            super::super::utils::try_to_set_pe(&path, &[], |pe_name, pe| {
+                pdb.set_pe(pe_name, pe, &pdb_buf)
+            });
+            let mut output = Vec::new();
+            pdb.dump(Cursor::new(&mut output)).unwrap();
+
+            let stream = PDBStream::new(&pdb_buf, &path, name, None, &options).unwrap();
+            let mut streamed = Vec::new();
+            stream.dump(Cursor::new(&mut streamed)).unwrap();
+
+            // Only the order of the records differs
+            assert_eq!(sorted(output), sorted(streamed));
+        }
+    }
+
+    #[test]
+    fn test_stream_file_names() {
+        let path = PathBuf::from("./test_data/windows/basic64.pdb");
+        let pdb_buf = crate::utils::read_file(&path);
+        let options = DumpOptions::default();
+        let mut stream = PDBStream::new(&pdb_buf, &path, "basic64.pdb", None, &options).unwrap();
+        stream.files[0] = "foo\nbar.cpp".to_string();
+
+        let mut output = Vec::new();
+        stream.dump(Cursor::new(&mut output)).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        // The control chars are escaped as in the other dumps
+        assert!(output.contains("\nFILE 0 foo\\x0abar.cpp\nFILE 1 "));
+    }
+
+    #[test]
+    fn test_thunk_names() {
+        assert_eq!(
+            symbol::get_ilt_target(b"@ILT+1234(?foo@@YAXXZ)"),
+            Some(&b"?foo@@YAXXZ"[..])
+        );
+        assert_eq!(symbol::get_ilt_target(b"ILT+0(_main)"), Some(&b"_main"[..]));
+        assert_eq!(symbol::get_ilt_target(b"@ILT+(foo)"), Some(&b"foo"[..]));
+        assert_eq!(symbol::get_ilt_target(b"ILT_foo"), None);
+
+        let output = dump_basic64(&DumpOptions {
+            thunk_names: ThunkNames::Annotate,
+            ..Default::default()
+        });
+
+        // The import thunks (S_THUNK32)
+        assert!(output.contains("PUBLIC b110 0 RtlCaptureContext [thunk]\n"));
+        assert!(output.contains(" test1(int*)\n"));
+    }
+
+    #[test]
+    fn test_parameter_names() {
+        let output = dump_basic64(&DumpOptions {
+            parameter_names: true,
+            ..Default::default()
+        });
+
+        assert!(output.contains(" test4(int x, unsigned int y, unsigned short z, double* t)\n"));
+        // The unnamed parameters have only their type
+        assert!(output.contains(" test7(void (*)(int, unsigned int, unsigned short, double*))\n"));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let path = PathBuf::from("./test_data/windows/basic32-min.pdb");
+        let pdb_buf = crate::utils::read_file(&path);
+        let pdb = PDBInfo::new(
+            &pdb_buf,
+            "basic32-min.pdb",
+            "",
+            None,
+            None,
+            &DumpOptions::default(),
+        )
+        .unwrap();
+        let records = pdb.get_provenance();
+        let (symbols, stack): (Vec<_>, Vec<_>) = records
+            .iter()
+            .partition(|r| r.record == "PUBLIC" || r.record == "FUNC");
+
+        // The STACK records have their own provenance: they're checked below
+        assert_eq!(stack.len(), common::get_stack_ranges(&pdb.stack).len());
+        for record in stack {
+            let expected = if record.record == "STACK WIN" {
+                Provenance::PdbFrameData
+            } else {
+                Provenance::PeExceptionData
+            };
+            assert_eq!(record.name, None);
+            assert_eq!(record.provenance, expected);
+        }
+
+        assert_eq!(symbols.len(), pdb.symbols.len());
+        for record in symbols {
+            let sym = &pdb.symbols[&record.address];
+            let expected = if sym.name == "<unknown>" {
+                Provenance::Heuristic
+            } else if sym.is_public {
+                Provenance::PdbPublics
+            } else {
+                Provenance::PdbSymbols
+            };
+            assert_eq!(record.provenance, expected);
+        }
+
+        let stack =
+            "STACK WIN 4 1000 20 6 0 4 0 0 0 1 $T0 .raSearch = $eip $T0 ^ = $esp $T0 4 + =\n\
+                     STACK WIN 0 2000 10 3 0 0 0 0 0 1\n\
+                     STACK CFI INIT 3000 10 .cfa: $rsp 8 + .ra: .cfa -8 + ^\n\
+                     STACK CFI 3004 .cfa: $rsp 16 +\n";
+        let symbols = PDBSymbols::default();
+        let records = get_provenance(&symbols, stack, &[(0x2000, 0x10)]);
+        let records: Vec<_> = records
+            .iter()
+            .map(|r| (r.record, r.address, r.size, r.provenance))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                ("STACK WIN", 0x1000, 0x20, Provenance::PdbFrameData),
+                ("STACK WIN", 0x2000, 0x10, Provenance::Heuristic),
+                ("STACK CFI", 0x3000, 0x10, Provenance::PeExceptionData),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_checksums() {
+        let output = dump_basic64(&DumpOptions {
+            file_checksums: true,
+            ..Default::default()
+        });
+        let files: Vec<_> = output.lines().filter(|l| l.starts_with("FILE ")).collect();
+
+        assert!(!files.is_empty());
+        for file in files {
+            let checksum = file.rsplit(' ').next().unwrap();
+            let (kind, hex) = checksum.split_once(':').unwrap();
+            let len = match kind {
+                "md5" => 32,
+                "sha1" => 40,
+                "sha256" => 64,
+                _ => panic!("Invalid checksum in {}", file),
+            };
+            assert_eq!(hex.len(), len);
+            assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+    }
+
+    #[test]
+    fn test_normalize_paths() {
+        let get_files = |options: &DumpOptions| {
+            dump_basic64(options)
+                .lines()
+                .filter_map(|l| l.strip_prefix("FILE "))
+                .map(|l| l.split_once(' ').unwrap().1.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let files = get_files(&DumpOptions::default());
+        let normalized = get_files(&DumpOptions {
+            normalize_paths: true,
+            fold_path_case: true,
+            ..Default::default()
+        });
+
+        assert!(!normalized.is_empty());
+        assert!(normalized.len() <= files.len());
+        assert!(normalized.windows(2).all(|w| w[0] < w[1]));
+        for file in normalized {
+            assert_eq!(file, file.to_lowercase());
+            assert!(!file.contains('/') && !file.contains("\\..\\"));
+        }
+    }
+
     #[test]
     fn test_win_mapping() {
         let mapping = PathMappings::new(
@@ -1118,6 +2759,7 @@ mod tests {
             &Some(vec![r"d:\\agent\\_work\\3\\s\\src\\(.*)"]),
             &Some(vec!["https://source/{rev}/{1}"]),
             &None,
+            &None,
         )
         .unwrap();
         let dll = "basic32.dll";
@@ -1127,17 +2769,19 @@ mod tests {
         let map = bp.file_map();
         let files: Vec<_> = map.values().collect();
 
-        assert_eq!(
-            files[6].replace('\\', "/"),
-            "https://source/abcdef/externalapis/windows/10/sdk/inc/winbase.h"
-        );
-        assert_eq!(
-            files[7].replace('\\', "/"),
-            "https://source/abcdef/externalapis/windows/10/sdk/inc/winerror.h"
-        );
-        assert_eq!(
-            files[files.len() - 1].replace('\\', "/"),
-            "https://source/abcdef/vctools/crt/vcruntime/src/string/i386/memcmp.c"
-        );
+        // The FILE records are sorted by name
+        let mut sorted = files.clone();
+        sorted.sort();
+        assert_eq!(files, sorted);
+
+        let files: Vec<_> = files.iter().map(|f| f.replace('\\', "/")).collect();
+
+        for file in &[
+            "https://source/abcdef/externalapis/windows/10/sdk/inc/winbase.h",
+            "https://source/abcdef/externalapis/windows/10/sdk/inc/winerror.h",
+            "https://source/abcdef/vctools/crt/vcruntime/src/string/i386/memcmp.c",
+        ] {
+            assert!(files.iter().any(|f| f == file), "{} is missing", file);
+        }
     }
 }
@@ -0,0 +1,102 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use failure::Fail;
+use std::borrow::Cow;
+use symbolic::debuginfo::dwarf::{Dwarf, DwarfDebugSession, DwarfSection, Endian};
+use symbolic::debuginfo::pe::PeObject;
+
+use crate::common;
+
+/*
+ The GCC and Clang MinGW (or Cygwin) toolchains don't produce any pdb: the DWARF sections are in
+ the PE itself (their names are longer than 8 bytes so they're in the COFF string table).
+ The addresses in the DWARF are virtual addresses so the image base is subtracted to get rvas.
+*/
+
+/// The DWARF sections of a PE
+pub(crate) struct PeDwarf<'a, 'data> {
+    pe: &'a PeObject<'data>,
+}
+
+impl<'a, 'data> PeDwarf<'a, 'data> {
+    pub(crate) fn new(pe: &'a PeObject<'data>) -> Self {
+        Self { pe }
+    }
+
+    /// Check if the PE contains some DWARF debug info
+    pub(crate) fn has_debug_info(&self) -> bool {
+        self.has_section("debug_info")
+    }
+
+    pub(crate) fn debug_session(&self) -> common::Result<DwarfDebugSession<'data>> {
+        DwarfDebugSession::parse(
+            self,
+            self.pe.symbol_map(),
+            self.pe.load_address() as i64,
+            self.pe.kind(),
+        )
+        .map_err(|e| e.compat().into())
+    }
+}
+
+/// Check if the buffer is a PE containing some DWARF debug info
+pub(crate) fn has_dwarf(buf: &[u8]) -> bool {
+    match PeObject::parse(buf) {
+        Ok(pe) => PeDwarf::new(&pe).has_debug_info(),
+        _ => false,
+    }
+}
+
+impl<'a, 'data> Dwarf<'data> for PeDwarf<'a, 'data> {
+    fn endianity(&self) -> Endian {
+        Endian::Little
+    }
+
+    fn raw_section(&self, name: &str) -> Option<DwarfSection<'data>> {
+        let data = self.pe.data();
+        let section = self
+            .pe
+            .sections()
+            .iter()
+            .find(|s| matches!(s.name(), Ok(n) if n.strip_prefix('.') == Some(name)))?;
+
+        // The raw size is rounded up to the file alignment
+        let size = if section.virtual_size == 0 {
+            section.size_of_raw_data
+        } else {
+            section.virtual_size.min(section.size_of_raw_data)
+        };
+        let start = section.pointer_to_raw_data as usize;
+        let data = data.get(start..start.checked_add(size as usize)?)?;
+
+        Some(DwarfSection {
+            address: self.pe.load_address() + u64::from(section.virtual_address),
+            offset: u64::from(section.pointer_to_raw_data),
+            align: 4096,
+            data: Cow::Borrowed(data),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_pe_without_dwarf() {
+        let path = PathBuf::from("./test_data/windows/basic64.dll");
+        let buf = crate::utils::read_file(&path);
+        let pe = PeObject::parse(&buf).unwrap();
+        let dwarf = PeDwarf::new(&pe);
+
+        assert!(!dwarf.has_debug_info());
+        assert!(!has_dwarf(&buf));
+        assert!(dwarf.raw_section("text").is_some());
+        assert!(dwarf.raw_section("debug_line").is_none());
+    }
+}
@@ -0,0 +1,195 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use symbolic::debuginfo::pe::PeObject;
+
+use super::symbol::PDBSymbols;
+use crate::common;
+
+// push ebp; mov ebp, esp (both encodings of the mov)
+const X86_PROLOGUES: &[&[u8]] = &[&[0x55, 0x8b, 0xec], &[0x55, 0x89, 0xe5]];
+// mov edi, edi: the 2-byte nop at the beginning of the hotpatchable functions (/hotpatch)
+const X86_HOTPATCH: &[u8] = &[0x8b, 0xff];
+// push rbp; mov rbp, rsp
+const X86_64_PROLOGUES: &[&[u8]] = &[&[0x55, 0x48, 0x8b, 0xec], &[0x55, 0x48, 0x89, 0xe5]];
+
+/// The functions with some unwind info: start -> end
+#[derive(Debug, Default)]
+struct Covered(BTreeMap<u32, u32>);
+
+impl Covered {
+    fn new(stack: &str) -> Self {
+        let mut covered = Self::default();
+        for (_, rva, size) in common::get_stack_ranges(stack) {
+            let end = rva.saturating_add(size);
+            let e = covered.0.entry(rva).or_insert(end);
+            *e = (*e).max(end);
+        }
+        covered
+    }
+
+    fn contains(&self, rva: u32) -> bool {
+        matches!(self.0.range(..=rva).next_back(), Some((_, end)) if rva < *end)
+    }
+}
+
+fn get_code<'a>(pe: &PeObject<'a>, rva: u32, len: usize) -> Option<&'a [u8]> {
+    let data = pe.data();
+    pe.sections().iter().find_map(|section| {
+        let start = section.virtual_address;
+        let size = section.virtual_size.max(section.size_of_raw_data);
+        if rva < start || rva - start >= size {
+            return None;
+        }
+
+        let offset = (rva - start) as usize;
+        if offset + len > section.size_of_raw_data as usize {
+            return None;
+        }
+        let offset = section.pointer_to_raw_data as usize + offset;
+        data.get(offset..offset + len)
+    })
+}
+
+/// Get the length of the frame pointer setup at the beginning of the code if any
+fn get_prologue_size(code: &[u8], is_64: bool) -> Option<u32> {
+    let (prologues, code, hotpatch) = if is_64 {
+        (X86_64_PROLOGUES, code, 0)
+    } else if let Some(code) = code.strip_prefix(X86_HOTPATCH) {
+        (X86_PROLOGUES, code, X86_HOTPATCH.len() as u32)
+    } else {
+        (X86_PROLOGUES, code, 0)
+    };
+    prologues
+        .iter()
+        .find(|p| code.starts_with(p))
+        .map(|p| hotpatch + p.len() as u32)
+}
+
+/// The maximal length of the prologues
+fn get_max_prologue_size(is_64: bool) -> usize {
+    if is_64 {
+        4
+    } else {
+        X86_HOTPATCH.len() + 3
+    }
+}
+
+fn write_stack(
+    stack: &mut String,
+    rva: u32,
+    len: u32,
+    prologue_size: u32,
+    parameter_size: u32,
+    is_64: bool,
+) {
+    if is_64 {
+        // Before the push, after the push and once rbp is set
+        writeln!(
+            stack,
+            "STACK CFI INIT {:x} {:x} .cfa: $rsp 8 + .ra: .cfa -8 + ^",
+            rva, len
+        )
+        .unwrap();
+        writeln!(
+            stack,
+            "STACK CFI {:x} .cfa: $rsp 16 + $rbp: .cfa -16 + ^",
+            rva + 1
+        )
+        .unwrap();
+        writeln!(stack, "STACK CFI {:x} .cfa: $rbp 16 +", rva + 4).unwrap();
+    } else {
+        // A FPO record for a function which allocates a base pointer
+        writeln!(
+            stack,
+            "STACK WIN 0 {:x} {:x} {:x} 0 {:x} 0 0 0 0 1",
+            rva, len, prologue_size, parameter_size
+        )
+        .unwrap();
+    }
+}
+
+/// Scan the prologues of the functions without unwind info and write some
+/// conservative STACK records for the ones setting up a frame pointer.
+/// The returned vector contains the (rva, len) of the functions which have been handled.
+pub(super) fn scan_prologues(
+    pe_buf: &[u8],
+    symbols: &PDBSymbols,
+    stack: &mut String,
+    is_64: bool,
+) -> Vec<(u32, u32)> {
+    let pe = match PeObject::parse(pe_buf) {
+        Ok(pe) => pe,
+        _ => return Vec::new(),
+    };
+    let covered = Covered::new(stack);
+    let mut heuristic = Vec::new();
+
+    for sym in symbols.values() {
+        if sym.is_public || sym.len == 0 || covered.contains(sym.rva) {
+            continue;
+        }
+
+        // The function can be shorter than the longest prologue
+        let max_len = get_max_prologue_size(is_64).min(sym.len as usize);
+        let code = match get_code(&pe, sym.rva, max_len) {
+            Some(code) => code,
+            _ => continue,
+        };
+
+        if let Some(prologue_size) = get_prologue_size(code, is_64) {
+            if prologue_size < sym.len {
+                write_stack(
+                    stack,
+                    sym.rva,
+                    sym.len,
+                    prologue_size,
+                    sym.parameter_size,
+                    is_64,
+                );
+                heuristic.push((sym.rva, sym.len));
+            }
+        }
+    }
+
+    heuristic
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_prologue() {
+        assert_eq!(get_prologue_size(&[0x55, 0x8b, 0xec, 0x83], false), Some(3));
+        assert_eq!(get_prologue_size(&[0x55, 0x89, 0xe5], false), Some(3));
+        assert_eq!(get_prologue_size(&[0x8b, 0xff, 0x55], false), None);
+        assert_eq!(
+            get_prologue_size(&[0x8b, 0xff, 0x55, 0x8b, 0xec], false),
+            Some(5)
+        );
+        assert_eq!(
+            get_prologue_size(&[0x8b, 0xff, 0x55, 0x48, 0x8b, 0xec], true),
+            None
+        );
+        assert_eq!(get_prologue_size(&[0x55, 0x48, 0x8b, 0xec], true), Some(4));
+        assert_eq!(get_prologue_size(&[0x55, 0x8b, 0xec], true), None);
+
+        let covered = Covered::new(
+            "STACK WIN 4 1000 20 6 0 4 0 0 0 1 $T0 .raSearch = $eip $T0 ^ = $esp $T0 4 + =\n\
+             STACK CFI INIT 2000 10 .cfa: $rsp 8 + .ra: .cfa -8 + ^\n\
+             STACK CFI 2004 .cfa: $rsp 16 +\n",
+        );
+        assert!(covered.contains(0x1000));
+        assert!(covered.contains(0x101f));
+        assert!(!covered.contains(0x1020));
+        assert!(covered.contains(0x2004));
+        assert!(!covered.contains(0x2010));
+        assert!(!covered.contains(0xfff));
+    }
+}
@@ -6,8 +6,8 @@
 use hashbrown::{hash_map, HashMap};
 use log::error;
 use pdb::{
-    AddressMap, FallibleIterator, FileIndex, LineInfo, LineProgram, PdbInternalSectionOffset,
-    Result, Source, StringRef, StringTable, PDB,
+    AddressMap, FallibleIterator, FileChecksum, FileIndex, LineInfo, LineProgram,
+    PdbInternalSectionOffset, Result, Source, StringRef, StringTable, PDB,
 };
 use std::collections::BTreeMap;
 use std::fs;
@@ -15,24 +15,28 @@ use std::ops::Bound::{Excluded, Included};
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use super::columns::Columns;
 use crate::line::Lines;
 use crate::mapping::PathMappings;
 use crate::utils;
 
-type RefToIds = HashMap<StringRef, u32>;
+/// The ids of the files (in the FILE records) by their references in the string table
+pub(super) type RefToIds = HashMap<StringRef, u32>;
 
 pub(super) struct SourceLineCollector<'a, 's> {
     address_map: &'a AddressMap<'s>,
-    source_files: &'a SourceFiles<'s>,
+    file_ids: &'a RefToIds,
     lines: BTreeMap<(u16, u32), LineInfo>,
     line_program: LineProgram<'a>,
+    columns: Columns,
 }
 
 impl<'a, 's> SourceLineCollector<'a, 's> {
     pub(super) fn new(
         address_map: &'a AddressMap<'s>,
-        source_files: &'a SourceFiles<'s>,
+        file_ids: &'a RefToIds,
         line_program: LineProgram<'a>,
+        columns: Columns,
     ) -> Result<Self> {
         let mut source_lines = BTreeMap::default();
         let mut lines = line_program.lines();
@@ -49,9 +53,10 @@ impl<'a, 's> SourceLineCollector<'a, 's> {
 
         Ok(Self {
             address_map,
-            source_files,
+            file_ids,
             lines: source_lines,
             line_program,
+            columns,
         })
     }
 
@@ -66,14 +71,31 @@ impl<'a, 's> SourceLineCollector<'a, 's> {
         let mut last_file_index = FileIndex(std::u32::MAX);
         let mut last_file_id = 0;
 
+        // The lines which can't be mapped on an address or a file are skipped
         for (_, line) in self.lines.range((Included(&start), Excluded(&end))) {
-            let rva = line.offset.to_internal_rva(self.address_map).unwrap();
+            let rva = match line.offset.to_internal_rva(self.address_map) {
+                Some(rva) => rva,
+                None => continue,
+            };
             if last_file_index != line.file_index {
-                let file = self.line_program.get_file_info(line.file_index).unwrap();
+                let file_id = self
+                    .line_program
+                    .get_file_info(line.file_index)
+                    .ok()
+                    .and_then(|file| self.file_ids.get(&file.name));
+                match file_id {
+                    Some(file_id) => last_file_id = *file_id,
+                    None => continue,
+                }
                 last_file_index = line.file_index;
-                last_file_id = self.source_files.get_id(file.name);
             }
-            source_lines.add_line(rva.0, line.line_start, last_file_id);
+            // The columns given by the pdb crate are wrong (see columns.rs)
+            let col = self
+                .columns
+                .get(&(line.offset.section, line.offset.offset))
+                .copied()
+                .unwrap_or(0);
+            source_lines.add_line_with_column(rva.0, line.line_start, col, last_file_id);
         }
 
         source_lines
@@ -85,13 +107,33 @@ pub(super) struct SourceFiles<'a> {
     string_table: Option<StringTable<'a>>,
     ref_to_id: RefToIds,
     id_to_ref: Vec<StringRef>,
+    checksums: Vec<Option<String>>,
     mapping: Option<Arc<PathMappings>>,
+    normalize: bool,
+    fold_case: bool,
+}
+
+fn get_checksum(checksum: &FileChecksum) -> Option<String> {
+    let (kind, bytes) = match checksum {
+        FileChecksum::None => return None,
+        FileChecksum::Md5(bytes) => ("md5", bytes),
+        FileChecksum::Sha1(bytes) => ("sha1", bytes),
+        FileChecksum::Sha256(bytes) => ("sha256", bytes),
+    };
+    if bytes.is_empty() {
+        return None;
+    }
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Some(format!("{}:{}", kind, hex))
 }
 
 impl<'a> SourceFiles<'a> {
     pub(super) fn new<S: 'a + Source<'a>>(
         pdb: &mut PDB<'a, S>,
         mapping: Option<Arc<PathMappings>>,
+        normalize: bool,
+        fold_case: bool,
     ) -> Result<Self> {
         // The string table may be empty: not a problem
         let string_table = match pdb.string_table() {
@@ -101,7 +143,10 @@ impl<'a> SourceFiles<'a> {
                     string_table: None,
                     ref_to_id: RefToIds::default(),
                     id_to_ref: Vec::new(),
+                    checksums: Vec::new(),
                     mapping: None,
+                    normalize,
+                    fold_case,
                 })
             }
         };
@@ -110,11 +155,14 @@ impl<'a> SourceFiles<'a> {
         let mut modules = dbi.modules()?;
         let mut ref_to_id = RefToIds::default();
         let mut id_to_ref = Vec::new();
-        let mut id = 0;
+        let mut checksums = Vec::new();
+        let mut key_to_id: HashMap<(String, Option<String>), u32> = HashMap::default();
 
         // Get all source files and generate an unique id for each one.
         // According to the docs: https://docs.rs/pdb/0.5.0/pdb/struct.PDB.html#method.string_table
-        // the table contains deduplicated strings so each source file must have an unique StringRef.
+        // the table contains deduplicated strings but the modules don't always share the same
+        // StringRef for the same file (e.g. the paths are spelled differently or the table has
+        // duplicates): so the ids are keyed by (normalized) path and checksum.
         while let Some(module) = modules.next()? {
             let module_info = match pdb.module_info(&module)? {
                 Some(info) => info,
@@ -123,13 +171,25 @@ impl<'a> SourceFiles<'a> {
 
             let mut files = module_info.line_program()?.files();
             while let Some(file) = files.next()? {
-                match ref_to_id.entry(file.name) {
-                    hash_map::Entry::Occupied(_) => {}
-                    hash_map::Entry::Vacant(e) => {
-                        e.insert(id);
-                        id_to_ref.push(file.name);
-                        id += 1;
-                    }
+                if let hash_map::Entry::Vacant(e) = ref_to_id.entry(file.name) {
+                    let name = string_table
+                        .get(file.name)
+                        .map(|s| s.to_string().into_owned())
+                        .unwrap_or_default();
+                    let name = if normalize {
+                        super::utils::normalize_file_path(&name, fold_case).unwrap_or(name)
+                    } else {
+                        name
+                    };
+                    let checksum = get_checksum(&file.checksum);
+                    let id = *key_to_id
+                        .entry((name, checksum.clone()))
+                        .or_insert_with(|| {
+                            id_to_ref.push(file.name);
+                            checksums.push(checksum);
+                            id_to_ref.len() as u32 - 1
+                        });
+                    e.insert(id);
                 }
             }
         }
@@ -138,12 +198,15 @@ impl<'a> SourceFiles<'a> {
             string_table: Some(string_table),
             ref_to_id,
             id_to_ref,
+            checksums,
             mapping,
+            normalize,
+            fold_case,
         })
     }
 
-    pub(super) fn get_id(&self, file_ref: StringRef) -> u32 {
-        *self.ref_to_id.get(&file_ref).unwrap()
+    pub(super) fn get_ids(&self) -> &RefToIds {
+        &self.ref_to_id
     }
 
     fn map(&self, file: String) -> String {
@@ -174,16 +237,53 @@ impl<'a> SourceFiles<'a> {
         new_path.unwrap_or(file)
     }
 
-    pub(super) fn get_mapping(&self) -> Vec<String> {
+    /// Get the file names as they're in the pdb (indexed by file id: an unreadable name is empty
+    /// as when the ids are assigned)
+    pub(super) fn get_names(&self) -> Vec<String> {
         if let Some(string_table) = self.string_table.as_ref() {
             self.id_to_ref
                 .iter()
-                .filter_map(|file_ref| string_table.get(*file_ref).ok())
-                .map(|s| s.to_string().into_owned())
-                .map(|s| self.map(s))
+                .map(|file_ref| {
+                    string_table
+                        .get(*file_ref)
+                        .map(|s| s.to_string().into_owned())
+                        .unwrap_or_default()
+                })
                 .collect()
         } else {
             Vec::new()
         }
     }
+
+    /// Get the file names once normalized (if required) and mapped
+    pub(super) fn get_mapping(&self) -> Vec<String> {
+        self.get_names()
+            .drain(..)
+            .map(|s| {
+                if self.normalize {
+                    super::utils::normalize_file_path(&s, self.fold_case).unwrap_or(s)
+                } else {
+                    s
+                }
+            })
+            .map(|s| self.map(s))
+            .collect()
+    }
+
+    /// Get the checksums (e.g. "md5:0123...") of the files as they're in the line programs
+    pub(super) fn get_checksums(&self) -> Vec<Option<String>> {
+        self.checksums.clone()
+    }
+
+    /// Get the urls (if any) for the files indexed in the srcsrv or sourcelink stream
+    pub(super) fn get_source_urls<F: Fn(&str) -> Option<String>>(
+        &self,
+        get_url: F,
+    ) -> Vec<(u32, String)> {
+        self.get_names()
+            .iter()
+            .enumerate()
+            .filter_map(|(id, name)| get_url(name).map(|url| (id as u32, url)))
+            .collect()
+    }
 }
@@ -0,0 +1,141 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use log::warn;
+use pdb::{Source, PDB};
+use serde::Deserialize;
+
+// The sourcelink stream contains a json document:
+//   {
+//     "documents": {
+//       "C:\\src\\*": "https://raw.githubusercontent.com/org/repo/abcdef/*",
+//       "C:\\src\\version.h": "https://example.com/version.h"
+//     }
+//   }
+// A key ending with a '*' matches any path starting with the prefix and the
+// rest of the path (with '/' as separator) replaces the '*' in the url.
+#[derive(Debug, Default, Deserialize)]
+struct Documents {
+    documents: HashMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct SourceLink {
+    // Exact paths (lowercased)
+    exact: HashMap<String, String>,
+    // (lowercased prefix, url), longest prefixes first
+    prefixes: Vec<(String, String)>,
+}
+
+impl SourceLink {
+    pub(super) fn new<'a, S: 'a + Source<'a>>(pdb: &mut PDB<'a, S>) -> Option<Self> {
+        // The linker may split the document in several streams: sourcelink, sourcelink$1, ...
+        let mut sourcelink = Self::default();
+        let names = std::iter::once("sourcelink".to_string())
+            .chain((1..16).map(|i| format!("sourcelink${}", i)));
+
+        for name in names {
+            let stream = match pdb.named_stream(name.as_bytes()) {
+                Ok(stream) => stream,
+                _ => continue,
+            };
+            match Self::parse(stream.as_slice()) {
+                Some(s) => sourcelink.extend(s),
+                None => warn!("Invalid sourcelink json in stream {}", name),
+            }
+        }
+
+        if sourcelink.is_empty() {
+            None
+        } else {
+            Some(sourcelink)
+        }
+    }
+
+    pub(super) fn parse(data: &[u8]) -> Option<Self> {
+        let documents = serde_json::from_slice::<Documents>(data).ok()?.documents;
+        let mut sourcelink = Self::default();
+
+        for (path, url) in documents {
+            let path = path.to_ascii_lowercase();
+            if let Some(prefix) = path.strip_suffix('*') {
+                sourcelink.prefixes.push((prefix.to_string(), url));
+            } else {
+                sourcelink.exact.insert(path, url);
+            }
+        }
+        sourcelink.sort();
+
+        Some(sourcelink)
+    }
+
+    fn extend(&mut self, other: Self) {
+        self.exact.extend(other.exact);
+        self.prefixes.extend(other.prefixes);
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.prefixes
+            .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.prefixes.is_empty()
+    }
+
+    /// Get the url for the given path (as written in the pdb)
+    pub(super) fn get_url(&self, path: &str) -> Option<String> {
+        let lpath = path.to_ascii_lowercase();
+        if let Some(url) = self.exact.get(&lpath) {
+            return Some(url.clone());
+        }
+
+        for (prefix, url) in self.prefixes.iter() {
+            if lpath.starts_with(prefix.as_str()) {
+                // The prefix is lowercased so get the rest from the original path
+                let rest = path.get(prefix.len()..)?.replace('\\', "/");
+                return Some(url.replacen('*', &rest, 1));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_sourcelink() {
+        let data = br#"{
+  "documents": {
+    "C:\\build\\src\\*": "https://raw.githubusercontent.com/org/repo/0123abcd/*",
+    "C:\\build\\src\\third_party\\*": "https://raw.githubusercontent.com/org/dep/4567ef/*",
+    "C:\\build\\obj\\version.h": "https://example.com/version.h"
+  }
+}"#;
+        let sourcelink = SourceLink::parse(data).unwrap();
+
+        assert_eq!(
+            sourcelink.get_url(r"c:\build\src\dom\Foo.cpp").unwrap(),
+            "https://raw.githubusercontent.com/org/repo/0123abcd/dom/Foo.cpp"
+        );
+        assert_eq!(
+            sourcelink
+                .get_url(r"C:\build\src\third_party\zlib\zlib.h")
+                .unwrap(),
+            "https://raw.githubusercontent.com/org/dep/4567ef/zlib/zlib.h"
+        );
+        assert_eq!(
+            sourcelink.get_url(r"C:\build\obj\version.h").unwrap(),
+            "https://example.com/version.h"
+        );
+        assert!(sourcelink.get_url(r"C:\build\obj\config.h").is_none());
+    }
+}
@@ -0,0 +1,251 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use pdb::{Source, PDB};
+
+// Avoid infinite loops with self-referencing variables
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Section {
+    None,
+    Ini,
+    Variables,
+    SourceFiles,
+}
+
+/*
+ The srcsrv stream is a text stream added by the source indexing tools:
+   SRCSRV: ini ------------------------------------------------
+   VERSION=2
+   SRCSRV: variables ------------------------------------------
+   HGSERVER=https://hg.mozilla.org/mozilla-central
+   SRCSRVTRG=%hgserver%/raw-file/%var3%/%var2%
+   SRCSRV: source files ---------------------------------------
+   c:\build\src\foo.cpp*src/foo.cpp*abcdef
+   SRCSRV: end ------------------------------------------------
+ Each source file line is a list of fields (var1, var2, ...) separated by '*'
+ and var1 is the path of the file as it's in the pdb.
+ The target (SRCSRVTRG) is expanded for each file to get its url.
+*/
+#[derive(Debug, Default)]
+pub(super) struct SrcSrv {
+    // Variable names are case insensitive so they're stored in lowercase
+    variables: HashMap<String, String>,
+    // The key is the lowercased path in the pdb
+    files: HashMap<String, Vec<String>>,
+}
+
+impl SrcSrv {
+    pub(super) fn new<'a, S: 'a + Source<'a>>(pdb: &mut PDB<'a, S>) -> Option<Self> {
+        let stream = pdb.named_stream(b"srcsrv").ok()?;
+        let data = String::from_utf8_lossy(stream.as_slice());
+        Self::parse(&data)
+    }
+
+    pub(super) fn parse(data: &str) -> Option<Self> {
+        let mut section = Section::None;
+        let mut srcsrv = Self::default();
+
+        for line in data.lines() {
+            let line = line.trim_end_matches('\r');
+            if let Some(header) = line.strip_prefix("SRCSRV:") {
+                let header = header.trim_start().to_lowercase();
+                section = if header.starts_with("ini") {
+                    Section::Ini
+                } else if header.starts_with("variables") {
+                    Section::Variables
+                } else if header.starts_with("source files") {
+                    Section::SourceFiles
+                } else {
+                    Section::None
+                };
+                continue;
+            }
+
+            match section {
+                Section::Variables => {
+                    if let Some((name, value)) = line.split_once('=') {
+                        srcsrv
+                            .variables
+                            .insert(name.trim().to_lowercase(), value.to_string());
+                    }
+                }
+                Section::SourceFiles => {
+                    let fields: Vec<_> = line.split('*').map(|f| f.to_string()).collect();
+                    if fields.len() >= 2 && !fields[0].is_empty() {
+                        srcsrv.files.insert(fields[0].to_lowercase(), fields);
+                    }
+                }
+                Section::Ini | Section::None => {}
+            }
+        }
+
+        if srcsrv.files.is_empty() || !srcsrv.variables.contains_key("srcsrvtrg") {
+            None
+        } else {
+            Some(srcsrv)
+        }
+    }
+
+    /// Get the url for the given path (as written in the pdb)
+    /// None is returned when the file isn't indexed or if the target isn't an url
+    /// (e.g. a path in a local cache for a command-based version control system).
+    pub(super) fn get_url(&self, path: &str) -> Option<String> {
+        let fields = self.files.get(&path.to_lowercase())?;
+        let target = self.variables.get("srcsrvtrg")?;
+        let url = self.expand(target, fields, 0)?;
+
+        if url.starts_with("http://") || url.starts_with("https://") {
+            Some(url)
+        } else {
+            None
+        }
+    }
+
+    fn get_var(&self, name: &str, fields: &[String], depth: usize) -> Option<String> {
+        let name = name.to_lowercase();
+        if let Some(n) = name.strip_prefix("var") {
+            if let Ok(n) = n.parse::<usize>() {
+                return fields.get(n.checked_sub(1)?).cloned();
+            }
+        }
+
+        let value = self.variables.get(&name)?;
+        self.expand(value, fields, depth + 1)
+    }
+
+    fn apply_function(
+        &self,
+        function: &str,
+        arg: &str,
+        fields: &[String],
+        depth: usize,
+    ) -> Option<String> {
+        let arg = self.expand(arg, fields, depth + 1)?;
+        match function {
+            "fnvar" => self.get_var(&arg, fields, depth),
+            "fnbksl" => Some(arg.replace('/', "\\")),
+            "fnfile" => Some(
+                arg.rsplit(&['\\', '/'][..])
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn expand(&self, s: &str, fields: &[String], depth: usize) -> Option<String> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        let mut buf = String::with_capacity(s.len() * 2);
+        let mut rest = s;
+
+        while let Some(start) = rest.find('%') {
+            buf.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let end = after.find('%')?;
+            let name = &after[..end];
+            rest = &after[end + 1..];
+
+            let lname = name.to_lowercase();
+            if lname.starts_with("fn") {
+                // A function: %fnbksl%( ... )
+                let (arg, remainder) = Self::get_function_arg(rest)?;
+                buf.push_str(&self.apply_function(&lname, arg, fields, depth)?);
+                rest = remainder;
+            } else {
+                buf.push_str(&self.get_var(name, fields, depth)?);
+            }
+        }
+        buf.push_str(rest);
+
+        Some(buf)
+    }
+
+    fn get_function_arg(s: &str) -> Option<(&str, &str)> {
+        let s = s.strip_prefix('(')?;
+        let mut level = 1;
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => level += 1,
+                ')' => {
+                    level -= 1;
+                    if level == 0 {
+                        return Some((&s[..i], &s[i + 1..]));
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_srcsrv_http() {
+        let data = "SRCSRV: ini ------------------------------------------------\r
+VERSION=2\r
+VERCTRL=http\r
+SRCSRV: variables ------------------------------------------\r
+HGSERVER=https://hg.mozilla.org/mozilla-central\r
+SRCSRVVERCTRL=http\r
+HTTP_EXTRACT_TARGET=%hgserver%/raw-file/%var3%/%var2%\r
+SRCSRVTRG=%http_extract_target%\r
+SRCSRV: source files ---------------------------------------\r
+c:\\build\\src\\dom\\base\\Foo.cpp*dom/base/Foo.cpp*6639deb894172375b05d6791f5f8c7d53ca79723\r
+SRCSRV: end ------------------------------------------------\r
+";
+        let srcsrv = SrcSrv::parse(data).unwrap();
+
+        assert_eq!(
+            srcsrv.get_url(r"C:\build\src\dom\base\foo.cpp").unwrap(),
+            "https://hg.mozilla.org/mozilla-central/raw-file/6639deb894172375b05d6791f5f8c7d53ca79723/dom/base/Foo.cpp"
+        );
+        assert!(srcsrv.get_url(r"c:\build\src\bar.cpp").is_none());
+    }
+
+    #[test]
+    fn test_srcsrv_functions() {
+        let data = "SRCSRV: ini ------------------------------------------------
+VERSION=1
+SRCSRV: variables ------------------------------------------
+SERVER=https://example.com
+DIR=%fnbksl%(%var2%)
+SRCSRVTRG=%SERVER%/%var3%/%fnfile%(%var1%)?dir=%fnvar%(dir)
+SRCSRV: source files ---------------------------------------
+d:\\agent\\s\\src\\bar.h*src/bar.h*1234
+SRCSRV: end ------------------------------------------------
+";
+        let srcsrv = SrcSrv::parse(data).unwrap();
+
+        assert_eq!(
+            srcsrv.get_url(r"d:\agent\s\src\bar.h").unwrap(),
+            r"https://example.com/1234/bar.h?dir=src\bar.h"
+        );
+    }
+
+    #[test]
+    fn test_srcsrv_no_url() {
+        let data = "SRCSRV: variables ------------------------------------------
+SRCSRVTRG=%targ%\\%var2%\\%fnfile%(%var1%)
+SRCSRVCMD=tf.exe view /version:%var4% /noprompt \"$%var3:~1%\"
+SRCSRV: source files ---------------------------------------
+d:\\agent\\s\\src\\bar.h*src*1234
+SRCSRV: end ------------------------------------------------
+";
+        let srcsrv = SrcSrv::parse(data).unwrap();
+        assert!(srcsrv.get_url(r"d:\agent\s\src\bar.h").is_none());
+    }
+}
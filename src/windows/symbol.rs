@@ -6,18 +6,23 @@
 use hashbrown::{hash_map, HashMap};
 use pdb::{
     AddressMap, FrameTable, PdbInternalRva, PdbInternalSectionOffset, ProcedureSymbol,
-    PublicSymbol, RegisterRelativeSymbol, TypeIndex,
+    PublicSymbol, RawString, RegisterRelativeSymbol, Result, TypeIndex,
 };
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 use std::rc::Rc;
+use std::time::Duration;
 use symbolic::debuginfo::pe::{ExceptionData, PeSymbolIterator};
 
 use super::pdb::{PDBContributions, PDBSections};
 use super::source::SourceLineCollector;
 use super::types::{FuncName, TypeDumper};
-use crate::common::LineFinalizer;
+use crate::common::{self, LineFinalizer, Provenance, PublicPrecedence};
 use crate::line::Lines;
+use crate::model::Public;
+use crate::record::RecordWriter;
+use crate::visit::{FunctionHeader, SymbolVisitor};
 
 pub(super) struct BlockInfo {
     pub rva: u32,
@@ -45,7 +50,11 @@ pub(super) struct SelectedSymbol {
     pub parameter_size: u32,
     pub source: Lines,
     pub ebp: Vec<EBPInfo>,
+    // The names of the parameters (with --parameter-names)
+    pub param_names: Vec<String>,
     pub id: usize,
+    // The names of the other symbols folded at the same address (ICF)
+    pub folded: Vec<String>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -58,12 +67,92 @@ pub(super) struct PDBSymbol {
     pub parameter_size: u32,
     pub source: Rc<Lines>,
     pub id: usize,
+    pub provenance: Provenance,
 }
 
 // it's safe because source (with Rc) isn't shared: it's just an internal thing
 unsafe impl Send for PDBSymbol {}
 
+/// A function whose address has been changed by OMAP or which has been folded
+/// with other ones (ICF)
+#[derive(Debug)]
+pub(super) struct AddressReportEntry {
+    pub name: String,
+    // The rva before OMAP translation
+    pub original_rva: u32,
+    // The (rva, len) in the final binary
+    pub ranges: Vec<(u32, u32)>,
+    pub folded: Vec<String>,
+}
+
+pub(super) type AddressReport = Vec<AddressReportEntry>;
+
+impl Display for AddressReportEntry {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{:x} ->", self.original_rva)?;
+        for (rva, len) in self.ranges.iter() {
+            if *len == 0 {
+                write!(f, " {:x}", rva)?;
+            } else {
+                write!(f, " {:x}-{:x}", rva, rva + len)?;
+            }
+        }
+        writeln!(f, " {}", self.name)?;
+
+        for name in self.folded.iter() {
+            writeln!(f, "  folded {}", name)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl PDBSymbol {
+    /// Write the PUBLIC record or the FUNC record and its LINE records
+    pub(super) fn write_records(
+        &self,
+        w: &mut RecordWriter,
+        f: &mut Formatter,
+    ) -> std::fmt::Result {
+        w.str(if self.is_public { "PUBLIC " } else { "FUNC " });
+        if self.is_multiple {
+            w.str("m ");
+        }
+        w.hex(self.rva.into()).space();
+        if !self.is_public {
+            w.hex(self.len.into()).space();
+        }
+        w.hex(self.parameter_size.into())
+            .space()
+            .name(&self.name)
+            .end();
+
+        if !self.is_public {
+            self.source.write_records(w, f)?;
+        }
+        w.flush_if_full(f)
+    }
+
+    /// Give the PUBLIC record or the FUNC record and its LINE records to the visitor
+    pub(super) fn visit<'a, V: SymbolVisitor<'a>>(&'a self, visitor: &mut V) -> common::Result<()> {
+        if self.is_public {
+            return visitor.public(Public {
+                address: self.rva.into(),
+                parameter_size: self.parameter_size.into(),
+                name: Cow::Borrowed(&self.name),
+                multiple: self.is_multiple,
+            });
+        }
+        visitor.function(FunctionHeader {
+            address: self.rva.into(),
+            size: self.len.into(),
+            parameter_size: self.parameter_size.into(),
+            name: &self.name,
+            multiple: self.is_multiple,
+        })?;
+        self.source.visit(visitor)
+    }
+
     fn get_from(&self, rva: u32, len: u32) -> PDBSymbol {
         PDBSymbol {
             name: self.name.clone(),
@@ -78,43 +167,28 @@ impl PDBSymbol {
                 Rc::clone(&self.source)
             },
             id: self.id,
+            provenance: self.provenance,
         }
     }
 }
 
 impl Display for PDBSymbol {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        if self.is_public {
-            writeln!(
-                f,
-                "PUBLIC {}{:x} {:x} {}",
-                if self.is_multiple { "m " } else { "" },
-                self.rva,
-                self.parameter_size,
-                self.name,
-            )?;
-        } else {
-            writeln!(
-                f,
-                "FUNC {}{:x} {:x} {:x} {}",
-                if self.is_multiple { "m " } else { "" },
-                self.rva,
-                self.len,
-                self.parameter_size,
-                self.name,
-            )?;
-
-            write!(f, "{}", self.source)?;
-        }
-
-        Ok(())
+        let mut writer = RecordWriter::new();
+        self.write_records(&mut writer, f)?;
+        writer.flush(f)
     }
 }
 
 impl SelectedSymbol {
     fn get_und(&self, dumper: &TypeDumper) -> FuncName {
+        if self.is_public && !dumper.demangle_publics() {
+            // Keep the mangled name (the parameter size is still guessed for the decorated C names)
+            return FuncName::get_unknown(self.name.clone());
+        }
+
         dumper
-            .dump_function(&self.name, self.type_index)
+            .dump_function(&self.name, self.type_index, &self.param_names)
             .unwrap_or_else(|_| FuncName::get_unknown(self.name.clone()))
     }
 
@@ -139,6 +213,13 @@ impl SelectedSymbol {
         }*/
 
         if self.ebp.is_empty() {
+            // The name isn't decorated: the type has the sizes of the arguments (Google's dump_syms
+            // writes 0 here so it's only done with --x86-param-sizes)
+            if self.parameter_size == 0 && dumper.x86_param_sizes() {
+                if let Some(sps) = dumper.get_stack_param_size(self.type_index) {
+                    self.parameter_size = sps;
+                }
+            }
             return self.parameter_size;
         }
 
@@ -176,22 +257,59 @@ impl SelectedSymbol {
         let fun_name = function.name.to_string().into_owned();
 
         if fun_name < self.name {
-            self.name = fun_name;
+            self.folded
+                .push(std::mem::replace(&mut self.name, fun_name));
             self.type_index = function.type_index;
+            self.param_names.clear();
             self.offset = block_info.offset;
             self.len = block_info.len;
             self.source = line_collector.collect_source_lines(block_info.offset, block_info.len);
+        } else {
+            self.folded.push(fun_name);
+        }
+    }
+
+    /// Merge the symbol found at the same rva in a module collected apart (as update_private does)
+    fn merge(&mut self, other: SelectedSymbol) {
+        self.is_multiple = true;
+        if other.name < self.name {
+            self.folded
+                .push(std::mem::replace(&mut self.name, other.name));
+            self.type_index = other.type_index;
+            self.param_names.clear();
+            self.offset = other.offset;
+            self.len = other.len;
+            self.source = other.source;
+        } else {
+            self.folded.push(other.name);
         }
+        self.folded.extend(other.folded);
     }
 
-    pub(super) fn update_public(&mut self, symbol: PublicSymbol) {
+    pub(super) fn update_public(&mut self, symbol: PublicSymbol, precedence: PublicPrecedence) {
         if self.is_public {
             self.is_multiple = true;
             let sym_name = symbol.name.to_string().into_owned();
             if sym_name < self.name {
-                self.name = sym_name;
+                self.folded
+                    .push(std::mem::replace(&mut self.name, sym_name));
                 self.offset = symbol.offset;
+            } else {
+                self.folded.push(sym_name);
             }
+        } else if precedence == PublicPrecedence::Public {
+            // The public replaces the function: its name (and the parameter size if decorated)
+            // are used but the lines are lost
+            self.name = symbol.name.to_string().into_owned();
+            self.type_index = TypeIndex(0);
+            self.param_names.clear();
+            self.is_public = true;
+            self.offset = symbol.offset;
+            self.sym_offset = None;
+            self.len = 0;
+            self.parameter_size = 0;
+            self.source = Lines::new();
+            self.ebp.clear();
         } else {
             let fun_name = symbol.name.to_string().into_owned();
             if self.type_index == TypeIndex(0x0) {
@@ -240,12 +358,20 @@ impl SelectedSymbol {
                 parameter_size: stack_param_size,
                 source: Rc::new(self.source),
                 id: self.id,
+                provenance: if self.is_public {
+                    Provenance::PdbPublics
+                } else {
+                    Provenance::PdbSymbols
+                },
             },
             self.offset,
         )
     }
 }
 
+/// The index of the symbol (in the collected ones) by range (rva, len)
+type SymbolRanges = BTreeMap<(u32, u32), usize>;
+
 #[derive(Default)]
 pub(super) struct RvaSymbols {
     map: HashMap<u32, SelectedSymbol>,
@@ -256,7 +382,37 @@ pub(super) struct RvaSymbols {
 
 impl RvaSymbols {
     pub(super) fn get_symbol_at(&self, rva: u32) -> Option<&SelectedSymbol> {
-        self.map.get(&rva)
+        match self.symbol.as_ref() {
+            // The parent of a separated block is still open
+            Some(symbol) if self.rva == rva => Some(symbol),
+            _ => self.map.get(&rva),
+        }
+    }
+
+    fn new_private(
+        &mut self,
+        line_collector: &SourceLineCollector,
+        function: ProcedureSymbol,
+        block_info: &BlockInfo,
+    ) -> SelectedSymbol {
+        let source = line_collector.collect_source_lines(block_info.offset, block_info.len);
+        let symbol = SelectedSymbol {
+            name: function.name.to_string().into_owned(),
+            type_index: function.type_index,
+            is_public: false,
+            is_multiple: false,
+            offset: block_info.offset,
+            sym_offset: Some(function.offset),
+            len: block_info.len,
+            parameter_size: 0,
+            source,
+            ebp: Vec::new(),
+            param_names: Vec::new(),
+            id: self.last_id,
+            folded: Vec::new(),
+        };
+        self.last_id += 1;
+        symbol
     }
 
     pub(super) fn add_procedure_symbol(
@@ -269,26 +425,27 @@ impl RvaSymbols {
         // we need to "select" the a symbol for a rva.
         // Anyway it could lead to strange backtraces.
 
-        let fun_name = function.name.to_string().into_owned();
         if let Some(selected) = self.map.get_mut(&block_info.rva) {
             selected.update_private(function, block_info, line_collector);
         } else {
-            let source = line_collector.collect_source_lines(block_info.offset, block_info.len);
+            self.symbol = Some(self.new_private(line_collector, function, &block_info));
             self.rva = block_info.rva;
-            self.symbol = Some(SelectedSymbol {
-                name: fun_name,
-                type_index: function.type_index,
-                is_public: false,
-                is_multiple: false,
-                offset: block_info.offset,
-                sym_offset: Some(function.offset),
-                len: block_info.len,
-                parameter_size: 0,
-                source,
-                ebp: Vec::new(),
-                id: self.last_id,
-            });
-            self.last_id += 1;
+        }
+    }
+
+    /// Add a part of a function which is out of its range (the cold part of a function
+    /// split by PGO): the function itself is still open
+    pub(super) fn add_fragment(
+        &mut self,
+        line_collector: &SourceLineCollector,
+        function: ProcedureSymbol,
+        block_info: BlockInfo,
+    ) {
+        if let Some(selected) = self.map.get_mut(&block_info.rva) {
+            selected.update_private(function, block_info, line_collector);
+        } else {
+            let symbol = self.new_private(line_collector, function, &block_info);
+            self.map.insert(block_info.rva, symbol);
         }
     }
 
@@ -308,12 +465,19 @@ impl RvaSymbols {
         Self::is_constant_string(name) || Self::is_constant_number(name)
     }
 
+    /// Check if the public is a vftable, a vbtable or a RTTI descriptor
+    fn is_vtable_or_rtti(name: &str) -> bool {
+        name.starts_with("??_7") || name.starts_with("??_8") || name.starts_with("??_R")
+    }
+
     pub(super) fn add_public_symbol(
         &mut self,
         symbol: PublicSymbol,
         pdb_sections: &PDBSections,
         pdb_contributions: &PDBContributions,
         address_map: &AddressMap,
+        precedence: PublicPrecedence,
+        data_publics: bool,
     ) {
         let rva = match symbol.offset.to_rva(address_map) {
             Some(rva) => rva,
@@ -324,6 +488,7 @@ impl RvaSymbols {
             || symbol.function
             || (pdb_sections.is_code(symbol.offset.section)
                 && pdb_contributions.is_code(symbol.offset.section, symbol.offset.offset))
+            || (data_publics && Self::is_vtable_or_rtti(&symbol.name.to_string()))
         {
             let sym_name = symbol.name.to_string().into_owned();
 
@@ -336,7 +501,7 @@ impl RvaSymbols {
             match self.map.entry(rva.0) {
                 hash_map::Entry::Occupied(selected) => {
                     let selected = selected.into_mut();
-                    selected.update_public(symbol);
+                    selected.update_public(symbol, precedence);
                 }
                 hash_map::Entry::Vacant(e) => {
                     let offset = symbol.offset;
@@ -351,7 +516,9 @@ impl RvaSymbols {
                         parameter_size: 0,
                         source: Lines::new(),
                         ebp: Vec::new(),
+                        param_names: Vec::new(),
                         id: self.last_id,
+                        folded: Vec::new(),
                     });
                     self.last_id += 1;
                 }
@@ -364,6 +531,9 @@ impl RvaSymbols {
             hash_map::Entry::Occupied(selected) => {
                 let selected = selected.into_mut();
                 selected.is_multiple = true;
+                if selected.name != function.name {
+                    selected.folded.push(function.name);
+                }
             }
             hash_map::Entry::Vacant(e) => {
                 e.insert(function);
@@ -380,49 +550,100 @@ impl RvaSymbols {
         }
     }
 
+    pub(super) fn add_param_name(&mut self, name: RawString) {
+        if let Some(symbol) = self.symbol.as_mut() {
+            symbol.param_names.push(name.to_string().into_owned());
+        }
+    }
+
+    /// Get the rvas of the symbols (the open procedure isn't included)
+    pub(super) fn get_rvas(&self) -> Vec<u32> {
+        self.map.keys().copied().collect()
+    }
+
     pub(super) fn close_procedure(&mut self) {
         if let Some(symbol) = self.symbol.take() {
             self.map.insert(self.rva, symbol);
         }
     }
 
+    /// Merge the symbols of a module collected apart: the modules must be merged in order
+    /// and the ids are shifted to stay unique
+    pub(super) fn merge(&mut self, mut other: RvaSymbols) {
+        other.close_procedure();
+        let base = self.last_id;
+        for (rva, mut symbol) in other.map.drain() {
+            symbol.id += base;
+            match self.map.entry(rva) {
+                hash_map::Entry::Occupied(selected) => selected.into_mut().merge(symbol),
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(symbol);
+                }
+            }
+        }
+        self.last_id += other.last_id;
+    }
+
     fn split_and_collect(
         mut self,
-        dumper: TypeDumper,
+        dumper: &TypeDumper,
         address_map: &AddressMap,
-        frame_table: FrameTable,
-    ) -> (Vec<PDBSymbol>, BTreeMap<(u32, u32), usize>) {
+        frame_table: &FrameTable,
+        mut report: Option<&mut AddressReport>,
+    ) -> Result<(Vec<PDBSymbol>, SymbolRanges)> {
         // The value in ranges is the index in all_syms
-        let mut ranges: BTreeMap<(u32, u32), usize> = BTreeMap::default();
+        let mut ranges = SymbolRanges::default();
         let mut all_syms = Vec::with_capacity(self.map.len());
 
-        for (rva, sym) in self.map.drain() {
-            let (sym, offset) = sym.mv_to_pdb_symbol(&dumper, rva, address_map, &frame_table);
+        for (rva, mut sym) in self.map.drain() {
+            // Dumping the names of the functions is long with a lot of types
+            dumper.check_cancelled()?;
+            let folded = std::mem::take(&mut sym.folded);
+            let (sym, offset) = sym.mv_to_pdb_symbol(dumper, rva, address_map, frame_table);
             let last = all_syms.len();
-            if sym.len == 0 {
-                ranges.insert((rva, 0), last);
-            } else {
-                let start = offset.to_internal_rva(address_map).unwrap();
-                let end = PdbInternalRva(start.0 + sym.len);
-                for (rva, len) in address_map
-                    .rva_ranges(start..end)
-                    .map(|r| (r.start.0, r.end.0 - r.start.0))
-                {
-                    ranges.insert((rva, len), last);
+            let start = offset.to_internal_rva(address_map);
+            let sym_ranges = match start {
+                Some(start) if sym.len != 0 => {
+                    let end = PdbInternalRva(start.0.saturating_add(sym.len));
+                    address_map
+                        .rva_ranges(start..end)
+                        .map(|r| (r.start.0, r.end.0 - r.start.0))
+                        .collect()
+                }
+                _ => vec![(rva, sym.len)],
+            };
+
+            if let Some(report) = report.as_mut() {
+                let original_rva = offset.to_internal_rva(address_map).map_or(rva, |r| r.0);
+                let moved = sym_ranges.len() != 1 || sym_ranges[0].0 != original_rva;
+                if moved || !folded.is_empty() {
+                    report.push(AddressReportEntry {
+                        name: sym.name.clone(),
+                        original_rva,
+                        ranges: sym_ranges.clone(),
+                        folded,
+                    });
                 }
             }
+
+            for range in sym_ranges {
+                ranges.insert(range, last);
+            }
             all_syms.push(sym);
         }
 
-        (all_syms, ranges)
+        Ok((all_syms, ranges))
     }
 
-    fn fill_the_gaps(all_syms: Vec<PDBSymbol>, ranges: BTreeMap<(u32, u32), usize>) -> PDBSymbols {
+    fn fill_the_gaps(all_syms: Vec<PDBSymbol>, ranges: SymbolRanges) -> PDBSymbols {
         let mut syms = PDBSymbols::default();
 
         // We initialize for first symbol
         let mut iterator = ranges.iter();
-        let ((rva, len), sym_pos) = iterator.next().unwrap();
+        let ((rva, len), sym_pos) = match iterator.next() {
+            Some(first) => first,
+            None => return syms,
+        };
 
         let mut last_rva = *rva;
         let mut last_len = *len;
@@ -451,19 +672,71 @@ impl RvaSymbols {
 
     pub(super) fn mv_to_pdb_symbols(
         self,
-        dumper: TypeDumper,
+        dumper: &TypeDumper,
         address_map: &AddressMap,
-        frame_table: FrameTable,
-    ) -> PDBSymbols {
+        frame_table: &FrameTable,
+        report: Option<&mut AddressReport>,
+    ) -> Result<PDBSymbols> {
         if self.map.is_empty() {
-            return PDBSymbols::default();
+            return Ok(PDBSymbols::default());
         }
 
-        let (all_syms, ranges) = self.split_and_collect(dumper, address_map, frame_table);
-        Self::fill_the_gaps(all_syms, ranges)
+        let (all_syms, ranges) =
+            self.split_and_collect(dumper, address_map, frame_table, report)?;
+        Ok(Self::fill_the_gaps(all_syms, ranges))
+    }
+}
+
+/// Give a size to the functions without any (or with one going beyond both the next symbol
+/// and the section contribution of the function) from the section contributions and the next
+/// symbol, so the stack walkers don't fall in a gap
+pub(super) fn fix_sizes(syms: &mut PDBSymbols, code_ranges: &[(u32, u32)]) {
+    let rvas: Vec<u32> = syms.keys().copied().collect();
+    for (i, rva) in rvas.iter().enumerate() {
+        let sym = match syms.get_mut(rva) {
+            Some(sym) if !sym.is_public => sym,
+            _ => continue,
+        };
+
+        let next = rvas.get(i + 1).copied();
+        let pos = code_ranges.partition_point(|(start, _)| *start <= *rva);
+        let contribution_end = pos
+            .checked_sub(1)
+            .map(|pos| code_ranges[pos].1)
+            .filter(|end| end > rva);
+
+        let end = if sym.len == 0 {
+            match (next, contribution_end) {
+                (Some(next), Some(end)) => next.min(end),
+                (Some(end), None) | (None, Some(end)) => end,
+                (None, None) => continue,
+            }
+        } else if rva.checked_add(sym.len).is_none() {
+            // A broken length: the function can't go beyond the end of the address space
+            match (next, contribution_end) {
+                (_, Some(end)) | (Some(end), None) => end,
+                (None, None) => u32::MAX,
+            }
+        } else {
+            match (next, contribution_end) {
+                (Some(next), Some(end)) if rva + sym.len > next.max(end) => end,
+                _ => continue,
+            }
+        };
+        *sym = sym.get_from(*rva, end - rva);
     }
 }
 
+/// Get the target of an incremental linking thunk: @ILT+1234(foo) gives foo
+pub(super) fn get_ilt_target(name: &[u8]) -> Option<&[u8]> {
+    let rest = name
+        .strip_prefix(b"@")
+        .unwrap_or(name)
+        .strip_prefix(b"ILT+")?;
+    let start = rest.iter().position(|c| !c.is_ascii_digit())?;
+    rest[start..].strip_prefix(b"(")?.strip_suffix(b")")
+}
+
 pub(super) fn append_dummy_symbol(mut syms: PDBSymbols, name: &str) -> PDBSymbols {
     let (rva, len, id) = if let Some((_, last_sym)) = syms.iter().next_back() {
         (last_sym.rva, last_sym.len, last_sym.id)
@@ -490,16 +763,42 @@ pub(super) fn append_dummy_symbol(mut syms: PDBSymbols, name: &str) -> PDBSymbol
             parameter_size: 0,
             source: Rc::new(Lines::new()),
             id: id + 1,
+            provenance: Provenance::Heuristic,
         },
     );
 
     syms
 }
 
+/// Print on stderr the number of records and the time spent to compute the names
+pub(super) fn print_stats(
+    name: &str,
+    symbols: &PDBSymbols,
+    demangle_publics: bool,
+    names: Duration,
+    total: Duration,
+) {
+    let publics = symbols.values().filter(|s| s.is_public).count();
+    eprintln!(
+        "{}: {} FUNC and {} PUBLIC records ({}), names computed in {:.2?}, dumped in {:.2?}",
+        name,
+        symbols.len() - publics,
+        publics,
+        if demangle_publics {
+            "demangled"
+        } else {
+            "not demangled"
+        },
+        names,
+        total
+    );
+}
+
 pub(super) fn symbolic_to_pdb_symbols(
     syms: PeSymbolIterator,
     exception_data: Option<&ExceptionData<'_>>,
     module_name: &str,
+    demangle: bool,
 ) -> PDBSymbols {
     let mut pdb_syms = PDBSymbols::default();
 
@@ -525,6 +824,7 @@ pub(super) fn symbolic_to_pdb_symbols(
                         parameter_size: 0,
                         source: Rc::new(Lines::new()),
                         id: 0,
+                        provenance: Provenance::PeExceptionData,
                     },
                 );
             });
@@ -532,7 +832,11 @@ pub(super) fn symbolic_to_pdb_symbols(
 
     for sym in syms {
         if let Some(name) = sym.name() {
-            let demangled_name = TypeDumper::demangle(name);
+            let demangled_name = if demangle {
+                TypeDumper::demangle(name)
+            } else {
+                FuncName::get_unknown(name.to_string())
+            };
             let (name, parameter_size) = match demangled_name {
                 FuncName::Undecorated(name) => (name, 0),
                 FuncName::Unknown((name, parameter_size)) => (name, parameter_size),
@@ -541,8 +845,16 @@ pub(super) fn symbolic_to_pdb_symbols(
             pdb_syms
                 .entry(rva)
                 .and_modify(|e| {
+                    if e.is_public || e.name != module_name {
+                        // Several exports for the same address: keep the smallest name
+                        e.is_multiple = true;
+                        if name >= e.name {
+                            return;
+                        }
+                    }
                     e.name = name.clone();
                     e.parameter_size = parameter_size;
+                    e.provenance = Provenance::PeExports;
                 })
                 .or_insert(PDBSymbol {
                     name,
@@ -553,6 +865,7 @@ pub(super) fn symbolic_to_pdb_symbols(
                     parameter_size,
                     source: Rc::new(Lines::new()),
                     id: 0,
+                    provenance: Provenance::PeExports,
                 });
         }
     }
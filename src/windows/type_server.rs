@@ -0,0 +1,195 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use log::{info, warn};
+use pdb::{Source, StreamIndex, TypeInformation, PDB};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+// The type stream
+const TPI_STREAM: StreamIndex = StreamIndex(2);
+pub(super) const LF_TYPESERVER2: u16 = 0x1515;
+
+/// A reference to a pdb containing the types (LF_TYPESERVER2)
+#[derive(Debug, PartialEq)]
+struct TypeServer {
+    guid: Uuid,
+    age: u32,
+    name: String,
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    let bytes = data.get(pos..pos + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/*
+ The type stream starts with a header (its size is the second u32) followed by the records:
+   length (u16, the length itself excluded), kind (u16), data.
+ The data of a LF_TYPESERVER2 are:
+   guid (16 bytes), age (u32), name (null terminated string).
+*/
+fn parse_type_server(data: &[u8]) -> Option<TypeServer> {
+    let guid = Uuid::from_fields(
+        read_u32(data, 0)?,
+        read_u16(data, 4)?,
+        read_u16(data, 6)?,
+        data.get(8..16)?,
+    )
+    .ok()?;
+    let age = read_u32(data, 16)?;
+    let name = data.get(20..)?;
+    let name = name.split(|c| *c == 0).next()?;
+
+    Some(TypeServer {
+        guid,
+        age,
+        name: String::from_utf8_lossy(name).into_owned(),
+    })
+}
+
+fn get_type_servers(tpi: &[u8]) -> Vec<TypeServer> {
+    let mut servers = Vec::new();
+    let mut pos = match read_u32(tpi, 4) {
+        Some(header_size) => header_size as usize,
+        _ => return servers,
+    };
+
+    while let Some(len) = read_u16(tpi, pos) {
+        let start = pos + 2;
+        let end = start + len as usize;
+        let record = match tpi.get(start..end) {
+            Some(record) => record,
+            _ => break,
+        };
+        if read_u16(record, 0) == Some(LF_TYPESERVER2) {
+            if let Some(server) = parse_type_server(&record[2..]) {
+                servers.push(server);
+            }
+        }
+        pos = end;
+    }
+
+    servers
+}
+
+fn get_candidates(name: &str, search_paths: &[String]) -> Vec<PathBuf> {
+    // The name is the path of the pdb on the build machine
+    let file_name = name.rsplit(['\\', '/']).next().unwrap_or(name);
+    let mut candidates = vec![PathBuf::from(name)];
+    candidates.extend(search_paths.iter().map(|p| Path::new(p).join(file_name)));
+    candidates
+}
+
+fn open_type_server(
+    server: &TypeServer,
+    search_paths: &[String],
+) -> Option<TypeInformation<'static>> {
+    for path in get_candidates(&server.name, search_paths) {
+        let buf = match fs::read(&path) {
+            Ok(buf) => buf,
+            _ => continue,
+        };
+        let mut pdb = match PDB::open(Cursor::new(buf)) {
+            Ok(pdb) => pdb,
+            _ => continue,
+        };
+        match pdb.pdb_information() {
+            Ok(pi) if pi.guid == server.guid => {}
+            _ => {
+                warn!(
+                    "The type server {} doesn't match {}",
+                    path.display(),
+                    server.name
+                );
+                continue;
+            }
+        }
+        if let Ok(type_info) = pdb.type_information() {
+            info!("Use the type server {}", path.display());
+            return Some(type_info);
+        }
+    }
+
+    warn!(
+        "Cannot find the type server {} (guid {}, age {})",
+        server.name, server.guid, server.age
+    );
+    None
+}
+
+/// Open the pdbs containing the types referenced by the LF_TYPESERVER2 records of the pdb:
+/// they're searched at their original path and then in the search paths.
+pub(super) fn load_type_servers<'a, S: 'a + Source<'a>>(
+    pdb: &mut PDB<'a, S>,
+    search_paths: &[String],
+) -> Vec<TypeInformation<'static>> {
+    let servers = match pdb.raw_stream(TPI_STREAM) {
+        Ok(Some(stream)) => get_type_servers(stream.as_slice()),
+        _ => return Vec::new(),
+    };
+
+    servers
+        .iter()
+        .filter_map(|server| open_type_server(server, search_paths))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn get_tpi(guid: &Uuid, name: &str) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&LF_TYPESERVER2.to_le_bytes());
+        let (d1, d2, d3, d4) = guid.as_fields();
+        record.extend_from_slice(&d1.to_le_bytes());
+        record.extend_from_slice(&d2.to_le_bytes());
+        record.extend_from_slice(&d3.to_le_bytes());
+        record.extend_from_slice(d4);
+        record.extend_from_slice(&1u32.to_le_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.push(0);
+
+        let mut tpi = vec![0; 56];
+        tpi[4] = 56;
+        tpi.extend_from_slice(&(record.len() as u16).to_le_bytes());
+        tpi.extend_from_slice(&record);
+        tpi
+    }
+
+    #[test]
+    fn test_type_servers() {
+        let buf = fs::read("./test_data/windows/basic64.pdb").unwrap();
+        let mut pdb = PDB::open(Cursor::new(&buf)).unwrap();
+        let guid = pdb.pdb_information().unwrap().guid;
+
+        let name = r"c:\build\basic64.pdb";
+        let servers = get_type_servers(&get_tpi(&guid, name));
+        assert_eq!(
+            servers,
+            vec![TypeServer {
+                guid,
+                age: 1,
+                name: name.to_string(),
+            }]
+        );
+
+        assert!(open_type_server(&servers[0], &[]).is_none());
+        let search_paths = vec!["./test_data/windows".to_string()];
+        assert!(open_type_server(&servers[0], &search_paths).is_some());
+
+        // No type server in a regular pdb
+        assert!(load_type_servers(&mut pdb, &search_paths).is_empty());
+    }
+}
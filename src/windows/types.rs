@@ -5,20 +5,142 @@
 
 use bitflags::bitflags;
 use hashbrown::HashMap;
-use log::{error, warn};
+use log::error;
 use pdb::{
     ArgumentList, ArrayType, ClassKind, ClassType, FallibleIterator, FunctionAttributes,
-    MemberFunctionType, ModifierType, PointerMode, PointerType, PrimitiveKind, PrimitiveType,
-    ProcedureType, RawString, Result, TypeData, TypeFinder, TypeIndex, TypeInformation, UnionType,
-    Variant,
+    Indirection, MemberFunctionType, ModifierType, PointerMode, PointerType, PrimitiveKind,
+    PrimitiveType, ProcedureType, RawString, Result, TypeData, TypeFinder, TypeIndex,
+    TypeInformation, UnionType, Variant,
 };
+use std::cell::Cell;
+use std::sync::{Arc, Mutex};
 use symbolic::common::{Language, Name, NameMangling};
-use symbolic::demangle::{Demangle, DemangleOptions};
+use symbolic_demangle::{Demangle, DemangleOptions};
 
+use super::type_server::LF_TYPESERVER2;
+use crate::cancel::CancelToken;
 use crate::common;
+use crate::diagnostics::{self, Category};
+use crate::error::Error;
+use crate::interner::Interner;
 
 type FwdRefSize<'a> = HashMap<RawString<'a>, u32>;
 
+/// The maximal depth of the nested types: a corrupted pdb can contain some self-referential types
+const MAX_TYPE_DEPTH: usize = 128;
+
+/// The placeholder for a type which is too deeply nested
+const CYCLIC_TYPE: &str = "<cyclic type>";
+const UNKNOWN_PRIMITIVE: &str = "<unknown>";
+
+thread_local! {
+    /// The depth of the types being dumped on this thread
+    static TYPE_DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// Set when the type being dumped on this thread has been cut by the depth limit
+    static TYPE_TRUNCATED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The placeholder of a type which is too deeply nested: the types containing it mustn't be cached
+/// since they can be complete when they're dumped from a shallower level
+fn cyclic_type() -> String {
+    TYPE_TRUNCATED.with(|truncated| truncated.set(true));
+    CYCLIC_TYPE.to_string()
+}
+
+/// Go one level deeper in the type graph (for as long as the guard lives)
+struct DepthGuard;
+
+impl DepthGuard {
+    fn enter() -> Option<Self> {
+        TYPE_DEPTH.with(|depth| {
+            if depth.get() >= MAX_TYPE_DEPTH {
+                None
+            } else {
+                depth.set(depth.get() + 1);
+                Some(Self)
+            }
+        })
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        TYPE_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// The number of xmm registers used for the arguments of an x86 __vectorcall function
+const VECTORCALL_XMMS: usize = 6;
+
+/// The kind of registers an argument of an x86 function can be put in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ArgClass {
+    /// An integer, an enum or a pointer: ecx and edx
+    General,
+    /// A float, a double or a vector (__m128, ...): xmm0-5 with __vectorcall
+    Vector,
+    /// An homogeneous vector aggregate with this number of members: the xmm registers which are
+    /// still free once the vector arguments have been allocated
+    Hva(usize),
+    /// Another aggregate (even a small one) or a long double: always on the stack
+    Stack,
+}
+
+/// Get the size on the stack of the arguments (size and class) of an x86 function using `gprs`
+/// general registers and `xmms` vector registers
+fn get_stack_size(args: &[(u32, ArgClass)], gprs: usize, xmms: usize) -> u32 {
+    let mut used_gprs = 0;
+    let mut used_xmms = 0;
+    let mut size = 0;
+    for (arg_size, class) in args {
+        match class {
+            ArgClass::General => {
+                // The variadic arguments are a NoType at the end
+                if used_gprs < gprs && *arg_size != 0 && *arg_size <= 4 {
+                    used_gprs += 1;
+                    continue;
+                }
+            }
+            ArgClass::Vector => {
+                if used_xmms < xmms {
+                    used_xmms += 1;
+                    continue;
+                }
+            }
+            ArgClass::Hva(_) => continue,
+            ArgClass::Stack => {}
+        }
+        size += (arg_size + 3) & !3;
+    }
+
+    // The HVAs are allocated once all the other vector arguments have their registers
+    for (arg_size, class) in args {
+        if let ArgClass::Hva(count) = class {
+            if used_xmms + count <= xmms {
+                used_xmms += count;
+            } else {
+                size += (arg_size + 3) & !3;
+            }
+        }
+    }
+
+    size
+}
+
+fn report_unsupported_primitive(kind: PrimitiveKind) {
+    diagnostics::report(
+        Category::UnhandledType,
+        format!("Unsupported primitive type {:?}", kind),
+    );
+}
+
+fn report_cycle<T: std::fmt::Debug>(typ: T) {
+    diagnostics::report(
+        Category::TypeCycle,
+        format!("Type nested too deeply (cycle?): {:?}", typ),
+    );
+}
+
 #[derive(Eq, PartialEq)]
 enum ThisKind {
     This,
@@ -53,6 +175,10 @@ bitflags! {
         const SPACE_AFTER_COMMA = 0b10;
         const SPACE_BEFORE_POINTER = 0b100;
         const NAME_ONLY = 0b1000;
+        const NO_DEMANGLE_PUBLICS = 0b1_0000;
+        const MSVC_TYPE_NAMES = 0b10_0000;
+        const X86_PARAM_SIZES = 0b100_0000;
+        const NO_METHOD_QUALIFIERS = 0b1000_0000;
     }
 }
 
@@ -62,11 +188,125 @@ impl Default for DumperFlags {
     }
 }
 
+/// Format the value of a constant (an enumerator) in decimal: the signed leaves are sign-extended
+/// and the unsigned ones zero-extended so -1 and 0x8000_0000 keep their values
+fn format_constant(value: Variant) -> String {
+    match value {
+        Variant::I8(v) => i64::from(v).to_string(),
+        Variant::I16(v) => i64::from(v).to_string(),
+        Variant::I32(v) => i64::from(v).to_string(),
+        Variant::I64(v) => v.to_string(),
+        Variant::U8(v) => u64::from(v).to_string(),
+        Variant::U16(v) => u64::from(v).to_string(),
+        Variant::U32(v) => u64::from(v).to_string(),
+        Variant::U64(v) => v.to_string(),
+    }
+}
+
+/// Get the name of a primitive type (the MSVC names are the ones used by undname)
+fn primitive_name(kind: PrimitiveKind, msvc: bool) -> &'static str {
+    if msvc {
+        match kind {
+            PrimitiveKind::I8 => return "__int8",
+            PrimitiveKind::U8 => return "unsigned __int8",
+            PrimitiveKind::I16 => return "__int16",
+            PrimitiveKind::U16 => return "unsigned __int16",
+            PrimitiveKind::Quad | PrimitiveKind::I64 => return "__int64",
+            PrimitiveKind::UQuad | PrimitiveKind::U64 => return "unsigned __int64",
+            PrimitiveKind::I128 | PrimitiveKind::Octa => return "__int128",
+            PrimitiveKind::U128 | PrimitiveKind::UOcta => return "unsigned __int128",
+            PrimitiveKind::HRESULT => return "long",
+            _ => {}
+        }
+    }
+
+    // TODO: check that these names are what we want to see
+    match kind {
+        PrimitiveKind::NoType => "<NoType>",
+        PrimitiveKind::Void => "void",
+        PrimitiveKind::Char => "signed char",
+        PrimitiveKind::UChar => "unsigned char",
+        PrimitiveKind::RChar => "char",
+        PrimitiveKind::WChar => "wchar_t",
+        PrimitiveKind::RChar16 => "char16_t",
+        PrimitiveKind::RChar32 => "char32_t",
+        PrimitiveKind::I8 => "int8_t",
+        PrimitiveKind::U8 => "uint8_t",
+        PrimitiveKind::Short => "short",
+        PrimitiveKind::UShort => "unsigned short",
+        PrimitiveKind::I16 => "int16_t",
+        PrimitiveKind::U16 => "uint16_t",
+        PrimitiveKind::Long => "long",
+        PrimitiveKind::ULong => "unsigned long",
+        PrimitiveKind::I32 => "int",
+        PrimitiveKind::U32 => "unsigned int",
+        PrimitiveKind::Quad => "long long",
+        PrimitiveKind::UQuad => "unsigned long long",
+        PrimitiveKind::I64 => "int64_t",
+        PrimitiveKind::U64 => "uint64_t",
+        PrimitiveKind::I128 | PrimitiveKind::Octa => "int128_t",
+        PrimitiveKind::U128 | PrimitiveKind::UOcta => "uint128_t",
+        PrimitiveKind::F16 => "float16_t",
+        PrimitiveKind::F32 => "float",
+        PrimitiveKind::F32PP => "float",
+        PrimitiveKind::F48 => "float48_t",
+        PrimitiveKind::F64 => "double",
+        PrimitiveKind::F80 => "long double",
+        PrimitiveKind::F128 => "long double",
+        PrimitiveKind::Complex32 => "complex<float>",
+        PrimitiveKind::Complex64 => "complex<double>",
+        PrimitiveKind::Complex80 => "complex<long double>",
+        PrimitiveKind::Complex128 => "complex<long double>",
+        PrimitiveKind::Bool8 => "bool",
+        PrimitiveKind::Bool16 => "bool16_t",
+        PrimitiveKind::Bool32 => "bool32_t",
+        PrimitiveKind::Bool64 => "bool64_t",
+        PrimitiveKind::HRESULT => "HRESULT",
+        _ => {
+            report_unsupported_primitive(kind);
+            UNKNOWN_PRIMITIVE
+        }
+    }
+}
+
+/// The size of a pointer to a primitive type
+fn indirection_size(indirection: Indirection) -> u32 {
+    match indirection {
+        Indirection::Near16 => 2,
+        Indirection::Far16 | Indirection::Huge16 | Indirection::Near32 => 4,
+        Indirection::Far32 => 6,
+        Indirection::Near64 => 8,
+        Indirection::Near128 => 16,
+    }
+}
+
+/// The qualifier of a pointer which hasn't the size of the pointers of the module
+fn pointer_qualifier(indirection: Indirection, ptr_size: u32) -> &'static str {
+    match (indirection_size(indirection), ptr_size) {
+        (4, 8) => " __ptr32",
+        (8, 4) => " __ptr64",
+        _ => "",
+    }
+}
+
 pub(super) struct TypeDumper<'a> {
     finder: TypeFinder<'a>,
+    // The finders for the type servers (if any)
+    servers: Vec<TypeFinder<'a>>,
     fwd: FwdRefSize<'a>,
     ptr_size: u32,
     flags: DumperFlags,
+    cancel: CancelToken,
+    // The types already dumped: the same ones are in the signatures of a lot of functions
+    cache: Mutex<TypeCache>,
+}
+
+/// The dumped types by index: a lot of indices give the same string (e.g. a class and its
+/// forward references) so the strings are interned
+#[derive(Default)]
+struct TypeCache {
+    types: HashMap<TypeIndex, Arc<str>>,
+    names: Interner,
 }
 
 pub enum FuncName {
@@ -83,25 +323,43 @@ impl FuncName {
         // __cdecl Leading underscore (_)
         // __stdcall Leading underscore (_) and a trailing at sign (@) followed by the number of bytes in the parameter list in decimal
         // __fastcall Leading and trailing at signs (@) followed by a decimal number representing the number of bytes in the parameter list
+        // __vectorcall Two trailing at signs (@@) followed by a decimal number of bytes in the parameter list
 
         if name.is_empty() {
             return FuncName::Unknown((name, 0));
         }
 
-        let (first, sub) = name.split_at(1);
+        if let Some((base, size)) = name.rsplit_once("@@") {
+            if !base.is_empty() && !base.contains(['@', '?', ':', '(']) {
+                if let Ok(stack_param_size) = size.parse::<u32>() {
+                    // The two first args are put in ECX and EDX as with __fastcall (the vector
+                    // registers used by the floating point args can't be known from the name)
+                    return FuncName::Unknown((
+                        base.to_string(),
+                        stack_param_size.saturating_sub(8),
+                    ));
+                }
+            }
+        }
+
+        // The first char isn't always ASCII
+        let (first, sub) = match name.chars().next() {
+            Some(first @ ('_' | '@')) => (first, &name[1..]),
+            _ => return FuncName::Unknown((name, 0)),
+        };
 
-        if (first != "_" && first != "@") || sub.find(|c: char| c == ':' || c == '(').is_some() {
+        if sub.find(|c: char| c == ':' || c == '(').is_some() {
             return FuncName::Unknown((name, 0));
         }
 
         let parts: Vec<_> = sub.rsplitn(2, '@').collect();
         if parts.len() <= 1 {
-            let name = if first == "_" { sub.to_string() } else { name };
+            let name = if first == '_' { sub.to_string() } else { name };
             return FuncName::Unknown((name, 0));
         }
 
         if let Ok(stack_param_size) = parts[0].parse::<u32>() {
-            let sps = if first == "@" {
+            let sps = if first == '@' {
                 // __fastcall: the two first args are put in ECX and EDX
                 if stack_param_size > 8 {
                     stack_param_size - 8
@@ -114,7 +372,7 @@ impl FuncName {
             return FuncName::Unknown((parts[1].to_string(), sps));
         }
 
-        let name = if first == "_" { sub.to_string() } else { name };
+        let name = if first == '_' { sub.to_string() } else { name };
 
         FuncName::Unknown((name, 0))
     }
@@ -124,18 +382,50 @@ impl<'a> TypeDumper<'a> {
     /// Collect all the Type and their TypeIndex to be able to search for a TypeIndex
     pub fn new<'b>(
         type_info: &'a TypeInformation<'b>,
+        type_servers: &'a [TypeInformation<'static>],
         ptr_size: u32,
         flags: DumperFlags,
-    ) -> Result<Self> {
-        let mut types = type_info.iter();
-        let mut finder = type_info.finder();
-
+        cancel: CancelToken,
+    ) -> common::Result<Self> {
         // Some struct are incomplete so they've no size but they're forward references
         // So create a map containing names defining the struct (when they aren't fwd ref) and their size.
         // Once we'll need to compute a size for a fwd ref, we just use this map.
         let mut fwd = FwdRefSize::default();
+        let finder = Self::collect(type_info, &mut fwd, &cancel)?;
+        let servers = type_servers
+            .iter()
+            .map(|type_info| Self::collect(type_info, &mut fwd, &cancel))
+            .collect::<common::Result<Vec<_>>>()?;
 
-        while let Some(typ) = types.next()? {
+        Ok(Self {
+            finder,
+            servers,
+            fwd,
+            ptr_size,
+            flags,
+            cancel,
+            cache: Mutex::new(TypeCache::default()),
+        })
+    }
+
+    fn collect<'b>(
+        type_info: &'a TypeInformation<'b>,
+        fwd: &mut FwdRefSize<'a>,
+        cancel: &CancelToken,
+    ) -> common::Result<TypeFinder<'a>> {
+        let mut types = type_info.iter();
+        let mut finder = type_info.finder();
+        // The types are numbered from 0x1000 (the lower indices are the primitive types)
+        let mut index = 0x1000;
+
+        loop {
+            let typ = match types.next() {
+                Ok(Some(typ)) => typ,
+                Ok(None) => break,
+                Err(source) => return Err(Error::PdbType { index, source }.into()),
+            };
+            index = typ.index().0 + 1;
+            cancel.check_pdb()?;
             finder.update(&types);
             if let Ok(typ) = typ.parse() {
                 match typ {
@@ -156,17 +446,22 @@ impl<'a> TypeDumper<'a> {
             }
         }
 
-        Ok(Self {
-            finder,
-            fwd,
-            ptr_size,
-            flags,
-        })
+        Ok(finder)
     }
 
     fn find(&self, index: TypeIndex) -> Result<TypeData> {
-        let typ = self.finder.find(index).unwrap();
-        typ.parse()
+        match self.finder.find(index) {
+            Ok(typ) if typ.raw_kind() != LF_TYPESERVER2 => typ.parse(),
+            res => {
+                // The type is in a type server
+                for server in self.servers.iter() {
+                    if let Ok(typ) = server.find(index) {
+                        return typ.parse();
+                    }
+                }
+                res?.parse()
+            }
+        }
     }
 
     fn get_class_size(&self, typ: &ClassType) -> u32 {
@@ -175,7 +470,13 @@ impl<'a> TypeDumper<'a> {
 
             // The name can not be in self.fwd because the type can be a forward reference to itself !!
             // (it's possible with an empty struct)
-            *self.fwd.get(&name).unwrap_or(&typ.size.into())
+            match self.fwd.get(&name) {
+                Some(size) => *size,
+                None => {
+                    Self::report_fwd_ref(name);
+                    typ.size.into()
+                }
+            }
         } else {
             typ.size.into()
         }
@@ -184,22 +485,159 @@ impl<'a> TypeDumper<'a> {
     fn get_union_size(&self, typ: &UnionType) -> u32 {
         if typ.properties.forward_reference() {
             let name = typ.unique_name.unwrap_or(typ.name);
-            *self.fwd.get(&name).unwrap_or(&typ.size)
+            match self.fwd.get(&name) {
+                Some(size) => *size,
+                None => {
+                    Self::report_fwd_ref(name);
+                    typ.size
+                }
+            }
         } else {
             typ.size
         }
     }
 
+    fn report_fwd_ref(name: RawString) {
+        diagnostics::report(
+            Category::FwdRefSize,
+            format!("No size for the forward reference {}", name),
+        );
+    }
+
     pub fn get_type_size(&self, index: TypeIndex) -> u32 {
+        let _guard = match DepthGuard::enter() {
+            Some(guard) => guard,
+            None => {
+                report_cycle(index);
+                return 0;
+            }
+        };
         let typ = self.find(index);
         typ.ok().map_or(0, |typ| self.get_data_size(&typ))
     }
 
+    /// Get the size of the parameters of an x86 function on the stack from its type: each argument
+    /// takes a multiple of 4 bytes, "this" is in ecx with __thiscall, the two first integer arguments
+    /// (if they fit) are in ecx and edx with __fastcall and __vectorcall and the six first vector
+    /// ones are in xmm0-5 with __vectorcall
+    pub(super) fn get_stack_param_size(&self, index: TypeIndex) -> Option<u32> {
+        if self.ptr_size != 4 || index == TypeIndex(0) {
+            return None;
+        }
+
+        let (attributes, argument_list, this) = match self.find(index).ok()? {
+            TypeData::MemberFunction(t) => (t.attributes, t.argument_list, t.this_pointer_type),
+            TypeData::Procedure(t) => (t.attributes, t.argument_list, None),
+            _ => return None,
+        };
+        let arguments = match self.find(argument_list).ok()? {
+            TypeData::ArgumentList(list) => list.arguments,
+            _ => return None,
+        };
+
+        // https://github.com/microsoft/microsoft-pdb/blob/master/include/cvconst.h (CV_call_e)
+        let (gprs, xmms) = match attributes.calling_convention() {
+            0x04 /* NEAR_FAST */ => (2, 0),
+            0x18 /* NEAR_VECTOR */ => (2, VECTORCALL_XMMS),
+            0x0b /* THISCALL */ => (usize::from(this.is_some()), 0),
+            _ => (0, 0),
+        };
+        let args: Vec<_> = this
+            .into_iter()
+            .chain(arguments)
+            .map(|index| (self.get_type_size(index), self.get_arg_class(index)))
+            .collect();
+
+        Some(get_stack_size(&args, gprs, xmms))
+    }
+
+    /// Get the kind of registers an argument of an x86 function can be put in
+    fn get_arg_class(&self, index: TypeIndex) -> ArgClass {
+        if self.is_vector_type(index) {
+            return ArgClass::Vector;
+        }
+        let fields = match self.find(index) {
+            Ok(TypeData::Primitive(t)) => {
+                return match (t.indirection, t.kind) {
+                    (Some(_), _) => ArgClass::General,
+                    (None, PrimitiveKind::F32 | PrimitiveKind::F64) => ArgClass::Vector,
+                    (
+                        None,
+                        PrimitiveKind::F16
+                        | PrimitiveKind::F32PP
+                        | PrimitiveKind::F48
+                        | PrimitiveKind::F80
+                        | PrimitiveKind::F128
+                        | PrimitiveKind::Complex32
+                        | PrimitiveKind::Complex64
+                        | PrimitiveKind::Complex80
+                        | PrimitiveKind::Complex128,
+                    ) => ArgClass::Stack,
+                    _ => ArgClass::General,
+                }
+            }
+            Ok(TypeData::Pointer(_) | TypeData::Enumeration(_)) => return ArgClass::General,
+            Ok(TypeData::Modifier(t)) => {
+                return match DepthGuard::enter() {
+                    Some(_guard) => self.get_arg_class(t.underlying_type),
+                    None => ArgClass::General,
+                }
+            }
+            // The forward references have no fields: they can't be known as HVAs
+            Ok(TypeData::Class(t)) => match t.fields {
+                Some(fields) => fields,
+                None => return ArgClass::Stack,
+            },
+            Ok(TypeData::Union(t)) => t.fields,
+            _ => return ArgClass::General,
+        };
+
+        // An HVA has one to four members of the same vector type
+        let members: Vec<_> = match self.find(fields) {
+            Ok(TypeData::FieldList(list)) => list
+                .fields
+                .iter()
+                .filter_map(|field| match field {
+                    TypeData::Member(m) => Some(m.field_type),
+                    _ => None,
+                })
+                .collect(),
+            _ => return ArgClass::Stack,
+        };
+        match members.first() {
+            Some(first)
+                if members.len() <= 4
+                    && members.iter().all(|m| m == first)
+                    && self.is_vector_type(*first) =>
+            {
+                ArgClass::Hva(members.len())
+            }
+            _ => ArgClass::Stack,
+        }
+    }
+
+    /// Check if the type is __m128, __m256, ... (const or not)
+    fn is_vector_type(&self, index: TypeIndex) -> bool {
+        let name = match self.find(index) {
+            Ok(TypeData::Class(t)) => t.name,
+            Ok(TypeData::Union(t)) => t.name,
+            Ok(TypeData::Modifier(t)) => {
+                return DepthGuard::enter()
+                    .map_or(false, |_guard| self.is_vector_type(t.underlying_type))
+            }
+            _ => return false,
+        };
+        let name = name.as_bytes();
+        [&b"__m128"[..], b"__m256", b"__m512"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+    }
+
     fn get_data_size(&self, typ: &TypeData) -> u32 {
         match typ {
             TypeData::Primitive(t) => {
-                if t.indirection.is_some() {
-                    return self.ptr_size;
+                if let Some(indirection) = t.indirection {
+                    return indirection_size(indirection);
                 }
                 match t.kind {
                     PrimitiveKind::NoType | PrimitiveKind::Void => 0,
@@ -244,7 +682,8 @@ impl<'a> TypeDumper<'a> {
                     PrimitiveKind::Complex80 => 20,
                     PrimitiveKind::Complex128 => 32,
                     _ => {
-                        panic!("Unsupported primitive type {:?}", t.kind);
+                        report_unsupported_primitive(t.kind);
+                        0
                     }
                 }
             }
@@ -252,7 +691,7 @@ impl<'a> TypeDumper<'a> {
             TypeData::MemberFunction(_) => self.ptr_size,
             TypeData::Procedure(_) => self.ptr_size,
             TypeData::Pointer(t) => t.attributes.size().into(),
-            TypeData::Array(t) => *t.dimensions.last().unwrap(),
+            TypeData::Array(t) => t.dimensions.last().copied().unwrap_or(0),
             TypeData::Union(t) => self.get_union_size(t),
             TypeData::Enumeration(t) => self.get_type_size(t.underlying_type),
             TypeData::Enumerate(t) => match t.value {
@@ -268,7 +707,26 @@ impl<'a> TypeDumper<'a> {
 
     /// Dump a ProcedureType at the given TypeIndex
     /// If the TypeIndex is 0 then try to use demanglers to have the correct name
-    pub fn dump_function(&self, name: &str, index: TypeIndex) -> Result<FuncName> {
+    /// Return an error when the dump has been cancelled
+    pub(super) fn check_cancelled(&self) -> Result<()> {
+        self.cancel.check_pdb()
+    }
+
+    pub(super) fn demangle_publics(&self) -> bool {
+        !self.flags.intersects(DumperFlags::NO_DEMANGLE_PUBLICS)
+    }
+
+    pub(super) fn x86_param_sizes(&self) -> bool {
+        self.flags.intersects(DumperFlags::X86_PARAM_SIZES)
+    }
+
+    /// The names of the parameters (if any) are written after their types
+    pub fn dump_function(
+        &self,
+        name: &str,
+        index: TypeIndex,
+        param_names: &[String],
+    ) -> Result<FuncName> {
         if name.is_empty() {
             Ok(FuncName::Undecorated("<name omitted>".to_string()))
         } else if index == TypeIndex(0) {
@@ -280,9 +738,15 @@ impl<'a> TypeDumper<'a> {
                     let (ztatic, const_meth, ret, args) = self.dump_method_parts(
                         t,
                         self.flags.intersects(DumperFlags::NO_FUNCTION_RETURN),
+                        param_names,
                     )?;
-                    let ztatic = if ztatic { "static " } else { "" };
-                    let konst = if const_meth { " const" } else { "" };
+                    let qualifiers = !self.flags.intersects(DumperFlags::NO_METHOD_QUALIFIERS);
+                    let ztatic = if ztatic && qualifiers { "static " } else { "" };
+                    let konst = if const_meth && qualifiers {
+                        " const"
+                    } else {
+                        ""
+                    };
                     Ok(FuncName::Undecorated(format!(
                         "{}{}{}({}){}",
                         ztatic,
@@ -296,6 +760,7 @@ impl<'a> TypeDumper<'a> {
                     let (ret, args) = self.dump_procedure_parts(
                         t,
                         self.flags.intersects(DumperFlags::NO_FUNCTION_RETURN),
+                        param_names,
                     )?;
                     Ok(FuncName::Undecorated(format!(
                         "{}{}({})",
@@ -354,7 +819,10 @@ impl<'a> TypeDumper<'a> {
                 }
             }
             None => {
-                warn!("Didn't manage to demangle {}", ident);
+                diagnostics::report(
+                    Category::Demangling,
+                    format!("Didn't manage to demangle {}", ident),
+                );
                 FuncName::Undecorated(ident.to_string())
             }
         }
@@ -375,9 +843,10 @@ impl<'a> TypeDumper<'a> {
         &self,
         typ: ProcedureType,
         no_return: bool,
+        param_names: &[String],
     ) -> Result<(String, String)> {
         let ret_typ = self.get_return_type(typ.return_type, typ.attributes, no_return);
-        let args_typ = self.dump_index(typ.argument_list)?;
+        let args_typ = self.dump_args(typ.argument_list, param_names)?;
 
         Ok((ret_typ, args_typ))
     }
@@ -415,20 +884,28 @@ impl<'a> TypeDumper<'a> {
         &self,
         typ: MemberFunctionType,
         no_return: bool,
+        param_names: &[String],
     ) -> Result<(bool, bool, String, String)> {
         let ret_typ = self.get_return_type(Some(typ.return_type), typ.attributes, no_return);
-        let args_typ = self.dump_index(typ.argument_list)?;
+        let ztatic = typ.this_pointer_type.is_none();
+        // The first parameter of a method is "this" (or "self" in rust code)
+        let (this_name, param_names) = match param_names.split_first() {
+            Some((this_name, param_names)) if !ztatic => (Some(this_name), param_names),
+            _ => (None, param_names),
+        };
+        let args_typ = self.dump_args(typ.argument_list, param_names)?;
         // Note: "this" isn't dumped but there are some cases in rust code where
         // a first argument shouldn't be "this" but in fact it is:
         // https://hg.mozilla.org/releases/mozilla-release/annotate/7ece03f6971968eede29275477502309bbe399da/toolkit/components/bitsdownload/src/bits_interface/task/service_task.rs#l217
         // So we dump "this" when the underlying type (modulo pointer) is different from the class type
 
-        let ztatic = typ.this_pointer_type.is_none();
-        let (args_typ, const_meth) = if !ztatic {
-            let this_typ = typ.this_pointer_type.unwrap();
+        let (args_typ, const_meth) = if let Some(this_typ) = typ.this_pointer_type {
             let this_kind = self.check_this_type(this_typ, typ.class_type)?;
             if this_kind == ThisKind::NotThis {
-                let this_typ = self.dump_index(this_typ)?;
+                let this_typ = match this_name {
+                    Some(this_name) => format!("{} {}", self.dump_index(this_typ)?, this_name),
+                    None => self.dump_index(this_typ)?,
+                };
                 if args_typ.is_empty() {
                     (this_typ, false)
                 } else {
@@ -456,6 +933,13 @@ impl<'a> TypeDumper<'a> {
                         buf.push_str(" const");
                     }
                 }
+                if self.flags.intersects(DumperFlags::SPACE_BEFORE_POINTER)
+                    && !buf.is_empty()
+                    && !buf.ends_with(' ')
+                {
+                    // "char * *" and not "char **"
+                    buf.push(' ');
+                }
                 match attr.mode {
                     PointerMode::Pointer => buf.push('*'),
                     PointerMode::LValueReference => buf.push('&'),
@@ -482,7 +966,7 @@ impl<'a> TypeDumper<'a> {
         attributes: Vec<PtrAttributes>,
     ) -> Result<String> {
         let class = self.dump_index(fun.class_type)?;
-        let (_, _, ret, args) = self.dump_method_parts(fun, false)?;
+        let (_, _, ret, args) = self.dump_method_parts(fun, false, &[])?;
         let attrs = self.dump_attributes(attributes);
         Ok(format!(
             "{}({}{})({})",
@@ -494,7 +978,7 @@ impl<'a> TypeDumper<'a> {
     }
 
     fn dump_proc_ptr(&self, fun: ProcedureType, attributes: Vec<PtrAttributes>) -> Result<String> {
-        let (ret, args) = self.dump_procedure_parts(fun, false)?;
+        let (ret, args) = self.dump_procedure_parts(fun, false, &[])?;
         let attrs = self.dump_attributes(attributes);
         Ok(format!("{}({})({})", Self::fix_return(ret), attrs, args))
     }
@@ -502,14 +986,12 @@ impl<'a> TypeDumper<'a> {
     fn dump_other_ptr(&self, typ: TypeData, attributes: Vec<PtrAttributes>) -> Result<String> {
         let typ = self.dump_data(typ)?;
         let attrs = self.dump_attributes(attributes);
-        let c = typ.chars().last().unwrap();
-        let space = if !attrs.starts_with('c')
-            && (c == '*' || c == '&' || !self.flags.intersects(DumperFlags::SPACE_BEFORE_POINTER))
-        {
-            ""
-        } else {
-            " "
-        };
+        let space =
+            if attrs.starts_with('c') || self.flags.intersects(DumperFlags::SPACE_BEFORE_POINTER) {
+                " "
+            } else {
+                ""
+            };
 
         Ok(format!("{}{}{}", typ, space, attrs))
     }
@@ -530,6 +1012,10 @@ impl<'a> TypeDumper<'a> {
         }];
         let mut ptr = ptr;
         loop {
+            if attributes.len() > MAX_TYPE_DEPTH {
+                report_cycle(ptr);
+                return Ok(cyclic_type());
+            }
             let typ = self.find(ptr.underlying_type)?;
             match typ {
                 TypeData::Pointer(t) => {
@@ -562,30 +1048,38 @@ impl<'a> TypeDumper<'a> {
         }
     }
 
-    fn get_array_info(&self, array: ArrayType) -> Result<(Vec<u32>, TypeData)> {
+    /// Get the dimensions and the element type of an array (None if it's too deeply nested)
+    fn get_array_info(&self, array: ArrayType) -> Result<Option<(Vec<u32>, TypeData)>> {
         // For an array int[12][34] it'll be represented as "int[34] *".
         // For any reason the 12 is lost...
         // The internal representation is: Pointer{ base: Array{ base: int, dim: 34 * sizeof(int)} }
         let mut base = array;
         let mut dims = Vec::new();
-        dims.push(base.dimensions[0]);
+        dims.push(base.dimensions.first().copied().unwrap_or(0));
 
         loop {
+            if dims.len() > MAX_TYPE_DEPTH {
+                report_cycle(base);
+                return Ok(None);
+            }
             let typ = self.find(base.element_type)?;
             match typ {
                 TypeData::Array(a) => {
-                    dims.push(a.dimensions[0]);
+                    dims.push(a.dimensions.first().copied().unwrap_or(0));
                     base = a;
                 }
                 _ => {
-                    return Ok((dims, typ));
+                    return Ok(Some((dims, typ)));
                 }
             }
         }
     }
 
     fn dump_array(&self, array: ArrayType) -> Result<String> {
-        let (dimensions, base) = self.get_array_info(array)?;
+        let (dimensions, base) = match self.get_array_info(array)? {
+            Some(info) => info,
+            None => return Ok(cyclic_type()),
+        };
         let base_size = self.get_data_size(&base);
         let mut size = base_size;
         let mut dims = dimensions
@@ -637,84 +1131,57 @@ impl<'a> TypeDumper<'a> {
         }
     }
 
-    fn dump_arg_list(&self, list: ArgumentList) -> Result<String> {
+    fn dump_args(&self, index: TypeIndex, param_names: &[String]) -> Result<String> {
+        match self.find(index)? {
+            TypeData::ArgumentList(list) => self.dump_arg_list(list, param_names),
+            typ => self.dump_data(typ),
+        }
+    }
+
+    fn dump_arg_list(&self, list: ArgumentList, param_names: &[String]) -> Result<String> {
         let mut buf = String::new();
         let comma = if self.flags.intersects(DumperFlags::SPACE_AFTER_COMMA) {
             ", "
         } else {
             ","
         };
-        if let Some((last, args)) = list.arguments.split_last() {
-            for index in args.iter() {
-                let typ = self.dump_index(*index)?;
-                buf.push_str(&typ);
+        for (i, index) in list.arguments.iter().enumerate() {
+            if i != 0 {
                 buf.push_str(comma);
             }
-            let typ = self.dump_index(*last)?;
+            let typ = self.dump_index(*index)?;
             buf.push_str(&typ);
+            // The unnamed parameters have no name or are __formal
+            if let Some(name) = param_names
+                .get(i)
+                .filter(|n| !n.is_empty() && *n != "__formal")
+            {
+                buf.push(' ');
+                buf.push_str(name);
+            }
         }
         Ok(buf)
     }
 
     fn dump_primitive(&self, prim: PrimitiveType, is_const: bool) -> String {
-        // TODO: check that these names are what we want to see
-        let name = match prim.kind {
-            PrimitiveKind::NoType => "<NoType>",
-            PrimitiveKind::Void => "void",
-            PrimitiveKind::Char => "signed char",
-            PrimitiveKind::UChar => "unsigned char",
-            PrimitiveKind::RChar => "char",
-            PrimitiveKind::WChar => "wchar_t",
-            PrimitiveKind::RChar16 => "char16_t",
-            PrimitiveKind::RChar32 => "char32_t",
-            PrimitiveKind::I8 => "int8_t",
-            PrimitiveKind::U8 => "uint8_t",
-            PrimitiveKind::Short => "short",
-            PrimitiveKind::UShort => "unsigned short",
-            PrimitiveKind::I16 => "int16_t",
-            PrimitiveKind::U16 => "uint16_t",
-            PrimitiveKind::Long => "long",
-            PrimitiveKind::ULong => "unsigned long",
-            PrimitiveKind::I32 => "int",
-            PrimitiveKind::U32 => "unsigned int",
-            PrimitiveKind::Quad => "long long",
-            PrimitiveKind::UQuad => "unsigned long long",
-            PrimitiveKind::I64 => "int64_t",
-            PrimitiveKind::U64 => "uint64_t",
-            PrimitiveKind::I128 | PrimitiveKind::Octa => "int128_t",
-            PrimitiveKind::U128 | PrimitiveKind::UOcta => "uint128_t",
-            PrimitiveKind::F16 => "float16_t",
-            PrimitiveKind::F32 => "float",
-            PrimitiveKind::F32PP => "float",
-            PrimitiveKind::F48 => "float48_t",
-            PrimitiveKind::F64 => "double",
-            PrimitiveKind::F80 => "long double",
-            PrimitiveKind::F128 => "long double",
-            PrimitiveKind::Complex32 => "complex<float>",
-            PrimitiveKind::Complex64 => "complex<double>",
-            PrimitiveKind::Complex80 => "complex<long double>",
-            PrimitiveKind::Complex128 => "complex<long double>",
-            PrimitiveKind::Bool8 => "bool",
-            PrimitiveKind::Bool16 => "bool16_t",
-            PrimitiveKind::Bool32 => "bool32_t",
-            PrimitiveKind::Bool64 => "bool64_t",
-            PrimitiveKind::HRESULT => "HRESULT",
-            _ => {
-                panic!("Unsupported primitive type {:?}", prim.kind);
-            }
-        };
-
-        if prim.indirection.is_some() {
+        let name = primitive_name(
+            prim.kind,
+            self.flags.intersects(DumperFlags::MSVC_TYPE_NAMES),
+        );
+
+        if let Some(indirection) = prim.indirection {
+            // A 32-bit pointer in a 64-bit module is an int* __ptr32
+            let qualifier = pointer_qualifier(indirection, self.ptr_size);
             if self.flags.intersects(DumperFlags::SPACE_BEFORE_POINTER) {
                 if is_const {
-                    format!("{} const *", name)
+                    format!("{} const *{}", name, qualifier)
                 } else {
-                    format!("{} *", name)
+                    format!("{} *{}", name, qualifier)
                 }
             } else if is_const {
-                format!("{} const*", name)
+                format!("{} const*{}", name, qualifier)
             } else {
-                format!("{}*", name)
+                format!("{}*{}", name, qualifier)
             }
         } else if is_const {
             format!("const {}", name)
@@ -731,35 +1198,71 @@ impl<'a> TypeDumper<'a> {
         }
     }
 
+    /// Get the name of a type (for the types of the variables)
+    pub(super) fn dump_type(&self, index: TypeIndex) -> String {
+        self.dump_index(index)
+            .unwrap_or_else(|_| format!("<unknown type 0x{:x}>", index.0))
+    }
+
     fn dump_index(&self, index: TypeIndex) -> Result<String> {
-        let typ = self.find(index)?;
-        self.dump_data(typ)
+        if let Some(typ) = self.cache.lock().unwrap().types.get(&index) {
+            return Ok(typ.to_string());
+        }
+        // The lock isn't held while dumping since the nested types are dumped with dump_index too
+        let outer = TYPE_TRUNCATED.with(|truncated| truncated.replace(false));
+        let typ = self.find(index).and_then(|typ| self.dump_data(typ));
+        // The types containing this one are truncated too
+        let nested = TYPE_DEPTH.with(|depth| depth.get()) != 0;
+        let truncated = TYPE_TRUNCATED
+            .with(|truncated| truncated.replace(nested && (outer || truncated.get())));
+        let typ = typ?;
+        if !truncated {
+            let mut cache = self.cache.lock().unwrap();
+            let interned = cache.names.intern(&typ);
+            cache.types.insert(index, interned);
+        }
+        Ok(typ)
     }
 
     fn dump_data(&self, typ: TypeData) -> Result<String> {
+        let _guard = match DepthGuard::enter() {
+            Some(guard) => guard,
+            None => {
+                report_cycle(typ);
+                return Ok(cyclic_type());
+            }
+        };
         let typ = match typ {
             TypeData::Primitive(t) => self.dump_primitive(t, false),
             TypeData::Class(t) => self.dump_class(t),
             TypeData::MemberFunction(t) => {
-                let (_, _, ret, args) = self
-                    .dump_method_parts(t, self.flags.intersects(DumperFlags::NO_FUNCTION_RETURN))?;
+                let (_, _, ret, args) = self.dump_method_parts(
+                    t,
+                    self.flags.intersects(DumperFlags::NO_FUNCTION_RETURN),
+                    &[],
+                )?;
                 format!("{}()({})", Self::fix_return(ret), args)
             }
             TypeData::Procedure(t) => {
                 let (ret, args) = self.dump_procedure_parts(
                     t,
                     self.flags.intersects(DumperFlags::NO_FUNCTION_RETURN),
+                    &[],
                 )?;
                 format!("{}()({})", Self::fix_return(ret), args)
             }
-            TypeData::ArgumentList(t) => self.dump_arg_list(t)?,
+            TypeData::ArgumentList(t) => self.dump_arg_list(t, &[])?,
             TypeData::Pointer(t) => self.dump_ptr(t, false)?,
             TypeData::Array(t) => self.dump_array(t)?,
             TypeData::Union(t) => self.dump_named("union", t.name),
             TypeData::Enumeration(t) => self.dump_named("enum", t.name),
-            TypeData::Enumerate(t) => self.dump_named("enum class", t.name),
+            // An enumerator: NAME = value
+            TypeData::Enumerate(t) => format!("{} = {}", t.name, format_constant(t.value)),
             TypeData::Modifier(t) => self.dump_modifier(t)?,
-            _ => format!("unhandled type /* {:?} */", typ),
+            _ => {
+                diagnostics::report(Category::UnhandledType, format!("Unhandled type {:?}", typ));
+                format!("unhandled type /* {:?} */", typ)
+            }
         };
 
         Ok(typ)
@@ -789,6 +1292,171 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_funcname_vectorcall() {
+        let get = |name: &str| match FuncName::get_unknown(name.to_string()) {
+            FuncName::Unknown(unknown) => unknown,
+            FuncName::Undecorated(name) => (name, u32::MAX),
+        };
+
+        // Three ints: the third one is on the stack
+        assert_eq!(get("foo@@12"), ("foo".to_string(), 4));
+        // A char and a short in the registers and two ints on the stack
+        assert_eq!(get("foo@@16"), ("foo".to_string(), 8));
+        assert_eq!(get("foo@@8"), ("foo".to_string(), 0));
+        assert_eq!(get("foo@@0"), ("foo".to_string(), 0));
+        assert_eq!(get("_foo@@4"), ("_foo".to_string(), 0));
+
+        // Not some vectorcall names
+        assert_eq!(get("?foo@@YAXXZ"), ("?foo@@YAXXZ".to_string(), 0));
+        assert_eq!(get("foo@@"), ("foo@@".to_string(), 0));
+        assert_eq!(get("_foo@12"), ("foo".to_string(), 12));
+    }
+
+    #[test]
+    fn test_dump_index_cache() {
+        let path = std::path::PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = pdb::PDB::open(std::io::Cursor::new(&buf)).unwrap();
+        let type_info = pdb.type_information().unwrap();
+        let dumper = TypeDumper::new(
+            &type_info,
+            &[],
+            8,
+            DumperFlags::default(),
+            CancelToken::default(),
+        )
+        .unwrap();
+
+        let mut types = type_info.iter();
+        let mut dumped = Vec::new();
+        while let Some(typ) = types.next().unwrap() {
+            if let Ok(name) = dumper.dump_index(typ.index()) {
+                dumped.push((typ.index(), name));
+            }
+        }
+        assert!(!dumped.is_empty());
+        let cache = dumper.cache.lock().unwrap();
+        assert!(cache.types.len() >= dumped.len());
+        drop(cache);
+        for (index, name) in dumped {
+            assert_eq!(dumper.dump_index(index).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_dump_index_truncated() {
+        let path = std::path::PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = pdb::PDB::open(std::io::Cursor::new(&buf)).unwrap();
+        let type_info = pdb.type_information().unwrap();
+        let dumper = TypeDumper::new(
+            &type_info,
+            &[],
+            8,
+            DumperFlags::default(),
+            CancelToken::default(),
+        )
+        .unwrap();
+
+        // A pointer to a class
+        let mut types = type_info.iter();
+        let index = loop {
+            let typ = types.next().unwrap().unwrap();
+            if let Ok(TypeData::Pointer(ptr)) = typ.parse() {
+                if let Ok(TypeData::Class(_)) = dumper.find(ptr.underlying_type) {
+                    break typ.index();
+                }
+            }
+        };
+
+        // The class is cut when the pointer is dumped at the depth limit
+        let guards: Vec<_> = (0..MAX_TYPE_DEPTH - 1)
+            .map(|_| DepthGuard::enter().unwrap())
+            .collect();
+        let truncated = dumper.dump_index(index).unwrap();
+        drop(guards);
+        assert!(truncated.contains(CYCLIC_TYPE));
+        assert!(!dumper.cache.lock().unwrap().types.contains_key(&index));
+
+        let name = dumper.dump_index(index).unwrap();
+        assert!(!name.contains(CYCLIC_TYPE));
+        assert!(dumper.cache.lock().unwrap().types.contains_key(&index));
+        assert!(!TYPE_TRUNCATED.with(|truncated| truncated.get()));
+    }
+
+    #[test]
+    fn test_format_constant() {
+        assert_eq!(format_constant(Variant::U16(0x7fff)), "32767");
+        assert_eq!(format_constant(Variant::I8(-1)), "-1");
+        assert_eq!(format_constant(Variant::I16(i16::MIN)), "-32768");
+        assert_eq!(format_constant(Variant::I32(-1)), "-1");
+        assert_eq!(format_constant(Variant::I32(i32::MIN)), "-2147483648");
+        assert_eq!(format_constant(Variant::U32(0x8000_0000)), "2147483648");
+        assert_eq!(format_constant(Variant::U32(u32::MAX)), "4294967295");
+        assert_eq!(
+            format_constant(Variant::I64(i64::MIN)),
+            "-9223372036854775808"
+        );
+        assert_eq!(
+            format_constant(Variant::U64(0x8000_0000_0000_0000)),
+            "9223372036854775808"
+        );
+
+        // The enumerators in a field list
+        let path = std::path::PathBuf::from("./test_data/windows/basic64.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = pdb::PDB::open(std::io::Cursor::new(&buf)).unwrap();
+        let type_info = pdb.type_information().unwrap();
+        let dumper = TypeDumper::new(
+            &type_info,
+            &[],
+            8,
+            DumperFlags::default(),
+            CancelToken::default(),
+        )
+        .unwrap();
+        let mut types = type_info.iter();
+        let enumerate = loop {
+            let typ = types.next().unwrap().unwrap();
+            if let Ok(TypeData::FieldList(list)) = typ.parse() {
+                if let Some(TypeData::Enumerate(e)) = list.fields.into_iter().next() {
+                    break e;
+                }
+            }
+        };
+        let expected = format!("{} = {}", enumerate.name, enumerate.value);
+        assert_eq!(
+            dumper.dump_data(TypeData::Enumerate(enumerate)).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_primitive_name() {
+        assert_eq!(primitive_name(PrimitiveKind::Quad, false), "long long");
+        assert_eq!(primitive_name(PrimitiveKind::Quad, true), "__int64");
+        assert_eq!(
+            primitive_name(PrimitiveKind::UQuad, true),
+            "unsigned __int64"
+        );
+        assert_eq!(primitive_name(PrimitiveKind::WChar, true), "wchar_t");
+        assert_eq!(primitive_name(PrimitiveKind::U32, true), "unsigned int");
+        assert_eq!(primitive_name(PrimitiveKind::Long, false), "long");
+        assert_eq!(primitive_name(PrimitiveKind::ULong, false), "unsigned long");
+        assert_eq!(primitive_name(PrimitiveKind::I32, false), "int");
+    }
+
+    #[test]
+    fn test_pointer_qualifier() {
+        assert_eq!(indirection_size(Indirection::Near64), 8);
+        assert_eq!(indirection_size(Indirection::Far32), 6);
+        assert_eq!(pointer_qualifier(Indirection::Near32, 8), " __ptr32");
+        assert_eq!(pointer_qualifier(Indirection::Near64, 4), " __ptr64");
+        assert_eq!(pointer_qualifier(Indirection::Near64, 8), "");
+        assert_eq!(pointer_qualifier(Indirection::Near32, 4), "");
+    }
+
     #[test]
     fn test_funcname() {
         if let FuncName::Unknown((name, sps)) = FuncName::get_unknown("_foo@123()".to_string()) {
@@ -820,5 +1488,89 @@ mod tests {
             assert_eq!(name, "@foobar");
             assert_eq!(sps, 0);
         }
+
+        // The first char is more than one byte
+        if let FuncName::Unknown((name, sps)) = FuncName::get_unknown("éfoo@4".to_string()) {
+            assert_eq!(name, "éfoo@4");
+            assert_eq!(sps, 0);
+        }
+
+        if let FuncName::Unknown((name, sps)) = FuncName::get_unknown("_éfoo@4".to_string()) {
+            assert_eq!(name, "éfoo");
+            assert_eq!(sps, 4);
+        }
+    }
+
+    #[test]
+    fn test_stack_size() {
+        use ArgClass::*;
+
+        let int = (4, General);
+        let double = (8, Vector);
+        let m128 = (16, Vector);
+
+        // __vectorcall: the ints in ecx and edx, the doubles in xmm0-5
+        assert_eq!(get_stack_size(&[int, double, int, double], 2, 6), 0);
+        assert_eq!(get_stack_size(&[double, int, double, int, int], 2, 6), 4);
+        assert_eq!(get_stack_size(&[int; 2], 2, 6), 0);
+        assert_eq!(get_stack_size(&[double; 7], 2, 6), 8);
+        assert_eq!(get_stack_size(&[m128, double, (64, Hva(4)), int], 2, 6), 0);
+        assert_eq!(get_stack_size(&[(64, Hva(4)), m128, m128, m128], 2, 6), 64);
+
+        // __fastcall: the doubles are on the stack
+        assert_eq!(get_stack_size(&[double, int, int, int], 2, 0), 12);
+        // __fastcall: a struct (even a small one) is on the stack and the next ints are in ecx and edx
+        let small_struct = (4, Stack);
+        assert_eq!(get_stack_size(&[small_struct, int, int], 2, 0), 4);
+        assert_eq!(get_stack_size(&[int, (2, Stack), int, int], 2, 0), 8);
+        // __vectorcall: the same for the long doubles
+        assert_eq!(get_stack_size(&[(10, Stack), int, double], 2, 6), 12);
+        // __stdcall
+        assert_eq!(get_stack_size(&[int, double, (1, General)], 0, 0), 16);
+    }
+
+    #[test]
+    fn test_arg_class() {
+        let path = std::path::PathBuf::from("./test_data/windows/basic-opt32.pdb");
+        let buf = crate::utils::read_file(&path);
+        let mut pdb = pdb::PDB::open(std::io::Cursor::new(&buf)).unwrap();
+        let type_info = pdb.type_information().unwrap();
+        let dumper = TypeDumper::new(
+            &type_info,
+            &[],
+            4,
+            DumperFlags::default(),
+            CancelToken::default(),
+        )
+        .unwrap();
+
+        // int, double, long double and int*
+        assert_eq!(dumper.get_arg_class(TypeIndex(0x74)), ArgClass::General);
+        assert_eq!(dumper.get_arg_class(TypeIndex(0x41)), ArgClass::Vector);
+        assert_eq!(dumper.get_arg_class(TypeIndex(0x42)), ArgClass::Stack);
+        assert_eq!(dumper.get_arg_class(TypeIndex(0x474)), ArgClass::General);
+
+        // A struct isn't put in ecx or edx whatever its size
+        let mut types = type_info.iter();
+        let index = loop {
+            let typ = types.next().unwrap().unwrap();
+            if let Ok(TypeData::Class(class)) = typ.parse() {
+                if class.fields.is_some() && class.size <= 4 {
+                    break typ.index();
+                }
+            }
+        };
+        assert_eq!(dumper.get_arg_class(index), ArgClass::Stack);
+    }
+
+    #[test]
+    fn test_depth_guard() {
+        let guards: Vec<_> = (0..MAX_TYPE_DEPTH)
+            .map(|_| DepthGuard::enter().unwrap())
+            .collect();
+        assert!(DepthGuard::enter().is_none());
+        drop(guards);
+        assert_eq!(TYPE_DEPTH.with(|depth| depth.get()), 0);
+        assert!(DepthGuard::enter().is_some());
     }
 }
@@ -4,13 +4,16 @@
 // copied, modified, or distributed except according to those terms.
 
 use log::warn;
+use pdb::PDB;
+use std::io::Cursor;
 use std::path::Path;
 use symbolic::debuginfo::pe::PeObject;
 use uuid::Uuid;
 
 use crate::cache::{self, SymbolServer};
+use crate::common;
+use crate::error::Error;
 use crate::utils;
-use crate::windows::pdb::PDBInfo;
 
 fn try_to_find_pdb(path: &Path, pdb_filename: &str) -> Option<Vec<u8>> {
     // Just check that the file is in the same directory as the PE one
@@ -23,11 +26,11 @@ fn try_to_find_pdb(path: &Path, pdb_filename: &str) -> Option<Vec<u8>> {
             return Some(utils::read_file(pdb));
         } else {
             // We try in CWD
-            let mut pdb =
-                std::env::current_dir().expect("Unable to get the current working directory");
-            pdb.set_file_name(pdb_filename);
-            if pdb.is_file() {
-                return Some(utils::read_file(pdb));
+            if let Ok(mut pdb) = std::env::current_dir() {
+                pdb.set_file_name(pdb_filename);
+                if pdb.is_file() {
+                    return Some(utils::read_file(pdb));
+                }
             }
         }
     }
@@ -43,7 +46,7 @@ fn os_specific_try_to_find_pdb(path: &Path, pdb_filename: &str) -> (Option<Vec<u
     let pdb_filename = pdb_filename.replace("\\", "/");
     let pdb_path = Path::new(&pdb_filename);
     if let Some(file_name) = pdb_path.file_name() {
-        let pdb_name = file_name.to_str().unwrap().to_string();
+        let pdb_name = file_name.to_string_lossy().into_owned();
         if pdb_path.is_file() {
             (Some(utils::read_file(pdb_path)), pdb_name)
         } else {
@@ -54,29 +57,37 @@ fn os_specific_try_to_find_pdb(path: &Path, pdb_filename: &str) -> (Option<Vec<u
     }
 }
 
+pub(crate) fn parse_pe<'a>(path: &Path, buf: &'a [u8]) -> common::Result<PeObject<'a>> {
+    PeObject::parse(buf).map_err(|source| {
+        Error::Pe {
+            path: path.display().to_string(),
+            source,
+        }
+        .into()
+    })
+}
+
 pub fn get_pe_pdb_buf<'a>(
     path: &Path,
     buf: &'a [u8],
     symbol_server: Option<&Vec<SymbolServer>>,
-) -> Option<(PeObject<'a>, Vec<u8>, String)> {
-    let pe = PeObject::parse(buf)
-        .unwrap_or_else(|_| panic!("Unable to parse the PE file {}", path.to_str().unwrap()));
-    if let Some(pdb_filename) = pe.debug_file_name() {
-        let pdb_filename = pdb_filename.into_owned();
-        let (pdb, pdb_name) = os_specific_try_to_find_pdb(path, &pdb_filename);
-        if pdb_name.is_empty() {
-            warn!("Invalid pdb filename in PE file: \"{}\"", pdb_filename);
-            None
-        } else if let Some(pdb_buf) = pdb {
-            Some((pe, pdb_buf, pdb_name))
-        } else {
-            // Not here so try symbol server (or cache)
-            let debug_id = get_pe_debug_id(Some(&pe)).unwrap();
-            let (pdb, pdb_name) = cache::search_file(pdb_name, &debug_id, symbol_server);
-            pdb.map(|pdb_buf| (pe, pdb_buf, pdb_name))
-        }
+) -> common::Result<Option<(PeObject<'a>, Vec<u8>, String)>> {
+    let pe = parse_pe(path, buf)?;
+    let pdb_filename = match pe.debug_file_name() {
+        Some(pdb_filename) => pdb_filename.into_owned(),
+        None => return Ok(None),
+    };
+    let (pdb, pdb_name) = os_specific_try_to_find_pdb(path, &pdb_filename);
+    if pdb_name.is_empty() {
+        warn!("Invalid pdb filename in PE file: \"{}\"", pdb_filename);
+        Ok(None)
+    } else if let Some(pdb_buf) = pdb {
+        Ok(Some((pe, pdb_buf, pdb_name)))
     } else {
-        None
+        // Not here so try symbol server (or cache)
+        let debug_id = get_pe_debug_id(Some(&pe)).unwrap_or_default();
+        let (pdb, pdb_name) = cache::search_file(pdb_name, &debug_id, symbol_server);
+        Ok(pdb.map(|pdb_buf| (pe, pdb_buf, pdb_name)))
     }
 }
 
@@ -100,22 +111,190 @@ fn fix_extension(ext: &str) -> &str {
     }
 }
 
-pub(crate) fn try_to_set_pe(path: &Path, pdb_info: &mut PDBInfo, pdb_buf: &[u8]) {
-    // Just check that the file is in the same directory as the PDB one
-    let mut path = path.to_path_buf();
-    for ext in vec!["dll", "dl_", "exe", "ex_"].drain(..) {
-        path.set_extension(ext);
-        if path.is_file() {
-            let buf = utils::read_file(&path);
-            if let Ok(pe) = PeObject::parse(&buf) {
-                if ext.ends_with('_') {
-                    path.set_extension(fix_extension(ext));
+/// Find the dll or the exe next to the pdb or in the binary paths and give it to set_pe (which
+/// returns true when it matches)
+pub(crate) fn try_to_set_pe<F: FnMut(String, PeObject) -> bool>(
+    path: &Path,
+    binary_paths: &[String],
+    mut set_pe: F,
+) {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        _ => return,
+    };
+    let dirs = path
+        .parent()
+        .into_iter()
+        .chain(binary_paths.iter().map(Path::new));
+    for dir in dirs {
+        let mut path = dir.join(file_name);
+        for ext in vec!["dll", "dl_", "exe", "ex_"].drain(..) {
+            path.set_extension(ext);
+            if path.is_file() {
+                let buf = utils::read_file(&path);
+                if let Ok(pe) = PeObject::parse(&buf) {
+                    if ext.ends_with('_') {
+                        path.set_extension(fix_extension(ext));
+                    }
+                    let filename = utils::get_filename(&path);
+                    if set_pe(filename, pe) {
+                        return;
+                    }
                 }
-                let filename = utils::get_filename(&path);
-                if pdb_info.set_pe(filename, pe, pdb_buf) {
-                    break;
+            }
+        }
+    }
+}
+
+/// Guess the code id of the dll or the exe of a pdb: the signature of the pdb is the timestamp of
+/// the link when the pdb has been written by it (not with an incremental link or a reproducible
+/// build) and the image ends with the last section
+pub(crate) fn get_pdb_code_id(buf: &[u8]) -> Option<String> {
+    let mut pdb = PDB::open(Cursor::new(buf)).ok()?;
+    let signature = pdb.pdb_information().ok()?.signature;
+    let sections = pdb.sections().ok()??;
+    let end = sections
+        .iter()
+        // The virtual size is in the union with the physical address
+        .map(|s| s.virtual_address + s.physical_address)
+        .max()?;
+    // The sections are aligned on pages
+    let size_of_image = (end + 0xfff) & !0xfff;
+    Some(format!("{:08X}{:X}", signature, size_of_image))
+}
+
+/// Search the dll or the exe of a pdb on the symbol servers (with its guessed code id) and give it
+/// to set_pe (which returns true when it matches)
+pub(crate) fn fetch_pe<F: FnMut(String, PeObject) -> bool>(
+    buf: &[u8],
+    path: &Path,
+    symbol_server: Option<&Vec<SymbolServer>>,
+    mut set_pe: F,
+) {
+    let code_id = match get_pdb_code_id(buf) {
+        Some(code_id) => code_id,
+        _ => return,
+    };
+    for ext in ["dll", "exe"].iter() {
+        let name = utils::get_filename(&path.with_extension(ext));
+        if let (Some(pe_buf), name) = cache::search_file(name, &code_id, symbol_server) {
+            if let Ok(pe) = PeObject::parse(&pe_buf) {
+                if set_pe(name, pe) {
+                    return;
                 }
             }
         }
     }
 }
+
+/// Normalize a Windows path from a pdb: the separators are unified to backslashes, the duplicated
+/// ones, the "." and the ".." are collapsed and the case is folded if required.
+/// None is returned for the paths which don't look like Windows ones (no drive and no backslash).
+pub(super) fn normalize_file_path(path: &str, fold_case: bool) -> Option<String> {
+    let bytes = path.as_bytes();
+    let has_drive = bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic();
+    if !has_drive && !path.contains('\\') {
+        return None;
+    }
+
+    let path = path.replace('/', "\\");
+    let (drive, rest) = path.split_at(if has_drive { 2 } else { 0 });
+    let is_unc = !has_drive && rest.starts_with("\\\\");
+    let is_rooted = rest.starts_with('\\');
+    // The server and the share of an UNC path can't be removed by a ".."
+    let fixed = if is_unc { 2 } else { 0 };
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split('\\') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.len() > fixed && components.last() != Some(&"..") {
+                    components.pop();
+                } else if !is_rooted {
+                    components.push("..");
+                }
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let root = if is_unc {
+        "\\\\"
+    } else if is_rooted {
+        "\\"
+    } else {
+        ""
+    };
+    let path = format!("{}{}{}", drive, root, components.join("\\"));
+    Some(if fold_case { path.to_lowercase() } else { path })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_pdb_code_id() {
+        let pdb = utils::read_file("./test_data/windows/basic64.pdb");
+        let dll = utils::read_file("./test_data/windows/basic64.dll");
+        let pe = PeObject::parse(&dll).unwrap();
+        let code_id = pe.code_id().unwrap().as_str().to_uppercase();
+        let guess = get_pdb_code_id(&pdb).unwrap();
+        assert_eq!(guess.len(), code_id.len());
+        // This pdb has been written after the link so only the size of the image matches
+        assert_eq!(&guess[..8], "5DDC18C7");
+        assert_eq!(&guess[8..], &code_id[8..]);
+    }
+
+    #[test]
+    fn test_binary_paths() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let pdb = tmp_dir.path().join("basic64.pdb");
+        std::fs::copy("./test_data/windows/basic64.pdb", &pdb).unwrap();
+
+        let mut found = Vec::new();
+        try_to_set_pe(&pdb, &[], |name, _| {
+            found.push(name);
+            true
+        });
+        assert!(found.is_empty());
+
+        // The pe which doesn't match is skipped
+        let binary_paths = vec!["./test_data/windows".to_string()];
+        try_to_set_pe(&pdb, &binary_paths, |name, _| {
+            found.push(name);
+            false
+        });
+        assert_eq!(found, vec!["basic64.dll"]);
+    }
+
+    #[test]
+    fn test_normalize_file_path() {
+        let normalize = |path| normalize_file_path(path, false);
+        assert_eq!(
+            normalize("c:/foo\\bar/../baz.cpp").unwrap(),
+            "c:\\foo\\baz.cpp"
+        );
+        assert_eq!(
+            normalize("C:\\foo\\.\\\\bar\\baz.cpp").unwrap(),
+            "C:\\foo\\bar\\baz.cpp"
+        );
+        assert_eq!(normalize("c:\\..\\foo.h").unwrap(), "c:\\foo.h");
+        assert_eq!(
+            normalize("..\\..\\foo\\..\\bar.h").unwrap(),
+            "..\\..\\bar.h"
+        );
+        assert_eq!(
+            normalize("\\\\server\\share\\..\\foo.h").unwrap(),
+            "\\\\server\\share\\foo.h"
+        );
+        assert_eq!(
+            normalize_file_path("C:\\Foo\\Bar.CPP", true).unwrap(),
+            "c:\\foo\\bar.cpp"
+        );
+        assert!(normalize("/builds/foo/../bar.cpp").is_none());
+        assert!(normalize("foo.cpp").is_none());
+    }
+}
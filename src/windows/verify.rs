@@ -0,0 +1,479 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use std::convert::TryInto;
+
+// https://llvm.org/docs/PDB/MsfFile.html
+const MSF7_MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+const MSF2_MAGIC: &[u8] = b"Microsoft C/C++ program database 2.00\r\n\x1aJG\0\0";
+const SUPERBLOCK_SIZE: usize = 56;
+const NIL_STREAM: u32 = 0xFFFF_FFFF;
+
+const PDB_STREAM: usize = 1;
+const TPI_STREAM: usize = 2;
+const DBI_STREAM: usize = 3;
+const IPI_STREAM: usize = 4;
+
+const PDB_VERSIONS: &[u32] = &[
+    19_941_610, 19_950_623, 19_950_814, 19_960_307, 19_970_604, 19_990_604, 20_000_404,
+];
+const TPI_VERSIONS: &[u32] = &[19_950_410, 19_951_122, 19_961_031, 19_990_903, 20_040_203];
+const DBI_VERSIONS: &[u32] = &[930_803, 19_960_307, 19_970_606, 19_990_903, 20_091_201];
+const TPI_HEADER_SIZE: usize = 56;
+const DBI_HEADER_SIZE: usize = 64;
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    buf.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn get_num_blocks(size: usize, block_size: usize) -> usize {
+    if size == 0 {
+        0
+    } else {
+        (size - 1) / block_size + 1
+    }
+}
+
+struct Msf<'a> {
+    buf: &'a [u8],
+    block_size: usize,
+    num_blocks: usize,
+    streams: Vec<Option<(usize, Vec<u32>)>>,
+    problems: Vec<String>,
+}
+
+impl<'a> Msf<'a> {
+    fn get_block(&self, index: u32) -> Option<&'a [u8]> {
+        let start = index as usize * self.block_size;
+        self.buf.get(start..start + self.block_size)
+    }
+
+    /// Check that a block index is valid: return false if it isn't
+    fn check_block(&mut self, index: u32, what: &dyn Fn() -> String) -> bool {
+        let index_usize = index as usize;
+        if index_usize >= self.num_blocks {
+            self.problems.push(format!(
+                "{}: block {} is out of range (the file has {} blocks)",
+                what(),
+                index,
+                self.num_blocks
+            ));
+            false
+        } else if index == 0 {
+            self.problems
+                .push(format!("{}: block 0 is the superblock", what()));
+            false
+        } else if matches!(index_usize % self.block_size, 1 | 2) {
+            self.problems.push(format!(
+                "{}: block {} is a free block map block",
+                what(),
+                index
+            ));
+            false
+        } else if self.get_block(index).is_none() {
+            self.problems.push(format!(
+                "{}: block {} is beyond the end of the file",
+                what(),
+                index
+            ));
+            false
+        } else {
+            true
+        }
+    }
+
+    fn parse_superblock(&mut self) -> Option<(usize, u32)> {
+        if self.buf.len() < SUPERBLOCK_SIZE {
+            self.problems.push(format!(
+                "The file is too short ({} bytes) to contain a MSF superblock",
+                self.buf.len()
+            ));
+            return None;
+        }
+
+        if self.buf.starts_with(MSF2_MAGIC) {
+            self.problems
+                .push("The file is a MSF 2.0 pdb which isn't supported".to_string());
+            return None;
+        }
+
+        if !self.buf.starts_with(MSF7_MAGIC) {
+            self.problems
+                .push("Invalid magic: the file isn't a MSF 7.0 pdb".to_string());
+            return None;
+        }
+
+        let block_size = read_u32(self.buf, 32).unwrap() as usize;
+        let free_block_map = read_u32(self.buf, 36).unwrap();
+        let num_blocks = read_u32(self.buf, 40).unwrap() as usize;
+        let num_directory_bytes = read_u32(self.buf, 44).unwrap() as usize;
+        let block_map_addr = read_u32(self.buf, 52).unwrap();
+
+        if !matches!(block_size, 512 | 1024 | 2048 | 4096) {
+            self.problems.push(format!(
+                "Invalid block size {} in the superblock",
+                block_size
+            ));
+            return None;
+        }
+
+        self.block_size = block_size;
+        self.num_blocks = num_blocks;
+
+        let expected = num_blocks * block_size;
+        if self.buf.len() < expected {
+            self.problems.push(format!(
+                "The file is truncated: {} blocks of {} bytes ({} bytes) are expected but the file has {} bytes",
+                num_blocks,
+                block_size,
+                expected,
+                self.buf.len()
+            ));
+        }
+
+        if free_block_map != 1 && free_block_map != 2 {
+            self.problems.push(format!(
+                "Invalid free block map index {} in the superblock (must be 1 or 2)",
+                free_block_map
+            ));
+        }
+
+        Some((num_directory_bytes, block_map_addr))
+    }
+
+    fn read_directory(&mut self, num_bytes: usize, block_map_addr: u32) -> Option<Vec<u8>> {
+        if !self.check_block(block_map_addr, &|| "Block map".to_string()) {
+            return None;
+        }
+
+        let num_blocks = get_num_blocks(num_bytes, self.block_size);
+        if num_blocks * 4 > self.block_size {
+            self.problems.push(format!(
+                "The stream directory ({} bytes) doesn't fit in the block map",
+                num_bytes
+            ));
+            return None;
+        }
+
+        let block_map = self.get_block(block_map_addr).unwrap();
+        let mut directory = Vec::with_capacity(num_blocks * self.block_size);
+        for i in 0..num_blocks {
+            let index = read_u32(block_map, i * 4).unwrap();
+            if !self.check_block(index, &|| format!("Stream directory (block #{})", i)) {
+                return None;
+            }
+            directory.extend_from_slice(self.get_block(index).unwrap());
+        }
+        directory.truncate(num_bytes);
+
+        Some(directory)
+    }
+
+    fn parse_directory(&mut self, directory: &[u8]) {
+        let num_streams = match read_u32(directory, 0) {
+            Some(n) => n as usize,
+            _ => {
+                self.problems
+                    .push("The stream directory is empty".to_string());
+                return;
+            }
+        };
+
+        // Don't trust num_streams to allocate
+        let mut sizes = Vec::with_capacity(num_streams.min(directory.len() / 4));
+        for i in 0..num_streams {
+            match read_u32(directory, 4 + i * 4) {
+                Some(size) => sizes.push(size),
+                _ => {
+                    self.problems.push(format!(
+                        "The stream directory is truncated: the size of the stream {} is missing ({} streams)",
+                        i, num_streams
+                    ));
+                    return;
+                }
+            }
+        }
+
+        // The block index -> stream index
+        let mut used = HashMap::new();
+        let mut pos = 4 + num_streams * 4;
+        for (stream, size) in sizes.into_iter().enumerate() {
+            if size == NIL_STREAM {
+                self.streams.push(None);
+                continue;
+            }
+
+            let size = size as usize;
+            let num_blocks = get_num_blocks(size, self.block_size);
+            let mut blocks = Vec::with_capacity(num_blocks.min(directory.len() / 4));
+            let mut valid = true;
+
+            for i in 0..num_blocks {
+                let index = match read_u32(directory, pos) {
+                    Some(index) => index,
+                    _ => {
+                        self.problems.push(format!(
+                            "The stream directory is truncated: the block #{} of the stream {} is missing",
+                            i, stream
+                        ));
+                        return;
+                    }
+                };
+                pos += 4;
+
+                valid &= self.check_block(index, &|| format!("Stream {} (block #{})", stream, i));
+                if let Some(other) = used.insert(index, stream) {
+                    self.problems.push(format!(
+                        "Stream {} (block #{}): block {} is already used by the stream {}",
+                        stream, i, index, other
+                    ));
+                    valid = false;
+                }
+                blocks.push(index);
+            }
+
+            self.streams
+                .push(if valid { Some((size, blocks)) } else { None });
+        }
+    }
+
+    fn get_stream(&self, index: usize) -> Option<Vec<u8>> {
+        let (size, blocks) = self.streams.get(index)?.as_ref()?;
+        let mut data = Vec::with_capacity(blocks.len() * self.block_size);
+        for block in blocks {
+            data.extend_from_slice(self.get_block(*block)?);
+        }
+        data.truncate(*size);
+        Some(data)
+    }
+
+    fn check_stream_index(&mut self, what: &str, index: u16) {
+        if index != 0xFFFF && index as usize >= self.streams.len() {
+            self.problems.push(format!(
+                "DBI stream: the {} stream index {} is out of range ({} streams)",
+                what,
+                index,
+                self.streams.len()
+            ));
+        }
+    }
+
+    fn check_pdb_stream(&mut self) {
+        let data = match self.get_stream(PDB_STREAM) {
+            Some(data) => data,
+            _ => {
+                self.problems
+                    .push("The PDB information stream is missing".to_string());
+                return;
+            }
+        };
+
+        if data.len() < 28 {
+            self.problems.push(format!(
+                "PDB information stream: too short ({} bytes)",
+                data.len()
+            ));
+            return;
+        }
+
+        let version = read_u32(&data, 0).unwrap();
+        if !PDB_VERSIONS.contains(&version) {
+            self.problems.push(format!(
+                "PDB information stream: unknown version {}",
+                version
+            ));
+        }
+    }
+
+    fn check_type_stream(&mut self, index: usize, name: &str) {
+        let data = match self.get_stream(index) {
+            Some(data) => data,
+            // No type information: not an error
+            _ => return,
+        };
+
+        if data.is_empty() {
+            return;
+        }
+
+        if data.len() < TPI_HEADER_SIZE {
+            self.problems
+                .push(format!("{} stream: too short ({} bytes)", name, data.len()));
+            return;
+        }
+
+        let version = read_u32(&data, 0).unwrap();
+        let header_size = read_u32(&data, 4).unwrap() as usize;
+        let begin = read_u32(&data, 8).unwrap();
+        let end = read_u32(&data, 12).unwrap();
+        let record_bytes = read_u32(&data, 16).unwrap() as usize;
+
+        if !TPI_VERSIONS.contains(&version) {
+            self.problems
+                .push(format!("{} stream: unknown version {}", name, version));
+        }
+        if header_size != TPI_HEADER_SIZE {
+            self.problems.push(format!(
+                "{} stream: invalid header size {}",
+                name, header_size
+            ));
+        }
+        if begin > end {
+            self.problems.push(format!(
+                "{} stream: invalid type index range [{:#x}, {:#x}[",
+                name, begin, end
+            ));
+        }
+        if header_size + record_bytes > data.len() {
+            self.problems.push(format!(
+                "{} stream: the records ({} bytes) don't fit in the stream ({} bytes)",
+                name,
+                record_bytes,
+                data.len()
+            ));
+        }
+    }
+
+    fn check_dbi_stream(&mut self) {
+        let data = match self.get_stream(DBI_STREAM) {
+            Some(data) => data,
+            _ => {
+                self.problems.push("The DBI stream is missing".to_string());
+                return;
+            }
+        };
+
+        if data.len() < DBI_HEADER_SIZE {
+            self.problems
+                .push(format!("DBI stream: too short ({} bytes)", data.len()));
+            return;
+        }
+
+        let signature = read_u32(&data, 0).unwrap();
+        let version = read_u32(&data, 4).unwrap();
+        if signature != 0xFFFF_FFFF {
+            self.problems
+                .push(format!("DBI stream: invalid signature {:#x}", signature));
+        }
+        if !DBI_VERSIONS.contains(&version) {
+            self.problems
+                .push(format!("DBI stream: unknown version {}", version));
+        }
+
+        self.check_stream_index("global symbols", read_u16(&data, 12).unwrap());
+        self.check_stream_index("public symbols", read_u16(&data, 16).unwrap());
+        self.check_stream_index("symbol records", read_u16(&data, 20).unwrap());
+
+        let substreams = [
+            ("module info", 24),
+            ("section contribution", 28),
+            ("section map", 32),
+            ("source info", 36),
+            ("type server map", 40),
+            ("optional debug header", 48),
+            ("EC", 52),
+        ];
+        let mut total = DBI_HEADER_SIZE;
+        for (name, offset) in substreams.iter() {
+            let size = read_u32(&data, *offset).unwrap() as i32;
+            if size < 0 {
+                self.problems.push(format!(
+                    "DBI stream: invalid {} substream size {}",
+                    name, size
+                ));
+                return;
+            }
+            total += size as usize;
+        }
+
+        if total > data.len() {
+            self.problems.push(format!(
+                "DBI stream: the substreams ({} bytes) don't fit in the stream ({} bytes)",
+                total,
+                data.len()
+            ));
+        }
+    }
+}
+
+/// Check the structure of a pdb (MSF superblock, stream directory, streams blocks)
+/// and the headers of the main streams.
+/// The returned vector contains the problems (it's empty when the pdb is fine).
+pub fn verify_pdb(buf: &[u8]) -> Vec<String> {
+    let mut msf = Msf {
+        buf,
+        block_size: 0,
+        num_blocks: 0,
+        streams: Vec::new(),
+        problems: Vec::new(),
+    };
+
+    let (num_directory_bytes, block_map_addr) = match msf.parse_superblock() {
+        Some(x) => x,
+        _ => return msf.problems,
+    };
+
+    let directory = match msf.read_directory(num_directory_bytes, block_map_addr) {
+        Some(directory) => directory,
+        _ => return msf.problems,
+    };
+
+    msf.parse_directory(&directory);
+    msf.check_pdb_stream();
+    msf.check_type_stream(TPI_STREAM, "TPI");
+    msf.check_dbi_stream();
+    msf.check_type_stream(IPI_STREAM, "IPI");
+
+    msf.problems
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    fn read_pdb(name: &str) -> Vec<u8> {
+        let path = PathBuf::from("./test_data/windows").join(name);
+        crate::utils::read_file(&path)
+    }
+
+    #[test]
+    fn test_verify_valid() {
+        for name in &["basic64.pdb", "basic-opt32.pdb", "dump_syms_regtest64.pdb"] {
+            let problems = verify_pdb(&read_pdb(name));
+            assert!(problems.is_empty(), "{}: {:?}", name, problems);
+        }
+    }
+
+    #[test]
+    fn test_verify_truncated() {
+        let buf = read_pdb("basic64.pdb");
+        let problems = verify_pdb(&buf[..buf.len() / 2]);
+
+        assert!(problems[0].starts_with("The file is truncated"));
+    }
+
+    #[test]
+    fn test_verify_corrupted_block_map() {
+        let mut buf = read_pdb("basic64.pdb");
+        // Make the stream directory point to a block out of range
+        let block_size = read_u32(&buf, 32).unwrap() as usize;
+        let block_map = read_u32(&buf, 52).unwrap() as usize * block_size;
+        buf[block_map..block_map + 4].copy_from_slice(&0xFFFF_u32.to_le_bytes());
+
+        let problems = verify_pdb(&buf);
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Stream directory (block #0): block 65535 is out of range"));
+    }
+}
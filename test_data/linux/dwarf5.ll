@@ -0,0 +1,46 @@
+; A C file with two functions (square in .text.unlikely to have a unit with some ranges)
+; compiled by clang -gdwarf-5 (written by hand for llc)
+; int square(int x) { return x * x; }
+; int main(int argc, char **argv) { return square(argc); }
+
+target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-f80:128-n8:16:32:64-S128"
+target triple = "x86_64-pc-linux-gnu"
+
+define dso_local i32 @square(i32 %x) #0 section ".text.unlikely" !dbg !10 {
+entry:
+  %x.addr = alloca i32, align 4
+  store i32 %x, i32* %x.addr, align 4
+  %0 = load i32, i32* %x.addr, align 4, !dbg !14
+  %1 = load i32, i32* %x.addr, align 4, !dbg !15
+  %mul = mul nsw i32 %0, %1, !dbg !16
+  ret i32 %mul, !dbg !17
+}
+
+define dso_local i32 @main(i32 %argc, i8** %argv) #0 !dbg !18 {
+entry:
+  %call = call i32 @square(i32 %argc), !dbg !20
+  ret i32 %call, !dbg !21
+}
+
+attributes #0 = { noinline nounwind optnone uwtable "frame-pointer"="all" }
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2, !3, !4}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: "clang version 14.0.6", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug, splitDebugInlining: false, nameTableKind: None)
+!1 = !DIFile(filename: "dwarf5.c", directory: "/tmp/dump_syms")
+!2 = !{i32 7, !"Dwarf Version", i32 5}
+!3 = !{i32 2, !"Debug Info Version", i32 3}
+!4 = !{i32 1, !"wchar_size", i32 4}
+!10 = distinct !DISubprogram(name: "square", scope: !1, file: !1, line: 1, type: !11, scopeLine: 1, flags: DIFlagPrototyped, spFlags: DISPFlagDefinition, unit: !0)
+!11 = !DISubroutineType(types: !12)
+!12 = !{!13, !13}
+!13 = !DIBasicType(name: "int", size: 32, encoding: DW_ATE_signed)
+!14 = !DILocation(line: 2, column: 12, scope: !10)
+!15 = !DILocation(line: 2, column: 16, scope: !10)
+!16 = !DILocation(line: 2, column: 14, scope: !10)
+!17 = !DILocation(line: 3, column: 5, scope: !10)
+!18 = distinct !DISubprogram(name: "main", scope: !1, file: !1, line: 6, type: !19, scopeLine: 6, flags: DIFlagPrototyped, spFlags: DISPFlagDefinition, unit: !0)
+!19 = !DISubroutineType(types: !12)
+!20 = !DILocation(line: 7, column: 12, scope: !18)
+!21 = !DILocation(line: 7, column: 5, scope: !18)